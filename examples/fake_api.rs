@@ -0,0 +1,20 @@
+//! Serves the fake PetRing API from the integration tests on its own, for running collar
+//! without a PetRing instance. See the README.
+
+#[allow(dead_code)]
+#[path = "../tests/common/fake_api.rs"]
+mod fake_api;
+
+use tracing::Level;
+
+#[tokio::main]
+async fn main() -> Result<(), fake_api::FakeError> {
+  tracing_subscriber::fmt().with_max_level(Level::INFO).init();
+
+  let addr = std::env::var("FAKE_API_ADDR").unwrap_or_else(|_| String::from("127.0.0.1:8787"));
+  let api = fake_api::spawn(&addr).await?;
+  println!("Point API_BASE_URL at {}", api.base_url());
+
+  tokio::signal::ctrl_c().await?;
+  Ok(())
+}
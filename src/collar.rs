@@ -1,35 +1,64 @@
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use dotenvy::dotenv;
-use poise::serenity_prelude::{CreateEmbed, CreateEmbedFooter, UserId};
+use poise::serenity_prelude::{
+  self as serenity, CreateEmbed, CreateEmbedFooter, GuildId, MessageId, UserId,
+};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use std::{
-  io::{Read, Write},
-  path::Path,
+  collections::{HashMap, HashSet},
   sync::Arc,
 };
-use tokio::{
-  sync::Mutex,
-  time::{Duration, Instant, interval_at},
-};
+use tokio::time::Duration;
 use tracing::{error, info, warn};
 
-pub(crate) mod commands;
-pub(crate) mod http;
-pub(crate) mod notifs;
+pub mod ad_archive;
+pub mod ad_hash;
+pub mod ad_image;
+pub mod audit;
+pub mod cache_file;
+pub mod client;
+pub mod commands;
+pub mod health;
+pub mod http;
+pub mod metrics;
+pub mod notif_outbox;
+pub mod notifs;
+pub mod ownership;
+pub mod site_check;
+pub mod state;
+pub mod submission_outbox;
+pub mod supervisor;
+pub mod tokens;
+pub mod url_policy;
 
-pub(crate) type CollarError = Box<dyn std::error::Error + Send + Sync>;
-pub(crate) type CollarContext<'a> = poise::Context<'a, Collar, CollarError>;
-pub(crate) type CollarAppContext<'a> = poise::ApplicationContext<'a, Collar, CollarError>;
+pub type CollarError = Box<dyn std::error::Error + Send + Sync>;
+pub type CollarContext<'a> = poise::Context<'a, Collar, CollarError>;
+pub type CollarAppContext<'a> = poise::ApplicationContext<'a, Collar, CollarError>;
 
-#[derive(Debug, Clone, Copy)]
-pub(crate) enum NotifChannelType {
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotifChannelType {
   UserSubmit,
   AdSubmit,
   UserVerify,
   AdVerify,
   General,
   DmFallback,
+  /// Where collar reports notifications it couldn't deliver.
+  Admin,
+}
+
+impl NotifChannelType {
+  pub const ALL: [NotifChannelType; 7] = [
+    NotifChannelType::UserSubmit,
+    NotifChannelType::AdSubmit,
+    NotifChannelType::UserVerify,
+    NotifChannelType::AdVerify,
+    NotifChannelType::General,
+    NotifChannelType::DmFallback,
+    NotifChannelType::Admin,
+  ];
 }
 
 impl std::fmt::Display for NotifChannelType {
@@ -41,235 +70,890 @@ impl std::fmt::Display for NotifChannelType {
       NotifChannelType::AdVerify => write!(f, "notif_channel_ids.ad_verify_id"),
       NotifChannelType::General => write!(f, "notif_channel_ids.general_id"),
       NotifChannelType::DmFallback => write!(f, "notif_channel_ids.dm_fallback_id"),
+      NotifChannelType::Admin => write!(f, "notif_channel_ids.admin_id"),
     }
   }
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
-pub(crate) struct Secrets {
+pub struct Secrets {
   pub access_token: String,
   pub refresh_token: String,
   pub access_token_expires_at: i64,
   pub refresh_token_expires_at: i64,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub(crate) struct NotifChannels {
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct NotifChannels {
   user_submit_id: Option<u64>,
   ad_submit_id: Option<u64>,
   user_verify_id: Option<u64>,
   ad_verify_id: Option<u64>,
   dm_fallback_id: Option<u64>,
   general_id: Option<u64>,
+  #[serde(default)]
+  admin_id: Option<u64>,
+}
+
+impl NotifChannels {
+  pub fn get(&self, notify_type: NotifChannelType) -> Option<u64> {
+    match notify_type {
+      NotifChannelType::UserSubmit => self.user_submit_id,
+      NotifChannelType::AdSubmit => self.ad_submit_id,
+      NotifChannelType::UserVerify => self.user_verify_id,
+      NotifChannelType::AdVerify => self.ad_verify_id,
+      NotifChannelType::General => self.general_id,
+      NotifChannelType::DmFallback => self.dm_fallback_id,
+      NotifChannelType::Admin => self.admin_id,
+    }
+  }
+
+  pub fn set(&mut self, channel_id: u64, notify_type: NotifChannelType) -> &mut Self {
+    match notify_type {
+      NotifChannelType::UserSubmit => self.user_submit_id = Some(channel_id),
+      NotifChannelType::AdSubmit => self.ad_submit_id = Some(channel_id),
+      NotifChannelType::UserVerify => self.user_verify_id = Some(channel_id),
+      NotifChannelType::AdVerify => self.ad_verify_id = Some(channel_id),
+      NotifChannelType::General => self.general_id = Some(channel_id),
+      NotifChannelType::DmFallback => self.dm_fallback_id = Some(channel_id),
+      NotifChannelType::Admin => self.admin_id = Some(channel_id),
+    }
+    self
+  }
 }
 
+/// Most guilds can pick this many templates, the last select menu slot is "other".
+pub const MAX_REJECT_REASONS: usize = 24;
+
+/// A named rejection reason moderators can pick instead of typing it out.
 #[derive(Clone, Debug, Deserialize, Serialize)]
-pub(crate) struct Urls {
+pub struct RejectReason {
+  pub name: String,
+  pub text: String,
+}
+
+/// Highest quorum a guild can ask for, past that reviews would never get done.
+pub const MAX_REVIEW_QUORUM: usize = 10;
+
+/// How many distinct moderators have to approve a submission before collar verifies it.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub struct ReviewQuorum {
+  user: usize,
+  ad: usize,
+}
+
+impl Default for ReviewQuorum {
+  fn default() -> Self {
+    Self { user: 1, ad: 1 }
+  }
+}
+
+impl ReviewQuorum {
+  pub fn get(&self, submit_type: notifs::SubmitType) -> usize {
+    match submit_type {
+      notifs::SubmitType::User => self.user,
+      notifs::SubmitType::Ad => self.ad,
+    }
+  }
+
+  pub fn set(&mut self, submit_type: notifs::SubmitType, approvals: usize) -> &mut Self {
+    match submit_type {
+      notifs::SubmitType::User => self.user = approvals,
+      notifs::SubmitType::Ad => self.ad = approvals,
+    }
+    self
+  }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Urls {
   api_base_url: String,
   web_base_url: String,
 }
 
+impl Urls {
+  pub fn new(api_base_url: String, web_base_url: String) -> Self {
+    Self {
+      api_base_url,
+      web_base_url,
+    }
+  }
+}
+
+/// Name of the instance configured through `API_BASE_URL`/`WEB_BASE_URL`, used by
+/// guilds that haven't been bound to another one.
+pub const DEFAULT_INSTANCE: &str = "default";
+
+/// One PetRing instance collar talks to, with its own tokens.
 #[derive(Clone, Debug, Deserialize, Serialize)]
-pub(crate) struct Cache {
+pub struct Instance {
+  urls: Urls,
   secrets: Secrets,
-  notif_channel_ids: NotifChannels,
+}
+
+impl Instance {
+  pub fn new(urls: Urls) -> Self {
+    Self {
+      urls,
+      secrets: Secrets {
+        access_token: String::new(),
+        refresh_token: String::new(),
+        access_token_expires_at: 0,
+        refresh_token_expires_at: 0,
+      },
+    }
+  }
+
+  #[allow(dead_code)]
+  pub fn get_urls(&self) -> Urls {
+    self.urls.clone()
+  }
+
+  pub fn get_web_base_url(&self) -> String {
+    self.urls.web_base_url.clone()
+  }
+
+  pub fn get_api_base_url(&self) -> String {
+    self.urls.api_base_url.clone()
+  }
+
+  pub fn get_secrets(&self) -> Secrets {
+    self.secrets.clone()
+  }
+
+  pub fn set_urls(&mut self, urls: Urls) -> &mut Self {
+    self.urls = urls;
+    self
+  }
+
+  pub fn set_secrets(&mut self, secrets: Secrets) -> &mut Self {
+    self.secrets = secrets;
+    self
+  }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Cache {
+  #[serde(default)]
+  instances: HashMap<String, Instance>,
+  #[serde(default)]
+  guild_instances: HashMap<GuildId, String>,
+  #[serde(default)]
+  guild_notif_channel_ids: HashMap<GuildId, NotifChannels>,
   feedback_webhook: Option<String>,
-  urls: Urls,
+  #[serde(default)]
+  pending_submissions: HashMap<MessageId, notifs::PendingSubmission>,
+  #[serde(default)]
+  guild_reject_reasons: HashMap<GuildId, Vec<RejectReason>>,
+  #[serde(default)]
+  guild_review_quorums: HashMap<GuildId, ReviewQuorum>,
+  #[serde(default)]
+  review_votes: Vec<notifs::ReviewVotes>,
+  #[serde(default)]
+  site_health: Vec<health::SiteHealth>,
+  #[serde(default)]
+  last_health_summary: Option<DateTime<Utc>>,
+  #[serde(default)]
+  ownership_challenges: Vec<ownership::OwnershipChallenge>,
+  #[serde(default)]
+  guilds_requiring_ownership: HashSet<GuildId>,
+  #[serde(default)]
+  guild_domain_policies: HashMap<GuildId, url_policy::DomainPolicy>,
+  #[serde(default)]
+  guild_ad_image_limits: HashMap<GuildId, ad_image::AdImageLimits>,
+  #[serde(default)]
+  ad_hashes: Vec<ad_hash::AdHashRecord>,
+  #[serde(default)]
+  archived_ads: Vec<ad_archive::ArchivedAd>,
+  #[serde(default)]
+  queued_submissions: Vec<submission_outbox::QueuedSubmission>,
+  #[serde(default)]
+  notif_outbox: Vec<notif_outbox::Delivery>,
+  #[serde(default)]
+  next_delivery_id: u64,
+  #[serde(default)]
+  roster: Vec<client::RosterEntry>,
 }
 
 #[derive(Clone)]
-pub(crate) struct Collar {
+pub struct Collar {
   http_client: Client,
-  cache: Arc<Mutex<Cache>>,
+  /// For urls members hand collar, see [`site_check::public_client`].
+  fetch_client: Client,
+  cache: state::SharedCache,
+  audit_log: audit::AuditLog,
+  tokens: tokens::TokenManager,
+  api_status: supervisor::ApiStatus,
+  metrics: metrics::RequestMetrics,
   bot_id: UserId,
+  reviews_in_flight: notifs::ReviewsInFlight,
+  site_checker: Arc<site_check::SiteChecker>,
+}
+
+impl Default for Cache {
+  fn default() -> Self {
+    let mut cache = Self::new();
+    cache.instances.insert(
+      DEFAULT_INSTANCE.to_string(),
+      Instance::new(Urls {
+        api_base_url: String::from("https://api.webring.pet"),
+        web_base_url: String::from("https://webring.pet"),
+      }),
+    );
+    cache
+  }
 }
 
 impl Cache {
   pub fn new() -> Self {
     Self {
-      secrets: Secrets {
-        access_token: String::new(),
-        refresh_token: String::new(),
-        access_token_expires_at: 0,
-        refresh_token_expires_at: 0,
-      },
-      notif_channel_ids: NotifChannels {
-        user_submit_id: None,
-        ad_submit_id: None,
-        user_verify_id: None,
-        ad_verify_id: None,
-        dm_fallback_id: None,
-        general_id: None,
-      },
+      instances: HashMap::new(),
+      guild_instances: HashMap::new(),
+      guild_notif_channel_ids: HashMap::new(),
       feedback_webhook: None,
-      urls: Urls {
-        api_base_url: String::new(),
-        web_base_url: String::new(),
-      },
+      pending_submissions: HashMap::new(),
+      guild_reject_reasons: HashMap::new(),
+      guild_review_quorums: HashMap::new(),
+      review_votes: Vec::new(),
+      site_health: Vec::new(),
+      last_health_summary: None,
+      ownership_challenges: Vec::new(),
+      guilds_requiring_ownership: HashSet::new(),
+      guild_domain_policies: HashMap::new(),
+      guild_ad_image_limits: HashMap::new(),
+      ad_hashes: Vec::new(),
+      archived_ads: Vec::new(),
+      queued_submissions: Vec::new(),
+      notif_outbox: Vec::new(),
+      next_delivery_id: 1,
+      roster: Vec::new(),
     }
   }
 
-  pub fn default() -> Self {
-    Self {
-      secrets: Secrets {
-        access_token: String::new(),
-        refresh_token: String::new(),
-        access_token_expires_at: 0,
-        refresh_token_expires_at: 0,
-      },
-      notif_channel_ids: NotifChannels {
-        user_submit_id: None,
-        ad_submit_id: None,
-        user_verify_id: None,
-        ad_verify_id: None,
-        dm_fallback_id: None,
-        general_id: None,
-      },
-      feedback_webhook: None,
-      urls: Urls {
-        api_base_url: String::from("https://api.webring.pet"),
-        web_base_url: String::from("https://webring.pet"),
-      },
+  pub fn write_to_disk(&self) -> Result<(), CollarError> {
+    cache_file::write(self)
+  }
+
+  pub fn get_notif_channel(&self, guild_id: GuildId, notify_type: NotifChannelType) -> Option<u64> {
+    self
+      .guild_notif_channel_ids
+      .get(&guild_id)
+      .and_then(|channels| channels.get(notify_type))
+  }
+
+  pub fn get_all_notif_channels(&self, guild_id: GuildId) -> NotifChannels {
+    self
+      .guild_notif_channel_ids
+      .get(&guild_id)
+      .cloned()
+      .unwrap_or_default()
+  }
+
+  pub fn get_feedback_webhook(&self) -> Option<String> {
+    self.feedback_webhook.clone()
+  }
+
+  pub fn get_instance(&self, name: &str) -> Option<&Instance> {
+    self.instances.get(name)
+  }
+
+  pub fn get_instance_mut(&mut self, name: &str) -> Option<&mut Instance> {
+    self.instances.get_mut(name)
+  }
+
+  pub fn get_instance_names(&self) -> Vec<String> {
+    let mut names = self.instances.keys().cloned().collect::<Vec<_>>();
+    names.sort();
+    names
+  }
+
+  /// Name of the instance `guild_id` uses, [`DEFAULT_INSTANCE`] unless it was bound to another.
+  /// `None` when it's bound to an instance that isn't configured anymore, a guild on a staging
+  /// ring must never end up talking to production.
+  pub fn get_guild_instance_name(&self, guild_id: Option<GuildId>) -> Option<String> {
+    match guild_id.and_then(|guild_id| self.guild_instances.get(&guild_id)) {
+      Some(name) => self.instances.contains_key(name).then(|| name.clone()),
+      None => Some(DEFAULT_INSTANCE.to_string()),
     }
   }
 
-  pub fn exists_on_disk() -> bool {
-    let path = Path::new("./.cache.json");
+  /// The instance `guild_id` was bound to with `/set_instance`, configured or not.
+  pub fn get_guild_binding(&self, guild_id: GuildId) -> Option<&String> {
+    self.guild_instances.get(&guild_id)
+  }
 
-    path.exists()
+  pub fn get_guild_instance(&self, guild_id: Option<GuildId>) -> Option<&Instance> {
+    self
+      .get_guild_instance_name(guild_id)
+      .and_then(|name| self.get_instance(&name))
   }
 
-  pub fn read_from_disk(&self) -> Result<Self, CollarError> {
-    dotenv().ok();
-    let path = std::env::var("CACHE_PATH").unwrap_or(".cache.json".to_string());
+  pub fn get_web_base_url(&self, guild_id: Option<GuildId>) -> String {
+    self
+      .get_guild_instance(guild_id)
+      .map(|instance| instance.get_web_base_url())
+      .unwrap_or_default()
+  }
 
-    let mut cache_buf = String::new();
-    let mut cache_file = match std::fs::File::open(path) {
-      Ok(file) => file,
-      Err(err) => {
-        return Err(CollarError::from(format!(
-          "There was an error opening the cache file: {err}"
-        )));
-      }
-    };
+  pub fn get_api_base_url(&self, guild_id: Option<GuildId>) -> String {
+    self
+      .get_guild_instance(guild_id)
+      .map(|instance| instance.get_api_base_url())
+      .unwrap_or_default()
+  }
 
-    match cache_file.read_to_string(&mut cache_buf) {
-      Ok(_) => (),
-      Err(err) => {
-        return Err(CollarError::from(format!(
-          "There was an error reading the cached secrets file: {err}"
-        )));
-      }
-    };
+  pub fn set_notif_channel(
+    &mut self,
+    guild_id: GuildId,
+    channel_id: u64,
+    notify_type: NotifChannelType,
+  ) -> &mut Self {
+    self
+      .guild_notif_channel_ids
+      .entry(guild_id)
+      .or_default()
+      .set(channel_id, notify_type);
+    self
+  }
+  pub fn add_pending_submission(
+    &mut self,
+    message_id: MessageId,
+    submission: notifs::PendingSubmission,
+  ) -> &mut Self {
+    self.pending_submissions.insert(message_id, submission);
+    self
+  }
+
+  pub fn get_pending_submission(
+    &self,
+    message_id: MessageId,
+  ) -> Option<&notifs::PendingSubmission> {
+    self.pending_submissions.get(&message_id)
+  }
 
-    match serde_json::from_str::<Cache>(&cache_buf) {
-      Ok(cache) => Ok(cache),
-      Err(err) => Err(CollarError::from(format!(
-        "Failed to deserialize cache: {err}"
-      ))),
+  /// Review messages still open for a submission to `instance`, oldest first.
+  pub fn get_pending_submissions_for(
+    &self,
+    instance: &str,
+    submit_type: notifs::SubmitType,
+    discord_id: UserId,
+  ) -> Vec<(MessageId, notifs::PendingSubmission)> {
+    let mut pending = self
+      .pending_submissions
+      .iter()
+      .filter(|(_, pending)| pending.submit_type == submit_type && pending.discord_id == discord_id)
+      .filter(|(_, pending)| {
+        self
+          .get_guild_instance_name(Some(pending.guild_id))
+          .as_deref()
+          == Some(instance)
+      })
+      .map(|(message_id, pending)| (*message_id, pending.clone()))
+      .collect::<Vec<_>>();
+    pending.sort_by_key(|(_, pending)| pending.submitted_at);
+    pending
+  }
+
+  pub fn remove_pending_submission(
+    &mut self,
+    message_id: MessageId,
+  ) -> Option<notifs::PendingSubmission> {
+    self.pending_submissions.remove(&message_id)
+  }
+
+  pub fn remove_pending_submissions_for(
+    &mut self,
+    instance: &str,
+    submit_type: notifs::SubmitType,
+    discord_id: UserId,
+  ) -> Vec<(MessageId, notifs::PendingSubmission)> {
+    let pending = self.get_pending_submissions_for(instance, submit_type, discord_id);
+    for (message_id, _) in &pending {
+      self.pending_submissions.remove(message_id);
     }
+    pending
   }
 
-  pub fn write_to_disk(&self) -> Result<(), CollarError> {
-    dotenv().ok();
-    let path = std::env::var("CACHE_PATH").unwrap_or(".cache.json".to_string());
-    let mut file_to_write = std::fs::File::create(path)?;
-    let secrets_str = serde_json::to_string(&self)?;
-
-    match file_to_write.write_all(secrets_str.as_bytes()) {
-      Ok(_) => Ok(()),
-      Err(err) => Err(CollarError::from(format!(
-        "Could not write to cache file: {err}"
-      ))),
+  pub fn get_reject_reasons(&self, guild_id: GuildId) -> Vec<RejectReason> {
+    self
+      .guild_reject_reasons
+      .get(&guild_id)
+      .cloned()
+      .unwrap_or_default()
+  }
+
+  /// Adds `reason`, replacing the one with the same name if there is one.
+  pub fn set_reject_reason(&mut self, guild_id: GuildId, reason: RejectReason) -> &mut Self {
+    let reasons = self.guild_reject_reasons.entry(guild_id).or_default();
+    match reasons
+      .iter_mut()
+      .find(|existing| existing.name.eq_ignore_ascii_case(&reason.name))
+    {
+      Some(existing) => *existing = reason,
+      None => reasons.push(reason),
     }
+    self
   }
 
-  pub async fn refresh_secrets(&mut self, http_client: &Client) -> Result<&mut Self, CollarError> {
-    let secrets = self
-      .secrets
-      .clone()
-      .refresh_secrets(http_client.clone(), self.get_api_base_url())
-      .await?;
+  pub fn remove_reject_reason(&mut self, guild_id: GuildId, name: &str) -> Option<RejectReason> {
+    let reasons = self.guild_reject_reasons.get_mut(&guild_id)?;
+    let index = reasons
+      .iter()
+      .position(|reason| reason.name.eq_ignore_ascii_case(name))?;
+    Some(reasons.remove(index))
+  }
 
-    self.secrets = secrets;
-    Ok(self)
+  pub fn get_review_quorum(&self, guild_id: Option<GuildId>) -> ReviewQuorum {
+    guild_id
+      .and_then(|guild_id| self.guild_review_quorums.get(&guild_id))
+      .copied()
+      .unwrap_or_default()
   }
 
-  pub async fn fetch_secrets(
+  pub fn set_review_quorum(
     &mut self,
-    http_client: &reqwest::Client,
-  ) -> Result<&mut Self, CollarError> {
-    let secrets = self.secrets.clone();
-    let secrets = secrets
-      .get_secrets(http_client.clone(), self.get_api_base_url())
-      .await?;
+    guild_id: GuildId,
+    submit_type: notifs::SubmitType,
+    approvals: usize,
+  ) -> &mut Self {
+    self
+      .guild_review_quorums
+      .entry(guild_id)
+      .or_default()
+      .set(submit_type, approvals);
+    self
+  }
 
-    self.set_secrets(secrets);
-    Ok(self)
+  /// Approvals and veto on a submission to `instance` so far, empty if nobody voted yet.
+  pub fn get_review_votes(
+    &self,
+    instance: &str,
+    submit_type: notifs::SubmitType,
+    discord_id: UserId,
+  ) -> notifs::ReviewVotes {
+    self
+      .review_votes
+      .iter()
+      .find(|votes| votes.is_for(instance, submit_type, discord_id))
+      .cloned()
+      .unwrap_or_else(|| notifs::ReviewVotes::new(instance, submit_type, discord_id))
   }
 
-  pub fn get_notif_channel(&self, notify_type: NotifChannelType) -> Option<u64> {
-    match notify_type {
-      NotifChannelType::UserSubmit => self.notif_channel_ids.user_submit_id,
-      NotifChannelType::AdSubmit => self.notif_channel_ids.ad_submit_id,
-      NotifChannelType::UserVerify => self.notif_channel_ids.user_verify_id,
-      NotifChannelType::AdVerify => self.notif_channel_ids.ad_verify_id,
-      NotifChannelType::General => self.notif_channel_ids.general_id,
-      NotifChannelType::DmFallback => self.notif_channel_ids.dm_fallback_id,
+  pub fn set_review_votes(&mut self, votes: notifs::ReviewVotes) -> &mut Self {
+    self.remove_review_votes(&votes.instance, votes.submit_type, votes.discord_id);
+    self.review_votes.push(votes);
+    self
+  }
+
+  pub fn remove_review_votes(
+    &mut self,
+    instance: &str,
+    submit_type: notifs::SubmitType,
+    discord_id: UserId,
+  ) -> &mut Self {
+    self
+      .review_votes
+      .retain(|votes| !votes.is_for(instance, submit_type, discord_id));
+    self
+  }
+
+  /// Guilds that set up notification channels and use instance `name`.
+  pub fn get_guilds_for_instance(&self, name: &str) -> Vec<GuildId> {
+    self
+      .guild_notif_channel_ids
+      .keys()
+      .filter(|guild_id| self.get_guild_instance_name(Some(**guild_id)).as_deref() == Some(name))
+      .copied()
+      .collect()
+  }
+
+  pub fn get_site_health(&self, instance: &str, discord_id: UserId) -> Option<health::SiteHealth> {
+    self
+      .site_health
+      .iter()
+      .find(|site| site.instance == instance && site.discord_id == discord_id)
+      .cloned()
+  }
+
+  pub fn get_site_health_for(&self, instance: &str) -> Vec<health::SiteHealth> {
+    self
+      .site_health
+      .iter()
+      .filter(|site| site.instance == instance)
+      .cloned()
+      .collect()
+  }
+
+  pub fn set_site_health(&mut self, site: health::SiteHealth) -> &mut Self {
+    self.remove_site_health(&site.instance.clone(), site.discord_id);
+    self.site_health.push(site);
+    self
+  }
+
+  pub fn remove_site_health(&mut self, instance: &str, discord_id: UserId) -> &mut Self {
+    self
+      .site_health
+      .retain(|site| site.instance != instance || site.discord_id != discord_id);
+    self
+  }
+
+  /// Forgets sites of members that left `instance` or aren't verified anymore.
+  pub fn retain_site_health(&mut self, instance: &str, discord_ids: &[UserId]) -> &mut Self {
+    self
+      .site_health
+      .retain(|site| site.instance != instance || discord_ids.contains(&site.discord_id));
+    self
+  }
+
+  pub fn get_last_health_summary(&self) -> Option<DateTime<Utc>> {
+    self.last_health_summary
+  }
+
+  pub fn set_last_health_summary(&mut self, at: DateTime<Utc>) -> &mut Self {
+    self.last_health_summary = Some(at);
+    self
+  }
+
+  pub fn get_ownership_challenge(
+    &self,
+    instance: &str,
+    discord_id: UserId,
+  ) -> Option<ownership::OwnershipChallenge> {
+    self
+      .ownership_challenges
+      .iter()
+      .find(|challenge| challenge.instance == instance && challenge.discord_id == discord_id)
+      .cloned()
+  }
+
+  /// Replaces the submitter's challenge on its instance, a new submission or url means a new
+  /// token.
+  pub fn set_ownership_challenge(&mut self, challenge: ownership::OwnershipChallenge) -> &mut Self {
+    self.ownership_challenges.retain(|existing| {
+      existing.instance != challenge.instance || existing.discord_id != challenge.discord_id
+    });
+    self.ownership_challenges.push(challenge);
+    self
+  }
+
+  pub fn requires_ownership(&self, guild_id: Option<GuildId>) -> bool {
+    guild_id.is_some_and(|guild_id| self.guilds_requiring_ownership.contains(&guild_id))
+  }
+
+  pub fn set_requires_ownership(&mut self, guild_id: GuildId, required: bool) -> &mut Self {
+    match required {
+      true => self.guilds_requiring_ownership.insert(guild_id),
+      false => self.guilds_requiring_ownership.remove(&guild_id),
+    };
+    self
+  }
+
+  /// Whether the guild wants proof of ownership that the submitter hasn't given yet.
+  pub fn ownership_blocks_verify(
+    &self,
+    guild_id: Option<GuildId>,
+    submit_type: notifs::SubmitType,
+    discord_id: UserId,
+  ) -> bool {
+    submit_type == notifs::SubmitType::User
+      && self.requires_ownership(guild_id)
+      && self
+        .get_guild_instance_name(guild_id)
+        .and_then(|instance| self.get_ownership_challenge(&instance, discord_id))
+        .is_none_or(|challenge| challenge.proven.is_none())
+  }
+
+  pub fn get_domain_policy(&self, guild_id: Option<GuildId>) -> url_policy::DomainPolicy {
+    guild_id
+      .and_then(|guild_id| self.guild_domain_policies.get(&guild_id))
+      .cloned()
+      .unwrap_or_default()
+  }
+
+  pub fn domain_policy_mut(&mut self, guild_id: GuildId) -> &mut url_policy::DomainPolicy {
+    self.guild_domain_policies.entry(guild_id).or_default()
+  }
+
+  pub fn get_ad_image_limits(&self, guild_id: Option<GuildId>) -> ad_image::AdImageLimits {
+    guild_id
+      .and_then(|guild_id| self.guild_ad_image_limits.get(&guild_id))
+      .cloned()
+      .unwrap_or_default()
+  }
+
+  pub fn ad_image_limits_mut(&mut self, guild_id: GuildId) -> &mut ad_image::AdImageLimits {
+    self.guild_ad_image_limits.entry(guild_id).or_default()
+  }
+
+  pub fn get_archived_ad(
+    &self,
+    instance: &str,
+    discord_id: UserId,
+  ) -> Option<ad_archive::ArchivedAd> {
+    self
+      .archived_ads
+      .iter()
+      .find(|archived| archived.instance == instance && archived.discord_id == discord_id)
+      .cloned()
+  }
+
+  /// Replaces the member's archived ad on that instance, returning the old one.
+  pub fn set_archived_ad(
+    &mut self,
+    archived: ad_archive::ArchivedAd,
+  ) -> Option<ad_archive::ArchivedAd> {
+    let position = self.archived_ads.iter().position(|existing| {
+      existing.instance == archived.instance && existing.discord_id == archived.discord_id
+    });
+    match position {
+      Some(position) => Some(std::mem::replace(
+        &mut self.archived_ads[position],
+        archived,
+      )),
+      None => {
+        self.archived_ads.push(archived);
+        None
+      }
     }
   }
 
-  pub fn get_all_notif_channels(&self) -> NotifChannels {
-    self.notif_channel_ids.clone()
+  /// Drops archived ads of members without a verified ad anymore, returning them.
+  pub fn retain_archived_ads(
+    &mut self,
+    instance: &str,
+    discord_ids: &[UserId],
+  ) -> Vec<ad_archive::ArchivedAd> {
+    let (kept, dropped) = std::mem::take(&mut self.archived_ads)
+      .into_iter()
+      .partition(|archived| {
+        archived.instance != instance || discord_ids.contains(&archived.discord_id)
+      });
+    self.archived_ads = kept;
+    dropped
   }
 
-  pub fn get_feedback_webhook(&self) -> Option<String> {
-    self.feedback_webhook.clone()
+  pub fn archive_file_in_use(&self, sha256: &str) -> bool {
+    self
+      .archived_ads
+      .iter()
+      .any(|archived| archived.sha256 == sha256)
   }
 
-  #[allow(dead_code)]
-  pub fn get_urls(&self) -> Urls {
-    self.urls.clone()
+  /// Oldest first, the order they get replayed in.
+  pub fn get_queued_submissions(&self) -> &[submission_outbox::QueuedSubmission] {
+    &self.queued_submissions
   }
 
-  pub fn get_web_base_url(&self) -> String {
-    self.urls.web_base_url.clone()
+  /// Queues a submission, replacing the one the member already had queued, returned if so.
+  pub fn queue_submission(
+    &mut self,
+    queued: submission_outbox::QueuedSubmission,
+  ) -> Option<submission_outbox::QueuedSubmission> {
+    let replaced = self.remove_queued_submission(&queued);
+    self.queued_submissions.push(queued);
+    replaced
   }
 
-  pub fn get_api_base_url(&self) -> String {
-    self.urls.api_base_url.clone()
+  pub fn queued_submission_mut(
+    &mut self,
+    queued: &submission_outbox::QueuedSubmission,
+  ) -> Option<&mut submission_outbox::QueuedSubmission> {
+    self
+      .queued_submissions
+      .iter_mut()
+      .find(|existing| existing.same_as(queued))
   }
 
-  pub fn get_secrets(&self) -> Secrets {
-    self.secrets.clone()
+  pub fn remove_queued_submission(
+    &mut self,
+    queued: &submission_outbox::QueuedSubmission,
+  ) -> Option<submission_outbox::QueuedSubmission> {
+    let position = self
+      .queued_submissions
+      .iter()
+      .position(|existing| existing.same_as(queued))?;
+    Some(self.queued_submissions.remove(position))
   }
-  pub fn set_notif_channel(&mut self, channel_id: u64, notify_type: NotifChannelType) -> &mut Self {
-    match notify_type {
-      NotifChannelType::UserSubmit => self.notif_channel_ids.user_submit_id = Some(channel_id),
-      NotifChannelType::AdSubmit => self.notif_channel_ids.ad_submit_id = Some(channel_id),
-      NotifChannelType::UserVerify => self.notif_channel_ids.user_verify_id = Some(channel_id),
-      NotifChannelType::AdVerify => self.notif_channel_ids.ad_verify_id = Some(channel_id),
-      NotifChannelType::General => self.notif_channel_ids.general_id = Some(channel_id),
-      NotifChannelType::DmFallback => self.notif_channel_ids.dm_fallback_id = Some(channel_id),
+
+  /// Queues a delivery under the next free id, returned.
+  pub fn queue_delivery(&mut self, mut delivery: notif_outbox::Delivery) -> u64 {
+    // Caches from before the outbox start counting at 0.
+    self.next_delivery_id = self.next_delivery_id.max(1);
+    let id = self.next_delivery_id;
+    self.next_delivery_id += 1;
+
+    delivery.id = id;
+    self.notif_outbox.push(delivery);
+    id
+  }
+
+  pub fn get_deliveries(&self) -> &[notif_outbox::Delivery] {
+    &self.notif_outbox
+  }
+
+  pub fn get_delivery(&self, id: u64) -> Option<&notif_outbox::Delivery> {
+    self.notif_outbox.iter().find(|delivery| delivery.id == id)
+  }
+
+  pub fn get_delivery_mut(&mut self, id: u64) -> Option<&mut notif_outbox::Delivery> {
+    self
+      .notif_outbox
+      .iter_mut()
+      .find(|delivery| delivery.id == id)
+  }
+
+  pub fn remove_delivery(&mut self, id: u64) -> Option<notif_outbox::Delivery> {
+    let position = self
+      .notif_outbox
+      .iter()
+      .position(|delivery| delivery.id == id)?;
+    Some(self.notif_outbox.remove(position))
+  }
+
+  pub fn in_roster(&self, entry: &client::RosterEntry) -> bool {
+    self.roster.contains(entry)
+  }
+
+  pub fn add_to_roster(&mut self, entry: client::RosterEntry) -> &mut Self {
+    if !self.in_roster(&entry) {
+      self.roster.push(entry);
+    }
+    self
+  }
+
+  pub fn remove_from_roster(&mut self, entry: &client::RosterEntry) -> &mut Self {
+    self.roster.retain(|existing| existing != entry);
+    self
+  }
+
+  /// Members on `instance` with a user or ad to look up. Caches from before the roster only
+  /// know members through their site health or archived ad, so those count too.
+  pub fn get_roster(&self, instance: &str, submit_type: notifs::SubmitType) -> Vec<UserId> {
+    let known = match submit_type {
+      notifs::SubmitType::User => self
+        .site_health
+        .iter()
+        .filter(|site| site.instance == instance)
+        .map(|site| site.discord_id)
+        .collect::<Vec<_>>(),
+      notifs::SubmitType::Ad => self
+        .archived_ads
+        .iter()
+        .filter(|archived| archived.instance == instance)
+        .map(|archived| archived.discord_id)
+        .collect::<Vec<_>>(),
+    };
+
+    let mut discord_ids = self
+      .roster
+      .iter()
+      .filter(|entry| entry.instance == instance && entry.submit_type == submit_type)
+      .map(|entry| entry.discord_id)
+      .chain(known)
+      .collect::<Vec<_>>();
+    discord_ids.sort();
+    discord_ids.dedup();
+    discord_ids
+  }
+
+  pub fn get_ad_hashes(&self) -> &[ad_hash::AdHashRecord] {
+    &self.ad_hashes
+  }
+
+  /// A member has one live ad, so any decision replaces the hash of their verified one.
+  pub fn add_ad_hash(&mut self, record: ad_hash::AdHashRecord) -> &mut Self {
+    self.ad_hashes.retain(|existing| {
+      existing.discord_id != record.discord_id || existing.outcome != ad_hash::AdOutcome::Verified
+    });
+    self.ad_hashes.push(record);
+
+    while self.ad_hashes.len() > ad_hash::MAX_AD_HASHES {
+      let oldest = self
+        .ad_hashes
+        .iter()
+        .position(|existing| existing.outcome != ad_hash::AdOutcome::Verified)
+        .unwrap_or(0);
+      self.ad_hashes.remove(oldest);
     }
     self
   }
+
   pub fn set_feedback_webhook(&mut self, webhook: String) -> &mut Self {
     self.feedback_webhook = Some(webhook);
     self
   }
 
-  pub fn set_urls(&mut self, urls: Urls) -> &mut Self {
-    self.urls = urls;
+  pub fn set_guild_instance(&mut self, guild_id: GuildId, name: String) -> &mut Self {
+    self.guild_instances.insert(guild_id, name);
     self
   }
 
-  pub fn set_secrets(&mut self, secrets: Secrets) -> &mut Self {
-    self.secrets = secrets;
+  /// Makes sure `name` exists with `urls`, keeping any tokens it already had.
+  pub fn set_instance_urls(&mut self, name: &str, urls: Urls) -> &mut Instance {
+    self
+      .instances
+      .entry(name.to_string())
+      .and_modify(|instance| {
+        instance.set_urls(urls.clone());
+      })
+      .or_insert_with(|| Instance::new(urls))
+  }
+
+  /// Drops instances that aren't configured anymore. Guilds bound to one keep the binding, so
+  /// they get told the instance is missing instead of quietly landing on the default one.
+  pub fn retain_instances(&mut self, names: &[String]) -> &mut Self {
+    self.instances.retain(|name, _| names.contains(name));
+
+    for (guild_id, name) in &self.guild_instances {
+      if !self.instances.contains_key(name) {
+        error!(
+          "Guild {guild_id} is bound to instance {name}, which isn't configured, its commands \
+           fail until {name} is configured again or /set_instance picks another one"
+        );
+      }
+    }
     self
   }
 }
 
+fn env_var(key: &str) -> Result<String, CollarError> {
+  std::env::var(key).map_err(|_| CollarError::from(format!("{key} isn't set")))
+}
+
+/// Reads the default instance from `API_BASE_URL`/`WEB_BASE_URL`, plus every instance named in
+/// `PETRING_INSTANCES` (comma separated) from `<NAME>_API_BASE_URL`/`<NAME>_WEB_BASE_URL`.
+fn instance_urls_from_env() -> Result<Vec<(String, Urls)>, CollarError> {
+  let mut instances = vec![(
+    DEFAULT_INSTANCE.to_string(),
+    Urls {
+      api_base_url: env_var("API_BASE_URL")?,
+      web_base_url: env_var("WEB_BASE_URL")?,
+    },
+  )];
+
+  let extra_instances = std::env::var("PETRING_INSTANCES").unwrap_or_default();
+  for name in extra_instances.split(',') {
+    let name = name.trim().to_lowercase();
+    if name.is_empty() || name == DEFAULT_INSTANCE {
+      continue;
+    }
+
+    let prefix = name.to_uppercase().replace('-', "_");
+    let api_base_url = env_var(&format!("{prefix}_API_BASE_URL"))?;
+    let web_base_url = env_var(&format!("{prefix}_WEB_BASE_URL"))?;
+
+    instances.push((
+      name,
+      Urls {
+        api_base_url,
+        web_base_url,
+      },
+    ));
+  }
+
+  Ok(instances)
+}
+
 struct EmbedWrapper;
 impl EmbedWrapper {
   fn new_normal(ctx: &CollarContext<'_>) -> CreateEmbed {
@@ -295,105 +979,139 @@ impl EmbedWrapper {
 
     CreateEmbed::default().footer(CreateEmbedFooter::new(COLLAR_FOOTER).icon_url(bot_pfp))
   }
+
+  /// For embeds built outside of a command, like from the global event handler.
+  fn new_event(ctx: &serenity::Context) -> CreateEmbed {
+    let bot_pfp = ctx.cache.current_user().face();
+
+    CreateEmbed::default().footer(CreateEmbedFooter::new(COLLAR_FOOTER).icon_url(bot_pfp))
+  }
 }
 
 pub const COLLAR_FOOTER: &str = "Collar :3, a Discord bot helper for PetRing and PetAds :3";
 
 impl Collar {
-  pub async fn new() -> Self {
+  /// Client for the instance `guild_id` is bound to.
+  pub fn petring(&self, guild_id: Option<GuildId>) -> client::PetringClient {
+    client::PetringClient::new(
+      self.http_client.clone(),
+      self.cache.clone(),
+      self.tokens.clone(),
+      self.api_status.clone(),
+      self.metrics.clone(),
+      client::InstanceTarget::Guild(guild_id),
+    )
+  }
+
+  /// Runs the site checks on a submitted website, formatted for the review embed.
+  pub async fn check_site(&self, guild_id: Option<GuildId>, url: &str, username: &str) -> String {
+    let web_base_url = self.cache.read().get_web_base_url(guild_id);
+    let results = self.site_checker.check(url, username, &web_base_url).await;
+    site_check::format_results(&results)
+  }
+
+  /// Fetches a submitted ad image and checks it against the guild's limits.
+  pub async fn inspect_ad_image(
+    &self,
+    guild_id: Option<GuildId>,
+    url: &str,
+  ) -> Result<ad_image::AdImage, ad_image::AdImageError> {
+    let limits = self.cache.read().get_ad_image_limits(guild_id);
+    ad_image::inspect(&self.fetch_client, url, &limits).await
+  }
+
+  pub fn petring_instance(&self, name: &str) -> client::PetringClient {
+    client::PetringClient::new(
+      self.http_client.clone(),
+      self.cache.clone(),
+      self.tokens.clone(),
+      self.api_status.clone(),
+      self.metrics.clone(),
+      client::InstanceTarget::Named(name.to_string()),
+    )
+  }
+
+  /// Doesn't wait for or give up on the PetRing API: without it collar still connects to
+  /// Discord, and commands that need the API say it's unavailable until the supervisor sees it
+  /// come back. Only fails when the instances aren't configured. `own_id` is used when `BOT_ID`
+  /// isn't set.
+  pub async fn new(own_id: UserId) -> Result<Self, CollarError> {
     dotenv().ok();
 
     let client = match http::make_reqwest_client().await {
       Ok(client) => client,
-      Err(e) => panic!("Failed to create reqwest client: {:?}", e),
+      Err(err) => {
+        error!("Failed to create reqwest client: {err}, trying again without proxies");
+        Client::builder().no_proxy().build().unwrap_or_default()
+      }
     };
 
-    let bot_id = std::env::var("BOT_ID").expect("missing BOT_ID");
-    let web_base_url = std::env::var("WEB_BASE_URL").expect("missing WEB_BASE_URL");
-    let api_base_url = std::env::var("API_BASE_URL").expect("missing API_BASE_URL");
-    let client_clone = client.clone();
-
-    let mut cache = if Cache::exists_on_disk() {
-      match Cache::new().read_from_disk() {
-        Ok(cache) => cache,
-        Err(err) => {
-          warn!("Failed to read cache from disk: {:?}, using defaults", err);
-          Cache::default()
-        }
+    let bot_id = match std::env::var("BOT_ID").map(|bot_id| bot_id.parse::<UserId>()) {
+      Ok(Ok(bot_id)) => bot_id,
+      Ok(Err(err)) => {
+        error!("BOT_ID isn't a user id ({err}), using {own_id} instead");
+        own_id
+      }
+      Err(_) => {
+        warn!("No BOT_ID set, using {own_id}");
+        own_id
       }
-    } else {
-      Cache::default()
     };
+    let configured_instances = instance_urls_from_env()?;
+    let instance_names = configured_instances
+      .iter()
+      .map(|(name, _)| name.clone())
+      .collect::<Vec<_>>();
+    let client_clone = client.clone();
 
-    let mut interval = interval_at(
-      Instant::now() + Duration::from_secs(30 * 60),
-      Duration::from_secs(30 * 60),
-    );
-
-    cache.set_urls(Urls {
-      api_base_url,
-      web_base_url,
-    });
+    let mut cache = tokio::task::spawn_blocking(cache_file::load).await?;
+    cache.retain_instances(&instance_names);
 
-    let refresh_expiry = cache.get_secrets().refresh_token_expires_at;
-    if refresh_expiry == 0 || refresh_expiry <= Utc::now().timestamp() {
-      info!("No valid refresh token, testing fetching");
-      match cache.fetch_secrets(&client).await {
-        Ok(cache) => {
-          info!("Successfully fetched secrets: {cache:?}");
-        }
-        Err(err) => {
-          panic!("Failed to get secrets overall: {err}");
-        }
-      }
-    }
-
-    let access_expiry = cache.get_secrets().access_token_expires_at;
-    if access_expiry == 0 || access_expiry <= Utc::now().timestamp() {
-      match cache.refresh_secrets(&client).await {
-        Ok(_) => {
-          info!("Successfully refreshed secrets, caching");
-          cache.write_to_disk().expect("Couldnt write to disk");
-        }
-        Err(err) => {
-          error!("Failed to refresh secrets: {err}");
-        }
-      }
+    for (name, urls) in configured_instances {
+      cache.set_instance_urls(&name, urls);
     }
 
     if let Err(err) = cache.write_to_disk() {
-      panic!("{err}");
+      error!("Failed to write cache to disk, running from memory until a write works: {err}");
     }
 
-    tokio::spawn(async move {
-      let mut new_cache = match Cache::new().read_from_disk() {
-        Ok(cache) => cache,
-        Err(err) => {
-          panic!("Failed to read cache from disk: {err}");
-        }
-      };
-
-      let client = match http::make_reqwest_client().await {
-        Ok(client) => client,
-        Err(e) => panic!("Failed to create reqwest client: {:?}", e),
-      };
-
-      loop {
-        info!("Starting background token refresh");
-        match new_cache.refresh_secrets(&client).await {
-          Ok(_) => {
-            new_cache.write_to_disk().expect("Couldnt write to disk");
+    let cache = state::SharedCache::new(cache);
+    let tokens = tokens::TokenManager::default();
+    let api_status = supervisor::ApiStatus::default();
+
+    for name in instance_names {
+      tokens.spawn(client.clone(), cache.clone(), name.clone());
+
+      // Checked in the background, so an API that's down doesn't hold up connecting to Discord.
+      let (tokens, cache, api_status) = (tokens.clone(), cache.clone(), api_status.clone());
+      tokio::spawn(async move {
+        match tokens
+          .wait_valid(&cache, &name, Duration::from_secs(30))
+          .await
+        {
+          Ok(secrets) => info!(
+            "Tokens for {name} are good until {}",
+            secrets.access_token_expires_at
+          ),
+          Err(err) => {
+            warn!("No tokens for {name} yet, commands needing it report it unavailable");
+            api_status.mark_down(&name, err.to_string());
           }
-          Err(e) => panic!("Failed to refresh secrets: {:?}", e),
-        };
-        interval.tick().await;
-      }
-    });
+        }
+      });
+    }
 
-    Self {
-      cache: Arc::new(Mutex::new(cache)),
+    Ok(Self {
+      cache,
+      audit_log: audit::AuditLog::new(audit::path()),
+      tokens,
+      api_status,
+      metrics: metrics::RequestMetrics::default(),
       http_client: client_clone,
-      bot_id: bot_id.parse::<UserId>().unwrap(),
-    }
+      fetch_client: site_check::public_client()?,
+      bot_id,
+      reviews_in_flight: notifs::ReviewsInFlight::default(),
+      site_checker: Arc::new(site_check::SiteChecker::new()?),
+    })
   }
 }
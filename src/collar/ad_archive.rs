@@ -0,0 +1,523 @@
+use super::{
+  Collar, CollarError, EmbedWrapper, NotifChannelType,
+  ad_image::{self, AdImageError, MAX_FETCH_BYTES},
+  notif_outbox::{self, Delivered, Outgoing, ReviewPost},
+  notifs::{self, Notif, ReviewAction, ReviewOrigin, SubmitType},
+};
+use chrono::{DateTime, Utc};
+use poise::serenity_prelude::{
+  self as serenity, ButtonStyle, Color, ComponentInteraction, CreateActionRow, CreateAttachment,
+  CreateButton, CreateEmbed, CreateInteractionResponse, CreateInteractionResponseMessage, GuildId,
+  Mentionable, UserId,
+};
+use reqwest::{StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::PathBuf;
+use tokio::time::{Duration, Instant, interval_at};
+use tracing::{error, info, warn};
+
+/// How often archived ads get compared to their live url unless
+/// `AD_ARCHIVE_CHECK_INTERVAL_MINUTES` says otherwise.
+const DEFAULT_CHECK_INTERVAL: Duration = Duration::from_secs(12 * 60 * 60);
+const REREVIEW_PREFIX: &str = "archive:rereview:";
+pub const ARCHIVE_FIELD: &str = "Archived copy";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LinkState {
+  #[default]
+  Live,
+  /// The live url answers 404 or 410.
+  Missing,
+  /// The live url serves something other than what was verified.
+  Changed,
+}
+
+/// A verified ad's image as it was when a moderator verified it.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ArchivedAd {
+  pub instance: String,
+  pub discord_id: UserId,
+  pub image_url: String,
+  pub sha256: String,
+  pub extension: String,
+  pub archived_at: DateTime<Utc>,
+  pub last_checked: Option<DateTime<Utc>>,
+  #[serde(default)]
+  pub state: LinkState,
+}
+
+impl ArchivedAd {
+  pub fn file_name(&self) -> String {
+    format!("{}.{}", self.sha256, self.extension)
+  }
+
+  fn path(&self) -> PathBuf {
+    archive_dir().join(self.file_name())
+  }
+
+  /// The stored copy, ready to go on a message as `attachment://{file_name}`.
+  pub async fn attachment(&self) -> Option<CreateAttachment> {
+    match tokio::fs::read(self.path()).await {
+      Ok(bytes) => Some(CreateAttachment::bytes(bytes, self.file_name())),
+      Err(err) => {
+        warn!("Failed to read archived ad {}: {err}", self.file_name());
+        None
+      }
+    }
+  }
+
+  /// Like [`ArchivedAd::attachment`] for the [`notif_outbox`], which reads it on every attempt.
+  pub async fn stored_path(&self) -> Option<PathBuf> {
+    match tokio::fs::try_exists(self.path()).await {
+      Ok(true) => Some(self.path()),
+      Ok(false) => {
+        warn!("Archived ad {} is gone", self.file_name());
+        None
+      }
+      Err(err) => {
+        warn!("Failed to read archived ad {}: {err}", self.file_name());
+        None
+      }
+    }
+  }
+
+  /// What the embed says about the live url compared to the archive.
+  pub fn state_text(&self) -> String {
+    let checked = self
+      .last_checked
+      .map(|at| format!(", last checked {}", at.format("%Y-%m-%d %H:%M UTC")))
+      .unwrap_or_default();
+    let state = match self.state {
+      LinkState::Live => "Live url still matches",
+      LinkState::Missing => "⚠️ Live url is gone, showing the archived copy",
+      LinkState::Changed => "⚠️ Live url changed since it was verified, showing the archived copy",
+    };
+
+    format!(
+      "{state}\nArchived {}{checked}",
+      self.archived_at.format("%Y-%m-%d %H:%M UTC")
+    )
+  }
+}
+
+fn archive_dir() -> PathBuf {
+  PathBuf::from(std::env::var("AD_ARCHIVE_DIR").unwrap_or(String::from("ad_archive")))
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+  Sha256::digest(bytes)
+    .iter()
+    .map(|byte| format!("{byte:02x}"))
+    .collect()
+}
+
+enum LiveImage {
+  Bytes(Vec<u8>),
+  Gone,
+  /// Not an image anymore, a parked domain or an error page.
+  Replaced,
+}
+
+async fn fetch_live(data: &Collar, image_url: &str) -> Result<LiveImage, AdImageError> {
+  let url = Url::parse(image_url).map_err(|_| AdImageError::InvalidUrl)?;
+  match ad_image::download(&data.fetch_client, url, MAX_FETCH_BYTES).await {
+    Ok((bytes, _)) => Ok(LiveImage::Bytes(bytes)),
+    Err(AdImageError::Status(StatusCode::NOT_FOUND | StatusCode::GONE)) => Ok(LiveImage::Gone),
+    Err(AdImageError::NotAnImage(_)) => Ok(LiveImage::Replaced),
+    Err(err) => Err(err),
+  }
+}
+
+/// Stores a copy of a freshly verified ad under its content hash, replacing the member's old one.
+pub async fn archive(
+  data: &Collar,
+  guild_id: Option<GuildId>,
+  discord_id: UserId,
+  image_url: &str,
+) {
+  let bytes = match fetch_live(data, image_url).await {
+    Ok(LiveImage::Bytes(bytes)) => bytes,
+    Ok(_) => {
+      warn!("Ad image {image_url} of {discord_id} isn't there to archive");
+      return;
+    }
+    Err(err) => {
+      warn!("Failed to fetch {image_url} to archive it: {err}");
+      return;
+    }
+  };
+
+  let extension = image::guess_format(&bytes)
+    .ok()
+    .and_then(|format| format.extensions_str().first().copied())
+    .unwrap_or("bin");
+  let instance = match data.cache.read().get_guild_instance_name(guild_id) {
+    Some(instance) => instance,
+    None => {
+      warn!("Not archiving the ad of {discord_id}, its guild's instance isn't configured");
+      return;
+    }
+  };
+  let archived = ArchivedAd {
+    instance,
+    discord_id,
+    image_url: image_url.to_string(),
+    sha256: sha256_hex(&bytes),
+    extension: extension.to_string(),
+    archived_at: Utc::now(),
+    last_checked: None,
+    state: LinkState::Live,
+  };
+
+  let path = archived.path();
+  if !path.exists() {
+    if let Err(err) = tokio::fs::create_dir_all(archive_dir()).await {
+      error!("Failed to create the ad archive directory: {err}");
+      return;
+    }
+    if let Err(err) = tokio::fs::write(&path, &bytes).await {
+      error!("Failed to archive {image_url} to {}: {err}", path.display());
+      return;
+    }
+  }
+
+  info!("Archived ad of {discord_id} as {}", archived.file_name());
+  let replaced = data.cache.write().set_archived_ad(archived);
+  prune_files(data, replaced.into_iter().collect()).await;
+}
+
+/// Deletes files no archived ad points at anymore, several members can share one.
+async fn prune_files(data: &Collar, dropped: Vec<ArchivedAd>) {
+  for archived in dropped {
+    let still_used = {
+      let cache = data.cache.read();
+      cache.archive_file_in_use(&archived.sha256)
+    };
+    if !still_used && let Err(err) = tokio::fs::remove_file(archived.path()).await {
+      warn!(
+        "Failed to delete archived ad {}: {err}",
+        archived.file_name()
+      );
+    }
+  }
+}
+
+/// Starts comparing archived ads with their live urls in the background.
+pub fn spawn(data: Collar, ctx: serenity::Context) {
+  let period = std::env::var("AD_ARCHIVE_CHECK_INTERVAL_MINUTES")
+    .ok()
+    .and_then(|minutes| minutes.parse::<u64>().ok())
+    .filter(|minutes| *minutes > 0)
+    .map(|minutes| Duration::from_secs(minutes * 60))
+    .unwrap_or(DEFAULT_CHECK_INTERVAL);
+
+  tokio::spawn(async move {
+    let mut interval = interval_at(Instant::now() + Duration::from_secs(10 * 60), period);
+
+    loop {
+      interval.tick().await;
+
+      let instances = data.cache.read().get_instance_names();
+      for instance in instances {
+        info!("Checking archived ads for {instance}");
+        if let Err(err) = check_instance(&data, &ctx, &instance).await {
+          error!("Archived ad checks for {instance} failed: {err}");
+        }
+      }
+    }
+  });
+}
+
+async fn check_instance(
+  data: &Collar,
+  ctx: &serenity::Context,
+  instance: &str,
+) -> Result<(), CollarError> {
+  let ads = data.petring_instance(instance).get_known_ads().await?;
+  let verified = ads
+    .into_iter()
+    .filter(|ad| ad.verified && ad.discord_id != 0)
+    .collect::<Vec<_>>();
+
+  let (dropped, guilds) = {
+    let mut cache = data.cache.write();
+    let discord_ids = verified
+      .iter()
+      .map(|ad| UserId::new(ad.discord_id))
+      .collect::<Vec<_>>();
+    (
+      cache.retain_archived_ads(instance, &discord_ids),
+      cache.get_guilds_for_instance(instance),
+    )
+  };
+  prune_files(data, dropped).await;
+
+  for ad in verified {
+    let discord_id = UserId::new(ad.discord_id);
+    let archived = {
+      let cache = data.cache.read();
+      cache.get_archived_ad(instance, discord_id)
+    };
+    let mut archived = match archived {
+      Some(archived) => archived,
+      // Verified before archiving existed, or the image was down when it was verified.
+      None => continue,
+    };
+
+    let state = match fetch_live(data, &ad.image_url).await {
+      Ok(LiveImage::Bytes(bytes)) if sha256_hex(&bytes) == archived.sha256 => LinkState::Live,
+      Ok(LiveImage::Bytes(_) | LiveImage::Replaced) => LinkState::Changed,
+      Ok(LiveImage::Gone) => LinkState::Missing,
+      Err(err) => {
+        warn!("Couldn't check the ad image of {discord_id}, trying again later: {err}");
+        continue;
+      }
+    };
+
+    archived.last_checked = Some(Utc::now());
+    if state != archived.state {
+      info!(
+        "Ad image of {discord_id} went from {:?} to {state:?}",
+        archived.state
+      );
+      archived.state = state;
+      if state != LinkState::Live {
+        notify(data, ctx, &guilds, &archived, &ad.image_url).await;
+      }
+    }
+
+    data.cache.write().set_archived_ad(archived);
+  }
+
+  Ok(())
+}
+
+fn archive_embed(ctx: &serenity::Context, archived: &ArchivedAd, live_url: &str) -> CreateEmbed {
+  EmbedWrapper::new_event(ctx)
+    .field("Member", archived.discord_id.mention().to_string(), true)
+    .field("Live url", live_url, false)
+    .field(ARCHIVE_FIELD, archived.state_text(), false)
+    .thumbnail(format!("attachment://{}", archived.file_name()))
+}
+
+/// Tells the owner and every guild on the instance, offering re-review when the content changed.
+async fn notify(
+  data: &Collar,
+  ctx: &serenity::Context,
+  guilds: &[GuildId],
+  archived: &ArchivedAd,
+  live_url: &str,
+) {
+  let (owner_title, owner_description, moderator_title) = match archived.state {
+    LinkState::Missing => (
+      "Your PetAds image is gone 3:",
+      "Your ad's image doesn't load anymore. Please put it back or edit your ad with `/edit_ad`",
+      "A verified ad's image is gone 3:",
+    ),
+    _ => (
+      "Your PetAds image changed 3:",
+      "Your ad's image isn't the one that was verified anymore, moderators will take another look",
+      "A verified ad's image changed 3:",
+    ),
+  };
+
+  let owner_embed = archive_embed(ctx, archived, live_url)
+    .title(owner_title)
+    .description(owner_description)
+    .color(Color::from_rgb(255, 0, 0));
+  if let Err(err) = Notif::from_embed(owner_embed)
+    .dm_notif_background(&ctx.http, data, guilds, archived.discord_id.get(), vec![])
+    .await
+  {
+    error!(
+      "Failed to tell {} about their ad image: {err}",
+      archived.discord_id
+    );
+  }
+
+  // Changed ads can be sent back to review, so they go where moderators review ads.
+  let (channel, components) = match archived.state {
+    LinkState::Changed => (
+      NotifChannelType::AdSubmit,
+      vec![CreateActionRow::Buttons(vec![
+        CreateButton::new(format!("{REREVIEW_PREFIX}{}", archived.discord_id))
+          .label("Send to re-review")
+          .style(ButtonStyle::Primary),
+      ])],
+    ),
+    _ => (NotifChannelType::General, vec![]),
+  };
+
+  for guild_id in guilds {
+    let embed = archive_embed(ctx, archived, live_url)
+      .title(moderator_title)
+      .color(Color::from_rgb(255, 0, 0));
+    let outgoing = Outgoing {
+      components: components.clone(),
+      attachment: archived.stored_path().await,
+      ..Outgoing::new(embed)
+    };
+
+    match notif_outbox::deliver(&ctx.http, data, *guild_id, channel, outgoing).await {
+      Delivered::Sent(_) | Delivered::Queued => (),
+      Delivered::NoChannel => {
+        warn!("Guild {guild_id} has no {channel:?} channel for archived ad alerts")
+      }
+      Delivered::Failed(err) => error!("Failed to post archived ad alert in {guild_id}: {err}"),
+    }
+  }
+}
+
+/// The member a "Send to re-review" button is for.
+pub fn parse_rereview(custom_id: &str) -> Option<UserId> {
+  custom_id
+    .strip_prefix(REREVIEW_PREFIX)?
+    .parse::<u64>()
+    .ok()
+    .filter(|id| *id != 0)
+    .map(UserId::new)
+}
+
+fn no_channel_embed(shard: &serenity::Context) -> CreateEmbed {
+  EmbedWrapper::new_event(shard)
+    .title("No channel")
+    .description("Set an Ad Submit channel with `/set_notif_channel` to re-review ads")
+    .color(Color::from_rgb(255, 0, 0))
+}
+
+async fn respond_ephemeral(
+  shard: &serenity::Context,
+  mci: &ComponentInteraction,
+  embed: CreateEmbed,
+) -> Result<(), CollarError> {
+  mci
+    .create_response(
+      &shard.http,
+      CreateInteractionResponse::Message(
+        CreateInteractionResponseMessage::new()
+          .embed(embed)
+          .ephemeral(true),
+      ),
+    )
+    .await?;
+  Ok(())
+}
+
+/// Handles "Send to re-review", posting the changed ad to the ad review channel.
+pub async fn handle_rereview(
+  shard: &serenity::Context,
+  data: &Collar,
+  mci: &ComponentInteraction,
+  discord_id: UserId,
+) -> Result<(), CollarError> {
+  let guild_id = match mci.guild_id {
+    Some(guild_id) => guild_id,
+    None => return Ok(()),
+  };
+
+  if !notifs::pressed_by_moderator(mci) {
+    warn!(
+      "{} pressed re-review on {discord_id} without moderator permissions",
+      mci.user.id
+    );
+    return notifs::refuse_non_moderator(shard, mci).await;
+  }
+
+  let (archived, has_channel) = {
+    let cache = data.cache.read();
+    (
+      cache
+        .get_guild_instance_name(Some(guild_id))
+        .and_then(|instance| cache.get_archived_ad(&instance, discord_id)),
+      cache
+        .get_notif_channel(guild_id, NotifChannelType::AdSubmit)
+        .is_some(),
+    )
+  };
+  if !has_channel {
+    return respond_ephemeral(shard, mci, no_channel_embed(shard)).await;
+  }
+
+  let ad = match data.petring(Some(guild_id)).get_ad(discord_id).await {
+    Ok(ad) => ad,
+    Err(err) => {
+      let embed = EmbedWrapper::new_event(shard)
+        .title("Couldn't fetch the ad 3:")
+        .description(err.to_string())
+        .color(Color::from_rgb(255, 0, 0));
+      return respond_ephemeral(shard, mci, embed).await;
+    }
+  };
+
+  let mut embed = EmbedWrapper::new_event(shard)
+    .title("Ad changed since it was verified")
+    .description(format!(
+      "Sent back to review by {}, the archived copy is what was verified",
+      mci.user.mention()
+    ))
+    .field("Petring Username", &ad.username, false)
+    .field("Ad url", &ad.ad_url, false)
+    .field("Live image", &ad.image_url, false)
+    .image(&ad.image_url)
+    .color(Color::from_rgb(0, 0, 255));
+  let mut attachment = None;
+  if let Some(archived) = &archived {
+    embed = embed
+      .field(ARCHIVE_FIELD, archived.state_text(), false)
+      .thumbnail(format!("attachment://{}", archived.file_name()));
+    attachment = archived.stored_path().await;
+  }
+
+  let buttons = ReviewAction::buttons(
+    ReviewOrigin::ReviewMessage,
+    SubmitType::Ad,
+    discord_id,
+    true,
+  )
+  .to_vec();
+  let outgoing = Outgoing {
+    components: vec![CreateActionRow::Buttons(buttons)],
+    attachment,
+    review: Some(ReviewPost {
+      submit_type: SubmitType::Ad,
+      discord_id,
+      reason: Some(String::from("Image changed after verification")),
+    }),
+    ..Outgoing::new(embed)
+  };
+  let channel_type = NotifChannelType::AdSubmit;
+  let posted =
+    match notif_outbox::deliver(&shard.http, data, guild_id, channel_type, outgoing).await {
+      Delivered::Sent(message) => message.link(),
+      Delivered::Queued => String::from("the post is queued and goes up once Discord takes it"),
+      Delivered::NoChannel => {
+        return respond_ephemeral(shard, mci, no_channel_embed(shard)).await;
+      }
+      Delivered::Failed(err) => return Err(err.into()),
+    };
+  info!("{} sent the ad of {discord_id} back to review", mci.user.id);
+
+  let embed = EmbedWrapper::new_event(shard)
+    .title("Sent to re-review :3")
+    .description(format!(
+      "{} sent {}'s ad back to review: {}",
+      mci.user.mention(),
+      discord_id.mention(),
+      posted
+    ))
+    .color(Color::from_rgb(0, 0, 255));
+  mci
+    .create_response(
+      &shard.http,
+      CreateInteractionResponse::UpdateMessage(
+        CreateInteractionResponseMessage::new()
+          .embed(embed)
+          .components(vec![]),
+      ),
+    )
+    .await?;
+
+  Ok(())
+}
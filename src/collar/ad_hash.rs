@@ -0,0 +1,257 @@
+use super::{
+  Collar,
+  ad_image::{self, MAX_FETCH_BYTES},
+};
+use chrono::{DateTime, Utc};
+use image::{ImageReader, imageops::FilterType};
+use poise::serenity_prelude::UserId;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use std::io::Cursor;
+use tracing::{info, warn};
+
+/// Hashes kept for matching, the oldest rejections and removals go first.
+pub const MAX_AD_HASHES: usize = 5000;
+/// Differing bits out of 64 for two images to count as the same ad, rescaled and recompressed
+/// copies usually land well under this.
+pub const SIMILAR_DISTANCE: u32 = 10;
+pub const SIMILAR_FIELD: &str = "Similar ads";
+pub const DUPLICATES_FIELD: &str = "Near-duplicate of";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AdOutcome {
+  Verified,
+  Rejected,
+  Removed,
+}
+
+impl std::fmt::Display for AdOutcome {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      AdOutcome::Verified => write!(f, "Verified"),
+      AdOutcome::Rejected => write!(f, "Rejected"),
+      AdOutcome::Removed => write!(f, "Removed"),
+    }
+  }
+}
+
+/// The perceptual hash of an ad image a moderator made a decision on.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AdHashRecord {
+  pub discord_id: UserId,
+  pub image_url: String,
+  pub hash: u64,
+  pub outcome: AdOutcome,
+  pub decided_by: UserId,
+  pub decided_at: DateTime<Utc>,
+  /// Message the decision was announced in.
+  pub decision_link: Option<String>,
+}
+
+impl AdHashRecord {
+  pub fn new(
+    discord_id: UserId,
+    image_url: &str,
+    hash: u64,
+    outcome: AdOutcome,
+    decided_by: UserId,
+    decision_link: Option<String>,
+  ) -> Self {
+    Self {
+      discord_id,
+      image_url: image_url.to_string(),
+      hash,
+      outcome,
+      decided_by,
+      decided_at: Utc::now(),
+      decision_link,
+    }
+  }
+}
+
+/// Difference hash: shrink to 9x8 greyscale and compare each pixel to its right neighbour, so
+/// rescaling, recompression and small colour shifts barely move it. Animations use their first
+/// frame. Decodes the whole image, see [`hash`] for calling it from async code.
+pub fn dhash(bytes: &[u8]) -> Option<u64> {
+  let mut reader = ImageReader::new(Cursor::new(bytes))
+    .with_guessed_format()
+    .ok()?;
+  reader.limits(ad_image::decode_limits());
+  let image = reader.decode().ok()?;
+  let small = image
+    .grayscale()
+    .resize_exact(9, 8, FilterType::Triangle)
+    .to_luma8();
+
+  let mut hash = 0u64;
+  for y in 0..8 {
+    for x in 0..8 {
+      hash <<= 1;
+      if small.get_pixel(x, y)[0] > small.get_pixel(x + 1, y)[0] {
+        hash |= 1;
+      }
+    }
+  }
+  Some(hash)
+}
+
+/// [`dhash`] on the blocking pool, decoding is CPU bound and the image is someone else's.
+pub async fn hash(bytes: Vec<u8>) -> Option<u64> {
+  tokio::task::spawn_blocking(move || dhash(&bytes))
+    .await
+    .ok()
+    .flatten()
+}
+
+pub fn distance(a: u64, b: u64) -> u32 {
+  (a ^ b).count_ones()
+}
+
+/// Earlier decisions `hash` is close to: any rejected or removed ad, and other members'
+/// verified ads. Closest first.
+pub fn similar(
+  records: &[AdHashRecord],
+  hash: u64,
+  discord_id: UserId,
+) -> Vec<(&AdHashRecord, u32)> {
+  let mut similar = records
+    .iter()
+    .filter(|record| record.outcome != AdOutcome::Verified || record.discord_id != discord_id)
+    .map(|record| (record, distance(record.hash, hash)))
+    .filter(|(_, distance)| *distance <= SIMILAR_DISTANCE)
+    .collect::<Vec<_>>();
+  similar.sort_by_key(|(record, distance)| (*distance, std::cmp::Reverse(record.decided_at)));
+  similar
+}
+
+/// The review embed field for [`similar`], `None` when nothing matched.
+pub fn similar_text(similar: &[(&AdHashRecord, u32)]) -> Option<String> {
+  if similar.is_empty() {
+    return None;
+  }
+
+  let lines = similar
+    .iter()
+    .take(5)
+    .map(|(record, distance)| {
+      let decision = match &record.decision_link {
+        Some(link) => format!("[{}]({link})", record.outcome),
+        None => record.outcome.to_string(),
+      };
+      format!(
+        "⚠️ <@{}>'s ad, {decision} by <@{}> on {}, {distance}/64 bits off",
+        record.discord_id,
+        record.decided_by,
+        record.decided_at.format("%Y-%m-%d")
+      )
+    })
+    .collect::<Vec<_>>();
+
+  Some(match similar.len() > lines.len() {
+    true => format!(
+      "{}\n...and {} more",
+      lines.join("\n"),
+      similar.len() - lines.len()
+    ),
+    false => lines.join("\n"),
+  })
+}
+
+/// Looks `hash` up against every decision so far, for a review embed.
+pub fn similar_to(data: &Collar, hash: u64, discord_id: UserId) -> Option<String> {
+  let cache = data.cache.read();
+  similar_text(&similar(cache.get_ad_hashes(), hash, discord_id))
+}
+
+/// Other members' verified ads `hash` is close to, for the verification announcement.
+pub fn verified_duplicates(data: &Collar, hash: u64, discord_id: UserId) -> Option<String> {
+  let cache = data.cache.read();
+  let duplicates = similar(cache.get_ad_hashes(), hash, discord_id)
+    .into_iter()
+    .filter(|(record, _)| record.outcome == AdOutcome::Verified)
+    .collect::<Vec<_>>();
+  similar_text(&duplicates)
+}
+
+/// Downloads an ad image to hash it, `None` when it's gone or unreadable.
+pub async fn fetch_hash(data: &Collar, image_url: &str) -> Option<u64> {
+  let url = Url::parse(image_url).ok()?;
+  match ad_image::download(&data.fetch_client, url, MAX_FETCH_BYTES).await {
+    Ok((bytes, _)) => hash(bytes).await,
+    Err(err) => {
+      warn!("Failed to fetch {image_url} to hash it: {err}");
+      None
+    }
+  }
+}
+
+pub async fn remember(data: &Collar, record: AdHashRecord) {
+  info!(
+    "{} ad image of {} hashed as {:016x}",
+    record.outcome, record.discord_id, record.hash
+  );
+
+  data.cache.write().add_ad_hash(record);
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use image::{ImageBuffer, ImageFormat, Rgb};
+
+  fn png(width: u32, height: u32) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    // Stripes and a fade that scale with the image, so bigger copies look the same.
+    ImageBuffer::from_fn(width, height, |x, y| {
+      Rgb([(x * 5 / width % 2 * 200) as u8, (y * 255 / height) as u8, 0])
+    })
+    .write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)
+    .unwrap();
+    bytes
+  }
+
+  fn record(discord_id: u64, hash: u64, outcome: AdOutcome) -> AdHashRecord {
+    AdHashRecord::new(
+      UserId::new(discord_id),
+      "https://example.com/ad.png",
+      hash,
+      outcome,
+      UserId::new(1),
+      None,
+    )
+  }
+
+  #[test]
+  fn rescaled_copies_match_earlier_decisions() {
+    let original = dhash(&png(88, 31)).unwrap();
+    let rescaled = dhash(&png(176, 62)).unwrap();
+    assert!(distance(original, rescaled) <= SIMILAR_DISTANCE);
+
+    let records = vec![
+      record(10, original, AdOutcome::Rejected),
+      record(20, !original, AdOutcome::Removed),
+      record(30, original, AdOutcome::Verified),
+    ];
+
+    // Someone's own verified ad isn't a duplicate of itself, their own rejection still is.
+    let matched = similar(&records, rescaled, UserId::new(30))
+      .into_iter()
+      .map(|(record, _)| record.discord_id.get())
+      .collect::<Vec<_>>();
+    assert_eq!(matched, vec![10]);
+
+    let matched = similar(&records, rescaled, UserId::new(10))
+      .into_iter()
+      .map(|(record, _)| record.discord_id.get())
+      .collect::<Vec<_>>();
+    assert_eq!(matched.len(), 2);
+    assert!(!matched.contains(&20));
+  }
+
+  #[tokio::test]
+  async fn hashing_stays_within_the_decode_limits() {
+    assert!(hash(png(88, 31)).await.is_some());
+    assert!(hash(png(5000, 1)).await.is_none());
+  }
+}
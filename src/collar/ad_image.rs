@@ -0,0 +1,379 @@
+use super::{ad_hash, site_check};
+use image::{
+  AnimationDecoder, ImageDecoder, ImageFormat, ImageReader, Limits,
+  codecs::{gif::GifDecoder, png::PngDecoder, webp::WebPDecoder},
+};
+use reqwest::{Client, StatusCode, Url, header::CONTENT_TYPE};
+use serde::{Deserialize, Serialize};
+use std::io::Cursor;
+use tokio::time::Duration;
+
+/// Hosts that expire links after a while, Discord attachments are signed and stop loading.
+pub const EPHEMERAL_HOSTS: [&str; 3] = ["cdn.discordapp.com", "cdn.discord.com", "discordapp.net"];
+/// Blocked hosts per guild, on top of [`EPHEMERAL_HOSTS`].
+pub const MAX_BLOCKED_HOSTS: usize = 25;
+pub const IMAGE_FIELD: &str = "Image";
+
+/// The most an image is downloaded for, whatever a guild's limit, see the `set_ad_limits` bounds.
+pub const MAX_FETCH_BYTES: u64 = 10 * 1024 * 1024;
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(15);
+/// Widest or tallest image collar decodes, ads are buttons and banners.
+const MAX_DECODE_SIDE: u32 = 4096;
+/// Most a single decode may allocate, a small file can still claim a huge canvas.
+const MAX_DECODE_ALLOC: u64 = 128 * 1024 * 1024;
+
+/// What decoding an image someone else uploaded is allowed to cost.
+pub fn decode_limits() -> Limits {
+  let mut limits = Limits::default();
+  limits.max_image_width = Some(MAX_DECODE_SIDE);
+  limits.max_image_height = Some(MAX_DECODE_SIDE);
+  limits.max_alloc = Some(MAX_DECODE_ALLOC);
+  limits
+}
+
+/// What a guild accepts as an ad image, checked before the ad reaches the API.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AdImageLimits {
+  pub max_bytes: u64,
+  pub width: Option<u32>,
+  pub height: Option<u32>,
+  /// Whether `width` and `height` have to match exactly, like 88x31 buttons, or are maximums.
+  pub exact_size: bool,
+  pub max_frames: u32,
+  #[serde(default)]
+  pub blocked_hosts: Vec<String>,
+}
+
+impl Default for AdImageLimits {
+  fn default() -> Self {
+    Self {
+      max_bytes: 1024 * 1024,
+      width: None,
+      height: None,
+      exact_size: false,
+      max_frames: 500,
+      blocked_hosts: Vec::new(),
+    }
+  }
+}
+
+impl AdImageLimits {
+  pub fn size_text(&self) -> String {
+    let side = |side: Option<u32>| side.map_or(String::from("any"), |side| side.to_string());
+    match (self.width, self.height, self.exact_size) {
+      (None, None, _) => String::from("Any"),
+      (width, height, true) => format!("Exactly {}x{}", side(width), side(height)),
+      (width, height, false) => format!("At most {}x{}", side(width), side(height)),
+    }
+  }
+
+  fn host_blocked(&self, host: &str) -> Option<String> {
+    EPHEMERAL_HOSTS
+      .iter()
+      .map(|blocked| blocked.to_string())
+      .chain(self.blocked_hosts.iter().cloned())
+      .find(|blocked| host == blocked || host.ends_with(&format!(".{blocked}")))
+  }
+}
+
+/// The properties of a fetched ad image, shown in the review embed.
+#[derive(Clone, Debug)]
+pub struct AdImage {
+  pub bytes: Vec<u8>,
+  pub content_type: String,
+  pub format: ImageFormat,
+  pub width: u32,
+  pub height: u32,
+  pub frames: u32,
+}
+
+impl AdImage {
+  pub async fn hash(&self) -> Option<u64> {
+    ad_hash::hash(self.bytes.clone()).await
+  }
+}
+
+impl std::fmt::Display for AdImage {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(
+      f,
+      "{:?}, {}x{}, {} frame{}, {} ({})",
+      self.format,
+      self.width,
+      self.height,
+      self.frames,
+      if self.frames == 1 { "" } else { "s" },
+      format_bytes(self.bytes.len() as u64),
+      self.content_type
+    )
+  }
+}
+
+#[derive(Debug)]
+pub enum AdImageError {
+  InvalidUrl,
+  EphemeralHost(String),
+  Fetch(String),
+  Status(StatusCode),
+  NotAnImage(String),
+  Unreadable,
+  TooLarge(u64),
+  WrongSize { width: u32, height: u32 },
+  TooManyFrames(u32),
+}
+
+impl std::fmt::Display for AdImageError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      AdImageError::InvalidUrl => write!(f, "that's not a valid http(s) image url"),
+      AdImageError::EphemeralHost(host) => write!(
+        f,
+        "links on `{host}` stop working after a while, host the image somewhere permanent"
+      ),
+      AdImageError::Fetch(err) => write!(f, "couldn't download the image ({err})"),
+      AdImageError::Status(status) => {
+        write!(f, "couldn't download the image (HTTP {})", status.as_u16())
+      }
+      AdImageError::NotAnImage(content_type) => {
+        write!(f, "the url serves `{content_type}`, not an image or gif")
+      }
+      AdImageError::Unreadable => write!(f, "the file isn't a png, gif, jpeg or webp image"),
+      AdImageError::TooLarge(max_bytes) => {
+        write!(f, "the image is bigger than {}", format_bytes(*max_bytes))
+      }
+      AdImageError::WrongSize { width, height } => {
+        write!(
+          f,
+          "the image is {width}x{height}, which this server doesn't accept"
+        )
+      }
+      AdImageError::TooManyFrames(max_frames) => {
+        write!(f, "the animation has more than {max_frames} frames")
+      }
+    }
+  }
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+  match bytes {
+    bytes if bytes >= 1024 * 1024 => format!("{:.1} MiB", bytes as f64 / (1024.0 * 1024.0)),
+    bytes if bytes >= 1024 => format!("{:.1} KiB", bytes as f64 / 1024.0),
+    bytes => format!("{bytes} B"),
+  }
+}
+
+/// Downloads at most `max_bytes`, so a huge file can't be pulled into memory.
+pub async fn download(
+  client: &Client,
+  url: Url,
+  max_bytes: u64,
+) -> Result<(Vec<u8>, String), AdImageError> {
+  let mut response = site_check::get_public(client, url, FETCH_TIMEOUT)
+    .await
+    .map_err(AdImageError::Fetch)?;
+
+  if !response.status().is_success() {
+    return Err(AdImageError::Status(response.status()));
+  }
+
+  let content_type = response
+    .headers()
+    .get(CONTENT_TYPE)
+    .and_then(|value| value.to_str().ok())
+    .map(|value| {
+      value
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase()
+    })
+    .unwrap_or_default();
+  if !content_type.starts_with("image/") {
+    return Err(AdImageError::NotAnImage(match content_type.is_empty() {
+      true => String::from("no content type"),
+      false => content_type,
+    }));
+  }
+
+  if response
+    .content_length()
+    .is_some_and(|length| length > max_bytes)
+  {
+    return Err(AdImageError::TooLarge(max_bytes));
+  }
+
+  let mut bytes = Vec::new();
+  while let Some(chunk) = response
+    .chunk()
+    .await
+    .map_err(|err| AdImageError::Fetch(err.without_url().to_string()))?
+  {
+    bytes.extend_from_slice(&chunk);
+    if bytes.len() as u64 > max_bytes {
+      return Err(AdImageError::TooLarge(max_bytes));
+    }
+  }
+
+  Ok((bytes, content_type))
+}
+
+/// Frames in the image, 1 for anything that isn't animated.
+fn count_frames(bytes: &[u8], format: ImageFormat, max_frames: u32) -> Result<u32, AdImageError> {
+  // Stops decoding one past the limit, the exact count doesn't matter once it's over.
+  let limit = max_frames as usize + 1;
+  let frames = match format {
+    ImageFormat::Gif => {
+      let mut decoder =
+        GifDecoder::new(Cursor::new(bytes)).map_err(|_| AdImageError::Unreadable)?;
+      decoder
+        .set_limits(decode_limits())
+        .map_err(|_| AdImageError::Unreadable)?;
+      decoder
+        .into_frames()
+        .take(limit)
+        .take_while(Result::is_ok)
+        .count()
+    }
+    ImageFormat::Png => {
+      let decoder = PngDecoder::with_limits(Cursor::new(bytes), decode_limits())
+        .map_err(|_| AdImageError::Unreadable)?;
+      match decoder.is_apng() {
+        Ok(true) => decoder
+          .apng()
+          .map_err(|_| AdImageError::Unreadable)?
+          .into_frames()
+          .take(limit)
+          .take_while(Result::is_ok)
+          .count(),
+        _ => 1,
+      }
+    }
+    ImageFormat::WebP => {
+      let mut decoder =
+        WebPDecoder::new(Cursor::new(bytes)).map_err(|_| AdImageError::Unreadable)?;
+      decoder
+        .set_limits(decode_limits())
+        .map_err(|_| AdImageError::Unreadable)?;
+      match decoder.has_animation() {
+        true => decoder
+          .into_frames()
+          .take(limit)
+          .take_while(Result::is_ok)
+          .count(),
+        false => 1,
+      }
+    }
+    _ => 1,
+  };
+
+  Ok(frames.max(1) as u32)
+}
+
+/// Fetches `url` and checks it against the guild's limits.
+pub async fn inspect(
+  client: &Client,
+  url: &str,
+  limits: &AdImageLimits,
+) -> Result<AdImage, AdImageError> {
+  let parsed = Url::parse(url.trim()).map_err(|_| AdImageError::InvalidUrl)?;
+  if !matches!(parsed.scheme(), "http" | "https") {
+    return Err(AdImageError::InvalidUrl);
+  }
+  let host = parsed
+    .host_str()
+    .ok_or(AdImageError::InvalidUrl)?
+    .to_ascii_lowercase();
+  if let Some(blocked) = limits.host_blocked(&host) {
+    return Err(AdImageError::EphemeralHost(blocked));
+  }
+
+  let (bytes, content_type) = download(client, parsed, limits.max_bytes).await?;
+
+  // Decoding is CPU bound and the file is someone else's, keep it off the runtime.
+  let limits = limits.clone();
+  let (bytes, format, width, height, frames) = tokio::task::spawn_blocking(move || {
+    examine(&bytes, &limits)
+      .map(|(format, width, height, frames)| (bytes, format, width, height, frames))
+  })
+  .await
+  .map_err(|_| AdImageError::Unreadable)??;
+
+  Ok(AdImage {
+    bytes,
+    content_type,
+    format,
+    width,
+    height,
+    frames,
+  })
+}
+
+/// Format, size and frame count of a downloaded image, as long as they fit `limits`.
+fn examine(
+  bytes: &[u8],
+  limits: &AdImageLimits,
+) -> Result<(ImageFormat, u32, u32, u32), AdImageError> {
+  let reader = ImageReader::new(Cursor::new(bytes))
+    .with_guessed_format()
+    .map_err(|_| AdImageError::Unreadable)?;
+  let format = match reader.format() {
+    Some(
+      format @ (ImageFormat::Png | ImageFormat::Gif | ImageFormat::Jpeg | ImageFormat::WebP),
+    ) => format,
+    _ => return Err(AdImageError::Unreadable),
+  };
+  let (width, height) = reader
+    .into_dimensions()
+    .map_err(|_| AdImageError::Unreadable)?;
+
+  let fits = |side: u32, limit: Option<u32>| {
+    limit.is_none_or(|limit| match limits.exact_size {
+      true => side == limit,
+      false => side <= limit,
+    })
+  };
+  // Only the header was read so far, past this frames aren't decoded at all.
+  let decodable = width <= MAX_DECODE_SIDE && height <= MAX_DECODE_SIDE;
+  if !decodable || !fits(width, limits.width) || !fits(height, limits.height) {
+    return Err(AdImageError::WrongSize { width, height });
+  }
+
+  let frames = count_frames(bytes, format, limits.max_frames)?;
+  if frames > limits.max_frames {
+    return Err(AdImageError::TooManyFrames(limits.max_frames));
+  }
+
+  Ok((format, width, height, frames))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use image::{ImageBuffer, Rgb};
+
+  fn png(width: u32, height: u32) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    ImageBuffer::<Rgb<u8>, _>::new(width, height)
+      .write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)
+      .unwrap();
+    bytes
+  }
+
+  #[test]
+  fn images_too_big_to_decode_are_refused_from_the_header() {
+    let limits = AdImageLimits::default();
+
+    let (format, width, height, frames) = examine(&png(88, 31), &limits).unwrap();
+    assert_eq!(
+      (format, width, height, frames),
+      (ImageFormat::Png, 88, 31, 1)
+    );
+
+    assert!(matches!(
+      examine(&png(MAX_DECODE_SIDE + 1, 1), &limits),
+      Err(AdImageError::WrongSize { .. })
+    ));
+  }
+}
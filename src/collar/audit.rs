@@ -0,0 +1,259 @@
+use super::{Collar, notifs::SubmitType};
+use chrono::{DateTime, Utc};
+use dotenvy::dotenv;
+use poise::serenity_prelude::{GuildId, UserId};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+  fs::{self, OpenOptions},
+  io::{self, BufRead, BufReader, Write},
+  path::PathBuf,
+  sync::{Arc, Mutex as StdMutex, PoisonError},
+};
+use tracing::{error, warn};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+  Verify,
+  Reject,
+  Remove,
+  Veto,
+}
+
+impl std::fmt::Display for AuditAction {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      AuditAction::Verify => write!(f, "verify"),
+      AuditAction::Reject => write!(f, "reject"),
+      AuditAction::Remove => write!(f, "remove"),
+      AuditAction::Veto => write!(f, "veto"),
+    }
+  }
+}
+
+/// One moderation action, kept in the [`AuditLog`] for `/audit`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AuditEntry {
+  pub at: DateTime<Utc>,
+  pub guild_id: Option<GuildId>,
+  pub action: AuditAction,
+  pub submit_type: SubmitType,
+  pub target: UserId,
+  pub moderator: UserId,
+  /// What the moderator gave as the reason, only set for rejections.
+  pub reason: Option<String>,
+  /// What the submitter wrote when submitting their website.
+  pub submission_reason: Option<String>,
+  /// Every moderator that approved a verification, the last one is `moderator`.
+  #[serde(default)]
+  pub approvers: Vec<UserId>,
+}
+
+impl AuditEntry {
+  pub fn new(
+    guild_id: Option<GuildId>,
+    action: AuditAction,
+    submit_type: SubmitType,
+    target: UserId,
+    moderator: UserId,
+  ) -> Self {
+    Self {
+      at: Utc::now(),
+      guild_id,
+      action,
+      submit_type,
+      target,
+      moderator,
+      reason: None,
+      submission_reason: None,
+      approvers: Vec::new(),
+    }
+  }
+
+  pub fn reason(mut self, reason: impl Into<String>) -> Self {
+    self.reason = Some(reason.into());
+    self
+  }
+
+  pub fn submission_reason(mut self, submission_reason: Option<String>) -> Self {
+    self.submission_reason = submission_reason;
+    self
+  }
+
+  pub fn approvers(mut self, approvers: Vec<UserId>) -> Self {
+    self.approvers = approvers;
+    self
+  }
+}
+
+pub fn path() -> PathBuf {
+  dotenv().ok();
+  PathBuf::from(std::env::var("AUDIT_LOG_PATH").unwrap_or(".audit.jsonl".to_string()))
+}
+
+/// Moderation history, one JSON entry per line. It only ever grows, so it lives in its own
+/// file instead of being rewritten with the cache.
+#[derive(Clone)]
+pub struct AuditLog {
+  path: PathBuf,
+  lock: Arc<StdMutex<()>>,
+}
+
+impl AuditLog {
+  pub fn new(path: PathBuf) -> Self {
+    Self {
+      path,
+      lock: Arc::new(StdMutex::new(())),
+    }
+  }
+
+  fn append_lines(&self, lines: &str) -> io::Result<()> {
+    let _guard = self.lock.lock().unwrap_or_else(PoisonError::into_inner);
+    OpenOptions::new()
+      .create(true)
+      .append(true)
+      .open(&self.path)?
+      .write_all(lines.as_bytes())
+  }
+
+  /// A single short append, cheap enough to do from async code.
+  pub fn append(&self, entry: &AuditEntry) -> io::Result<()> {
+    let mut line = serde_json::to_string(entry)?;
+    line.push('\n');
+    self.append_lines(&line)
+  }
+
+  /// Takes over the entries cache files before version 2 kept. Skipped when the log already has
+  /// entries, that means they were moved before and an older backup is being read.
+  pub fn import(&self, entries: Vec<Value>) -> io::Result<()> {
+    let has_entries = fs::metadata(&self.path).is_ok_and(|metadata| metadata.len() > 0);
+    if entries.is_empty() || has_entries {
+      return Ok(());
+    }
+
+    let mut lines = String::new();
+    for entry in entries {
+      lines.push_str(&serde_json::to_string(&entry)?);
+      lines.push('\n');
+    }
+    self.append_lines(&lines)
+  }
+
+  /// Entries for `guild_id`, oldest first. Reads the whole file, so keep it off the runtime.
+  pub fn read(&self, guild_id: GuildId) -> io::Result<Vec<AuditEntry>> {
+    let _guard = self.lock.lock().unwrap_or_else(PoisonError::into_inner);
+    let file = match fs::File::open(&self.path) {
+      Ok(file) => file,
+      Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+      Err(err) => return Err(err),
+    };
+
+    let mut entries = Vec::new();
+    for (n, line) in BufReader::new(file).lines().enumerate() {
+      let line = line?;
+      if line.trim().is_empty() {
+        continue;
+      }
+
+      match serde_json::from_str::<AuditEntry>(&line) {
+        Ok(entry) if entry.guild_id == Some(guild_id) => entries.push(entry),
+        Ok(_) => (),
+        Err(err) => warn!("Skipping line {} of {}: {err}", n + 1, self.path.display()),
+      }
+    }
+
+    Ok(entries)
+  }
+}
+
+/// The reason the submitter gave, if collar still has the review message for it.
+pub fn submission_reason(
+  data: &Collar,
+  instance: &str,
+  submit_type: SubmitType,
+  discord_id: UserId,
+) -> Option<String> {
+  let cache = data.cache.read();
+
+  cache
+    .get_pending_submissions_for(instance, submit_type, discord_id)
+    .into_iter()
+    .find_map(|(_, pending)| pending.reason)
+}
+
+pub fn record(data: &Collar, entry: AuditEntry) {
+  if let Err(err) = data.audit_log.append(&entry) {
+    error!("Failed to write to the audit log: {err}");
+  }
+}
+
+fn csv_field(field: &str) -> String {
+  // Spreadsheets run cells starting with these as formulas, reasons are written by anyone.
+  let field = match field.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+    true => format!("'{field}"),
+    false => field.to_string(),
+  };
+
+  if field.contains([',', '"', '\n', '\r']) {
+    format!("\"{}\"", field.replace('"', "\"\""))
+  } else {
+    field
+  }
+}
+
+pub fn to_csv(entries: &[AuditEntry]) -> String {
+  let mut csv =
+    String::from("at,guild_id,action,type,target,moderator,reason,submission_reason,approvers\n");
+
+  for entry in entries {
+    let submit_type = match entry.submit_type {
+      SubmitType::User => "user",
+      SubmitType::Ad => "ad",
+    };
+    let row = [
+      entry.at.to_rfc3339(),
+      entry
+        .guild_id
+        .map(|guild_id| guild_id.to_string())
+        .unwrap_or_default(),
+      entry.action.to_string(),
+      submit_type.to_string(),
+      entry.target.to_string(),
+      entry.moderator.to_string(),
+      entry.reason.clone().unwrap_or_default(),
+      entry.submission_reason.clone().unwrap_or_default(),
+      entry
+        .approvers
+        .iter()
+        .map(|approver| approver.to_string())
+        .collect::<Vec<_>>()
+        .join(" "),
+    ];
+
+    csv.push_str(
+      &row
+        .iter()
+        .map(|field| csv_field(field))
+        .collect::<Vec<_>>()
+        .join(","),
+    );
+    csv.push('\n');
+  }
+
+  csv
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn csv_cells_cant_start_a_formula() {
+    assert_eq!(csv_field("=HYPERLINK(\"x\")"), "\"'=HYPERLINK(\"\"x\"\")\"");
+    assert_eq!(csv_field("+1"), "'+1");
+    assert_eq!(csv_field("-1"), "'-1");
+    assert_eq!(csv_field("@SUM(A1)"), "'@SUM(A1)");
+    assert_eq!(csv_field("fine"), "fine");
+  }
+}
@@ -0,0 +1,511 @@
+use super::{Cache, CollarError, DEFAULT_INSTANCE, audit};
+use dotenvy::dotenv;
+use poise::serenity_prelude::{Channel, ChannelId, GuildId, Http};
+use serde::Serialize;
+use serde_json::{Map, Value, json};
+use std::{
+  fs,
+  io::{self, Write},
+  path::{Path, PathBuf},
+  time::Duration,
+};
+use tokio::runtime::Handle;
+use tracing::{error, info, warn};
+
+/// Version written to disk, bump it and add a migration whenever a field changes shape.
+pub const CURRENT_VERSION: u32 = 4;
+const VERSION_KEY: &str = "version";
+
+/// Backups kept unless `CACHE_BACKUPS` says otherwise, `0` turns them off.
+const DEFAULT_BACKUPS: usize = 5;
+/// How old the newest backup gets before a write rotates in a new one.
+const BACKUP_EVERY: Duration = Duration::from_secs(6 * 60 * 60);
+
+type Migration = fn(&mut Map<String, Value>) -> Result<(), String>;
+
+/// Where `/set_feedback_webhook` used to write the webhook.
+const LEGACY_FEEDBACK_WEBHOOK: &str = ".feedback_webhook";
+
+/// `MIGRATIONS[n]` takes a version `n` file to version `n + 1`.
+const MIGRATIONS: [Migration; CURRENT_VERSION as usize] = [v0_to_v1, v1_to_v2, v2_to_v3, v3_to_v4];
+
+/// Unversioned files can still have the layout from before collar served several instances
+/// and guilds: top level `secrets`/`urls`, one global `notif_channel_ids`, and the feedback
+/// webhook in its own file. Everything added since is defaulted.
+fn v0_to_v1(cache: &mut Map<String, Value>) -> Result<(), String> {
+  move_legacy_instance(cache)?;
+  move_legacy_notif_channels(cache, channel_guild)?;
+  move_legacy_feedback_webhook(cache, Path::new(LEGACY_FEEDBACK_WEBHOOK));
+  Ok(())
+}
+
+/// Folds the top level `secrets`/`urls` into [`DEFAULT_INSTANCE`].
+fn move_legacy_instance(cache: &mut Map<String, Value>) -> Result<(), String> {
+  let urls = cache.remove("urls");
+  let secrets = match cache.remove("secrets") {
+    Some(Value::Null) | None => return Ok(()),
+    Some(secrets) => secrets,
+  };
+
+  let instances = object_entry(cache, "instances")?;
+  if instances.contains_key(DEFAULT_INSTANCE) {
+    return Ok(());
+  }
+
+  info!("Moving the cached secrets to the {DEFAULT_INSTANCE} instance");
+  let urls = match urls {
+    Some(Value::Null) | None => json!({ "api_base_url": "", "web_base_url": "" }),
+    Some(urls) => urls,
+  };
+  instances.insert(
+    DEFAULT_INSTANCE.to_string(),
+    json!({ "urls": urls, "secrets": secrets }),
+  );
+
+  Ok(())
+}
+
+/// Moves each channel of the global mapping into the guild it belongs to, guilds that already
+/// have that channel type configured keep their setting. Channels Discord can't place are
+/// dropped, they'd have to be set again with `/set_notif_channel`. `guild_of` places a channel.
+fn move_legacy_notif_channels(
+  cache: &mut Map<String, Value>,
+  guild_of: impl Fn(u64) -> Result<GuildId, String>,
+) -> Result<(), String> {
+  let legacy = match cache.remove("notif_channel_ids") {
+    Some(Value::Object(legacy)) => legacy,
+    Some(Value::Null) | None => return Ok(()),
+    Some(_) => return Err(String::from("notif_channel_ids isn't an object")),
+  };
+
+  info!("Moving the global notification channels to per-guild channels");
+  let guilds = object_entry(cache, "guild_notif_channel_ids")?;
+  for (field, channel_id) in legacy {
+    let channel_id = match channel_id.as_u64() {
+      Some(channel_id) if channel_id != 0 => channel_id,
+      _ => continue,
+    };
+
+    let guild_id = match guild_of(channel_id) {
+      Ok(guild_id) => guild_id,
+      Err(err) => {
+        warn!("Dropping notif_channel_ids.{field} ({channel_id}), {err}");
+        continue;
+      }
+    };
+
+    let channels = object_entry(guilds, &guild_id.get().to_string())?;
+    if channels.get(&field).is_none_or(Value::is_null) {
+      channels.insert(field, Value::from(channel_id));
+    }
+  }
+
+  Ok(())
+}
+
+/// Asks Discord which guild a channel is in. Migrations run on the blocking pool (see
+/// [`load`]), so this can wait on the runtime.
+fn channel_guild(channel_id: u64) -> Result<GuildId, String> {
+  let runtime = Handle::try_current().map_err(|_| "there's no runtime to ask Discord with")?;
+  let token = std::env::var("DISCORD_BOT_TOKEN").map_err(|_| "DISCORD_BOT_TOKEN isn't set")?;
+
+  match runtime.block_on(Http::new(&token).get_channel(ChannelId::new(channel_id))) {
+    Ok(Channel::Guild(channel)) => Ok(channel.guild_id),
+    Ok(_) => Err(String::from("it isn't a guild channel")),
+    Err(err) => Err(format!("couldn't fetch the channel: {err}")),
+  }
+}
+
+/// `/set_feedback_webhook` used to keep the webhook in its own file, which nothing read back.
+fn move_legacy_feedback_webhook(cache: &mut Map<String, Value>, path: &Path) {
+  if cache
+    .get("feedback_webhook")
+    .is_some_and(|webhook| !webhook.is_null())
+    || !path.exists()
+  {
+    return;
+  }
+
+  match fs::read_to_string(path) {
+    Ok(webhook) if !webhook.trim().is_empty() => {
+      info!("Moving the feedback webhook into the cache");
+      cache.insert(
+        String::from("feedback_webhook"),
+        Value::from(webhook.trim()),
+      );
+    }
+    Ok(_) => (),
+    Err(err) => warn!("Failed to read {}: {err}", path.display()),
+  }
+}
+
+fn object_entry<'a>(
+  map: &'a mut Map<String, Value>,
+  key: &str,
+) -> Result<&'a mut Map<String, Value>, String> {
+  map
+    .entry(key)
+    .or_insert_with(|| Value::Object(Map::new()))
+    .as_object_mut()
+    .ok_or_else(|| format!("{key} isn't an object"))
+}
+
+/// The audit log moves out of the cache into its own file, see [`audit::AuditLog`].
+fn v1_to_v2(cache: &mut Map<String, Value>) -> Result<(), String> {
+  let entries = match cache.remove("audit_log") {
+    Some(Value::Array(entries)) => entries,
+    Some(_) => return Err(String::from("audit_log isn't a list")),
+    None => return Ok(()),
+  };
+
+  audit::AuditLog::new(audit::path())
+    .import(entries)
+    .map_err(|err| {
+      format!(
+        "couldn't move the audit log to {}: {err}",
+        audit::path().display()
+      )
+    })
+}
+
+/// Review votes and ownership challenges are per instance, the ones from before were all on
+/// [`DEFAULT_INSTANCE`].
+fn v2_to_v3(cache: &mut Map<String, Value>) -> Result<(), String> {
+  for key in ["review_votes", "ownership_challenges"] {
+    let entries = match cache.get_mut(key) {
+      Some(Value::Array(entries)) => entries,
+      Some(_) => return Err(format!("{key} isn't a list")),
+      None => continue,
+    };
+
+    for entry in entries {
+      match entry {
+        Value::Object(entry) => {
+          entry
+            .entry("instance")
+            .or_insert_with(|| Value::from(DEFAULT_INSTANCE));
+        }
+        _ => return Err(format!("{key} has an entry that isn't an object")),
+      }
+    }
+  }
+
+  Ok(())
+}
+
+/// Queued notifications keep the whole message instead of only its embed, so components
+/// survive a retry.
+fn v3_to_v4(cache: &mut Map<String, Value>) -> Result<(), String> {
+  let deliveries = match cache.get_mut("notif_outbox") {
+    Some(Value::Array(deliveries)) => deliveries,
+    Some(_) => return Err(String::from("notif_outbox isn't a list")),
+    None => return Ok(()),
+  };
+
+  for delivery in deliveries {
+    let delivery = match delivery {
+      Value::Object(delivery) => delivery,
+      _ => {
+        return Err(String::from(
+          "notif_outbox has an entry that isn't an object",
+        ));
+      }
+    };
+    if let Some(embed) = delivery.remove("embed") {
+      delivery.insert(String::from("message"), json!({ "embeds": [embed] }));
+    }
+  }
+
+  Ok(())
+}
+
+/// Why a cache file couldn't be loaded.
+#[derive(Debug)]
+pub enum LoadError {
+  Io(io::Error),
+  Syntax(serde_json::Error),
+  NotAnObject,
+  BadVersion(Value),
+  TooNew(u32),
+  Migration { from: u32, reason: String },
+  Field(serde_path_to_error::Error<serde_json::Error>),
+}
+
+impl std::fmt::Display for LoadError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      LoadError::Io(err) => write!(f, "couldn't be read: {err}"),
+      LoadError::Syntax(err) => write!(f, "isn't valid JSON: {err}"),
+      LoadError::NotAnObject => write!(f, "isn't a JSON object"),
+      LoadError::BadVersion(version) => write!(f, "has an unreadable version {version}"),
+      LoadError::TooNew(version) => write!(
+        f,
+        "is version {version}, this collar only understands up to {CURRENT_VERSION}"
+      ),
+      LoadError::Migration { from, reason } => {
+        write!(f, "failed to migrate from version {from}: {reason}")
+      }
+      LoadError::Field(err) => write!(f, "has a bad value at `{}`: {}", err.path(), err.inner()),
+    }
+  }
+}
+
+impl std::error::Error for LoadError {}
+
+#[derive(Serialize)]
+struct Versioned<'a> {
+  version: u32,
+  #[serde(flatten)]
+  cache: &'a Cache,
+}
+
+pub fn path() -> PathBuf {
+  dotenv().ok();
+  PathBuf::from(std::env::var("CACHE_PATH").unwrap_or(".cache.json".to_string()))
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+  let mut path = path.as_os_str().to_owned();
+  path.push(suffix);
+  PathBuf::from(path)
+}
+
+fn backup_path(path: &Path, n: usize) -> PathBuf {
+  with_suffix(path, &format!(".bak.{n}"))
+}
+
+fn backup_count() -> usize {
+  std::env::var("CACHE_BACKUPS")
+    .ok()
+    .and_then(|count| count.parse::<usize>().ok())
+    .unwrap_or(DEFAULT_BACKUPS)
+}
+
+/// Reads a cache file of any version up to [`CURRENT_VERSION`], migrating it on the way.
+pub fn read(path: &Path) -> Result<Cache, LoadError> {
+  let text = fs::read_to_string(path).map_err(LoadError::Io)?;
+  let mut cache = match serde_json::from_str::<Value>(&text).map_err(LoadError::Syntax)? {
+    Value::Object(cache) => cache,
+    _ => return Err(LoadError::NotAnObject),
+  };
+
+  let version = match cache.remove(VERSION_KEY) {
+    None => 0,
+    Some(version) => match version.as_u64().and_then(|v| u32::try_from(v).ok()) {
+      Some(version) => version,
+      None => return Err(LoadError::BadVersion(version)),
+    },
+  };
+  if version > CURRENT_VERSION {
+    return Err(LoadError::TooNew(version));
+  }
+
+  for from in version..CURRENT_VERSION {
+    MIGRATIONS[from as usize](&mut cache)
+      .map_err(|reason| LoadError::Migration { from, reason })?;
+    info!(
+      "Migrated {} from version {from} to {}",
+      path.display(),
+      from + 1
+    );
+  }
+
+  serde_path_to_error::deserialize(Value::Object(cache)).map_err(LoadError::Field)
+}
+
+/// Loads the cache, falling back to the newest backup that still reads when the file is
+/// broken. A broken file is moved aside instead of being overwritten, so nothing is lost.
+/// Blocks, and migrating an unversioned file can wait on Discord, so call it from the
+/// blocking pool.
+pub fn load() -> Cache {
+  let path = path();
+  if !path.exists() {
+    info!("No cache at {}, starting fresh", path.display());
+    return Cache::default();
+  }
+
+  match read(&path) {
+    Ok(cache) => {
+      if let Err(err) = rotate_backups(&path) {
+        warn!("Failed to back up {}: {err}", path.display());
+      }
+      return cache;
+    }
+    Err(err) => error!("{} {err}", path.display()),
+  }
+
+  let broken = with_suffix(
+    &path,
+    &format!(".broken.{}", chrono::Utc::now().timestamp()),
+  );
+  match fs::rename(&path, &broken) {
+    Ok(()) => warn!("Moved the broken cache to {}", broken.display()),
+    Err(err) => error!("Failed to move the broken cache aside: {err}"),
+  }
+
+  for n in 1..=backup_count() {
+    let backup = backup_path(&path, n);
+    if !backup.exists() {
+      continue;
+    }
+
+    match read(&backup) {
+      Ok(cache) => {
+        warn!("Recovered the cache from {}", backup.display());
+        return cache;
+      }
+      Err(err) => error!("{} {err}", backup.display()),
+    }
+  }
+
+  error!(
+    "No readable backup of {}, starting from defaults",
+    path.display()
+  );
+  Cache::default()
+}
+
+/// Writes next to the cache file and renames over it, so a crash mid-write leaves the old
+/// file in place instead of half of the new one.
+pub fn write(cache: &Cache) -> Result<(), CollarError> {
+  let path = path();
+  let temp_path = with_suffix(&path, ".tmp");
+
+  let cache_str = serde_json::to_string(&Versioned {
+    version: CURRENT_VERSION,
+    cache,
+  })?;
+  let mut file_to_write = fs::File::create(&temp_path)?;
+
+  if let Err(err) = file_to_write
+    .write_all(cache_str.as_bytes())
+    .and_then(|_| file_to_write.sync_all())
+  {
+    return Err(CollarError::from(format!(
+      "Could not write to cache file: {err}"
+    )));
+  }
+
+  if backup_due(&path)
+    && let Err(err) = rotate_backups(&path)
+  {
+    warn!("Failed to back up {}: {err}", path.display());
+  }
+
+  fs::rename(&temp_path, &path)
+    .map_err(|err| CollarError::from(format!("Could not replace cache file: {err}")))
+}
+
+fn backup_due(path: &Path) -> bool {
+  match fs::metadata(backup_path(path, 1)).and_then(|metadata| metadata.modified()) {
+    Ok(modified) => modified.elapsed().map_or(true, |age| age >= BACKUP_EVERY),
+    Err(_) => true,
+  }
+}
+
+/// Shifts `.bak.1` to `.bak.2` and so on, dropping the oldest, then copies the current file
+/// into `.bak.1`.
+fn rotate_backups(path: &Path) -> io::Result<()> {
+  let count = backup_count();
+  if count == 0 || !path.exists() {
+    return Ok(());
+  }
+
+  for n in (1..count).rev() {
+    let from = backup_path(path, n);
+    if from.exists() {
+      fs::rename(from, backup_path(path, n + 1))?;
+    }
+  }
+  fs::copy(path, backup_path(path, 1))?;
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::collar::NotifChannelType;
+
+  #[test]
+  fn legacy_secrets_become_the_default_instance() {
+    let mut cache = json!({
+      "secrets": {
+        "access_token": "a",
+        "refresh_token": "r",
+        "access_token_expires_at": 1,
+        "refresh_token_expires_at": 2,
+      },
+      "urls": { "api_base_url": "https://api", "web_base_url": "https://web" },
+    });
+    move_legacy_instance(cache.as_object_mut().unwrap()).unwrap();
+
+    let cache = serde_json::from_value::<Cache>(cache).unwrap();
+    let instance = cache.get_instance(DEFAULT_INSTANCE).unwrap();
+    assert_eq!(instance.get_secrets().access_token, "a");
+    assert_eq!(instance.get_api_base_url(), "https://api");
+  }
+
+  #[test]
+  fn legacy_channels_and_webhook_move_into_the_cache() {
+    let webhook = std::env::temp_dir().join(format!("collar-webhook-{}", std::process::id()));
+    fs::write(&webhook, "https://discord.com/api/webhooks/1/abc\n").unwrap();
+
+    let mut cache = json!({
+      "notif_channel_ids": { "user_submit_id": 11, "ad_submit_id": 22, "general_id": 33 },
+      "guild_notif_channel_ids": { "100": { "ad_submit_id": 44 } },
+    });
+    let map = cache.as_object_mut().unwrap();
+    move_legacy_notif_channels(map, |channel_id| match channel_id {
+      33 => Err(String::from("it isn't a guild channel")),
+      _ => Ok(GuildId::new(100)),
+    })
+    .unwrap();
+    move_legacy_feedback_webhook(map, &webhook);
+    fs::remove_file(&webhook).unwrap();
+
+    let cache = serde_json::from_value::<Cache>(cache).unwrap();
+    let guild = GuildId::new(100);
+    assert_eq!(
+      cache.get_notif_channel(guild, NotifChannelType::UserSubmit),
+      Some(11)
+    );
+    // The guild's own setting wins, and channels Discord couldn't place are dropped.
+    assert_eq!(
+      cache.get_notif_channel(guild, NotifChannelType::AdSubmit),
+      Some(44)
+    );
+    assert_eq!(
+      cache.get_notif_channel(guild, NotifChannelType::General),
+      None
+    );
+    assert_eq!(
+      cache.get_feedback_webhook().as_deref(),
+      Some("https://discord.com/api/webhooks/1/abc")
+    );
+  }
+
+  #[test]
+  fn unversioned_files_migrate_to_the_current_version() {
+    let path = std::env::temp_dir().join(format!("collar-cache-v0-{}", std::process::id()));
+    let legacy = json!({
+      "secrets": {
+        "access_token": "a",
+        "refresh_token": "r",
+        "access_token_expires_at": 1,
+        "refresh_token_expires_at": 2,
+      },
+      "urls": { "api_base_url": "https://api", "web_base_url": "https://web" },
+      "notif_channel_ids": { "general_id": 0 },
+    });
+    fs::write(&path, legacy.to_string()).unwrap();
+
+    let cache = read(&path);
+    fs::remove_file(&path).unwrap();
+
+    let cache = cache.unwrap();
+    let instance = cache.get_instance(DEFAULT_INSTANCE).unwrap();
+    assert_eq!(instance.get_secrets().refresh_token, "r");
+    assert_eq!(instance.get_web_base_url(), "https://web");
+  }
+}
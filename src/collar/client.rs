@@ -0,0 +1,397 @@
+use super::{
+  Cache, CollarError,
+  commands::{Ad, EditedUser, ImageSubmission, User, UserEditSubmission, UserSubmission},
+  http::make_request,
+  metrics::RequestMetrics,
+  notifs::SubmitType,
+  state::SharedCache,
+  supervisor::ApiStatus,
+  tokens::TokenManager,
+};
+use chrono::{DateTime, Utc};
+use futures::{StreamExt, stream};
+use poise::serenity_prelude::{GuildId, UserId};
+use reqwest::{Client, Method, StatusCode};
+use serde::{Deserialize, Deserializer, Serialize};
+use tracing::warn;
+
+/// Roster members looked up at once by [`PetringClient::get_known_users`] and
+/// [`PetringClient::get_known_ads`].
+const LOOKUPS_AT_ONCE: usize = 8;
+
+/// Everything that can go wrong when talking to the PetRing API.
+#[derive(Debug)]
+pub enum PetringError {
+  /// The request never got a response (connection refused, dns, timeout...).
+  Transport(reqwest::Error),
+  /// The API answered with an error body.
+  Api { status: StatusCode, message: String },
+  /// The API answered, but the body wasn't what we expected.
+  Decode {
+    status: StatusCode,
+    body: String,
+    source: serde_json::Error,
+  },
+  /// We got a 401 and couldn't get a fresh token to retry with.
+  Auth(CollarError),
+  /// The instance the request was meant for isn't configured.
+  UnknownInstance(String),
+  /// The instance's API is known to be down, or collar has no tokens for it yet.
+  Unavailable(String),
+}
+
+impl PetringError {
+  /// Whether the API couldn't be reached at all, rather than turning the request down.
+  pub fn is_unavailable(&self) -> bool {
+    matches!(
+      self,
+      PetringError::Transport(_) | PetringError::Unavailable(_)
+    )
+  }
+
+  /// Whether the request certainly never reached the API: it couldn't connect, or collar
+  /// didn't try because the API is known to be down. A timeout may have come after the API
+  /// took the request, so sending it again could make it happen twice.
+  pub fn never_reached(&self) -> bool {
+    match self {
+      PetringError::Transport(err) => err.is_connect(),
+      PetringError::Unavailable(_) => true,
+      _ => false,
+    }
+  }
+
+  pub fn status(&self) -> Option<StatusCode> {
+    match self {
+      PetringError::Transport(err) => err.status(),
+      PetringError::Api { status, .. } | PetringError::Decode { status, .. } => Some(*status),
+      PetringError::Auth(_) => Some(StatusCode::UNAUTHORIZED),
+      PetringError::UnknownInstance(_) | PetringError::Unavailable(_) => None,
+    }
+  }
+}
+
+impl std::fmt::Display for PetringError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      PetringError::Transport(err) => write!(f, "Couldn't reach the PetRing API: {err}"),
+      PetringError::Api { message, .. } => write!(f, "{message}"),
+      PetringError::Decode { source, body, .. } => {
+        write!(
+          f,
+          "Unexpected response from the PetRing API: {source}, response: {body}"
+        )
+      }
+      PetringError::Auth(err) => write!(f, "Couldn't authenticate with the PetRing API: {err}"),
+      PetringError::UnknownInstance(name) => write!(
+        f,
+        "PetRing instance {name} isn't configured, pick another one with /set_instance"
+      ),
+      PetringError::Unavailable(reason) => write!(f, "The PetRing API is unavailable: {reason}"),
+    }
+  }
+}
+
+impl std::error::Error for PetringError {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    match self {
+      PetringError::Transport(err) => Some(err),
+      PetringError::Decode { source, .. } => Some(source),
+      _ => None,
+    }
+  }
+}
+
+impl From<reqwest::Error> for PetringError {
+  fn from(err: reqwest::Error) -> Self {
+    PetringError::Transport(err)
+  }
+}
+
+/// Which PetRing instance a [`PetringClient`] talks to.
+#[derive(Clone, Debug)]
+pub enum InstanceTarget {
+  /// Whatever instance the guild is bound to, resolved on every request.
+  Guild(Option<GuildId>),
+  Named(String),
+}
+
+impl InstanceTarget {
+  /// Fails for a guild bound to an instance that isn't configured anymore.
+  pub fn resolve(&self, cache: &Cache) -> Result<String, PetringError> {
+    match self {
+      InstanceTarget::Guild(guild_id) => {
+        cache.get_guild_instance_name(*guild_id).ok_or_else(|| {
+          let bound = guild_id.and_then(|guild_id| cache.get_guild_binding(guild_id).cloned());
+          PetringError::UnknownInstance(bound.unwrap_or_default())
+        })
+      }
+      InstanceTarget::Named(name) => Ok(name.clone()),
+    }
+  }
+}
+
+/// A member collar saw a user or ad of on an instance. The API has no list endpoints, so this is
+/// how collar knows who to look up for health checks, archives, duplicates and `/pending`.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RosterEntry {
+  pub instance: String,
+  pub submit_type: SubmitType,
+  pub discord_id: UserId,
+}
+
+/// Typed wrapper around the PetRing API, one method per endpoint.
+#[derive(Clone)]
+pub struct PetringClient {
+  pub(super) http_client: Client,
+  pub(super) cache: SharedCache,
+  pub(super) tokens: TokenManager,
+  pub(super) api_status: ApiStatus,
+  pub(super) metrics: RequestMetrics,
+  pub(super) target: InstanceTarget,
+}
+
+impl PetringClient {
+  pub fn new(
+    http_client: Client,
+    cache: SharedCache,
+    tokens: TokenManager,
+    api_status: ApiStatus,
+    metrics: RequestMetrics,
+    target: InstanceTarget,
+  ) -> Self {
+    Self {
+      http_client,
+      cache,
+      tokens,
+      api_status,
+      metrics,
+      target,
+    }
+  }
+
+  async fn request<T, R>(
+    &self,
+    body: Option<T>,
+    route: &str,
+    method: Method,
+  ) -> Result<R, PetringError>
+  where
+    T: serde::Serialize + Clone,
+    R: for<'de> Deserialize<'de> + std::fmt::Debug,
+  {
+    make_request(self, body, route, method).await
+  }
+
+  /// Keeps the roster in line with what the API said about the member.
+  fn track<R>(
+    &self,
+    submit_type: SubmitType,
+    discord_id: UserId,
+    result: &Result<R, PetringError>,
+  ) {
+    match result {
+      Ok(_) => self.set_tracked(submit_type, discord_id, true),
+      Err(err) if err.status() == Some(StatusCode::NOT_FOUND) => {
+        self.set_tracked(submit_type, discord_id, false)
+      }
+      Err(_) => (),
+    }
+  }
+
+  /// Only takes the write lock, and with it a cache write, when the roster actually changes.
+  fn set_tracked(&self, submit_type: SubmitType, discord_id: UserId, tracked: bool) {
+    let instance = match self.target.resolve(&self.cache.read()) {
+      Ok(instance) => instance,
+      Err(_) => return,
+    };
+    let entry = RosterEntry {
+      instance,
+      submit_type,
+      discord_id,
+    };
+    if self.cache.read().in_roster(&entry) == tracked {
+      return;
+    }
+    match tracked {
+      true => self.cache.write().add_to_roster(entry),
+      false => self.cache.write().remove_from_roster(&entry),
+    };
+  }
+
+  pub async fn get_user_by_discord(&self, discord_id: UserId) -> Result<User, PetringError> {
+    let user = self
+      .request(
+        None::<()>,
+        &format!("/get/user/by-discord/{discord_id}"),
+        Method::GET,
+      )
+      .await;
+    self.track(SubmitType::User, discord_id, &user);
+    user
+  }
+
+  /// Users of every member on the roster. The API has no listing, so members collar never
+  /// looked up, like ones that only submitted on the website, aren't in it. Members that are
+  /// gone drop off the roster, the lookup only fails as a whole when the API can't be reached.
+  pub async fn get_known_users(&self) -> Result<Vec<User>, PetringError> {
+    self
+      .get_known(SubmitType::User, |discord_id| {
+        self.get_user_by_discord(discord_id)
+      })
+      .await
+  }
+
+  /// Looks up [`LOOKUPS_AT_ONCE`] roster members at a time, in no particular order.
+  async fn get_known<T, F, Fut>(
+    &self,
+    submit_type: SubmitType,
+    lookup: F,
+  ) -> Result<Vec<T>, PetringError>
+  where
+    F: Fn(UserId) -> Fut,
+    Fut: Future<Output = Result<T, PetringError>>,
+  {
+    let mut lookups = stream::iter(self.roster(submit_type))
+      .map(|discord_id| {
+        let looked_up = lookup(discord_id);
+        async move { (discord_id, looked_up.await) }
+      })
+      .buffer_unordered(LOOKUPS_AT_ONCE);
+
+    let mut found = Vec::new();
+    while let Some((discord_id, looked_up)) = lookups.next().await {
+      match looked_up {
+        Ok(item) => found.push(item),
+        Err(err) if err.is_unavailable() => return Err(err),
+        Err(err) if err.status() == Some(StatusCode::NOT_FOUND) => (),
+        Err(err) => warn!("Failed to look up the {submit_type:?} of {discord_id}, skipping: {err}"),
+      }
+    }
+    Ok(found)
+  }
+
+  pub async fn submit_user(&self, submission: UserSubmission) -> Result<User, PetringError> {
+    let discord_id = UserId::new(submission.discord_id);
+    let user = self
+      .request(Some(submission), "/post/user/submit", Method::POST)
+      .await;
+    self.track(SubmitType::User, discord_id, &user);
+    user
+  }
+
+  pub async fn edit_user(
+    &self,
+    submission: UserEditSubmission,
+  ) -> Result<EditedUser, PetringError> {
+    self
+      .request(Some(submission), "/patch/user/edit/", Method::PATCH)
+      .await
+  }
+
+  pub async fn verify_user(&self, discord_id: UserId) -> Result<User, PetringError> {
+    let user = self
+      .request(
+        None::<()>,
+        &format!("/patch/user/verify/{discord_id}"),
+        Method::PATCH,
+      )
+      .await;
+    self.track(SubmitType::User, discord_id, &user);
+    user
+  }
+
+  pub async fn delete_user(&self, discord_id: UserId) -> Result<User, PetringError> {
+    let user = self
+      .request(
+        None::<()>,
+        &format!("/delete/user/by-discord/{discord_id}"),
+        Method::DELETE,
+      )
+      .await;
+    if user.is_ok() {
+      self.set_tracked(SubmitType::User, discord_id, false);
+    }
+    user
+  }
+
+  pub async fn get_ad(&self, discord_id: UserId) -> Result<Ad, PetringError> {
+    let ad = self
+      .request(None::<()>, &format!("/get/ad/{discord_id}"), Method::GET)
+      .await;
+    self.track(SubmitType::Ad, discord_id, &ad);
+    ad
+  }
+
+  /// Ads of every member on the roster, see [`PetringClient::get_known_users`].
+  pub async fn get_known_ads(&self) -> Result<Vec<Ad>, PetringError> {
+    self
+      .get_known(SubmitType::Ad, |discord_id| self.get_ad(discord_id))
+      .await
+  }
+
+  pub async fn submit_ad(&self, submission: ImageSubmission) -> Result<Ad, PetringError> {
+    let discord_id = UserId::new(submission.discord_id);
+    let ad = self
+      .request(Some(submission), "/post/ad/submit", Method::POST)
+      .await;
+    self.track(SubmitType::Ad, discord_id, &ad);
+    ad
+  }
+
+  pub async fn edit_ad(&self, submission: ImageSubmission) -> Result<Ad, PetringError> {
+    self
+      .request(Some(submission), "/patch/ad/edit/", Method::PATCH)
+      .await
+  }
+
+  pub async fn verify_ad(&self, discord_id: UserId) -> Result<Ad, PetringError> {
+    let ad = self
+      .request(
+        None::<()>,
+        &format!("/patch/ad/verify/{discord_id}"),
+        Method::PATCH,
+      )
+      .await;
+    self.track(SubmitType::Ad, discord_id, &ad);
+    ad
+  }
+
+  pub async fn delete_ad(&self, discord_id: UserId) -> Result<Ad, PetringError> {
+    let ad = self
+      .request(
+        None::<()>,
+        &format!("/delete/ad/by-discord/{discord_id}"),
+        Method::DELETE,
+      )
+      .await;
+    if ad.is_ok() {
+      self.set_tracked(SubmitType::Ad, discord_id, false);
+    }
+    ad
+  }
+
+  fn roster(&self, submit_type: SubmitType) -> Vec<UserId> {
+    let cache = self.cache.read();
+    match self.target.resolve(&cache) {
+      Ok(instance) => cache.get_roster(&instance, submit_type),
+      Err(_) => Vec::new(),
+    }
+  }
+}
+
+/// PetRing sends `""` for timestamps that haven't happened yet (`edited_at`, `verified_at`).
+pub fn deserialize_optional_timestamp<'de, D>(
+  deserializer: D,
+) -> Result<Option<DateTime<Utc>>, D::Error>
+where
+  D: Deserializer<'de>,
+{
+  let raw = Option::<String>::deserialize(deserializer)?;
+
+  match raw.as_deref() {
+    None | Some("") => Ok(None),
+    Some(timestamp) => DateTime::parse_from_rfc3339(timestamp)
+      .map(|timestamp| Some(timestamp.with_timezone(&Utc)))
+      .map_err(serde::de::Error::custom),
+  }
+}
@@ -1,14 +1,28 @@
 use super::{
   COLLAR_FOOTER, CollarAppContext, CollarContext, CollarError, EmbedWrapper, NotifChannelType,
-  http, notifs,
+  client::{self, PetringError},
+  notifs, url_policy,
+};
+use chrono::{DateTime, Utc};
+use poise::{
+  ChoiceParameter, CreateReply, Modal,
+  serenity_prelude::{Color, CreateEmbed, FormattedTimestamp, FormattedTimestampStyle, Timestamp},
 };
-use poise::{ChoiceParameter, CreateReply, Modal, serenity_prelude::Color};
 use serde::{Deserialize, Serialize};
 
+pub mod ad_limits;
+pub mod audit;
+pub mod domain_policy;
+pub mod instances;
 pub mod misc;
 pub mod notifications;
+pub mod outbox;
+pub mod ownership;
+pub mod pending;
 pub mod petads;
 pub mod petring;
+pub mod quorum;
+pub mod reject_reasons;
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct User {
@@ -16,9 +30,11 @@ pub struct User {
   pub discord_id: u64,
   pub url: String,
   pub verified: bool,
-  pub created_at: String,
-  pub edited_at: String,
-  pub verified_at: String,
+  pub created_at: DateTime<Utc>,
+  #[serde(default, deserialize_with = "client::deserialize_optional_timestamp")]
+  pub edited_at: Option<DateTime<Utc>>,
+  #[serde(default, deserialize_with = "client::deserialize_optional_timestamp")]
+  pub verified_at: Option<DateTime<Utc>>,
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -97,9 +113,11 @@ pub struct Ad {
   pub image_url: String,
   pub ad_url: String,
   pub verified: bool,
-  pub created_at: String,
-  pub edited_at: String,
-  pub verified_at: String,
+  pub created_at: DateTime<Utc>,
+  #[serde(default, deserialize_with = "client::deserialize_optional_timestamp")]
+  pub edited_at: Option<DateTime<Utc>>,
+  #[serde(default, deserialize_with = "client::deserialize_optional_timestamp")]
+  pub verified_at: Option<DateTime<Utc>>,
 }
 
 #[derive(Deserialize, Debug, Clone, Modal)]
@@ -134,6 +152,63 @@ pub enum NotifType {
   #[name = "DM Fallback"]
   #[name = "Incase User DM fails, send the message to this channel instead"]
   DmFallback,
+
+  #[name = "Admin"]
+  #[name = "Notification channel for notifications collar failed to deliver"]
+  Admin,
+}
+
+#[derive(Clone, Copy, ChoiceParameter)]
+pub enum AuditActionType {
+  #[name = "Verify"]
+  #[name = "Submissions that were verified"]
+  Verify,
+  #[name = "Reject"]
+  #[name = "Submissions that were rejected from review"]
+  Reject,
+  #[name = "Remove"]
+  #[name = "Users or ads that were removed"]
+  Remove,
+  #[name = "Veto"]
+  #[name = "Submissions that were vetoed during review"]
+  Veto,
+}
+
+#[derive(Clone, Copy, ChoiceParameter)]
+pub enum SubmissionType {
+  #[name = "Website"]
+  #[name = "Websites submitted with /submit_user"]
+  User,
+  #[name = "Ad"]
+  #[name = "Ads submitted with /submit_ad"]
+  Ad,
+}
+
+#[derive(Clone, Copy, ChoiceParameter)]
+pub enum PolicyListType {
+  #[name = "Allow"]
+  #[name = "Only websites matching these get in, when there are any"]
+  Allow,
+  #[name = "Deny"]
+  #[name = "Websites matching these are turned away"]
+  Deny,
+}
+
+impl From<PolicyListType> for url_policy::PolicyList {
+  fn from(list: PolicyListType) -> Self {
+    match list {
+      PolicyListType::Allow => url_policy::PolicyList::Allow,
+      PolicyListType::Deny => url_policy::PolicyList::Deny,
+    }
+  }
+}
+
+#[derive(Clone, Copy, ChoiceParameter)]
+pub enum ExportFormat {
+  #[name = "CSV"]
+  Csv,
+  #[name = "JSON"]
+  Json,
 }
 
 #[derive(ChoiceParameter)]
@@ -234,3 +309,72 @@ pub async fn send_generic_error_normal(
 
   Ok(())
 }
+
+pub fn format_timestamp(
+  timestamp: impl Into<Option<DateTime<Utc>>>,
+  style: FormattedTimestampStyle,
+) -> String {
+  match timestamp.into() {
+    Some(timestamp) => FormattedTimestamp::new(Timestamp::from(timestamp), Some(style)).to_string(),
+    None => String::from("Never"),
+  }
+}
+
+fn petring_error_embed(embed: CreateEmbed, error: &PetringError) -> CreateEmbed {
+  if error.is_unavailable() {
+    return embed
+      .title("PetRing API unavailable 3:")
+      .description(
+        "Collar can't reach PetRing right now. It keeps retrying in the background and the \
+         server gets told once it's back, try again in a bit :3",
+      )
+      .field(
+        "Details",
+        error.to_string().chars().take(1024).collect::<String>(),
+        false,
+      )
+      .color(Color::from_rgb(255, 0, 0));
+  }
+
+  let title = match error.status() {
+    Some(status) => format!("Error {}", status.as_u16()),
+    None => String::from("Error"),
+  };
+
+  embed
+    .title(title)
+    .description(error.to_string())
+    .color(Color::from_rgb(255, 0, 0))
+}
+
+pub async fn send_petring_error_application(
+  ctx: CollarAppContext<'_>,
+  error: &PetringError,
+) -> Result<(), CollarError> {
+  let embed = petring_error_embed(EmbedWrapper::new_application(&ctx), error);
+
+  let reply = CreateReply::default()
+    .embed(embed)
+    .reply(true)
+    .ephemeral(true);
+
+  ctx.send(reply).await?;
+
+  Ok(())
+}
+
+pub async fn send_petring_error_normal(
+  ctx: CollarContext<'_>,
+  error: &PetringError,
+) -> Result<(), CollarError> {
+  let embed = petring_error_embed(EmbedWrapper::new_normal(&ctx), error);
+
+  let reply = CreateReply::default()
+    .embed(embed)
+    .reply(true)
+    .ephemeral(true);
+
+  ctx.send(reply).await?;
+
+  Ok(())
+}
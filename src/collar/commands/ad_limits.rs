@@ -0,0 +1,167 @@
+use crate::collar::ad_image::{self, AdImageLimits};
+
+use super::{CollarAppContext, CollarError, EmbedWrapper, send_generic_error_application};
+use poise::serenity_prelude::{Color, CreateEmbed};
+use poise::{CreateReply, command};
+use tracing::info;
+
+fn limits_embed(embed: CreateEmbed, limits: &AdImageLimits) -> CreateEmbed {
+  let blocked_hosts = ad_image::EPHEMERAL_HOSTS
+    .iter()
+    .map(|host| format!("`{host}` (always)"))
+    .chain(limits.blocked_hosts.iter().map(|host| format!("`{host}`")))
+    .collect::<Vec<_>>()
+    .join("\n");
+
+  embed
+    .field(
+      "Max file size",
+      ad_image::format_bytes(limits.max_bytes),
+      true,
+    )
+    .field("Size", limits.size_text(), true)
+    .field("Max frames", limits.max_frames.to_string(), true)
+    .field("Blocked hosts", blocked_hosts, false)
+}
+
+#[command(
+  slash_command,
+  description_localized(
+    locale = "en-US",
+    description = "Set what ad images have to look like before they reach review"
+  ),
+  description_localized(
+    locale = "sv-SE",
+    description = "Ange hur annonsbilder måste se ut innan de granskas"
+  ),
+  name_localized(locale = "en-US", name = "set_ad_limits"),
+  name_localized(locale = "sv-SE", name = "ställ_in_annonsgränser"),
+  category = "Review",
+  required_permissions = "MANAGE_GUILD"
+)]
+pub async fn set_ad_limits(
+  ctx: CollarAppContext<'_>,
+  #[description = "Largest file size in KiB"]
+  #[min = 1]
+  #[max = 10240]
+  max_kib: Option<u64>,
+  #[description = "Width in pixels, 0 for any"]
+  #[max = 4096]
+  width: Option<u32>,
+  #[description = "Height in pixels, 0 for any"]
+  #[max = 4096]
+  height: Option<u32>,
+  #[description = "Whether the size has to match exactly, like 88x31"] exact_size: Option<bool>,
+  #[description = "Most frames an animation may have"]
+  #[min = 1]
+  #[max = 10000]
+  max_frames: Option<u32>,
+  #[description = "Comma separated hosts to turn away on top of Discord's, \"none\" to clear"]
+  blocked_hosts: Option<String>,
+) -> Result<(), CollarError> {
+  let guild_id = match ctx.guild_id() {
+    Some(guild_id) => guild_id,
+    None => return send_generic_error_application(ctx, "Ad limits are per server").await,
+  };
+
+  let blocked_hosts = blocked_hosts.map(|hosts| match hosts.trim().eq_ignore_ascii_case("none") {
+    true => Vec::new(),
+    false => hosts
+      .split(',')
+      .map(|host| host.trim().trim_start_matches("*.").to_ascii_lowercase())
+      .filter(|host| !host.is_empty())
+      .collect::<Vec<_>>(),
+  });
+  if blocked_hosts
+    .as_ref()
+    .is_some_and(|hosts| hosts.len() > ad_image::MAX_BLOCKED_HOSTS)
+  {
+    return send_generic_error_application(
+      ctx,
+      &format!(
+        "Only {} extra hosts can be blocked",
+        ad_image::MAX_BLOCKED_HOSTS
+      ),
+    )
+    .await;
+  }
+
+  let limits = {
+    let mut cache = ctx.data().cache.write();
+    let limits = cache.ad_image_limits_mut(guild_id);
+    if let Some(max_kib) = max_kib {
+      limits.max_bytes = max_kib * 1024;
+    }
+    if let Some(width) = width {
+      limits.width = Some(width).filter(|width| *width != 0);
+    }
+    if let Some(height) = height {
+      limits.height = Some(height).filter(|height| *height != 0);
+    }
+    if let Some(exact_size) = exact_size {
+      limits.exact_size = exact_size;
+    }
+    if let Some(max_frames) = max_frames {
+      limits.max_frames = max_frames;
+    }
+    if let Some(blocked_hosts) = blocked_hosts {
+      limits.blocked_hosts = blocked_hosts;
+    }
+    limits.clone()
+  };
+  info!("Set ad image limits for guild {guild_id}: {limits:?}");
+
+  let embed = limits_embed(
+    EmbedWrapper::new_application(&ctx)
+      .title("Ad limits set!")
+      .description("New and edited ads are checked against these"),
+    &limits,
+  )
+  .color(Color::from_rgb(0, 255, 0));
+
+  let reply = CreateReply::default()
+    .embed(embed)
+    .reply(true)
+    .ephemeral(true);
+
+  ctx.send(reply).await?;
+  Ok(())
+}
+
+#[command(
+  slash_command,
+  description_localized(
+    locale = "en-US",
+    description = "See what ad images have to look like on this server"
+  ),
+  description_localized(
+    locale = "sv-SE",
+    description = "Se hur annonsbilder måste se ut på den här servern"
+  ),
+  name_localized(locale = "en-US", name = "get_ad_limits"),
+  name_localized(locale = "sv-SE", name = "hämta_annonsgränser"),
+  category = "Review",
+  required_permissions = "MANAGE_CHANNELS | BAN_MEMBERS | KICK_MEMBERS | MUTE_MEMBERS"
+)]
+pub async fn get_ad_limits(ctx: CollarAppContext<'_>) -> Result<(), CollarError> {
+  let guild_id = match ctx.guild_id() {
+    Some(guild_id) => guild_id,
+    None => return send_generic_error_application(ctx, "Ad limits are per server").await,
+  };
+
+  let limits = ctx.data().cache.read().get_ad_image_limits(Some(guild_id));
+
+  let embed = limits_embed(
+    EmbedWrapper::new_application(&ctx).title("Ad limits for this server"),
+    &limits,
+  )
+  .color(Color::from_rgb(0, 0, 255));
+
+  let reply = CreateReply::default()
+    .embed(embed)
+    .reply(true)
+    .ephemeral(true);
+
+  ctx.send(reply).await?;
+  Ok(())
+}
@@ -0,0 +1,154 @@
+use crate::collar::{
+  EmbedWrapper,
+  audit::{self as audit_log, AuditAction, AuditEntry},
+  commands::{format_timestamp, send_generic_error_application},
+};
+
+use super::{AuditActionType, CollarAppContext, CollarError, ExportFormat, notifs::SubmitType};
+use chrono::{NaiveDate, NaiveTime};
+use poise::{CreateReply, command, serenity_prelude as serenity};
+use serenity::{Color, CreateAttachment, FormattedTimestampStyle, Mentionable};
+
+/// How many entries fit in the embed, exports always get everything.
+const AUDIT_EMBED_LIMIT: usize = 10;
+
+fn parse_date(date: &str) -> Option<NaiveDate> {
+  NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d").ok()
+}
+
+fn describe_entry(entry: &AuditEntry) -> String {
+  let submission = match entry.submit_type {
+    SubmitType::User => "website",
+    SubmitType::Ad => "ad",
+  };
+
+  let mut line = format!(
+    "{} **{}** {submission} of {} by {}",
+    format_timestamp(entry.at, FormattedTimestampStyle::ShortDateTime),
+    entry.action,
+    entry.target.mention(),
+    entry.moderator.mention()
+  );
+
+  if entry.approvers.len() > 1 {
+    let approvers = entry
+      .approvers
+      .iter()
+      .map(|approver| approver.mention().to_string())
+      .collect::<Vec<_>>()
+      .join(", ");
+    line.push_str(&format!(", approved by {approvers}"));
+  }
+
+  // Keeps ten entries under the embed description limit, exports have the full reason.
+  if let Some(reason) = &entry.reason {
+    let reason = reason.chars().take(200).collect::<String>();
+    line.push_str(&format!(": {reason}"));
+  }
+
+  line
+}
+
+#[command(
+  slash_command,
+  description_localized(
+    locale = "en-US",
+    description = "Look through verify, reject and remove actions taken by moderators"
+  ),
+  description_localized(
+    locale = "sv-SE",
+    description = "Bläddra bland verifieringar, avvisningar och borttagningar av moderatorer"
+  ),
+  name_localized(locale = "en-US", name = "audit"),
+  name_localized(locale = "sv-SE", name = "granskningslogg"),
+  category = "Review",
+  required_permissions = "MANAGE_CHANNELS | BAN_MEMBERS | KICK_MEMBERS | MUTE_MEMBERS"
+)]
+pub async fn audit(
+  ctx: CollarAppContext<'_>,
+  #[description = "User the action was taken on"] user: Option<serenity::User>,
+  #[description = "Moderator that took the action"] moderator: Option<serenity::User>,
+  #[description = "Kind of action"] action: Option<AuditActionType>,
+  #[description = "Earliest day to include, as YYYY-MM-DD"] from: Option<String>,
+  #[description = "Last day to include, as YYYY-MM-DD"] to: Option<String>,
+  #[description = "Attach every matching entry as a file"] export: Option<ExportFormat>,
+) -> Result<(), CollarError> {
+  let guild_id = match ctx.guild_id() {
+    Some(guild_id) => guild_id,
+    None => return send_generic_error_application(ctx, "The audit log is per server").await,
+  };
+
+  let from = match from.as_deref().map(parse_date) {
+    Some(None) => {
+      return send_generic_error_application(ctx, "`from` should look like 2025-01-31").await;
+    }
+    Some(Some(date)) => Some(date.and_time(NaiveTime::MIN).and_utc()),
+    None => None,
+  };
+  let to = match to.as_deref().map(parse_date) {
+    Some(None) => {
+      return send_generic_error_application(ctx, "`to` should look like 2025-01-31").await;
+    }
+    Some(Some(date)) => date
+      .succ_opt()
+      .map(|next_day| next_day.and_time(NaiveTime::MIN).and_utc()),
+    None => None,
+  };
+  let action = action.map(|action| match action {
+    AuditActionType::Verify => AuditAction::Verify,
+    AuditActionType::Reject => AuditAction::Reject,
+    AuditActionType::Remove => AuditAction::Remove,
+    AuditActionType::Veto => AuditAction::Veto,
+  });
+
+  let log = ctx.data().audit_log.clone();
+  let entries = tokio::task::spawn_blocking(move || log.read(guild_id)).await??;
+  let entries = entries
+    .into_iter()
+    .filter(|entry| user.as_ref().is_none_or(|user| entry.target == user.id))
+    .filter(|entry| {
+      moderator
+        .as_ref()
+        .is_none_or(|moderator| entry.moderator == moderator.id)
+    })
+    .filter(|entry| action.is_none_or(|action| entry.action == action))
+    .filter(|entry| from.is_none_or(|from| entry.at >= from))
+    .filter(|entry| to.is_none_or(|to| entry.at < to))
+    .collect::<Vec<_>>();
+
+  let description = match entries.is_empty() {
+    true => String::from("Nothing matched those filters"),
+    false => entries
+      .iter()
+      .rev()
+      .take(AUDIT_EMBED_LIMIT)
+      .map(describe_entry)
+      .collect::<Vec<_>>()
+      .join("\n"),
+  };
+
+  let embed = EmbedWrapper::new_application(&ctx)
+    .title(format!("Audit log ({} entries)", entries.len()))
+    .description(description)
+    .color(Color::from_rgb(0, 0, 255));
+
+  let mut reply = CreateReply::default()
+    .embed(embed)
+    .reply(true)
+    .ephemeral(true);
+
+  match export {
+    Some(ExportFormat::Csv) => {
+      let csv = audit_log::to_csv(&entries);
+      reply = reply.attachment(CreateAttachment::bytes(csv.into_bytes(), "audit.csv"));
+    }
+    Some(ExportFormat::Json) => {
+      let json = serde_json::to_vec_pretty(&entries)?;
+      reply = reply.attachment(CreateAttachment::bytes(json, "audit.json"));
+    }
+    None => (),
+  }
+
+  ctx.send(reply).await?;
+  Ok(())
+}
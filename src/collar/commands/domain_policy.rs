@@ -0,0 +1,196 @@
+use crate::collar::url_policy::MAX_DOMAIN_RULES;
+
+use super::{
+  CollarAppContext, CollarError, EmbedWrapper, PolicyListType, send_generic_error_application,
+};
+use poise::{ChoiceParameter, CreateReply, command, serenity_prelude as serenity};
+use serenity::Color;
+use tracing::info;
+
+async fn autocomplete_rule(ctx: CollarAppContext<'_>, partial: &str) -> Vec<String> {
+  let cache = ctx.data().cache.read();
+  let policy = cache.get_domain_policy(ctx.guild_id());
+
+  policy
+    .allow
+    .into_iter()
+    .chain(policy.deny)
+    .filter(|rule| rule.starts_with(&partial.to_lowercase()))
+    .take(25)
+    .collect()
+}
+
+#[command(
+  slash_command,
+  subcommands("add", "remove", "list"),
+  subcommand_required,
+  description_localized(
+    locale = "en-US",
+    description = "Manage which website domains and urls this server accepts"
+  ),
+  description_localized(
+    locale = "sv-SE",
+    description = "Hantera vilka domäner och adresser servern tar emot"
+  ),
+  name_localized(locale = "en-US", name = "domain_policy"),
+  name_localized(locale = "sv-SE", name = "domänpolicy"),
+  category = "Review",
+  required_permissions = "MANAGE_GUILD"
+)]
+pub async fn domain_policy(_ctx: CollarAppContext<'_>) -> Result<(), CollarError> {
+  Ok(())
+}
+
+#[command(
+  slash_command,
+  description_localized(
+    locale = "en-US",
+    description = "Add a domain like example.com, or a pattern like example.com/~*"
+  ),
+  description_localized(
+    locale = "sv-SE",
+    description = "Lägg till en domän som example.com, eller ett mönster som example.com/~*"
+  ),
+  required_permissions = "MANAGE_GUILD"
+)]
+pub async fn add(
+  ctx: CollarAppContext<'_>,
+  #[description = "Which list to add it to"] list: PolicyListType,
+  #[description = "Domain, or url pattern with * wildcards"]
+  #[max_length = 200]
+  rule: String,
+) -> Result<(), CollarError> {
+  let guild_id = match ctx.guild_id() {
+    Some(guild_id) => guild_id,
+    None => return send_generic_error_application(ctx, "Domain policies are per server").await,
+  };
+
+  let rule = rule
+    .trim()
+    .trim_start_matches("https://")
+    .trim_start_matches("http://")
+    .to_lowercase();
+  if rule.is_empty() {
+    return send_generic_error_application(ctx, "The rule can't be empty").await;
+  }
+
+  let problem = {
+    let mut cache = ctx.data().cache.write();
+    let rules = cache.domain_policy_mut(guild_id).list_mut(list.into());
+
+    if rules.contains(&rule) {
+      Some(format!("`{rule}` is already on that list"))
+    } else if rules.len() >= MAX_DOMAIN_RULES {
+      Some(format!(
+        "A list can have at most {MAX_DOMAIN_RULES} rules, remove one first"
+      ))
+    } else {
+      info!("Adding domain rule {rule} for guild {guild_id}");
+      rules.push(rule.clone());
+      None
+    }
+  };
+
+  if let Some(problem) = problem {
+    return send_generic_error_application(ctx, &problem).await;
+  }
+
+  let embed = EmbedWrapper::new_application(&ctx)
+    .title("Domain rule added!")
+    .description(format!("`{rule}` is on the {} list now", list.name()))
+    .color(Color::from_rgb(0, 255, 0));
+
+  let reply = CreateReply::default()
+    .embed(embed)
+    .reply(true)
+    .ephemeral(true);
+
+  ctx.send(reply).await?;
+  Ok(())
+}
+
+#[command(
+  slash_command,
+  description_localized(locale = "en-US", description = "Remove a domain rule"),
+  description_localized(locale = "sv-SE", description = "Ta bort en domänregel"),
+  required_permissions = "MANAGE_GUILD"
+)]
+pub async fn remove(
+  ctx: CollarAppContext<'_>,
+  #[description = "The rule to remove, from either list"]
+  #[autocomplete = "autocomplete_rule"]
+  rule: String,
+) -> Result<(), CollarError> {
+  let guild_id = match ctx.guild_id() {
+    Some(guild_id) => guild_id,
+    None => return send_generic_error_application(ctx, "Domain policies are per server").await,
+  };
+
+  let rule = rule.trim().to_lowercase();
+  let removed = {
+    let mut cache = ctx.data().cache.write();
+    let policy = cache.domain_policy_mut(guild_id);
+    let before = policy.allow.len() + policy.deny.len();
+    policy.allow.retain(|existing| *existing != rule);
+    policy.deny.retain(|existing| *existing != rule);
+    policy.allow.len() + policy.deny.len() < before
+  };
+
+  if !removed {
+    return send_generic_error_application(ctx, &format!("There's no rule `{rule}`")).await;
+  }
+
+  let embed = EmbedWrapper::new_application(&ctx)
+    .title("Domain rule removed!")
+    .description(format!("`{rule}` doesn't apply anymore"))
+    .color(Color::from_rgb(0, 255, 0));
+
+  let reply = CreateReply::default()
+    .embed(embed)
+    .reply(true)
+    .ephemeral(true);
+
+  ctx.send(reply).await?;
+  Ok(())
+}
+
+#[command(
+  slash_command,
+  description_localized(locale = "en-US", description = "List the domain rules"),
+  description_localized(locale = "sv-SE", description = "Lista domänreglerna"),
+  required_permissions = "MANAGE_GUILD"
+)]
+pub async fn list(ctx: CollarAppContext<'_>) -> Result<(), CollarError> {
+  let policy = ctx.data().cache.read().get_domain_policy(ctx.guild_id());
+
+  let format_rules = |rules: &[String], empty: &str| match rules.is_empty() {
+    true => String::from(empty),
+    false => {
+      let rules = rules
+        .iter()
+        .map(|rule| format!("`{rule}`"))
+        .collect::<Vec<_>>()
+        .join(", ");
+      // Fifty rules of up to 200 characters don't always fit in a field.
+      rules.chars().take(1024).collect()
+    }
+  };
+
+  let embed = EmbedWrapper::new_application(&ctx)
+    .title("Domain policy for this server")
+    .field(
+      "Allow",
+      format_rules(&policy.allow, "Empty, every website can get in"),
+      false,
+    )
+    .field("Deny", format_rules(&policy.deny, "Empty"), false)
+    .color(Color::from_rgb(0, 0, 255));
+
+  let reply = CreateReply::default()
+    .embed(embed)
+    .reply(true)
+    .ephemeral(true);
+
+  ctx.send(reply).await?;
+  Ok(())
+}
@@ -0,0 +1,142 @@
+use super::{CollarContext, CollarError, EmbedWrapper, send_generic_error_normal};
+use poise::{CreateReply, command, serenity_prelude as serenity};
+use serenity::Color;
+use tracing::info;
+
+async fn autocomplete_instance(ctx: CollarContext<'_>, partial: &str) -> Vec<String> {
+  let cache = ctx.data().cache.read();
+
+  cache
+    .get_instance_names()
+    .into_iter()
+    .filter(|name| name.starts_with(&partial.to_lowercase()))
+    .collect()
+}
+
+#[command(
+  slash_command,
+  description_localized(
+    locale = "en-US",
+    description = "Set which PetRing instance this server uses"
+  ),
+  description_localized(
+    locale = "sv-SE",
+    description = "Ange vilken PetRing-instans den här servern använder"
+  ),
+  name_localized(locale = "en-US", name = "set_instance"),
+  name_localized(locale = "sv-SE", name = "ställ_in_instans"),
+  category = "Instances",
+  required_permissions = "MANAGE_GUILD"
+)]
+pub async fn set_instance(
+  ctx: CollarContext<'_>,
+  #[description = "Name of the PetRing instance"]
+  #[autocomplete = "autocomplete_instance"]
+  instance: String,
+) -> Result<(), CollarError> {
+  let data = ctx.data();
+
+  let guild_id = match ctx.guild_id() {
+    Some(guild_id) => guild_id,
+    None => return send_generic_error_normal(ctx, "PetRing instances are set per server").await,
+  };
+
+  let instance = instance.trim().to_lowercase();
+  let found = {
+    let mut cache = data.cache.write();
+    match cache.get_instance(&instance) {
+      Some(found) => {
+        let web_base_url = found.get_web_base_url();
+
+        info!("Binding guild {guild_id} to instance {instance}");
+        cache.set_guild_instance(guild_id, instance.clone());
+        Ok(web_base_url)
+      }
+      None => Err(cache.get_instance_names().join(", ")),
+    }
+  };
+
+  let web_base_url = match found {
+    Ok(web_base_url) => web_base_url,
+    Err(available) => {
+      return send_generic_error_normal(
+        ctx,
+        &format!("There's no instance named {instance}, pick one of: {available}"),
+      )
+      .await;
+    }
+  };
+
+  let embed = EmbedWrapper::new_normal(&ctx)
+    .title("PetRing instance set!")
+    .description(format!(
+      "This server now uses **{instance}** ({web_base_url})"
+    ))
+    .color(Color::from_rgb(0, 255, 0));
+
+  let reply = CreateReply::default()
+    .embed(embed)
+    .reply(true)
+    .ephemeral(true);
+
+  ctx.send(reply).await?;
+
+  Ok(())
+}
+
+#[command(
+  slash_command,
+  description_localized(
+    locale = "en-US",
+    description = "Get which PetRing instance this server uses"
+  ),
+  description_localized(
+    locale = "sv-SE",
+    description = "Hämta vilken PetRing-instans den här servern använder"
+  ),
+  name_localized(locale = "en-US", name = "get_instance"),
+  name_localized(locale = "sv-SE", name = "hämta_instans"),
+  category = "Instances"
+)]
+pub async fn get_instance(ctx: CollarContext<'_>) -> Result<(), CollarError> {
+  let (current, available) = {
+    let cache = ctx.data().cache.read();
+
+    let current = match cache.get_guild_instance_name(ctx.guild_id()) {
+      Some(current) => current,
+      None => format!(
+        "{} (not configured, commands fail until you pick another one)",
+        ctx
+          .guild_id()
+          .and_then(|guild_id| cache.get_guild_binding(guild_id).cloned())
+          .unwrap_or_default()
+      ),
+    };
+    let available = cache
+      .get_instance_names()
+      .into_iter()
+      .filter_map(|name| {
+        cache
+          .get_instance(&name)
+          .map(|instance| format!("**{name}**: {}", instance.get_web_base_url()))
+      })
+      .collect::<Vec<_>>()
+      .join("\n");
+    (current, available)
+  };
+
+  let embed = EmbedWrapper::new_normal(&ctx)
+    .title("PetRing instance for this server")
+    .field("Current", current, true)
+    .field("Available", available, false)
+    .color(Color::from_rgb(0, 0, 255));
+
+  let reply = CreateReply::default()
+    .embed(embed)
+    .reply(true)
+    .ephemeral(true);
+
+  ctx.send(reply).await?;
+
+  Ok(())
+}
@@ -1,21 +1,21 @@
+use crate::collar::supervisor::Availability;
+
 use super::{
   COLLAR_FOOTER, CollarAppContext, CollarContext, CollarError, EmbedWrapper, FeedbackSubmission,
   FeedbackTopicType, WebhookEmbed, WebhookEmbedAuthor, WebhookEmbedFooter, WebhookEmbedThumbnail,
-  WebhookPost,
+  WebhookPost, format_timestamp, send_petring_error_normal,
 };
 use poise::{
   CreateReply, Modal, command, samples::HelpConfiguration, serenity_prelude as serenity,
 };
 use reqwest::Method;
-use serenity::Color;
-use std::io::Write;
+use serenity::{Color, FormattedTimestampStyle};
 use tokio::time::Instant;
 
 async fn measure_api_latency(ctx: CollarContext<'_>) -> Result<(u128, u128), reqwest::Error> {
   let total_start = Instant::now();
   let http_client = ctx.data().http_client.clone();
-  let cache = ctx.data().cache.lock().await;
-  let url = cache.urls.api_base_url.clone();
+  let url = ctx.data().cache.read().get_api_base_url(ctx.guild_id());
 
   let res = http_client.get(url).send().await?;
 
@@ -29,8 +29,7 @@ async fn measure_api_latency(ctx: CollarContext<'_>) -> Result<(u128, u128), req
 
 async fn measure_web_latency(ctx: CollarContext<'_>) -> Result<(u128, u128), reqwest::Error> {
   let http_client = ctx.data().http_client.clone();
-  let cache = ctx.data().cache.lock().await;
-  let web_base_url = cache.urls.web_base_url.clone();
+  let web_base_url = ctx.data().cache.read().get_web_base_url(ctx.guild_id());
 
   let total_start = Instant::now();
   let res = http_client.get(web_base_url).send().await?;
@@ -85,6 +84,103 @@ pub async fn ping(ctx: CollarContext<'_>) -> Result<(), CollarError> {
   Ok(())
 }
 
+/// Routes listed in `/api_stats`, the rest are summed up in one line.
+const API_STATS_ROUTES: usize = 15;
+
+#[command(
+  slash_command,
+  description_localized(
+    locale = "en-US",
+    description = "See how requests to the PetRing API have been doing since startup"
+  ),
+  description_localized(
+    locale = "sv-SE",
+    description = "Se hur anrop till PetRing API:et har gått sedan start"
+  ),
+  name_localized(locale = "en-US", name = "api_stats"),
+  name_localized(locale = "sv-SE", name = "api_statistik"),
+  category = "Miscellaneous",
+  required_permissions = "MANAGE_GUILD"
+)]
+pub async fn api_stats(ctx: CollarContext<'_>) -> Result<(), CollarError> {
+  let data = ctx.data();
+  let instance = data
+    .petring(ctx.guild_id())
+    .target
+    .resolve(&data.cache.read());
+  let instance = match instance {
+    Ok(instance) => instance,
+    Err(err) => return send_petring_error_normal(ctx, &err).await,
+  };
+  let routes = data.metrics.snapshot(&instance);
+
+  let (status, color) = match data.api_status.get(&instance) {
+    Availability::Up => ("Up".to_string(), Color::from_rgb(0, 255, 0)),
+    Availability::Down { since, error } => (
+      format!(
+        "Failing fast since {}: {error}",
+        format_timestamp(since, FormattedTimestampStyle::ShortDateTime)
+      ),
+      Color::from_rgb(255, 0, 0),
+    ),
+  };
+
+  let mut lines = routes
+    .iter()
+    .take(API_STATS_ROUTES)
+    .map(|(route, stats)| {
+      let mut line = format!(
+        "`{route}` {} requests, {} errors, {} retries, avg {}ms, max {}ms",
+        stats.requests,
+        stats.errors,
+        stats.retries,
+        stats.average_latency().as_millis(),
+        stats.max_latency.as_millis()
+      );
+      if let Some(error) = &stats.last_error {
+        let error = error.chars().take(100).collect::<String>();
+        line.push_str(&format!("\n-# last error: {error}"));
+      }
+      line
+    })
+    .collect::<Vec<_>>();
+  if routes.len() > API_STATS_ROUTES {
+    lines.push(format!(
+      "...and {} more routes",
+      routes.len() - API_STATS_ROUTES
+    ));
+  }
+  if lines.is_empty() {
+    lines.push("No requests yet".to_string());
+  }
+
+  let queued = {
+    let cache = data.cache.read();
+    cache
+      .get_queued_submissions()
+      .iter()
+      .filter(|queued| {
+        cache
+          .get_guild_instance_name(Some(queued.guild_id))
+          .as_deref()
+          == Some(instance.as_str())
+      })
+      .count()
+  };
+
+  let embed = EmbedWrapper::new_normal(&ctx)
+    .title(format!("PetRing API stats for {instance}"))
+    .field("Circuit breaker", status, false)
+    .field("Queued submissions", queued.to_string(), true)
+    .description(lines.join("\n"))
+    .color(color);
+
+  ctx
+    .send(CreateReply::default().reply(true).embed(embed))
+    .await?;
+  Ok(())
+}
+
 #[poise::command(slash_command, category = "Miscellaneous")]
 pub async fn help(
   ctx: CollarContext<'_>,
@@ -113,21 +209,11 @@ pub async fn set_feedback_webhook(
   ctx: CollarAppContext<'_>,
   #[description = "Webhook to send feedback to"] webhook: String,
 ) -> Result<(), CollarError> {
-  let mut cache = ctx.data().cache.lock().await;
-  cache.set_feedback_webhook(webhook.clone());
-
-  let mut file_to_write = match std::fs::File::create(".feedback_webhook") {
-    Ok(file) => file,
-    Err(err) => {
-      return Err(CollarError::from(format!(
-        "Failed to create .feedback_webhook: {err}"
-      )));
-    }
-  };
-
-  if file_to_write.write_all(webhook.as_bytes()).is_err() {
-    return Err(CollarError::from("Failed to cache to .feedback_webhook"));
-  }
+  ctx
+    .data()
+    .cache
+    .write()
+    .set_feedback_webhook(webhook.clone());
 
   let embed = EmbedWrapper::new_application(&ctx)
     .title("Feedback webhook set!")
@@ -149,9 +235,8 @@ pub async fn feedback(
   #[description = "Topic to send feedback about"] topic: FeedbackTopicType,
 ) -> Result<(), CollarError> {
   let data = ctx.data();
-  let cache = data.cache.lock().await;
   let http_client = data.http_client.clone();
-  let feedback_webhook = cache.get_feedback_webhook();
+  let feedback_webhook = data.cache.read().get_feedback_webhook();
   let webhook = match feedback_webhook.clone() {
     Some(webhook) => webhook,
     None => {
@@ -1,4 +1,6 @@
-use super::{CollarContext, CollarError, EmbedWrapper, NotifChannelType, NotifType};
+use super::{
+  CollarContext, CollarError, EmbedWrapper, NotifChannelType, NotifType, send_generic_error_normal,
+};
 use poise::{CreateReply, command, serenity_prelude as serenity};
 use serenity::Color;
 use tracing::{error, info};
@@ -24,11 +26,19 @@ pub async fn set_notif_channel(
   channel_type: NotifType,
 ) -> Result<(), CollarError> {
   let data = ctx.data();
-  let mut cache = data.cache.lock().await;
 
-  match channel.clone().guild() {
-    Some(_) => {}
-    None => {
+  let guild_id = match (ctx.guild_id(), channel.clone().guild()) {
+    (Some(guild_id), Some(guild_channel)) if guild_channel.guild_id == guild_id => guild_id,
+    (Some(_), Some(_)) => {
+      let embed = EmbedWrapper::new_normal(&ctx)
+        .title("That channel isn't in this server :3")
+        .description("Pick a channel from the server you're setting notifications up for :3")
+        .color(Color::from_rgb(255, 0, 0));
+      let reply = CreateReply::default().embed(embed).reply(true);
+      ctx.send(reply).await?;
+      return Ok(());
+    }
+    _ => {
       let embed = EmbedWrapper::new_normal(&ctx)
         .title("You can't set a notification channel in a DM :3")
         .description("You need to be in a server to set a notification channel :3")
@@ -37,7 +47,7 @@ pub async fn set_notif_channel(
       ctx.send(reply).await?;
       return Ok(());
     }
-  }
+  };
 
   let channel_type_to_set = match channel_type {
     NotifType::UserSubmit => NotifChannelType::UserSubmit,
@@ -46,14 +56,19 @@ pub async fn set_notif_channel(
     NotifType::AdVerify => NotifChannelType::AdVerify,
     NotifType::General => NotifChannelType::General,
     NotifType::DmFallback => NotifChannelType::DmFallback,
+    NotifType::Admin => NotifChannelType::Admin,
   };
 
   info!(
-    "Setting notif_channel_id.{} to {}",
+    "Setting {} to {} for guild {}",
     channel_type_to_set,
-    channel.id()
+    channel.id(),
+    guild_id
   );
-  cache.set_notif_channel(channel.id().into(), channel_type_to_set);
+  data
+    .cache
+    .write()
+    .set_notif_channel(guild_id, channel.id().into(), channel_type_to_set);
 
   let channel_type_str = match channel_type {
     NotifType::UserSubmit => "User Submit",
@@ -62,6 +77,7 @@ pub async fn set_notif_channel(
     NotifType::AdVerify => "Ad Verify",
     NotifType::General => "General",
     NotifType::DmFallback => "DM Fallback",
+    NotifType::Admin => "Admin",
   };
 
   let channel_type_desc = match channel_type {
@@ -71,6 +87,7 @@ pub async fn set_notif_channel(
     NotifType::AdVerify => "when someone's ad gets verified",
     NotifType::General => "when someone deletes, edits a website or ad",
     NotifType::DmFallback => "when the I fail to dm a user",
+    NotifType::Admin => "when a notification fails to deliver",
   };
 
   let embed = EmbedWrapper::new_normal(&ctx)
@@ -108,7 +125,12 @@ pub async fn get_notif_channel(
   channel_type: NotifType,
 ) -> Result<(), CollarError> {
   let data = ctx.data();
-  let cache = data.cache.lock().await;
+
+  let guild_id = match ctx.guild_id() {
+    Some(guild_id) => guild_id,
+    None => return send_generic_error_normal(ctx, "Notification channels are per server").await,
+  };
+
   let cache_channel_type = match channel_type {
     NotifType::UserSubmit => NotifChannelType::UserSubmit,
     NotifType::AdSubmit => NotifChannelType::AdSubmit,
@@ -116,9 +138,13 @@ pub async fn get_notif_channel(
     NotifType::AdVerify => NotifChannelType::AdVerify,
     NotifType::General => NotifChannelType::General,
     NotifType::DmFallback => NotifChannelType::DmFallback,
+    NotifType::Admin => NotifChannelType::Admin,
   };
 
-  let channel_id = cache.get_notif_channel(cache_channel_type);
+  let channel_id = data
+    .cache
+    .read()
+    .get_notif_channel(guild_id, cache_channel_type);
 
   let channel_id = match channel_id {
     Some(channel_id) => channel_id,
@@ -142,6 +168,7 @@ pub async fn get_notif_channel(
     NotifType::AdVerify => "Ad Verify",
     NotifType::General => "General",
     NotifType::DmFallback => "DM Fallback",
+    NotifType::Admin => "Admin",
   };
 
   let channel_type_desc = match channel_type {
@@ -151,6 +178,7 @@ pub async fn get_notif_channel(
     NotifType::AdVerify => "when someone's ad gets verified",
     NotifType::General => "when someone deletes, edits a website or ad",
     NotifType::DmFallback => "when the I fail to dm a user",
+    NotifType::Admin => "when a notification fails to deliver",
   };
 
   let embed = EmbedWrapper::new_normal(&ctx)
@@ -184,17 +212,30 @@ pub async fn get_notif_channel(
 )]
 pub async fn get_all_notif_channels(ctx: CollarContext<'_>) -> Result<(), CollarError> {
   let data = ctx.data();
-  let cache = data.cache.lock().await;
 
-  let all_notif_channel_ids = cache.get_all_notif_channels();
+  let guild_id = match ctx.guild_id() {
+    Some(guild_id) => guild_id,
+    None => return send_generic_error_normal(ctx, "Notification channels are per server").await,
+  };
 
-  let (is_user_submit, is_ad_submit, is_user_verify, is_ad_verify, is_general, is_dm_fallback) = (
+  let all_notif_channel_ids = data.cache.read().get_all_notif_channels(guild_id);
+
+  let (
+    is_user_submit,
+    is_ad_submit,
+    is_user_verify,
+    is_ad_verify,
+    is_general,
+    is_dm_fallback,
+    is_admin,
+  ) = (
     all_notif_channel_ids.user_submit_id.is_some(),
     all_notif_channel_ids.ad_submit_id.is_some(),
     all_notif_channel_ids.user_verify_id.is_some(),
     all_notif_channel_ids.ad_verify_id.is_some(),
     all_notif_channel_ids.general_id.is_some(),
     all_notif_channel_ids.dm_fallback_id.is_some(),
+    all_notif_channel_ids.admin_id.is_some(),
   );
 
   let user_submit = if is_user_submit {
@@ -275,14 +316,28 @@ pub async fn get_all_notif_channels(ctx: CollarContext<'_>) -> Result<(), Collar
     String::from("Unset! Set it using `/set_notification_channel`")
   };
 
+  let admin = if is_admin {
+    match ctx
+      .http()
+      .get_channel(all_notif_channel_ids.admin_id.unwrap().into())
+      .await
+    {
+      Ok(channel) => channel.to_string(),
+      Err(_) => String::from("Invalid channel id, did a channel get deleted?"),
+    }
+  } else {
+    String::from("Unset! Set it using `/set_notification_channel`")
+  };
+
   let embed = EmbedWrapper::new_normal(&ctx)
-    .title("Notification channels")
+    .title("Notification channels for this server")
     .field("User Submit", user_submit, true)
     .field("Ad Submit", ad_submit, true)
     .field("User Verify", user_verify, true)
     .field("Ad Verify", ad_verify, true)
     .field("General", general, true)
-    .field("DM Fallback", dm_fallback, true);
+    .field("DM Fallback", dm_fallback, true)
+    .field("Admin", admin, true);
 
   let reply = CreateReply::default()
     .embed(embed)
@@ -0,0 +1,246 @@
+use crate::collar::notif_outbox::{self, MAX_ATTEMPTS};
+
+use super::{
+  CollarAppContext, CollarError, EmbedWrapper, FormattedTimestampStyle, format_timestamp,
+  send_generic_error_application,
+};
+use poise::{CreateReply, command, serenity_prelude::Color};
+use tracing::info;
+
+/// Deliveries listed at once, the embed runs out of room after that.
+const LIST_LIMIT: usize = 15;
+
+#[command(
+  slash_command,
+  subcommands("list", "replay", "discard"),
+  subcommand_required,
+  description_localized(
+    locale = "en-US",
+    description = "Look into notifications collar couldn't deliver"
+  ),
+  description_localized(
+    locale = "sv-SE",
+    description = "Se över notifieringar collar inte kunde leverera"
+  ),
+  name_localized(locale = "en-US", name = "notification_outbox"),
+  name_localized(locale = "sv-SE", name = "notifieringskö"),
+  category = "Notifications",
+  required_permissions = "MANAGE_GUILD"
+)]
+pub async fn notification_outbox(_ctx: CollarAppContext<'_>) -> Result<(), CollarError> {
+  Ok(())
+}
+
+#[command(
+  slash_command,
+  description_localized(
+    locale = "en-US",
+    description = "List notifications that are being retried or gave up"
+  ),
+  description_localized(
+    locale = "sv-SE",
+    description = "Lista notifieringar som försöks igen eller gav upp"
+  ),
+  required_permissions = "MANAGE_GUILD"
+)]
+pub async fn list(ctx: CollarAppContext<'_>) -> Result<(), CollarError> {
+  let guild_id = match ctx.guild_id() {
+    Some(guild_id) => guild_id,
+    None => return send_generic_error_application(ctx, "The outbox is per server").await,
+  };
+
+  let deliveries = ctx
+    .data()
+    .cache
+    .read()
+    .get_deliveries()
+    .iter()
+    .filter(|delivery| delivery.guild_id == guild_id)
+    .cloned()
+    .collect::<Vec<_>>();
+
+  let mut lines = deliveries
+    .iter()
+    .take(LIST_LIMIT)
+    .map(|delivery| {
+      let state = match delivery.dead_at {
+        Some(dead_at) => format!(
+          "**gave up** {}",
+          format_timestamp(dead_at, FormattedTimestampStyle::RelativeTime)
+        ),
+        None => format!(
+          "attempt {}/{MAX_ATTEMPTS}, next {}",
+          delivery.attempts,
+          format_timestamp(
+            delivery.next_attempt_at,
+            FormattedTimestampStyle::RelativeTime
+          )
+        ),
+      };
+      let error = delivery.last_error.chars().take(100).collect::<String>();
+      format!(
+        "`{}` {} to {}, {state}\n-# {error}",
+        delivery.id,
+        delivery.title(),
+        delivery.channel_type
+      )
+    })
+    .collect::<Vec<_>>();
+  if deliveries.len() > LIST_LIMIT {
+    lines.push(format!("...and {} more", deliveries.len() - LIST_LIMIT));
+  }
+
+  let embed = match lines.is_empty() {
+    true => EmbedWrapper::new_application(&ctx)
+      .title("Nothing in the outbox :3")
+      .description("Every notification went through")
+      .color(Color::from_rgb(0, 255, 0)),
+    false => EmbedWrapper::new_application(&ctx)
+      .title("Undelivered notifications")
+      .description(lines.join("\n"))
+      .color(Color::from_rgb(0, 0, 255)),
+  };
+
+  let reply = CreateReply::default()
+    .embed(embed)
+    .reply(true)
+    .ephemeral(true);
+
+  ctx.send(reply).await?;
+  Ok(())
+}
+
+#[command(
+  slash_command,
+  description_localized(
+    locale = "en-US",
+    description = "Try delivering a notification again, or every one that gave up"
+  ),
+  description_localized(
+    locale = "sv-SE",
+    description = "Försök leverera en notifiering igen, eller alla som gav upp"
+  ),
+  required_permissions = "MANAGE_GUILD"
+)]
+pub async fn replay(
+  ctx: CollarAppContext<'_>,
+  #[description = "Id from `/notification_outbox list`, leave out for every one that gave up"]
+  id: Option<u64>,
+) -> Result<(), CollarError> {
+  let guild_id = match ctx.guild_id() {
+    Some(guild_id) => guild_id,
+    None => return send_generic_error_application(ctx, "The outbox is per server").await,
+  };
+
+  // Replayed deliveries get a fresh set of attempts.
+  let ids = {
+    let mut cache = ctx.data().cache.write();
+    let ids = cache
+      .get_deliveries()
+      .iter()
+      .filter(|delivery| delivery.guild_id == guild_id)
+      .filter(|delivery| match id {
+        Some(id) => delivery.id == id,
+        None => delivery.dead_at.is_some(),
+      })
+      .map(|delivery| delivery.id)
+      .collect::<Vec<_>>();
+    for id in &ids {
+      if let Some(delivery) = cache.get_delivery_mut(*id) {
+        delivery.attempts = 0;
+        delivery.dead_at = None;
+      }
+    }
+    ids
+  };
+
+  if ids.is_empty() {
+    let problem = match id {
+      Some(id) => format!("There's no notification `{id}` in this server's outbox"),
+      None => String::from("No notification in this server's outbox gave up"),
+    };
+    return send_generic_error_application(ctx, &problem).await;
+  }
+
+  info!("Replaying notifications {ids:?} for guild {guild_id}");
+  let mut delivered = 0;
+  for id in &ids {
+    if notif_outbox::attempt(ctx.http(), ctx.data(), *id).await {
+      delivered += 1;
+    }
+  }
+
+  let embed = EmbedWrapper::new_application(&ctx)
+    .title(match delivered == ids.len() {
+      true => "Delivered :3",
+      false => "Still failing 3:",
+    })
+    .description(format!(
+      "{delivered} of {} notification(s) went through, the rest keep retrying",
+      ids.len()
+    ))
+    .color(match delivered == ids.len() {
+      true => Color::from_rgb(0, 255, 0),
+      false => Color::from_rgb(255, 0, 0),
+    });
+
+  let reply = CreateReply::default()
+    .embed(embed)
+    .reply(true)
+    .ephemeral(true);
+
+  ctx.send(reply).await?;
+  Ok(())
+}
+
+#[command(
+  slash_command,
+  description_localized(
+    locale = "en-US",
+    description = "Drop a notification from the outbox without delivering it"
+  ),
+  description_localized(
+    locale = "sv-SE",
+    description = "Släng en notifiering från kön utan att leverera den"
+  ),
+  required_permissions = "MANAGE_GUILD"
+)]
+pub async fn discard(
+  ctx: CollarAppContext<'_>,
+  #[description = "Id from `/notification_outbox list`"] id: u64,
+) -> Result<(), CollarError> {
+  let guild_id = match ctx.guild_id() {
+    Some(guild_id) => guild_id,
+    None => return send_generic_error_application(ctx, "The outbox is per server").await,
+  };
+
+  let removed = {
+    let mut cache = ctx.data().cache.write();
+    match cache.get_delivery(id) {
+      Some(delivery) if delivery.guild_id == guild_id => cache.remove_delivery(id),
+      _ => None,
+    }
+  };
+
+  let delivery = match removed {
+    Some(delivery) => delivery,
+    None => {
+      let problem = format!("There's no notification `{id}` in this server's outbox");
+      return send_generic_error_application(ctx, &problem).await;
+    }
+  };
+
+  info!("Discarded notification {id} for guild {guild_id}");
+  let embed = EmbedWrapper::new_application(&ctx)
+    .title("Notification discarded")
+    .description(format!("`{id}` {} won't be delivered", delivery.title()))
+    .color(Color::from_rgb(0, 255, 0));
+
+  let reply = CreateReply::default()
+    .embed(embed)
+    .reply(true)
+    .ephemeral(true);
+
+  ctx.send(reply).await?;
+  Ok(())
+}
@@ -0,0 +1,55 @@
+use super::{CollarAppContext, CollarError, EmbedWrapper, send_generic_error_application};
+use poise::{CreateReply, command, serenity_prelude::Color};
+use tracing::info;
+
+#[command(
+  slash_command,
+  description_localized(
+    locale = "en-US",
+    description = "Require website submitters to prove they own their site before verifying"
+  ),
+  description_localized(
+    locale = "sv-SE",
+    description = "Kräv att inskickare bevisar att de äger sin webbplats innan verifiering"
+  ),
+  name_localized(locale = "en-US", name = "require_ownership"),
+  name_localized(locale = "sv-SE", name = "kräv_ägarskap"),
+  category = "Review",
+  required_permissions = "MANAGE_GUILD"
+)]
+pub async fn require_ownership(
+  ctx: CollarAppContext<'_>,
+  #[description = "Whether the verify button stays disabled until ownership is proven"]
+  required: bool,
+) -> Result<(), CollarError> {
+  let guild_id = match ctx.guild_id() {
+    Some(guild_id) => guild_id,
+    None => {
+      return send_generic_error_application(ctx, "Ownership requirements are per server").await;
+    }
+  };
+
+  info!("Setting ownership requirement for guild {guild_id} to {required}");
+  {
+    let mut cache = ctx.data().cache.write();
+    cache.set_requires_ownership(guild_id, required);
+  }
+
+  let description = match required {
+    true => "Websites can only be verified once the submitter proves they own them",
+    false => "Proving ownership is optional again, reviewers still see whether it was proven",
+  };
+
+  let embed = EmbedWrapper::new_application(&ctx)
+    .title("Ownership requirement updated!")
+    .description(description)
+    .color(Color::from_rgb(0, 255, 0));
+
+  let reply = CreateReply::default()
+    .embed(embed)
+    .reply(true)
+    .ephemeral(true);
+
+  ctx.send(reply).await?;
+  Ok(())
+}
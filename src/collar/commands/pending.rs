@@ -0,0 +1,427 @@
+use crate::collar::{
+  EmbedWrapper, ad_hash, ad_image,
+  client::PetringError,
+  commands::{format_timestamp, send_generic_error_application, send_petring_error_application},
+  notifs::{PendingSubmission, ReviewAction, ReviewOrigin, approvals_text},
+  ownership,
+};
+
+use super::{
+  CollarAppContext, CollarError,
+  notifs::{Notif, SubmitType},
+};
+use chrono::{DateTime, Utc};
+use poise::{CreateReply, command, serenity_prelude as serenity};
+use serenity::{
+  ButtonStyle, Color, ComponentInteractionCollector, CreateActionRow, CreateButton, CreateEmbed,
+  CreateEmbedAuthor, CreateEmbedFooter, CreateInteractionResponse, EditInteractionResponse,
+  FormattedTimestampStyle, Mentionable, MessageId, UserId,
+};
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// PetRing can't list every submission, so `/pending` only knows the members on collar's roster.
+const ROSTER_LIMITATION: &str = "Only members collar has seen are listed, submissions made on \
+  the website show up once they're submitted or looked up through collar";
+
+/// An unverified user or ad of a member on the roster.
+struct PendingEntry {
+  instance: String,
+  submit_type: SubmitType,
+  discord_id: u64,
+  username: String,
+  url: String,
+  created_at: DateTime<Utc>,
+}
+
+enum ReviewMessageState {
+  Open(MessageId, PendingSubmission),
+  Deleted,
+  /// Submitted on the website, or before collar kept track of review messages.
+  Untracked,
+}
+
+async fn fetch_entries(ctx: &CollarAppContext<'_>) -> Result<Vec<PendingEntry>, PetringError> {
+  let petring = ctx.data().petring(ctx.guild_id());
+  let instance = petring.target.resolve(&ctx.data().cache.read())?;
+  let (users, ads) = tokio::join!(petring.get_known_users(), petring.get_known_ads());
+
+  let users = users?
+    .into_iter()
+    .filter(|user| !user.verified)
+    .map(|user| PendingEntry {
+      instance: instance.clone(),
+      submit_type: SubmitType::User,
+      discord_id: user.discord_id,
+      username: user.username,
+      url: user.url,
+      created_at: user.created_at,
+    });
+  let ads = ads?
+    .into_iter()
+    .filter(|ad| !ad.verified)
+    .map(|ad| PendingEntry {
+      instance: instance.clone(),
+      submit_type: SubmitType::Ad,
+      discord_id: ad.discord_id,
+      username: ad.username,
+      url: ad.image_url,
+      created_at: ad.created_at,
+    });
+
+  let mut entries = users.chain(ads).collect::<Vec<_>>();
+  entries.sort_by_key(|entry| entry.created_at);
+  Ok(entries)
+}
+
+async fn review_message_state(
+  ctx: &CollarAppContext<'_>,
+  entry: &PendingEntry,
+) -> ReviewMessageState {
+  if entry.discord_id == 0 {
+    return ReviewMessageState::Untracked;
+  }
+
+  let pending = {
+    let cache = ctx.data().cache.read();
+    cache.get_pending_submissions_for(
+      &entry.instance,
+      entry.submit_type,
+      UserId::new(entry.discord_id),
+    )
+  };
+
+  if pending.is_empty() {
+    return ReviewMessageState::Untracked;
+  }
+
+  for (message_id, submission) in pending {
+    if submission
+      .channel_id
+      .message(ctx.http(), message_id)
+      .await
+      .is_ok()
+    {
+      return ReviewMessageState::Open(message_id, submission);
+    }
+  }
+
+  ReviewMessageState::Deleted
+}
+
+async fn render_page(
+  ctx: &CollarAppContext<'_>,
+  entries: &[PendingEntry],
+  page: usize,
+) -> (CreateEmbed, Vec<CreateActionRow>) {
+  let ctx_id = ctx.id();
+  let navigation = CreateActionRow::Buttons(vec![
+    CreateButton::new(format!("{ctx_id}:prev"))
+      .label("Previous")
+      .style(ButtonStyle::Secondary)
+      .disabled(page == 0),
+    CreateButton::new(format!("{ctx_id}:next"))
+      .label("Next")
+      .style(ButtonStyle::Secondary)
+      .disabled(page + 1 >= entries.len()),
+    CreateButton::new(format!("{ctx_id}:refresh"))
+      .label("Refresh")
+      .style(ButtonStyle::Secondary),
+  ]);
+
+  let entry = match entries.get(page) {
+    Some(entry) => entry,
+    None => {
+      let embed = EmbedWrapper::new_application(ctx)
+        .title("Nothing pending :3")
+        .description(format!(
+          "Every user and ad collar knows of is verified. {ROSTER_LIMITATION}"
+        ))
+        .color(Color::from_rgb(0, 255, 0));
+      return (embed, vec![navigation]);
+    }
+  };
+
+  let state = review_message_state(ctx, entry).await;
+  let review_message = match &state {
+    ReviewMessageState::Open(message_id, submission) => {
+      message_id.link(submission.channel_id, Some(submission.guild_id))
+    }
+    ReviewMessageState::Deleted => String::from("Deleted, repost it to review it there"),
+    ReviewMessageState::Untracked if entry.discord_id == 0 => {
+      String::from("None, no linked Discord account to verify")
+    }
+    ReviewMessageState::Untracked => String::from("None, repost it to review it there"),
+  };
+
+  let (kind, link_name) = match entry.submit_type {
+    SubmitType::User => ("Website", "Website"),
+    SubmitType::Ad => ("Ad", "Ad image"),
+  };
+
+  let mut embed = EmbedWrapper::new_application(ctx)
+    .title(format!(
+      "Pending submissions ({}/{})",
+      page + 1,
+      entries.len()
+    ))
+    .author(CreateEmbedAuthor::new(entry.username.clone()))
+    .field("Type", kind, true)
+    .field(
+      "Submitted",
+      format_timestamp(entry.created_at, FormattedTimestampStyle::LongDateTime),
+      true,
+    )
+    .field(
+      "Age",
+      format_timestamp(entry.created_at, FormattedTimestampStyle::RelativeTime),
+      true,
+    )
+    .field(link_name, entry.url.clone(), false)
+    .field("Review message", review_message, false)
+    .footer(CreateEmbedFooter::new(ROSTER_LIMITATION))
+    .color(Color::from_rgb(0, 0, 255));
+
+  if entry.submit_type == SubmitType::Ad {
+    embed = embed.image(&entry.url);
+  }
+
+  if entry.discord_id == 0 {
+    return (embed, vec![navigation]);
+  }
+
+  let discord_id = UserId::new(entry.discord_id);
+  let (votes, quorum, ownership, verify_enabled) = {
+    let cache = ctx.data().cache.read();
+    (
+      cache.get_review_votes(&entry.instance, entry.submit_type, discord_id),
+      cache
+        .get_review_quorum(ctx.guild_id())
+        .get(entry.submit_type),
+      ownership::status_text(
+        cache
+          .get_ownership_challenge(&entry.instance, discord_id)
+          .as_ref(),
+        cache.requires_ownership(ctx.guild_id()),
+      ),
+      !cache.ownership_blocks_verify(ctx.guild_id(), entry.submit_type, discord_id),
+    )
+  };
+  embed = embed.field("Approvals", approvals_text(&votes, quorum), false);
+  if let Some(vetoed_by) = votes.vetoed_by {
+    embed = embed.field("Vetoed by", vetoed_by.mention().to_string(), false);
+  }
+  if entry.submit_type == SubmitType::User {
+    embed = embed.field(ownership::OWNERSHIP_FIELD, ownership, false);
+  }
+
+  let mut review_buttons = ReviewAction::buttons(
+    ReviewOrigin::PendingList,
+    entry.submit_type,
+    discord_id,
+    verify_enabled,
+  )
+  .to_vec();
+  review_buttons.push(
+    CreateButton::new(format!("{ctx_id}:repost"))
+      .label("Repost to review channel")
+      .style(ButtonStyle::Primary)
+      .disabled(matches!(state, ReviewMessageState::Open(..))),
+  );
+
+  (
+    embed,
+    vec![navigation, CreateActionRow::Buttons(review_buttons)],
+  )
+}
+
+/// Posts a fresh review message for `entry`, then drops the entries of the ones that got
+/// deleted. Those stay put if the new message doesn't make it.
+async fn repost(ctx: &CollarAppContext<'_>, entry: &PendingEntry) -> Result<(), CollarError> {
+  let discord_id = UserId::new(entry.discord_id);
+
+  let stale = {
+    let cache = ctx.data().cache.read();
+    cache.get_pending_submissions_for(&entry.instance, entry.submit_type, discord_id)
+  };
+  let reason = stale.iter().find_map(|(_, pending)| pending.reason.clone());
+
+  let (title, link_name) = match entry.submit_type {
+    SubmitType::User => ("New submission :3", "Website"),
+    SubmitType::Ad => ("New ad submission :3", "Ad"),
+  };
+
+  let mut submission_embed = EmbedWrapper::new_application(ctx)
+    .title(title)
+    .author(CreateEmbedAuthor::new(format!("from: {}", entry.username)))
+    .field(link_name, entry.url.clone(), false)
+    .field(
+      "Created at",
+      format_timestamp(entry.created_at, FormattedTimestampStyle::LongDateTime),
+      false,
+    )
+    .description(format!("Reposted by {}", ctx.author().name))
+    .color(Color::from_rgb(0, 0, 255));
+
+  match entry.submit_type {
+    SubmitType::Ad => {
+      let (image, similar) = match ctx
+        .data()
+        .inspect_ad_image(ctx.guild_id(), &entry.url)
+        .await
+      {
+        Ok(image) => {
+          let similar = match image.hash().await {
+            Some(hash) => ad_hash::similar_to(ctx.data(), hash, discord_id),
+            None => None,
+          };
+          (image.to_string(), similar)
+        }
+        Err(err) => (format!("❌ {err}"), None),
+      };
+      submission_embed = submission_embed
+        .field(ad_image::IMAGE_FIELD, image, false)
+        .image(&entry.url);
+      if let Some(similar) = similar {
+        submission_embed = submission_embed.field(ad_hash::SIMILAR_FIELD, similar, false);
+      }
+    }
+    SubmitType::User => {
+      let site_check = ctx
+        .data()
+        .check_site(ctx.guild_id(), &entry.url, &entry.username)
+        .await;
+      let ownership = {
+        let cache = ctx.data().cache.read();
+        ownership::status_text(
+          cache
+            .get_ownership_challenge(&entry.instance, discord_id)
+            .as_ref(),
+          cache.requires_ownership(ctx.guild_id()),
+        )
+      };
+      submission_embed = submission_embed
+        .field("Site check", site_check, false)
+        .field(ownership::OWNERSHIP_FIELD, ownership, false);
+    }
+  }
+
+  info!("Reposting review message for {}", entry.discord_id);
+  Notif::new(ctx)
+    .set_embed(submission_embed)
+    .submit(ctx, entry.discord_id, entry.submit_type, reason)
+    .await?;
+
+  let mut cache = ctx.data().cache.write();
+  for (message_id, _) in stale {
+    cache.remove_pending_submission(message_id);
+  }
+  Ok(())
+}
+
+#[command(
+  slash_command,
+  description_localized(
+    locale = "en-US",
+    description = "List unverified users and ads waiting for review that collar has seen"
+  ),
+  description_localized(
+    locale = "sv-SE",
+    description = "Lista overifierade användare och annonser som collar har sett och som väntar på granskning"
+  ),
+  name_localized(locale = "en-US", name = "pending"),
+  name_localized(locale = "sv-SE", name = "väntande"),
+  category = "Review",
+  required_permissions = "MANAGE_CHANNELS | BAN_MEMBERS | KICK_MEMBERS | MUTE_MEMBERS"
+)]
+pub async fn pending(ctx: CollarAppContext<'_>) -> Result<(), CollarError> {
+  if ctx.guild_id().is_none() {
+    return send_generic_error_application(ctx, "Pending submissions are per server").await;
+  }
+
+  ctx.defer_ephemeral().await?;
+
+  let mut entries = match fetch_entries(&ctx).await {
+    Ok(entries) => entries,
+    Err(error) => return send_petring_error_application(ctx, &error).await,
+  };
+  let mut page = 0;
+
+  let (embed, components) = render_page(&ctx, &entries, page).await;
+  let reply = CreateReply::default()
+    .embed(embed)
+    .components(components)
+    .ephemeral(true);
+  ctx.send(reply).await?;
+
+  let ctx_id = ctx.id();
+  while let Some(press) = ComponentInteractionCollector::new(ctx)
+    .filter(move |press| press.data.custom_id.starts_with(&format!("{ctx_id}:")))
+    .timeout(Duration::from_secs(10 * 60))
+    .await
+  {
+    let button = press
+      .data
+      .custom_id
+      .trim_start_matches(&format!("{ctx_id}:"))
+      .to_string();
+    if !matches!(button.as_str(), "prev" | "next" | "refresh" | "repost") {
+      continue;
+    }
+
+    // Refreshing and reposting can take longer than the 3 seconds Discord waits for an answer,
+    // so the press is acknowledged first and the message edited once the page is ready.
+    if let Err(err) = press
+      .create_response(
+        ctx.serenity_context(),
+        CreateInteractionResponse::Acknowledge,
+      )
+      .await
+    {
+      warn!("Failed to acknowledge {button} on /pending: {err}");
+      continue;
+    }
+
+    match button.as_str() {
+      "prev" => page = page.saturating_sub(1),
+      "next" => page = (page + 1).min(entries.len().saturating_sub(1)),
+      "refresh" => match fetch_entries(&ctx).await {
+        Ok(fresh) => {
+          entries = fresh;
+          page = page.min(entries.len().saturating_sub(1));
+        }
+        Err(err) => warn!("Failed to refresh pending submissions: {err}"),
+      },
+      "repost" => {
+        if let Some(entry) = entries.get(page)
+          && let Err(err) = repost(&ctx, entry).await
+        {
+          warn!(
+            "Failed to repost the review message for {}: {err}",
+            entry.discord_id
+          );
+          let problem = format!("Couldn't repost the review message: {err}");
+          if let Err(err) = send_generic_error_application(ctx, &problem).await {
+            warn!("Failed to report the failed repost: {err}");
+          }
+        }
+      }
+      _ => (),
+    }
+
+    let (embed, components) = render_page(&ctx, &entries, page).await;
+    if let Err(err) = press
+      .edit_response(
+        ctx.serenity_context(),
+        EditInteractionResponse::new()
+          .embed(embed)
+          .components(components),
+      )
+      .await
+    {
+      warn!("Failed to update /pending after {button}: {err}");
+    }
+  }
+
+  Ok(())
+}
@@ -1,19 +1,42 @@
-use crate::collar::notifs::VerifyType;
+use crate::collar::{
+  Collar,
+  ad_archive::{self, LinkState},
+  ad_hash::{self, AdHashRecord, AdOutcome},
+  ad_image::{self, AdImageError},
+  audit::{self, AuditAction, AuditEntry},
+  notifs::VerifyType,
+  submission_outbox::{self, QueuedSubmission},
+};
 
 use super::{
   Ad, AdEditSubmission, AdSubmission, CollarAppContext, CollarError, EmbedWrapper, ImageSubmission,
-  http::{ErrorResponse, ResponseTypes, make_request},
-  notifs::{Notif, SubmitType},
-  send_generic_error_application,
+  format_timestamp,
+  notifs::{self, Notif, SubmitType},
+  send_generic_error_application, send_petring_error_application,
 };
-use chrono::DateTime;
 use poise::{CreateReply, Modal, command, serenity_prelude as serenity};
-use reqwest::Method;
 use serenity::{
-  Color, CreateEmbedAuthor, FormattedTimestamp, FormattedTimestampStyle, Mentionable, Timestamp,
+  Color, CreateEmbed, CreateEmbedAuthor, FormattedTimestampStyle, Mentionable, UserId,
 };
 use tracing::info;
 
+async fn send_image_rejected(
+  ctx: CollarAppContext<'_>,
+  err: &AdImageError,
+) -> Result<(), CollarError> {
+  let embed = EmbedWrapper::new_application(&ctx)
+    .title("Ad image not accepted 3:")
+    .description(format!("Sorry, {err}"))
+    .color(Color::from_rgb(255, 0, 0));
+
+  let reply = CreateReply::default()
+    .embed(embed)
+    .reply(true)
+    .ephemeral(true);
+  ctx.send(reply).await?;
+  Ok(())
+}
+
 #[command(
   slash_command,
   description_localized(
@@ -31,42 +54,113 @@ use tracing::info;
 pub async fn my_ad(ctx: CollarAppContext<'_>) -> Result<(), CollarError> {
   let user_id = ctx.author().id;
 
-  let response = make_request(
-    ctx.data().clone(),
-    None::<String>,
-    &format!("/get/ad/{user_id}"),
-    Method::GET,
-  )
-  .await?;
-  match response {
-    ResponseTypes::Success(_ad) => {
-      let ad: Ad = _ad;
-      let user_id_u64: u64 = user_id.into();
-
-      if ad.discord_id != user_id_u64 {
-        return send_generic_error_application(ctx, "Ad not found").await;
-      }
+  let ad = match ctx.data().petring(ctx.guild_id()).get_ad(user_id).await {
+    Ok(ad) => ad,
+    Err(error) => return send_petring_error_application(ctx, &error).await,
+  };
+
+  if ad.discord_id != user_id.get() {
+    return send_generic_error_application(ctx, "Ad not found").await;
+  }
+
+  if !ad.verified {
+    return send_generic_error_application(ctx, "Ad not verified").await;
+  }
+
+  let reply = ad_reply(&ctx, &ad, &ctx.author().face(), "Your ad :3").await;
+  ctx.send(reply).await?;
+
+  Ok(())
+}
 
-      if !ad.verified {
-        return send_generic_error_application(ctx, "Ad not verified").await;
+/// The ad with its archived copy attached, shown instead of the live image when that broke.
+async fn ad_reply(ctx: &CollarAppContext<'_>, ad: &Ad, icon_url: &str, title: &str) -> CreateReply {
+  let (web_base_url, archived) = {
+    let cache = ctx.data().cache.read();
+    (
+      cache.get_web_base_url(ctx.guild_id()),
+      cache
+        .get_guild_instance_name(ctx.guild_id())
+        .and_then(|instance| cache.get_archived_ad(&instance, UserId::new(ad.discord_id))),
+    )
+  };
+
+  let mut embed = EmbedWrapper::new_application(ctx)
+    .title(title)
+    .author(
+      CreateEmbedAuthor::new(&ad.username)
+        .url(format!("{web_base_url}/user/{}", &ad.username))
+        .icon_url(icon_url),
+    )
+    .field("Ad url", &ad.ad_url, false)
+    .field("Image url", &ad.image_url, false)
+    .field(
+      "Created",
+      format_timestamp(ad.created_at, FormattedTimestampStyle::LongDateTime),
+      false,
+    )
+    .field(
+      "Verified",
+      format_timestamp(ad.verified_at, FormattedTimestampStyle::LongDateTime),
+      false,
+    )
+    .color(Color::from_rgb(0, 0, 255));
+  let mut reply = CreateReply::default().reply(true).ephemeral(true);
+
+  let attachment = match &archived {
+    Some(archived) => archived.attachment().await,
+    None => None,
+  };
+  embed = match (archived, attachment) {
+    (Some(archived), Some(attachment)) => {
+      let archived_url = format!("attachment://{}", archived.file_name());
+      reply = reply.attachment(attachment);
+      let embed = embed.field(ad_archive::ARCHIVE_FIELD, archived.state_text(), false);
+      match archived.state {
+        LinkState::Live => embed.image(&ad.image_url).thumbnail(archived_url),
+        _ => embed.image(archived_url),
       }
     }
-    ResponseTypes::Error(_error) => {
-      let error: ErrorResponse = _error;
+    _ => embed
+      .field(ad_archive::ARCHIVE_FIELD, "None yet", false)
+      .image(&ad.image_url),
+  };
 
-      let embed = EmbedWrapper::new_application(&ctx)
-        .title(format!("Error {}", error.status))
-        .description(error.message)
-        .color(Color::from_rgb(255, 0, 0));
+  reply.embed(embed)
+}
 
-      let reply = CreateReply::default()
-        .embed(embed)
-        .reply(true)
-        .ephemeral(true);
+#[command(
+  slash_command,
+  description_localized(
+    locale = "en-US",
+    description = "Look up a member's ad and its archived copy"
+  ),
+  description_localized(
+    locale = "sv-SE",
+    description = "Slå upp en medlems annons och dess arkiverade kopia"
+  ),
+  name_localized(locale = "en-US", name = "get_ad"),
+  name_localized(locale = "sv-SE", name = "hämta_annons"),
+  category = "PetAds",
+  required_permissions = "MANAGE_CHANNELS | BAN_MEMBERS | KICK_MEMBERS | MUTE_MEMBERS"
+)]
+pub async fn get_ad(ctx: CollarAppContext<'_>, user: serenity::User) -> Result<(), CollarError> {
+  let ad = match ctx.data().petring(ctx.guild_id()).get_ad(user.id).await {
+    Ok(ad) => ad,
+    Err(error) => return send_petring_error_application(ctx, &error).await,
+  };
 
-      ctx.send(reply).await?;
-    }
+  if ad.discord_id != user.id.get() {
+    return send_generic_error_application(ctx, "Ad not found").await;
   }
+
+  let title = match ad.verified {
+    true => format!("{}'s ad", user.name),
+    false => format!("{}'s ad (not verified)", user.name),
+  };
+  let reply = ad_reply(&ctx, &ad, &user.face(), &title).await;
+  ctx.send(reply).await?;
+
   Ok(())
 }
 
@@ -86,8 +180,7 @@ pub async fn my_ad(ctx: CollarAppContext<'_>) -> Result<(), CollarError> {
 )]
 pub async fn submit_ad(ctx: CollarAppContext<'_>) -> Result<(), CollarError> {
   let data = ctx.data();
-  let cache = data.cache.lock().await;
-  let web_base_url = cache.get_web_base_url();
+  let web_base_url = data.cache.read().get_web_base_url(ctx.guild_id());
 
   let modal_data = AdSubmission::execute(ctx).await?;
   let modal_data = match modal_data {
@@ -104,85 +197,113 @@ pub async fn submit_ad(ctx: CollarAppContext<'_>) -> Result<(), CollarError> {
     }
   };
 
-  let image_url = modal_data.image_url;
-
-  let response = make_request(
-    ctx.data().clone(),
-    Some(ImageSubmission {
-      image_url,
-      discord_id: ctx.author().id.into(),
-    }),
-    "/post/ad/submit",
-    Method::POST,
-  )
-  .await?;
-  match response {
-    ResponseTypes::Success(ad) => {
-      let ad: Ad = ad;
+  let image_url = modal_data.image_url.trim().to_string();
+  let image = match data.inspect_ad_image(ctx.guild_id(), &image_url).await {
+    Ok(image) => image,
+    Err(err) => return send_image_rejected(ctx, &err).await,
+  };
 
-      let user_id_u64: u64 = ctx.author().id.into();
+  let submission = ImageSubmission {
+    image_url,
+    discord_id: ctx.author().id.into(),
+  };
 
-      if user_id_u64 != ad.discord_id {
-        return Err("User not found".into());
+  let ad = match data
+    .petring(ctx.guild_id())
+    .submit_ad(submission.clone())
+    .await
+  {
+    Ok(ad) => ad,
+    // The queue replays into the guild's review channel, so it needs one.
+    Err(error) => match (error.never_reached(), ctx.guild_id()) {
+      (true, Some(guild_id)) => {
+        let queued = QueuedSubmission::ad(guild_id, submission);
+        return submission_outbox::send_queued(ctx, queued, &error).await;
       }
+      _ => return send_petring_error_application(ctx, &error).await,
+    },
+  };
 
-      let user_pfp = ctx.author().face();
-
-      let formatted_created_at_timestamp = FormattedTimestamp::new(
-        Timestamp::from(DateTime::parse_from_rfc3339(&ad.created_at)?),
-        Some(FormattedTimestampStyle::LongDateTime),
-      )
-      .to_string();
+  if ctx.author().id.get() != ad.discord_id {
+    return Err("User not found".into());
+  }
 
-      let embed = EmbedWrapper::new_application(&ctx)
-        .title("Your ad submission was successful! :3")
-        .author(
-          CreateEmbedAuthor::new(&ad.username)
-            .url(format!("{web_base_url}/user/{}", &ad.username))
-            .icon_url(&user_pfp),
-        )
-        .thumbnail(&ad.image_url)
-        .field("Ad url", &ad.ad_url, false)
-        .field("Created at", formatted_created_at_timestamp, false)
-        .color(Color::from_rgb(0, 255, 0));
-
-      let reply = CreateReply::default()
-        .embed(embed)
-        .reply(true)
-        .ephemeral(true);
-      ctx.send(reply).await?;
+  let (embed, submission_embed) = ad_submitted(
+    data,
+    EmbedWrapper::new_application(&ctx),
+    ctx.author(),
+    &web_base_url,
+    &ad,
+    Ok(&image),
+  )
+  .await;
 
-      let submission_embed = EmbedWrapper::new_application(&ctx)
-        .title("New ad submission :3")
-        .author(CreateEmbedAuthor::new(format!("from: {}", ctx.author().name)).icon_url(&user_pfp))
-        .field("Petring Username", &ad.username, false)
-        .field("Ad url", &ad.ad_url, false)
-        .thumbnail(&ad.image_url)
-        .color(Color::from_rgb(0, 0, 255));
-
-      Notif::new(&ctx)
-        .set_embed(submission_embed)
-        .submit(&ctx, ad.discord_id, SubmitType::Ad)
-        .await?;
-    }
-    ResponseTypes::Error(error) => {
-      let error: ErrorResponse = error;
+  let reply = CreateReply::default()
+    .embed(embed)
+    .reply(true)
+    .ephemeral(true);
+  ctx.send(reply).await?;
 
-      let embed = EmbedWrapper::new_application(&ctx)
-        .title(format!("Error {}", error.status))
-        .description(error.message)
-        .color(Color::from_rgb(255, 0, 0));
+  Notif::new(&ctx)
+    .set_embed(submission_embed)
+    .submit(&ctx, ad.discord_id, SubmitType::Ad, None)
+    .await?;
 
-      let reply = CreateReply::default()
-        .embed(embed)
-        .reply(true)
-        .ephemeral(true);
+  Ok(())
+}
 
-      ctx.send(reply).await?;
+/// The submitter's confirmation and the review embed for an ad the API took.
+pub async fn ad_submitted(
+  data: &Collar,
+  base: CreateEmbed,
+  author: &serenity::User,
+  web_base_url: &str,
+  ad: &Ad,
+  image: Result<&ad_image::AdImage, String>,
+) -> (CreateEmbed, CreateEmbed) {
+  let user_pfp = author.face();
+
+  let formatted_created_at_timestamp =
+    format_timestamp(ad.created_at, FormattedTimestampStyle::LongDateTime);
+
+  let embed = base
+    .clone()
+    .title("Your ad submission was successful! :3")
+    .author(
+      CreateEmbedAuthor::new(&ad.username)
+        .url(format!("{web_base_url}/user/{}", &ad.username))
+        .icon_url(&user_pfp),
+    )
+    .thumbnail(&ad.image_url)
+    .field("Ad url", &ad.ad_url, false)
+    .field("Created at", formatted_created_at_timestamp, false)
+    .color(Color::from_rgb(0, 255, 0));
+
+  let mut submission_embed = base
+    .title("New ad submission :3")
+    .author(CreateEmbedAuthor::new(format!("from: {}", author.name)).icon_url(&user_pfp))
+    .field("Petring Username", &ad.username, false)
+    .field("Ad url", &ad.ad_url, false)
+    .thumbnail(&ad.image_url)
+    .color(Color::from_rgb(0, 0, 255));
+  let image = match image {
+    Ok(image) => image,
+    // The ad exists already, the reviewers look at the image themselves.
+    Err(problem) => {
+      submission_embed =
+        submission_embed.field(ad_image::IMAGE_FIELD, format!("❌ {problem}"), false);
+      return (embed, submission_embed);
     }
+  };
+
+  submission_embed = submission_embed.field(ad_image::IMAGE_FIELD, image.to_string(), false);
+  if let Some(hash) = image.hash().await
+    && let Some(similar) = ad_hash::similar_to(data, hash, author.id)
+  {
+    submission_embed = submission_embed.field(ad_hash::SIMILAR_FIELD, similar, false);
   }
 
-  Ok(())
+  (embed, submission_embed)
 }
 
 #[command(
@@ -205,8 +326,7 @@ pub async fn edit_ad(ctx: CollarAppContext<'_>) -> Result<(), CollarError> {
   let user_pfp = user.face();
 
   let data = ctx.data();
-  let cache = data.cache.lock().await;
-  let web_base_url = cache.get_web_base_url();
+  let web_base_url = data.cache.read().get_web_base_url(ctx.guild_id());
 
   let modal_data = AdEditSubmission::execute(ctx).await?;
   let modal_data = match modal_data {
@@ -223,98 +343,74 @@ pub async fn edit_ad(ctx: CollarAppContext<'_>) -> Result<(), CollarError> {
     }
   };
 
-  let image_url = modal_data.image_url;
-
-  let response = make_request(
-    ctx.data().clone(),
-    Some(ImageSubmission {
-      image_url,
-      discord_id: ctx.author().id.into(),
-    }),
-    "/patch/ad/edit/",
-    Method::PATCH,
-  )
-  .await?;
-  match response {
-    ResponseTypes::Success(ad) => {
-      let ad: Ad = ad;
-
-      let user_id_u64: u64 = ctx.author().id.into();
-
-      if user_id_u64 != ad.discord_id {
-        return Err("User not found".into());
-      }
-
-      let formatted_created_at_timestamp = FormattedTimestamp::new(
-        Timestamp::from(DateTime::parse_from_rfc3339(&ad.created_at)?),
-        Some(FormattedTimestampStyle::LongDateTime),
-      )
-      .to_string();
-
-      let formatted_edited_at_timestamp = FormattedTimestamp::new(
-        Timestamp::from(DateTime::parse_from_rfc3339(&ad.edited_at)?),
-        Some(FormattedTimestampStyle::RelativeTime),
-      )
-      .to_string();
-
-      let formatted_verified_at_timestamp = FormattedTimestamp::new(
-        Timestamp::from(DateTime::parse_from_rfc3339(&ad.verified_at)?),
-        Some(FormattedTimestampStyle::LongDateTime),
-      )
-      .to_string();
-
-      let embed = EmbedWrapper::new_application(&ctx)
-        .title("Your edit was successful! :3")
-        .author(
-          CreateEmbedAuthor::new(&ad.username)
-            .url(format!("{web_base_url}/user/{}", &ad.username))
-            .icon_url(&user_pfp),
-        )
-        .thumbnail(&ad.image_url)
-        .field("Ad url", &ad.ad_url, false)
-        .field("Created", &formatted_created_at_timestamp, false)
-        .field("Edited", &formatted_edited_at_timestamp, false)
-        .field("Verified", &formatted_verified_at_timestamp, false)
-        .color(Color::from_rgb(0, 255, 0));
-
-      let reply = CreateReply::default()
-        .embed(embed)
-        .reply(true)
-        .ephemeral(true);
-      ctx.send(reply).await?;
+  let image_url = modal_data.image_url.trim().to_string();
+  let image = match data.inspect_ad_image(ctx.guild_id(), &image_url).await {
+    Ok(image) => image,
+    Err(err) => return send_image_rejected(ctx, &err).await,
+  };
 
-      let edit_notif_embed = EmbedWrapper::new_application(&ctx)
-        .title("Ad edited :3")
-        .description(format!("{user_mention} has edited their ad in PetAds :P"))
-        .field("Created", &formatted_created_at_timestamp, false)
-        .field("Verified", &formatted_verified_at_timestamp, false)
-        .field("Edited", &formatted_edited_at_timestamp, false)
-        .author(CreateEmbedAuthor::new(format!("Edited by {}", user.name)).icon_url(&user_pfp))
-        .thumbnail(&ad.image_url)
-        .color(Color::from_rgb(0, 255, 0));
-
-      Notif::new(&ctx)
-        .set_embed(edit_notif_embed)
-        .general(&ctx)
-        .await?;
-    }
-    ResponseTypes::Error(error) => {
-      let error: ErrorResponse = error;
+  let submission = ImageSubmission {
+    image_url,
+    discord_id: ctx.author().id.into(),
+  };
 
-      let embed = EmbedWrapper::new_application(&ctx)
-        .title(format!("Error {}", error.status))
-        .description(error.message)
-        .color(Color::from_rgb(255, 0, 0));
+  let ad = match data.petring(ctx.guild_id()).edit_ad(submission).await {
+    Ok(ad) => ad,
+    Err(error) => return send_petring_error_application(ctx, &error).await,
+  };
 
-      let reply = CreateReply::default()
-        .embed(embed)
-        .reply(true)
-        .ephemeral(true);
+  if ctx.author().id.get() != ad.discord_id {
+    return Err("User not found".into());
+  }
 
-      ctx.send(reply).await?;
-    }
+  let formatted_created_at_timestamp =
+    format_timestamp(ad.created_at, FormattedTimestampStyle::LongDateTime);
+  let formatted_edited_at_timestamp =
+    format_timestamp(ad.edited_at, FormattedTimestampStyle::RelativeTime);
+  let formatted_verified_at_timestamp =
+    format_timestamp(ad.verified_at, FormattedTimestampStyle::LongDateTime);
+
+  let embed = EmbedWrapper::new_application(&ctx)
+    .title("Your edit was successful! :3")
+    .author(
+      CreateEmbedAuthor::new(&ad.username)
+        .url(format!("{web_base_url}/user/{}", &ad.username))
+        .icon_url(&user_pfp),
+    )
+    .thumbnail(&ad.image_url)
+    .field("Ad url", &ad.ad_url, false)
+    .field("Created", &formatted_created_at_timestamp, false)
+    .field("Edited", &formatted_edited_at_timestamp, false)
+    .field("Verified", &formatted_verified_at_timestamp, false)
+    .color(Color::from_rgb(0, 255, 0));
+
+  let reply = CreateReply::default()
+    .embed(embed)
+    .reply(true)
+    .ephemeral(true);
+  ctx.send(reply).await?;
+
+  let mut edit_notif_embed = EmbedWrapper::new_application(&ctx)
+    .title("Ad edited :3")
+    .description(format!("{user_mention} has edited their ad in PetAds :P"))
+    .field("Created", &formatted_created_at_timestamp, false)
+    .field("Verified", &formatted_verified_at_timestamp, false)
+    .field("Edited", &formatted_edited_at_timestamp, false)
+    .field(ad_image::IMAGE_FIELD, image.to_string(), false)
+    .author(CreateEmbedAuthor::new(format!("Edited by {}", user.name)).icon_url(&user_pfp))
+    .thumbnail(&ad.image_url)
+    .color(Color::from_rgb(0, 255, 0));
+  if let Some(hash) = image.hash().await
+    && let Some(similar) = ad_hash::similar_to(data, hash, ctx.author().id)
+  {
+    edit_notif_embed = edit_notif_embed.field(ad_hash::SIMILAR_FIELD, similar, false);
   }
 
+  Notif::new(&ctx)
+    .set_embed(edit_notif_embed)
+    .general(&ctx)
+    .await?;
+
   Ok(())
 }
 
@@ -329,95 +425,129 @@ pub async fn edit_ad(ctx: CollarAppContext<'_>) -> Result<(), CollarError> {
 )]
 pub async fn verify_ad(ctx: CollarAppContext<'_>, user: serenity::User) -> Result<(), CollarError> {
   let user_id = user.id;
-  let url = format!("/patch/ad/verify/{}", user_id);
 
   let user_mention = ctx.http().get_user(user_id).await?.mention();
 
-  let response = make_request(ctx.data().clone(), None::<String>, &url, Method::PATCH).await?;
-  match response {
-    ResponseTypes::Success(ad) => {
-      let ad: Ad = ad;
-
-      if !ad.verified {
-        return Err("User failed to verify".into());
-      }
-
-      let user_id_u64: u64 = user_id.into();
-      if user_id_u64 != ad.discord_id {
-        return Err("User not found".into());
-      }
-
-      let user_pfp = ctx.author().face();
-
-      let created_at_timestamp = FormattedTimestamp::from(Timestamp::from(
-        DateTime::parse_from_rfc3339(&ad.created_at).unwrap(),
-      ))
-      .to_string();
-
-      let verified_at_timestamp = FormattedTimestamp::from(Timestamp::from(
-        DateTime::parse_from_rfc3339(&ad.verified_at).unwrap(),
-      ))
-      .to_string();
-
-      let embed = EmbedWrapper::new_application(&ctx)
-        .title("Your verification was successful")
-        .author(CreateEmbedAuthor::new(format!("for: {}", &ad.username)).icon_url(&user_pfp))
-        .url(&ad.ad_url)
-        .thumbnail(&ad.image_url)
-        .field("Created", created_at_timestamp, false)
-        .field("Verified", verified_at_timestamp, false)
-        .color(Color::from_rgb(0, 255, 0));
-
-      let dm_ad_verify_embed = EmbedWrapper::new_application(&ctx)
-        .title("Your ad was verified!!")
-        .description(format!(
-          "Hi, there, {user_mention}, your ad has been verified :3"
-        ))
-        .author(CreateEmbedAuthor::new(format!(
-          "Verified by: {}",
-          ctx.author().name
-        )))
-        .thumbnail(&ad.image_url)
-        .author(CreateEmbedAuthor::new(user.name.clone()))
-        .color(Color::from_rgb(0, 255, 0));
-
-      let ad_verification_done_embed = EmbedWrapper::new_application(&ctx)
-        .title("An Ad has been verified :3")
-        .description(format!("Verified ad for: {}", user_mention))
-        .color(Color::from_rgb(0, 255, 0));
-
-      let reply = CreateReply::default()
-        .embed(embed)
-        .reply(true)
-        .ephemeral(true);
-      ctx.send(reply).await?;
-      info!("Sending verify ad notif dm");
-
-      Notif::new(&ctx)
-        .set_embed(dm_ad_verify_embed)
-        .dm_notif(&ctx, user_id.get())
-        .await?;
+  let (instance, _claim) = match notifs::claim_direct_verify(
+    ctx.data(),
+    ctx.guild_id(),
+    SubmitType::Ad,
+    user_id,
+    ctx.author().id,
+  ) {
+    Ok(claimed) => claimed,
+    Err(refusal) => return send_generic_error_application(ctx, &refusal).await,
+  };
 
-      Notif::new(&ctx)
-        .set_embed(ad_verification_done_embed)
-        .verification(&ctx, VerifyType::Ad)
-        .await?;
-    }
-    ResponseTypes::Error(_error) => {
-      let error: ErrorResponse = _error;
+  let ad = match ctx.data().petring(ctx.guild_id()).verify_ad(user_id).await {
+    Ok(ad) => ad,
+    Err(error) => return send_petring_error_application(ctx, &error).await,
+  };
 
-      let embed = EmbedWrapper::new_application(&ctx)
-        .title(format!("Error {}", error.status))
-        .description(error.message)
-        .color(Color::from_rgb(255, 0, 0));
+  if !ad.verified {
+    return Err("User failed to verify".into());
+  }
 
-      let reply = CreateReply::default()
-        .embed(embed)
-        .reply(true)
-        .ephemeral(true);
+  if user_id.get() != ad.discord_id {
+    return Err("User not found".into());
+  }
 
-      ctx.send(reply).await?;
-    }
+  let submission_reason = audit::submission_reason(ctx.data(), &instance, SubmitType::Ad, user_id);
+  audit::record(
+    ctx.data(),
+    AuditEntry::new(
+      ctx.guild_id(),
+      AuditAction::Verify,
+      SubmitType::Ad,
+      user_id,
+      ctx.author().id,
+    )
+    .submission_reason(submission_reason)
+    .approvers(vec![ctx.author().id]),
+  );
+  notifs::drop_review_messages(
+    ctx.http(),
+    ctx.data(),
+    &instance,
+    SubmitType::Ad,
+    user_id,
+    None,
+  )
+  .await;
+
+  let user_pfp = ctx.author().face();
+
+  let created_at_timestamp =
+    format_timestamp(ad.created_at, FormattedTimestampStyle::ShortDateTime);
+  let verified_at_timestamp =
+    format_timestamp(ad.verified_at, FormattedTimestampStyle::ShortDateTime);
+
+  let embed = EmbedWrapper::new_application(&ctx)
+    .title("Your verification was successful")
+    .author(CreateEmbedAuthor::new(format!("for: {}", &ad.username)).icon_url(&user_pfp))
+    .url(&ad.ad_url)
+    .thumbnail(&ad.image_url)
+    .field("Created", created_at_timestamp, false)
+    .field("Verified", verified_at_timestamp, false)
+    .color(Color::from_rgb(0, 255, 0));
+
+  let dm_ad_verify_embed = EmbedWrapper::new_application(&ctx)
+    .title("Your ad was verified!!")
+    .description(format!(
+      "Hi, there, {user_mention}, your ad has been verified :3"
+    ))
+    .author(CreateEmbedAuthor::new(format!(
+      "Verified by: {}",
+      ctx.author().name
+    )))
+    .thumbnail(&ad.image_url)
+    .author(CreateEmbedAuthor::new(user.name.clone()))
+    .color(Color::from_rgb(0, 255, 0));
+
+  let mut ad_verification_done_embed = EmbedWrapper::new_application(&ctx)
+    .title("An Ad has been verified :3")
+    .description(format!("Verified ad for: {}", user_mention))
+    .color(Color::from_rgb(0, 255, 0));
+
+  let reply = CreateReply::default()
+    .embed(embed)
+    .reply(true)
+    .ephemeral(true);
+  ctx.send(reply).await?;
+  ad_archive::archive(ctx.data(), ctx.guild_id(), user_id, &ad.image_url).await;
+  let hash = ad_hash::fetch_hash(ctx.data(), &ad.image_url).await;
+  info!("Sending verify ad notif dm");
+
+  Notif::new(&ctx)
+    .set_embed(dm_ad_verify_embed)
+    .dm_notif(&ctx, user_id.get())
+    .await?;
+
+  if let Some(hash) = hash
+    && let Some(duplicates) = ad_hash::verified_duplicates(ctx.data(), hash, user_id)
+  {
+    ad_verification_done_embed =
+      ad_verification_done_embed.field(ad_hash::DUPLICATES_FIELD, duplicates, false);
+  }
+  let announcement = Notif::new(&ctx)
+    .set_embed(ad_verification_done_embed)
+    .verification(&ctx, VerifyType::Ad)
+    .await?;
+
+  // Same as verifying from the review message, so later submissions get compared to it.
+  if let Some(hash) = hash {
+    ad_hash::remember(
+      ctx.data(),
+      AdHashRecord::new(
+        user_id,
+        &ad.image_url,
+        hash,
+        AdOutcome::Verified,
+        ctx.author().id,
+        announcement.map(|message| message.link()),
+      ),
+    )
+    .await;
   }
 
   Ok(())
@@ -440,69 +570,96 @@ pub async fn verify_ad(ctx: CollarAppContext<'_>, user: serenity::User) -> Resul
 )]
 pub async fn remove_ad(ctx: CollarAppContext<'_>, user: serenity::User) -> Result<(), CollarError> {
   let user_id = user.id;
-  let url = format!("/delete/ad/by-discord/{}", user_id);
 
   let user_mention = user.mention();
   let user_pfp = user.face();
 
-  let response = make_request(ctx.data().clone(), None::<String>, &url, Method::DELETE).await?;
-  match response {
-    ResponseTypes::Success(response) => {
-      let deleted_ad: Ad = response;
-
-      let user_id_u64: u64 = user_id.into();
-      if deleted_ad.discord_id != user_id_u64 {
-        return Err("Ad not found".into());
-      }
-
-      let embed = EmbedWrapper::new_application(&ctx)
-        .title("Successfully removed ad :3")
-        .description(format!("{user_mention}'s ad has been removed :3",))
-        .field("Petring Username", &deleted_ad.username, false)
-        .field("Ad url", &deleted_ad.ad_url, false)
-        .thumbnail(&deleted_ad.image_url)
-        .author(CreateEmbedAuthor::new(format!("Bye {}", user.name)).icon_url(&user_pfp))
-        .color(Color::from_rgb(255, 0, 0));
+  let deleted_ad = match ctx.data().petring(ctx.guild_id()).delete_ad(user_id).await {
+    Ok(deleted_ad) => deleted_ad,
+    Err(error) => return send_petring_error_application(ctx, &error).await,
+  };
 
-      let formatted_created_at_timestamp = FormattedTimestamp::new(
-        Timestamp::from(DateTime::parse_from_rfc3339(&deleted_ad.created_at)?),
-        Some(FormattedTimestampStyle::LongDateTime),
-      )
-      .to_string();
-
-      let delete_ad_notif_embed = EmbedWrapper::new_application(&ctx)
-        .title("Ad deleted 3:")
-        .description(format!("{user_mention} got their ad deleted in PetAds 3':",))
-        .thumbnail(&deleted_ad.image_url)
-        .author(
-          CreateEmbedAuthor::new(format!("Deleted by: {}", ctx.author().name)).icon_url(&user_pfp),
-        )
-        .field("Petring Username", &deleted_ad.username, false)
-        .field("Ad url", &deleted_ad.ad_url, false)
-        .field("Verified", deleted_ad.verified.to_string(), false)
-        .field("Created", formatted_created_at_timestamp, false)
-        .color(Color::from_rgb(255, 0, 0));
+  if deleted_ad.discord_id != user_id.get() {
+    return Err("Ad not found".into());
+  }
 
-      let reply = CreateReply::default()
-        .embed(embed)
-        .reply(true)
-        .ephemeral(true);
-      ctx.send(reply).await?;
+  audit::record(
+    ctx.data(),
+    AuditEntry::new(
+      ctx.guild_id(),
+      AuditAction::Remove,
+      SubmitType::Ad,
+      user_id,
+      ctx.author().id,
+    ),
+  );
+  // An open review would otherwise let a later click act on the deleted ad.
+  let instance = ctx
+    .data()
+    .cache
+    .read()
+    .get_guild_instance_name(ctx.guild_id());
+  if let Some(instance) = instance {
+    notifs::drop_review_messages(
+      ctx.http(),
+      ctx.data(),
+      &instance,
+      SubmitType::Ad,
+      user_id,
+      None,
+    )
+    .await;
+  }
 
-      Notif::new(&ctx)
-        .set_embed(delete_ad_notif_embed)
-        .general(&ctx)
-        .await?;
-    }
-    ResponseTypes::Error(error) => {
-      let error: ErrorResponse = error;
-      let embed = EmbedWrapper::new_application(&ctx)
-        .title(format!("Error {}", error.status))
-        .description(error.message)
-        .color(Color::from_rgb(255, 0, 0));
-      let reply = CreateReply::default().embed(embed).reply(true);
-      ctx.send(reply).await?;
-    }
+  let embed = EmbedWrapper::new_application(&ctx)
+    .title("Successfully removed ad :3")
+    .description(format!("{user_mention}'s ad has been removed :3",))
+    .field("Petring Username", &deleted_ad.username, false)
+    .field("Ad url", &deleted_ad.ad_url, false)
+    .thumbnail(&deleted_ad.image_url)
+    .author(CreateEmbedAuthor::new(format!("Bye {}", user.name)).icon_url(&user_pfp))
+    .color(Color::from_rgb(255, 0, 0));
+
+  let formatted_created_at_timestamp =
+    format_timestamp(deleted_ad.created_at, FormattedTimestampStyle::LongDateTime);
+
+  let delete_ad_notif_embed = EmbedWrapper::new_application(&ctx)
+    .title("Ad deleted 3:")
+    .description(format!("{user_mention} got their ad deleted in PetAds 3':",))
+    .thumbnail(&deleted_ad.image_url)
+    .author(
+      CreateEmbedAuthor::new(format!("Deleted by: {}", ctx.author().name)).icon_url(&user_pfp),
+    )
+    .field("Petring Username", &deleted_ad.username, false)
+    .field("Ad url", &deleted_ad.ad_url, false)
+    .field("Verified", deleted_ad.verified.to_string(), false)
+    .field("Created", formatted_created_at_timestamp, false)
+    .color(Color::from_rgb(255, 0, 0));
+
+  let reply = CreateReply::default()
+    .embed(embed)
+    .reply(true)
+    .ephemeral(true);
+  ctx.send(reply).await?;
+
+  let announcement = Notif::new(&ctx)
+    .set_embed(delete_ad_notif_embed)
+    .general(&ctx)
+    .await?;
+
+  if let Some(hash) = ad_hash::fetch_hash(ctx.data(), &deleted_ad.image_url).await {
+    ad_hash::remember(
+      ctx.data(),
+      AdHashRecord::new(
+        user_id,
+        &deleted_ad.image_url,
+        hash,
+        AdOutcome::Removed,
+        ctx.author().id,
+        announcement.map(|message| message.link()),
+      ),
+    )
+    .await;
   }
 
   Ok(())
@@ -1,23 +1,46 @@
 use crate::collar::{
-  EmbedWrapper,
-  commands::{send_generic_error_application, send_generic_error_normal},
+  Collar, EmbedWrapper,
+  audit::{self, AuditAction, AuditEntry},
+  commands::{
+    format_timestamp, send_generic_error_application, send_generic_error_normal,
+    send_petring_error_application, send_petring_error_normal,
+  },
   notifs::VerifyType,
+  ownership::{self, OwnershipChallenge},
+  submission_outbox::{self, QueuedSubmission},
+  url_policy,
 };
 
 use super::{
-  AddWebsite, CollarAppContext, CollarContext, CollarError, EditSubmission, EditedUser, User,
+  AddWebsite, CollarAppContext, CollarContext, CollarError, EditSubmission, User,
   UserEditSubmission, UserSubmission,
-  http::{ErrorResponse, ResponseTypes, make_request},
-  notifs::{Notif, SubmitType},
+  notifs::{self, Notif, SubmitType},
 };
-use chrono::DateTime;
 use poise::{CreateReply, Modal, command, serenity_prelude as serenity};
-use reqwest::Method;
 use serenity::{
-  Color, CreateEmbedAuthor, FormattedTimestamp, FormattedTimestampStyle, Mentionable, Timestamp,
+  Color, CreateActionRow, CreateEmbed, CreateEmbedAuthor, FormattedTimestamp,
+  FormattedTimestampStyle, GuildId, Mentionable,
 };
 use tracing::info;
 
+async fn send_url_rejected(
+  ctx: CollarAppContext<'_>,
+  reason: &impl std::fmt::Display,
+) -> Result<(), CollarError> {
+  let embed = EmbedWrapper::new_application(&ctx)
+    .title("Website not accepted 3:")
+    .description(format!("Sorry, {reason}"))
+    .color(Color::from_rgb(255, 0, 0));
+
+  let reply = CreateReply::default()
+    .embed(embed)
+    .reply(true)
+    .ephemeral(true);
+
+  ctx.send(reply).await?;
+  Ok(())
+}
+
 #[command(
   slash_command,
   description_localized(
@@ -34,97 +57,59 @@ use tracing::info;
 )]
 pub async fn me(ctx: CollarContext<'_>) -> Result<(), CollarError> {
   let data = ctx.data();
-  let cache = data.cache.lock().await;
 
   let user_id = ctx.author().id;
-  let web_base_url = cache.get_web_base_url();
+  let web_base_url = data.cache.read().get_web_base_url(ctx.guild_id());
+
+  let user = match data
+    .petring(ctx.guild_id())
+    .get_user_by_discord(user_id)
+    .await
+  {
+    Ok(user) => user,
+    Err(error) => return send_petring_error_normal(ctx, &error).await,
+  };
 
-  let response = make_request(
-    data.clone(),
-    None::<String>,
-    &format!("/get/user/by-discord/{}", user_id),
-    Method::GET,
-  )
-  .await?;
-  match response {
-    ResponseTypes::Success(user) => {
-      let user: User = user;
-
-      let user_id_u64: u64 = user_id.into();
-
-      if user.discord_id != user_id_u64 {
-        return send_generic_error_normal(ctx, "User not found").await;
-      }
-
-      if !user.verified {
-        return send_generic_error_normal(ctx, "User not verified").await;
-      }
-
-      let avatar_url = match ctx.http().get_user(user_id).await {
-        Ok(user) => user.avatar_url().unwrap(),
-        Err(_) => {
-          return Err("User not found".into());
-        }
-      };
-
-      let created_at_timestamp = Timestamp::from(DateTime::parse_from_rfc3339(&user.created_at)?);
-
-      let formatted_created_at_timestamp = FormattedTimestamp::new(
-        created_at_timestamp,
-        Some(FormattedTimestampStyle::LongDateTime),
-      )
-      .to_string();
-
-      let mut formatted_edited_at_timestamp = "Never".to_string();
-
-      let formatted_verified_at_timestamp = FormattedTimestamp::new(
-        Timestamp::from(DateTime::parse_from_rfc3339(&user.verified_at).unwrap()),
-        Some(FormattedTimestampStyle::LongDateTime),
-      )
-      .to_string();
-
-      if !user.edited_at.is_empty() {
-        formatted_edited_at_timestamp = FormattedTimestamp::new(
-          Timestamp::from(DateTime::parse_from_rfc3339(&user.edited_at).unwrap()),
-          Some(FormattedTimestampStyle::RelativeTime),
-        )
-        .to_string();
-      }
-
-      let user_url = format!("{web_base_url}/user/{}", user.username);
-
-      info!("User url: {}", user_url);
-
-      let embed = EmbedWrapper::new_normal(&ctx)
-        .title("Your information :3")
-        .author(
-          CreateEmbedAuthor::new(format!("{} (press here to visit)", user.username)).url(user_url),
-        )
-        .thumbnail(avatar_url)
-        .field("User Website", user.url, false)
-        .field("Created at", formatted_created_at_timestamp, false)
-        .field("Edited at", formatted_edited_at_timestamp, false)
-        .field("Verified at", formatted_verified_at_timestamp, false)
-        .color(Color::from_rgb(0, 0, 255));
-
-      let reply = CreateReply::default().embed(embed).reply(true);
-      ctx.send(reply).await?;
-    }
-    ResponseTypes::Error(_error) => {
-      let error: ErrorResponse = _error;
+  if user.discord_id != user_id.get() {
+    return send_generic_error_normal(ctx, "User not found").await;
+  }
 
-      let embed = EmbedWrapper::new_normal(&ctx)
-        .title(format!("Error {}", error.status))
-        .description(error.message)
-        .color(Color::from_rgb(255, 0, 0));
+  if !user.verified {
+    return send_generic_error_normal(ctx, "User not verified").await;
+  }
 
-      let reply = CreateReply::default()
-        .embed(embed)
-        .reply(true)
-        .ephemeral(true);
-      ctx.send(reply).await?;
+  let avatar_url = match ctx.http().get_user(user_id).await {
+    Ok(user) => user.avatar_url().unwrap(),
+    Err(_) => {
+      return Err("User not found".into());
     }
-  }
+  };
+
+  let formatted_created_at_timestamp =
+    format_timestamp(user.created_at, FormattedTimestampStyle::LongDateTime);
+  let formatted_edited_at_timestamp =
+    format_timestamp(user.edited_at, FormattedTimestampStyle::RelativeTime);
+  let formatted_verified_at_timestamp =
+    format_timestamp(user.verified_at, FormattedTimestampStyle::LongDateTime);
+
+  let user_url = format!("{web_base_url}/user/{}", user.username);
+
+  info!("User url: {}", user_url);
+
+  let embed = EmbedWrapper::new_normal(&ctx)
+    .title("Your information :3")
+    .author(
+      CreateEmbedAuthor::new(format!("{} (press here to visit)", user.username)).url(user_url),
+    )
+    .thumbnail(avatar_url)
+    .field("User Website", user.url, false)
+    .field("Created at", formatted_created_at_timestamp, false)
+    .field("Edited at", formatted_edited_at_timestamp, false)
+    .field("Verified at", formatted_verified_at_timestamp, false)
+    .color(Color::from_rgb(0, 0, 255));
+
+  let reply = CreateReply::default().embed(embed).reply(true);
+  ctx.send(reply).await?;
 
   Ok(())
 }
@@ -145,93 +130,57 @@ pub async fn me(ctx: CollarContext<'_>) -> Result<(), CollarError> {
 )]
 pub async fn get_user(ctx: CollarContext<'_>, user: serenity::User) -> Result<(), CollarError> {
   let data = ctx.data();
-  let cache = data.cache.lock().await;
   let user_id = user.id;
-  let web_base_url = cache.get_web_base_url();
+  let web_base_url = data.cache.read().get_web_base_url(ctx.guild_id());
   let user_pfp = user.avatar_url().unwrap();
 
-  let response = make_request(
-    data.clone(),
-    None::<String>,
-    &format!("/get/user/by-discord/{}", user_id),
-    Method::GET,
-  )
-  .await?;
-  match response {
-    ResponseTypes::Success(user) => {
-      let user: User = user;
-      let user_id_u64: u64 = user_id.into();
-
-      if user.discord_id != user_id_u64 {
-        return send_generic_error_normal(ctx, "User not found").await;
-      }
-
-      if !user.verified {
-        return send_generic_error_normal(ctx, "User not verified").await;
-      }
-
-      let avatar_url = match ctx.http().get_user(user_id).await {
-        Ok(user) => user.avatar_url().unwrap(),
-        Err(_) => {
-          return send_generic_error_normal(ctx, "User not found").await;
-        }
-      };
-
-      let formatted_created_at_timestamp = FormattedTimestamp::new(
-        Timestamp::from(DateTime::parse_from_rfc3339(&user.created_at)?),
-        Some(FormattedTimestampStyle::LongDateTime),
-      )
-      .to_string();
-
-      let mut formatted_edited_at_timestamp = "Never".to_string();
-
-      let formatted_verified_at_timestamp = FormattedTimestamp::new(
-        Timestamp::from(DateTime::parse_from_rfc3339(&user.verified_at)?),
-        Some(FormattedTimestampStyle::LongDateTime),
-      )
-      .to_string();
-
-      if !user.edited_at.is_empty() {
-        formatted_edited_at_timestamp = FormattedTimestamp::new(
-          Timestamp::from(DateTime::parse_from_rfc3339(&user.edited_at)?),
-          Some(FormattedTimestampStyle::RelativeTime),
-        )
-        .to_string();
-      }
-
-      let embed = EmbedWrapper::new_normal(&ctx)
-        .title(format!("Info for {} :3c", user.username))
-        .author(
-          CreateEmbedAuthor::new(format!("{} (press here to visit)", user.username))
-            .url(format!("{web_base_url}/user/{}", user.username))
-            .icon_url(user_pfp),
-        )
-        .thumbnail(avatar_url)
-        .field("User Website", user.url, false)
-        .field("Created", formatted_created_at_timestamp, false)
-        .field("Edited", formatted_edited_at_timestamp, false)
-        .field("Verified", formatted_verified_at_timestamp, false)
-        .color(Color::from_rgb(0, 0, 255));
-
-      let reply = CreateReply::default().embed(embed).reply(true);
-      ctx.send(reply).await?;
-    }
-    ResponseTypes::Error(_error) => {
-      let error: ErrorResponse = _error;
+  let user = match data
+    .petring(ctx.guild_id())
+    .get_user_by_discord(user_id)
+    .await
+  {
+    Ok(user) => user,
+    Err(error) => return send_petring_error_normal(ctx, &error).await,
+  };
 
-      let embed = EmbedWrapper::new_normal(&ctx)
-        .title(format!("Error {}", error.status))
-        .description(error.message)
-        .color(Color::from_rgb(255, 0, 0));
+  if user.discord_id != user_id.get() {
+    return send_generic_error_normal(ctx, "User not found").await;
+  }
 
-      let reply = CreateReply::default()
-        .embed(embed)
-        .reply(true)
-        .ephemeral(true);
+  if !user.verified {
+    return send_generic_error_normal(ctx, "User not verified").await;
+  }
 
-      ctx.send(reply).await?;
+  let avatar_url = match ctx.http().get_user(user_id).await {
+    Ok(user) => user.avatar_url().unwrap(),
+    Err(_) => {
+      return send_generic_error_normal(ctx, "User not found").await;
     }
-  }
+  };
+
+  let formatted_created_at_timestamp =
+    format_timestamp(user.created_at, FormattedTimestampStyle::LongDateTime);
+  let formatted_edited_at_timestamp =
+    format_timestamp(user.edited_at, FormattedTimestampStyle::RelativeTime);
+  let formatted_verified_at_timestamp =
+    format_timestamp(user.verified_at, FormattedTimestampStyle::LongDateTime);
+
+  let embed = EmbedWrapper::new_normal(&ctx)
+    .title(format!("Info for {} :3c", user.username))
+    .author(
+      CreateEmbedAuthor::new(format!("{} (press here to visit)", user.username))
+        .url(format!("{web_base_url}/user/{}", user.username))
+        .icon_url(user_pfp),
+    )
+    .thumbnail(avatar_url)
+    .field("User Website", user.url, false)
+    .field("Created", formatted_created_at_timestamp, false)
+    .field("Edited", formatted_edited_at_timestamp, false)
+    .field("Verified", formatted_verified_at_timestamp, false)
+    .color(Color::from_rgb(0, 0, 255));
+
+  let reply = CreateReply::default().embed(embed).reply(true);
+  ctx.send(reply).await?;
 
   Ok(())
 }
@@ -272,127 +221,176 @@ pub async fn submit_user(ctx: CollarAppContext<'_>) -> Result<(), CollarError> {
   };
 
   let username = modal_data.username;
-  let user_url = modal_data.url;
   let reason = modal_data.reason;
   let discord_id = ctx.author().id;
 
-  let response = make_request(
-    ctx.data().clone(),
-    Some(UserSubmission {
-      username,
-      url: user_url,
-      discord_id: discord_id.into(),
-    }),
-    "/post/user/submit",
-    Method::POST,
+  let user_url = match url_policy::normalize(&modal_data.url) {
+    Ok(user_url) => user_url,
+    Err(err) => return send_url_rejected(ctx, &err).await,
+  };
+  let policy = ctx.data().cache.read().get_domain_policy(ctx.guild_id());
+  if let Err(violation) = policy.check(&user_url) {
+    return send_url_rejected(ctx, &violation).await;
+  }
+
+  let submission = UserSubmission {
+    username,
+    url: user_url,
+    discord_id: discord_id.into(),
+  };
+
+  let user = match ctx
+    .data()
+    .petring(ctx.guild_id())
+    .submit_user(submission.clone())
+    .await
+  {
+    Ok(user) => user,
+    Err(error) if error.never_reached() => {
+      let queued = QueuedSubmission::user(guild_id, submission, reason);
+      return submission_outbox::send_queued(ctx, queued, &error).await;
+    }
+    Err(error) => return send_petring_error_application(ctx, &error).await,
+  };
+
+  if user_id.get() != user.discord_id {
+    return send_generic_error_application(ctx, "User not found").await;
+  }
+
+  let (embed, mut submission_embed) = user_submitted(
+    ctx.http(),
+    ctx.data(),
+    EmbedWrapper::new_application(&ctx),
+    guild_id,
+    ctx.author(),
+    &user,
+    reason.clone(),
   )
   .await?;
-  match response {
-    ResponseTypes::Success(user) => {
-      let user: User = user;
-      let user_id_u64: u64 = user_id.into();
-
-      if user_id_u64 != user.discord_id {
-        return send_generic_error_application(ctx, "User not found").await;
-      }
-
-      let avatar_url = match ctx.http().get_user(user_id).await {
-        Ok(user) => user.avatar_url().unwrap(),
-        Err(_) => {
-          return send_generic_error_application(ctx, "User not found").await;
-        }
-      };
-
-      let joined_at_timestamp = match ctx.http().get_member(guild_id, user_id).await?.joined_at {
-        Some(joined_at) => joined_at,
-        None => return send_generic_error_application(ctx, "Failed to get joined at").await,
-      };
-
-      let user_created_at_timestamp = ctx.http().get_user(user_id).await?.created_at();
-
-      let created_at_timestamp =
-        Timestamp::from(DateTime::parse_from_rfc3339(&user.created_at).unwrap());
-
-      let formatted_created_at_timestamp = FormattedTimestamp::new(
-        created_at_timestamp,
-        Some(FormattedTimestampStyle::LongDateTime),
-      )
-      .to_string();
-
-      let formatted_joined_at_timestamp = FormattedTimestamp::new(
-        joined_at_timestamp,
-        Some(FormattedTimestampStyle::RelativeTime),
-      )
-      .to_string();
-
-      let formatted_user_created_at_timestamp = FormattedTimestamp::new(
-        user_created_at_timestamp,
-        Some(FormattedTimestampStyle::LongDateTime),
-      )
-      .to_string();
 
-      let embed = EmbedWrapper::new_application(&ctx)
-        .title("Your submission was successful! :3")
-        .author(CreateEmbedAuthor::new(user.username))
-        .thumbnail(avatar_url)
-        .field("User Website", user.url.clone(), false)
-        .field(
-          "Verification",
-          "You're not verified yet, but we'll let you know when you are :3",
-          false,
-        )
-        .field("Created", formatted_created_at_timestamp.clone(), false)
-        .color(Color::from_rgb(0, 0, 255));
-
-      let mut submission_embed = EmbedWrapper::new_application(&ctx)
-        .title("New submission :3")
-        .author(CreateEmbedAuthor::new(format!(
-          "from: {}",
-          ctx.author().name
-        )))
-        .field("Website", user.url, false)
-        .field("Created at", formatted_created_at_timestamp, false)
-        .field("User joined at", formatted_joined_at_timestamp, false)
-        .field(
-          "User Created at",
-          formatted_user_created_at_timestamp,
-          false,
-        )
-        .color(Color::from_rgb(0, 0, 255));
-
-      if let Some(reason) = reason {
-        submission_embed = submission_embed.description(reason);
-      }
+  let reply = CreateReply::default()
+    .embed(embed)
+    .components(vec![CreateActionRow::Buttons(vec![
+      ownership::check_button(user_id),
+    ])])
+    .reply(true)
+    .ephemeral(true);
 
-      let reply = CreateReply::default()
-        .embed(embed)
-        .reply(true)
-        .ephemeral(true);
+  ctx.send(reply).await?;
 
-      ctx.send(reply).await?;
+  let site_check = ctx
+    .data()
+    .check_site(ctx.guild_id(), &user.url, &user.username)
+    .await;
+  submission_embed = submission_embed.field("Site check", site_check, false);
 
-      Notif::new(&ctx)
-        .set_embed(submission_embed)
-        .submit(&ctx, user_id.get(), SubmitType::User)
-        .await?;
-    }
-    ResponseTypes::Error(_error) => {
-      let error: ErrorResponse = _error;
-      let embed = EmbedWrapper::new_application(&ctx)
-        .title(format!("Error {}", error.status))
-        .description(error.message)
-        .color(Color::from_rgb(255, 0, 0));
+  Notif::new(&ctx)
+    .set_embed(submission_embed)
+    .submit(&ctx, user_id.get(), SubmitType::User, reason)
+    .await?;
 
-      let reply = CreateReply::default()
-        .embed(embed)
-        .reply(true)
-        .ephemeral(true);
+  Ok(())
+}
 
-      ctx.send(reply).await?;
+/// Everything between the API taking a website submission and it going up for review: the
+/// submitter's confirmation and the review embed, short of the site check. Starts the
+/// ownership challenge on the way.
+pub async fn user_submitted(
+  http: &serenity::Http,
+  data: &Collar,
+  base: CreateEmbed,
+  guild_id: GuildId,
+  author: &serenity::User,
+  user: &User,
+  reason: Option<String>,
+) -> Result<(CreateEmbed, CreateEmbed), CollarError> {
+  let user_id = author.id;
+  let joined_at = http.get_member(guild_id, user_id).await?.joined_at;
+
+  let formatted_created_at_timestamp =
+    format_timestamp(user.created_at, FormattedTimestampStyle::LongDateTime);
+
+  let formatted_joined_at_timestamp = match joined_at {
+    Some(joined_at) => {
+      FormattedTimestamp::new(joined_at, Some(FormattedTimestampStyle::RelativeTime)).to_string()
     }
+    None => String::from("Unknown"),
+  };
+
+  let formatted_user_created_at_timestamp = FormattedTimestamp::new(
+    author.created_at(),
+    Some(FormattedTimestampStyle::LongDateTime),
+  )
+  .to_string();
+
+  let embed = base
+    .clone()
+    .title("Your submission was successful! :3")
+    .author(CreateEmbedAuthor::new(user.username.clone()))
+    .thumbnail(author.face())
+    .field("User Website", user.url.clone(), false)
+    .field(
+      "Verification",
+      "You're not verified yet, but we'll let you know when you are :3",
+      false,
+    )
+    .field("Created", formatted_created_at_timestamp.clone(), false)
+    .color(Color::from_rgb(0, 0, 255));
+
+  let mut submission_embed = base
+    .title("New submission :3")
+    .author(CreateEmbedAuthor::new(format!("from: {}", author.name)))
+    .field("Website", user.url.clone(), false)
+    .field("Created at", formatted_created_at_timestamp, false)
+    .field("User joined at", formatted_joined_at_timestamp, false)
+    .field(
+      "User Created at",
+      formatted_user_created_at_timestamp,
+      false,
+    )
+    .color(Color::from_rgb(0, 0, 255));
+
+  if let Some(reason) = reason {
+    submission_embed = submission_embed.description(reason);
   }
 
-  Ok(())
+  let duplicates = url_policy::duplicates(&data.petring(Some(guild_id)), &user.url, user_id).await;
+  if !duplicates.is_empty() {
+    // A long list would push the embed past its size limit, the first few make the point.
+    let listed = duplicates
+      .iter()
+      .take(5)
+      .cloned()
+      .collect::<Vec<_>>()
+      .join("\n");
+    submission_embed = submission_embed.field("Possible duplicate", listed, false);
+  }
+
+  let (challenge, required) = {
+    let mut cache = data.cache.write();
+    let instance = cache
+      .get_guild_instance_name(Some(guild_id))
+      .ok_or("This server's PetRing instance isn't configured")?;
+    let challenge = OwnershipChallenge::new(&instance, user_id, user.url.clone());
+    cache.set_ownership_challenge(challenge.clone());
+    (challenge, cache.requires_ownership(Some(guild_id)))
+  };
+
+  let embed = embed.field(
+    match required {
+      true => "Prove you own your site",
+      false => "Prove you own your site (optional)",
+    },
+    challenge.instructions(),
+    false,
+  );
+  submission_embed = submission_embed.field(
+    ownership::OWNERSHIP_FIELD,
+    ownership::status_text(Some(&challenge), required),
+    false,
+  );
+
+  Ok((embed, submission_embed))
 }
 
 #[command(
@@ -411,10 +409,9 @@ pub async fn submit_user(ctx: CollarAppContext<'_>) -> Result<(), CollarError> {
 )]
 pub async fn edit_user(ctx: CollarAppContext<'_>) -> Result<(), CollarError> {
   let data = ctx.data();
-  let cache = data.cache.lock().await;
 
   let user_id = ctx.author().id;
-  let web_base_url = cache.get_web_base_url();
+  let web_base_url = data.cache.read().get_web_base_url(ctx.guild_id());
 
   let modal_data = EditSubmission::execute(ctx).await?;
   let modal_data = match modal_data {
@@ -436,149 +433,145 @@ pub async fn edit_user(ctx: CollarAppContext<'_>) -> Result<(), CollarError> {
   };
 
   let username = modal_data.username;
-  let user_url = modal_data.url;
-
-  let response = make_request(
-    data.clone(),
-    Some(UserEditSubmission {
-      username,
-      url: user_url,
-      discord_id: user_id.into(),
-    }),
-    "/patch/user/edit/",
-    Method::PATCH,
-  )
-  .await?;
-  match response {
-    ResponseTypes::Success(user) => {
-      let user: EditedUser = user;
-      let user_id_u64: u64 = user_id.into();
-
-      if user_id_u64 != user.new.discord_id {
-        return send_generic_error_application(ctx, "User not found").await;
-      }
-
-      let avatar_url = match ctx.http().get_user(user_id).await {
-        Ok(user) => user.avatar_url().unwrap(),
-        Err(_) => {
-          return send_generic_error_application(ctx, "User not found").await;
-        }
-      };
-
-      let created_at_timestamp =
-        Timestamp::from(DateTime::parse_from_rfc3339(&user.new.created_at)?);
-
-      let formatted_created_at_timestamp = FormattedTimestamp::new(
-        created_at_timestamp,
-        Some(FormattedTimestampStyle::LongDateTime),
-      )
-      .to_string();
-
-      let mut formatted_edited_at_timestamp = "Never".to_string();
-      let mut formatted_verified_at_timestamp = "Never".to_string();
-
-      if !user.new.verified_at.is_empty() {
-        let verified_at_timestamp =
-          Timestamp::from(DateTime::parse_from_rfc3339(&user.new.verified_at)?);
-        formatted_verified_at_timestamp = FormattedTimestamp::new(
-          verified_at_timestamp,
-          Some(FormattedTimestampStyle::LongDateTime),
-        )
-        .to_string();
-      }
-
-      if !user.new.edited_at.is_empty() {
-        let edited_at_timestamp =
-          Timestamp::from(DateTime::parse_from_rfc3339(&user.new.edited_at)?);
-        formatted_edited_at_timestamp = FormattedTimestamp::new(
-          edited_at_timestamp,
-          Some(FormattedTimestampStyle::RelativeTime),
-        )
-        .to_string();
-      }
-
-      let user_url = format!("{web_base_url}/user/{}", user.new.username);
-
-      let mut embed = EmbedWrapper::new_application(&ctx)
-        .title("Your edit was successful! :3")
-        .thumbnail(avatar_url.clone())
-        .field("Created", &formatted_created_at_timestamp, false)
-        .field("Verified", &formatted_verified_at_timestamp, false)
-        .field("Edited", &formatted_edited_at_timestamp, false)
-        .color(Color::from_rgb(0, 255, 0));
-
-      let mut user_edit_notif_embed = EmbedWrapper::new_application(&ctx)
-        .title("User edited :3")
-        .thumbnail(avatar_url)
-        .field("Created", &formatted_created_at_timestamp, false)
-        .field("Verified", &formatted_verified_at_timestamp, false)
-        .field("Edited", &formatted_edited_at_timestamp, false)
-        .color(Color::from_rgb(0, 255, 0));
-
-      if user.new.username != user.old.username {
-        embed = embed.author(
-          CreateEmbedAuthor::new(format!("{} → {}", user.old.username, user.new.username))
-            .url(user_url.clone()),
-        );
-
-        user_edit_notif_embed = user_edit_notif_embed.author(
-          CreateEmbedAuthor::new(format!("{} → {}", user.old.username, user.new.username))
-            .url(user_url),
-        );
-      } else {
-        embed = embed.author(
-          CreateEmbedAuthor::new(format!("{} (press here to visit)", user.new.username))
-            .url(user_url.clone()),
-        );
-        user_edit_notif_embed = user_edit_notif_embed.author(
-          CreateEmbedAuthor::new(format!("{} (press here to visit)", user.new.username))
-            .url(user_url),
-        );
-      }
-
-      if user.new.url != user.old.url {
-        embed = embed.field(
-          "Website",
-          format!("{} → {}", user.old.url, user.new.url),
-          false,
-        );
-        user_edit_notif_embed = user_edit_notif_embed.field(
-          "Website",
-          format!("{} → {}", user.old.url, user.new.url),
-          false,
-        );
-      } else {
-        embed = embed.field("Website", user.new.url.clone(), false);
-        user_edit_notif_embed = user_edit_notif_embed.field("Website", user.new.url.clone(), false);
-      }
+  let user_url = match modal_data.url.as_deref().map(url_policy::normalize) {
+    Some(Ok(user_url)) => Some(user_url),
+    Some(Err(err)) => return send_url_rejected(ctx, &err).await,
+    None => None,
+  };
+  let policy = data.cache.read().get_domain_policy(ctx.guild_id());
+  if let Some(user_url) = &user_url
+    && let Err(violation) = policy.check(user_url)
+  {
+    return send_url_rejected(ctx, &violation).await;
+  }
 
-      let reply = CreateReply::default()
-        .embed(embed)
-        .reply(true)
-        .ephemeral(true);
-      ctx.send(reply).await?;
+  let submission = UserEditSubmission {
+    username,
+    url: user_url,
+    discord_id: user_id.into(),
+  };
+
+  let user = match data.petring(ctx.guild_id()).edit_user(submission).await {
+    Ok(user) => user,
+    Err(error) => return send_petring_error_application(ctx, &error).await,
+  };
+
+  if user_id.get() != user.new.discord_id {
+    return send_generic_error_application(ctx, "User not found").await;
+  }
 
-      Notif::new(&ctx)
-        .set_embed(user_edit_notif_embed)
-        .general(&ctx)
-        .await?;
+  let avatar_url = match ctx.http().get_user(user_id).await {
+    Ok(user) => user.avatar_url().unwrap(),
+    Err(_) => {
+      return send_generic_error_application(ctx, "User not found").await;
     }
-    ResponseTypes::Error(_error) => {
-      let error: ErrorResponse = _error;
-      let embed = EmbedWrapper::new_application(&ctx)
-        .title(format!("Error {}", error.status))
-        .description(error.message)
-        .color(Color::from_rgb(255, 0, 0));
+  };
 
-      let reply = CreateReply::default()
-        .embed(embed)
-        .reply(true)
-        .ephemeral(true);
+  let formatted_created_at_timestamp =
+    format_timestamp(user.new.created_at, FormattedTimestampStyle::LongDateTime);
+  let formatted_edited_at_timestamp =
+    format_timestamp(user.new.edited_at, FormattedTimestampStyle::RelativeTime);
+  let formatted_verified_at_timestamp =
+    format_timestamp(user.new.verified_at, FormattedTimestampStyle::LongDateTime);
+
+  let user_url = format!("{web_base_url}/user/{}", user.new.username);
+
+  let mut embed = EmbedWrapper::new_application(&ctx)
+    .title("Your edit was successful! :3")
+    .thumbnail(avatar_url.clone())
+    .field("Created", &formatted_created_at_timestamp, false)
+    .field("Verified", &formatted_verified_at_timestamp, false)
+    .field("Edited", &formatted_edited_at_timestamp, false)
+    .color(Color::from_rgb(0, 255, 0));
+
+  let mut user_edit_notif_embed = EmbedWrapper::new_application(&ctx)
+    .title("User edited :3")
+    .thumbnail(avatar_url)
+    .field("Created", &formatted_created_at_timestamp, false)
+    .field("Verified", &formatted_verified_at_timestamp, false)
+    .field("Edited", &formatted_edited_at_timestamp, false)
+    .color(Color::from_rgb(0, 255, 0));
+
+  if user.new.username != user.old.username {
+    embed = embed.author(
+      CreateEmbedAuthor::new(format!("{} → {}", user.old.username, user.new.username))
+        .url(user_url.clone()),
+    );
+
+    user_edit_notif_embed = user_edit_notif_embed.author(
+      CreateEmbedAuthor::new(format!("{} → {}", user.old.username, user.new.username))
+        .url(user_url),
+    );
+  } else {
+    embed = embed.author(
+      CreateEmbedAuthor::new(format!("{} (press here to visit)", user.new.username))
+        .url(user_url.clone()),
+    );
+    user_edit_notif_embed = user_edit_notif_embed.author(
+      CreateEmbedAuthor::new(format!("{} (press here to visit)", user.new.username)).url(user_url),
+    );
+  }
 
-      ctx.send(reply).await?;
+  if user.new.url != user.old.url {
+    embed = embed.field(
+      "Website",
+      format!("{} → {}", user.old.url, user.new.url),
+      false,
+    );
+    user_edit_notif_embed = user_edit_notif_embed.field(
+      "Website",
+      format!("{} → {}", user.old.url, user.new.url),
+      false,
+    );
+  } else {
+    embed = embed.field("Website", user.new.url.clone(), false);
+    user_edit_notif_embed = user_edit_notif_embed.field("Website", user.new.url.clone(), false);
+  }
+
+  // The old token proved the old site, a new url has to be proven again.
+  let challenge = match user.new.url != user.old.url {
+    true => {
+      let mut cache = data.cache.write();
+      cache
+        .get_guild_instance_name(ctx.guild_id())
+        .map(|instance| {
+          let challenge = OwnershipChallenge::new(&instance, user_id, user.new.url.clone());
+          cache.set_ownership_challenge(challenge.clone());
+          (challenge, cache.requires_ownership(ctx.guild_id()))
+        })
     }
+    false => None,
+  };
+
+  let mut reply = CreateReply::default();
+  if let Some((challenge, required)) = &challenge
+    && !user.new.verified
+  {
+    embed = embed.field(
+      match required {
+        true => "Prove you own your new site",
+        false => "Prove you own your new site (optional)",
+      },
+      challenge.instructions(),
+      false,
+    );
+    reply = reply.components(vec![CreateActionRow::Buttons(vec![
+      ownership::check_button(user_id),
+    ])]);
+  }
+
+  let reply = reply.embed(embed).reply(true).ephemeral(true);
+  ctx.send(reply).await?;
+
+  if let Some((challenge, _)) = &challenge {
+    ownership::refresh_review_messages(ctx.http(), data, challenge).await;
   }
 
+  Notif::new(&ctx)
+    .set_embed(user_edit_notif_embed)
+    .general(&ctx)
+    .await?;
+
   Ok(())
 }
 
@@ -599,105 +592,110 @@ pub async fn verify_user(
   user: serenity::User,
 ) -> Result<(), CollarError> {
   let user_id = user.id;
-  let url = format!("/patch/user/verify/{}", user_id);
 
   let user_mention = user.mention();
 
   let data = ctx.data();
-  let cache = data.cache.lock().await;
-
-  let response = make_request(ctx.data().clone(), None::<String>, &url, Method::PATCH).await?;
-  match response {
-    ResponseTypes::Success(_user) => {
-      let petring_user: User = _user;
-
-      if !petring_user.verified {
-        return send_generic_error_application(ctx, "User failed to verify").await;
-      }
-
-      let user_id_u64: u64 = user_id.into();
-      if user_id_u64 != petring_user.discord_id {
-        return send_generic_error_application(ctx, "User not found").await;
-      }
-
-      let user_pfp = user.face();
-
-      let created_at_timestamp = FormattedTimestamp::from(Timestamp::from(
-        DateTime::parse_from_rfc3339(&petring_user.created_at).unwrap(),
-      ))
-      .to_string();
-
-      let verified_at_timestamp = FormattedTimestamp::from(Timestamp::from(
-        DateTime::parse_from_rfc3339(&petring_user.verified_at).unwrap(),
-      ))
-      .to_string();
-
-      let web_base_url = cache.get_web_base_url();
-      let embed = EmbedWrapper::new_application(&ctx)
-        .title("Your verification was successful")
-        .author(
-          CreateEmbedAuthor::new(format!("for: {}", user.name))
-            .icon_url(&user_pfp)
-            .url(format!("{web_base_url}/user/{}", &petring_user.username)),
-        )
-        .field("Created", created_at_timestamp, false)
-        .field("Verified", verified_at_timestamp, false)
-        .color(Color::from_rgb(0, 255, 0));
-
-      let dm_user_verify_embed = EmbedWrapper::new_application(&ctx)
-        .title("You've been verified!!")
-        .description(format!("Hi there, {user_mention}, you've been verified :3"))
-        .author(
-          CreateEmbedAuthor::new(format!("Verified by: {}", ctx.author().name))
-            .icon_url(ctx.author().face()),
-        )
-        .color(Color::from_rgb(0, 255, 0));
-
-      let user_verification_done_embed = EmbedWrapper::new_application(&ctx)
-        .title("A User has been verified :3")
-        .description(format!("Verified user: {}", user_mention))
-        .author(
-          CreateEmbedAuthor::new(format!("Verified by: {}", ctx.author().name))
-            .icon_url(ctx.author().face()),
-        )
-        .color(Color::from_rgb(0, 255, 0));
-
-      let reply = CreateReply::default()
-        .embed(embed)
-        .reply(true)
-        .ephemeral(true);
-
-      ctx.send(reply).await?;
-
-      info!("Sending user notif dm");
-
-      Notif::new(&ctx)
-        .set_embed(dm_user_verify_embed)
-        .dm_notif(&ctx, user_id.get())
-        .await?;
 
-      Notif::new(&ctx)
-        .set_embed(user_verification_done_embed)
-        .verification(&ctx, VerifyType::User)
-        .await?;
-    }
-    ResponseTypes::Error(_error) => {
-      let error: ErrorResponse = _error;
+  let (instance, _claim) = match notifs::claim_direct_verify(
+    data,
+    ctx.guild_id(),
+    SubmitType::User,
+    user_id,
+    ctx.author().id,
+  ) {
+    Ok(claimed) => claimed,
+    Err(refusal) => return send_generic_error_application(ctx, &refusal).await,
+  };
 
-      let embed = EmbedWrapper::new_application(&ctx)
-        .title(format!("Error {}", error.status))
-        .description(error.message)
-        .color(Color::from_rgb(255, 0, 0));
+  let petring_user = match data.petring(ctx.guild_id()).verify_user(user_id).await {
+    Ok(petring_user) => petring_user,
+    Err(error) => return send_petring_error_application(ctx, &error).await,
+  };
 
-      let reply = CreateReply::default()
-        .embed(embed)
-        .reply(true)
-        .ephemeral(true);
+  if !petring_user.verified {
+    return send_generic_error_application(ctx, "User failed to verify").await;
+  }
 
-      ctx.send(reply).await?;
-    }
+  if user_id.get() != petring_user.discord_id {
+    return send_generic_error_application(ctx, "User not found").await;
   }
 
+  let submission_reason = audit::submission_reason(data, &instance, SubmitType::User, user_id);
+  audit::record(
+    data,
+    AuditEntry::new(
+      ctx.guild_id(),
+      AuditAction::Verify,
+      SubmitType::User,
+      user_id,
+      ctx.author().id,
+    )
+    .submission_reason(submission_reason)
+    .approvers(vec![ctx.author().id]),
+  );
+  notifs::drop_review_messages(ctx.http(), data, &instance, SubmitType::User, user_id, None).await;
+
+  let user_pfp = user.face();
+
+  let created_at_timestamp = format_timestamp(
+    petring_user.created_at,
+    FormattedTimestampStyle::ShortDateTime,
+  );
+  let verified_at_timestamp = format_timestamp(
+    petring_user.verified_at,
+    FormattedTimestampStyle::ShortDateTime,
+  );
+
+  let web_base_url = data.cache.read().get_web_base_url(ctx.guild_id());
+  let embed = EmbedWrapper::new_application(&ctx)
+    .title("Your verification was successful")
+    .author(
+      CreateEmbedAuthor::new(format!("for: {}", user.name))
+        .icon_url(&user_pfp)
+        .url(format!("{web_base_url}/user/{}", &petring_user.username)),
+    )
+    .field("Created", created_at_timestamp, false)
+    .field("Verified", verified_at_timestamp, false)
+    .color(Color::from_rgb(0, 255, 0));
+
+  let dm_user_verify_embed = EmbedWrapper::new_application(&ctx)
+    .title("You've been verified!!")
+    .description(format!("Hi there, {user_mention}, you've been verified :3"))
+    .author(
+      CreateEmbedAuthor::new(format!("Verified by: {}", ctx.author().name))
+        .icon_url(ctx.author().face()),
+    )
+    .color(Color::from_rgb(0, 255, 0));
+
+  let user_verification_done_embed = EmbedWrapper::new_application(&ctx)
+    .title("A User has been verified :3")
+    .description(format!("Verified user: {}", user_mention))
+    .author(
+      CreateEmbedAuthor::new(format!("Verified by: {}", ctx.author().name))
+        .icon_url(ctx.author().face()),
+    )
+    .color(Color::from_rgb(0, 255, 0));
+
+  let reply = CreateReply::default()
+    .embed(embed)
+    .reply(true)
+    .ephemeral(true);
+
+  ctx.send(reply).await?;
+
+  info!("Sending user notif dm");
+
+  Notif::new(&ctx)
+    .set_embed(dm_user_verify_embed)
+    .dm_notif(&ctx, user_id.get())
+    .await?;
+
+  Notif::new(&ctx)
+    .set_embed(user_verification_done_embed)
+    .verification(&ctx, VerifyType::User)
+    .await?;
+
   Ok(())
 }
 
@@ -721,56 +719,75 @@ pub async fn remove_user(
   user: serenity::User,
 ) -> Result<(), CollarError> {
   let user_id = user.id;
-  let url = format!("/delete/user/by-discord/{}", user_id);
-
-  let response = make_request(ctx.data().clone(), None::<String>, &url, Method::DELETE).await?;
-  match response {
-    ResponseTypes::Success(response) => {
-      let deleted_user: User = response;
-
-      let user_mention = user.mention();
-      let user_pfp = user.face();
 
-      let embed = EmbedWrapper::new_application(&ctx)
-        .title("Successfully removed user :3")
-        .description(format!("{user_mention} has been removed :3",))
-        .author(CreateEmbedAuthor::new(format!("Bye {}", user.name)).icon_url(&user_pfp))
-        .color(Color::from_rgb(255, 0, 0));
-
-      let user_delete_notif_embed = EmbedWrapper::new_application(&ctx)
-        .title("User deleted 3:")
-        .description(format!(
-          "{user_mention}, also known as {} got their spot deleted in the petring 3':",
-          deleted_user.username
-        ))
-        .author(
-          CreateEmbedAuthor::new(format!("Deleted by: {}", ctx.author().name)).icon_url(&user_pfp),
-        )
-        .color(Color::from_rgb(255, 0, 0));
-
-      let reply = CreateReply::default()
-        .embed(embed)
-        .reply(true)
-        .ephemeral(true);
-      ctx.send(reply).await?;
-
-      Notif::new(&ctx)
-        .set_embed(user_delete_notif_embed)
-        .general(&ctx)
-        .await?;
-    }
-    ResponseTypes::Error(_error) => {
-      let error: ErrorResponse = _error;
-
-      let embed = EmbedWrapper::new_application(&ctx)
-        .title(format!("Error {}", error.status))
-        .description(error.message)
-        .color(Color::from_rgb(255, 0, 0));
+  let deleted_user = match ctx
+    .data()
+    .petring(ctx.guild_id())
+    .delete_user(user_id)
+    .await
+  {
+    Ok(deleted_user) => deleted_user,
+    Err(error) => return send_petring_error_application(ctx, &error).await,
+  };
 
-      let reply = CreateReply::default().embed(embed).reply(true);
-      ctx.send(reply).await?;
-    }
+  audit::record(
+    ctx.data(),
+    AuditEntry::new(
+      ctx.guild_id(),
+      AuditAction::Remove,
+      SubmitType::User,
+      user_id,
+      ctx.author().id,
+    ),
+  );
+  // An open review would otherwise let a later click act on the deleted user.
+  let instance = ctx
+    .data()
+    .cache
+    .read()
+    .get_guild_instance_name(ctx.guild_id());
+  if let Some(instance) = instance {
+    notifs::drop_review_messages(
+      ctx.http(),
+      ctx.data(),
+      &instance,
+      SubmitType::User,
+      user_id,
+      None,
+    )
+    .await;
   }
 
+  let user_mention = user.mention();
+  let user_pfp = user.face();
+
+  let embed = EmbedWrapper::new_application(&ctx)
+    .title("Successfully removed user :3")
+    .description(format!("{user_mention} has been removed :3",))
+    .author(CreateEmbedAuthor::new(format!("Bye {}", user.name)).icon_url(&user_pfp))
+    .color(Color::from_rgb(255, 0, 0));
+
+  let user_delete_notif_embed = EmbedWrapper::new_application(&ctx)
+    .title("User deleted 3:")
+    .description(format!(
+      "{user_mention}, also known as {} got their spot deleted in the petring 3':",
+      deleted_user.username
+    ))
+    .author(
+      CreateEmbedAuthor::new(format!("Deleted by: {}", ctx.author().name)).icon_url(&user_pfp),
+    )
+    .color(Color::from_rgb(255, 0, 0));
+
+  let reply = CreateReply::default()
+    .embed(embed)
+    .reply(true)
+    .ephemeral(true);
+  ctx.send(reply).await?;
+
+  Notif::new(&ctx)
+    .set_embed(user_delete_notif_embed)
+    .general(&ctx)
+    .await?;
+
   Ok(())
 }
@@ -0,0 +1,105 @@
+use crate::collar::MAX_REVIEW_QUORUM;
+
+use super::{
+  CollarAppContext, CollarError, EmbedWrapper, SubmissionType, notifs::SubmitType,
+  send_generic_error_application,
+};
+use poise::serenity_prelude::Color;
+use poise::{CreateReply, command};
+use tracing::info;
+
+#[command(
+  slash_command,
+  description_localized(
+    locale = "en-US",
+    description = "Set how many moderators have to approve a submission before it's verified"
+  ),
+  description_localized(
+    locale = "sv-SE",
+    description = "Ange hur många moderatorer som måste godkänna en inskickning"
+  ),
+  name_localized(locale = "en-US", name = "set_quorum"),
+  name_localized(locale = "sv-SE", name = "ställ_in_beslutförhet"),
+  category = "Review",
+  required_permissions = "MANAGE_GUILD"
+)]
+pub async fn set_quorum(
+  ctx: CollarAppContext<'_>,
+  #[description = "Kind of submission"] submission: SubmissionType,
+  #[description = "Distinct moderators that have to approve"]
+  #[min = 1]
+  #[max = 10]
+  approvals: usize,
+) -> Result<(), CollarError> {
+  let guild_id = match ctx.guild_id() {
+    Some(guild_id) => guild_id,
+    None => return send_generic_error_application(ctx, "Review quorums are per server").await,
+  };
+
+  let approvals = approvals.clamp(1, MAX_REVIEW_QUORUM);
+  let (submit_type, kind) = match submission {
+    SubmissionType::User => (SubmitType::User, "websites"),
+    SubmissionType::Ad => (SubmitType::Ad, "ads"),
+  };
+
+  info!("Setting {kind} quorum for guild {guild_id} to {approvals}");
+  {
+    let mut cache = ctx.data().cache.write();
+    cache.set_review_quorum(guild_id, submit_type, approvals);
+  }
+
+  let embed = EmbedWrapper::new_application(&ctx)
+    .title("Review quorum set!")
+    .description(format!(
+      "New {kind} now need {approvals} approval{} before they're verified",
+      if approvals == 1 { "" } else { "s" }
+    ))
+    .color(Color::from_rgb(0, 255, 0));
+
+  let reply = CreateReply::default()
+    .embed(embed)
+    .reply(true)
+    .ephemeral(true);
+
+  ctx.send(reply).await?;
+  Ok(())
+}
+
+#[command(
+  slash_command,
+  description_localized(
+    locale = "en-US",
+    description = "See how many approvals submissions need before they're verified"
+  ),
+  description_localized(
+    locale = "sv-SE",
+    description = "Se hur många godkännanden en inskickning behöver"
+  ),
+  name_localized(locale = "en-US", name = "get_quorum"),
+  name_localized(locale = "sv-SE", name = "hämta_beslutförhet"),
+  category = "Review",
+  required_permissions = "MANAGE_CHANNELS | BAN_MEMBERS | KICK_MEMBERS | MUTE_MEMBERS"
+)]
+pub async fn get_quorum(ctx: CollarAppContext<'_>) -> Result<(), CollarError> {
+  let guild_id = match ctx.guild_id() {
+    Some(guild_id) => guild_id,
+    None => return send_generic_error_application(ctx, "Review quorums are per server").await,
+  };
+
+  let quorum = ctx.data().cache.read().get_review_quorum(Some(guild_id));
+
+  let embed = EmbedWrapper::new_application(&ctx)
+    .title("Review quorum for this server")
+    .field("Websites", quorum.get(SubmitType::User).to_string(), true)
+    .field("Ads", quorum.get(SubmitType::Ad).to_string(), true)
+    .description("Any moderator can veto a submission to hold it back, whatever the quorum")
+    .color(Color::from_rgb(0, 0, 255));
+
+  let reply = CreateReply::default()
+    .embed(embed)
+    .reply(true)
+    .ephemeral(true);
+
+  ctx.send(reply).await?;
+  Ok(())
+}
@@ -0,0 +1,208 @@
+use crate::collar::{MAX_REJECT_REASONS, RejectReason};
+
+use super::{CollarAppContext, CollarError, EmbedWrapper, send_generic_error_application};
+use poise::{CreateReply, command, serenity_prelude as serenity};
+use serenity::Color;
+use tracing::info;
+
+async fn autocomplete_reason(ctx: CollarAppContext<'_>, partial: &str) -> Vec<String> {
+  let guild_id = match ctx.guild_id() {
+    Some(guild_id) => guild_id,
+    None => return Vec::new(),
+  };
+  let cache = ctx.data().cache.read();
+
+  cache
+    .get_reject_reasons(guild_id)
+    .into_iter()
+    .map(|reason| reason.name)
+    .filter(|name| name.to_lowercase().starts_with(&partial.to_lowercase()))
+    .collect()
+}
+
+#[command(
+  slash_command,
+  subcommands("add", "remove", "list"),
+  subcommand_required,
+  description_localized(
+    locale = "en-US",
+    description = "Manage the preset reasons offered when rejecting a submission"
+  ),
+  description_localized(
+    locale = "sv-SE",
+    description = "Hantera förinställda anledningar när en inskickning avvisas"
+  ),
+  name_localized(locale = "en-US", name = "reject_reasons"),
+  name_localized(locale = "sv-SE", name = "avvisningsanledningar"),
+  category = "Review",
+  required_permissions = "MANAGE_CHANNELS | BAN_MEMBERS | KICK_MEMBERS | MUTE_MEMBERS"
+)]
+pub async fn reject_reasons(_ctx: CollarAppContext<'_>) -> Result<(), CollarError> {
+  Ok(())
+}
+
+#[command(
+  slash_command,
+  description_localized(
+    locale = "en-US",
+    description = "Add a preset rejection reason, or change the one with the same name"
+  ),
+  description_localized(
+    locale = "sv-SE",
+    description = "Lägg till en förinställd avvisningsanledning"
+  ),
+  required_permissions = "MANAGE_CHANNELS | BAN_MEMBERS | KICK_MEMBERS | MUTE_MEMBERS"
+)]
+pub async fn add(
+  ctx: CollarAppContext<'_>,
+  #[description = "Short name shown in the menu"]
+  #[max_length = 100]
+  name: String,
+  #[description = "What the submitter gets told"]
+  #[min_length = 10]
+  #[max_length = 1000]
+  text: String,
+) -> Result<(), CollarError> {
+  let guild_id = match ctx.guild_id() {
+    Some(guild_id) => guild_id,
+    None => return send_generic_error_application(ctx, "Rejection reasons are per server").await,
+  };
+
+  let name = name.trim().to_string();
+  let replaces = {
+    let mut cache = ctx.data().cache.write();
+    let reasons = cache.get_reject_reasons(guild_id);
+    let replaces = reasons
+      .iter()
+      .any(|reason| reason.name.eq_ignore_ascii_case(&name));
+
+    match !replaces && reasons.len() >= MAX_REJECT_REASONS {
+      true => None,
+      false => {
+        info!("Setting rejection reason {name} for guild {guild_id}");
+        cache.set_reject_reason(
+          guild_id,
+          RejectReason {
+            name: name.clone(),
+            text: text.clone(),
+          },
+        );
+        Some(replaces)
+      }
+    }
+  };
+
+  let replaces = match replaces {
+    Some(replaces) => replaces,
+    None => {
+      return send_generic_error_application(
+        ctx,
+        &format!("You can have at most {MAX_REJECT_REASONS} reasons, remove one first"),
+      )
+      .await;
+    }
+  };
+
+  let embed = EmbedWrapper::new_application(&ctx)
+    .title(match replaces {
+      true => "Rejection reason updated!",
+      false => "Rejection reason added!",
+    })
+    .field(name, text, false)
+    .color(Color::from_rgb(0, 255, 0));
+
+  let reply = CreateReply::default()
+    .embed(embed)
+    .reply(true)
+    .ephemeral(true);
+
+  ctx.send(reply).await?;
+  Ok(())
+}
+
+#[command(
+  slash_command,
+  description_localized(locale = "en-US", description = "Remove a preset rejection reason"),
+  description_localized(
+    locale = "sv-SE",
+    description = "Ta bort en förinställd avvisningsanledning"
+  ),
+  required_permissions = "MANAGE_CHANNELS | BAN_MEMBERS | KICK_MEMBERS | MUTE_MEMBERS"
+)]
+pub async fn remove(
+  ctx: CollarAppContext<'_>,
+  #[description = "Name of the reason"]
+  #[autocomplete = "autocomplete_reason"]
+  name: String,
+) -> Result<(), CollarError> {
+  let guild_id = match ctx.guild_id() {
+    Some(guild_id) => guild_id,
+    None => return send_generic_error_application(ctx, "Rejection reasons are per server").await,
+  };
+
+  let removed = ctx
+    .data()
+    .cache
+    .write()
+    .remove_reject_reason(guild_id, name.trim());
+
+  let removed = match removed {
+    Some(removed) => removed,
+    None => {
+      return send_generic_error_application(ctx, &format!("There's no reason named {name}")).await;
+    }
+  };
+
+  let embed = EmbedWrapper::new_application(&ctx)
+    .title("Rejection reason removed!")
+    .field(removed.name, removed.text, false)
+    .color(Color::from_rgb(0, 255, 0));
+
+  let reply = CreateReply::default()
+    .embed(embed)
+    .reply(true)
+    .ephemeral(true);
+
+  ctx.send(reply).await?;
+  Ok(())
+}
+
+#[command(
+  slash_command,
+  description_localized(locale = "en-US", description = "List the preset rejection reasons"),
+  description_localized(
+    locale = "sv-SE",
+    description = "Lista de förinställda avvisningsanledningarna"
+  ),
+  required_permissions = "MANAGE_CHANNELS | BAN_MEMBERS | KICK_MEMBERS | MUTE_MEMBERS"
+)]
+pub async fn list(ctx: CollarAppContext<'_>) -> Result<(), CollarError> {
+  let guild_id = match ctx.guild_id() {
+    Some(guild_id) => guild_id,
+    None => return send_generic_error_application(ctx, "Rejection reasons are per server").await,
+  };
+
+  let reasons = ctx.data().cache.read().get_reject_reasons(guild_id);
+
+  let mut embed = EmbedWrapper::new_application(&ctx)
+    .title("Rejection reasons for this server")
+    .color(Color::from_rgb(0, 0, 255));
+
+  if reasons.is_empty() {
+    embed = embed.description("None yet! Add one using `/reject_reasons add`");
+  }
+
+  // Cut short so a full list stays under the embed size limit.
+  for reason in reasons {
+    let text = reason.text.chars().take(120).collect::<String>();
+    embed = embed.field(reason.name, text, false);
+  }
+
+  let reply = CreateReply::default()
+    .embed(embed)
+    .reply(true)
+    .ephemeral(true);
+
+  ctx.send(reply).await?;
+  Ok(())
+}
@@ -0,0 +1,566 @@
+use super::{
+  Collar, CollarError, EmbedWrapper, NotifChannelType,
+  audit::{self, AuditAction, AuditEntry},
+  notif_outbox::Delivered,
+  notifs::{self, Notif, SubmitType},
+  site_check,
+};
+use chrono::{DateTime, Utc};
+use poise::serenity_prelude::{
+  self as serenity, ButtonStyle, Color, ComponentInteraction, CreateActionRow, CreateButton,
+  CreateEmbed, CreateInteractionResponse, CreateInteractionResponseMessage, GuildId, Mentionable,
+  UserId,
+};
+use reqwest::{Client, Url};
+use serde::{Deserialize, Serialize};
+use tokio::time::{Duration, Instant, interval_at};
+use tracing::{error, info, warn};
+
+/// How often sites get checked unless `SITE_CHECK_INTERVAL_MINUTES` says otherwise.
+const DEFAULT_CHECK_INTERVAL: Duration = Duration::from_secs(6 * 60 * 60);
+const SITE_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Consecutive failed checks before each escalation step.
+const OWNER_AFTER_FAILURES: u32 = 3;
+const MODERATORS_AFTER_FAILURES: u32 = 6;
+const REMOVAL_AFTER_FAILURES: u32 = 10;
+
+const SUMMARY_EVERY: chrono::Duration = chrono::Duration::weeks(1);
+/// Failing sites listed in the weekly summary, the rest are only counted.
+const SUMMARY_LIMIT: usize = 15;
+
+const REMOVAL_PREFIX: &str = "health:remove:";
+
+/// How far collar got in chasing a broken site, each step is only taken once.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Escalation {
+  #[default]
+  None,
+  OwnerNotified,
+  ModeratorsAlerted,
+  RemovalOffered,
+}
+
+impl Escalation {
+  fn for_failures(failures: u32) -> Self {
+    match failures {
+      n if n >= REMOVAL_AFTER_FAILURES => Escalation::RemovalOffered,
+      n if n >= MODERATORS_AFTER_FAILURES => Escalation::ModeratorsAlerted,
+      n if n >= OWNER_AFTER_FAILURES => Escalation::OwnerNotified,
+      _ => Escalation::None,
+    }
+  }
+}
+
+/// What the monitor knows about a verified member's site.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SiteHealth {
+  pub instance: String,
+  pub discord_id: UserId,
+  pub username: String,
+  pub url: String,
+  pub consecutive_failures: u32,
+  pub last_checked: Option<DateTime<Utc>>,
+  pub last_ok: Option<DateTime<Utc>>,
+  pub last_problem: Option<String>,
+  #[serde(default)]
+  pub escalation: Escalation,
+}
+
+#[derive(Debug)]
+enum SiteProblem {
+  Unreachable(String),
+  Status(reqwest::StatusCode),
+  NoLinkBack(String),
+}
+
+impl std::fmt::Display for SiteProblem {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      SiteProblem::Unreachable(err) => write!(f, "unreachable: {err}"),
+      SiteProblem::Status(status) => write!(f, "responded with {status}"),
+      SiteProblem::NoLinkBack(ring) => write!(f, "doesn't link back to {ring}"),
+    }
+  }
+}
+
+/// `https://webring.pet/` → `webring.pet`, so both http and https links count.
+fn ring_host(web_base_url: &str) -> &str {
+  web_base_url
+    .trim_start_matches("https://")
+    .trim_start_matches("http://")
+    .trim_end_matches('/')
+}
+
+async fn check_site(client: &Client, url: &str, web_base_url: &str) -> Result<(), SiteProblem> {
+  let url = Url::parse(url).map_err(|err| SiteProblem::Unreachable(err.to_string()))?;
+  let response = site_check::get_public(client, url, SITE_TIMEOUT)
+    .await
+    .map_err(SiteProblem::Unreachable)?;
+
+  if !response.status().is_success() {
+    return Err(SiteProblem::Status(response.status()));
+  }
+
+  let page = site_check::read_capped(response, site_check::MAX_PAGE_BYTES)
+    .await
+    .map_err(|err| SiteProblem::Unreachable(err.to_string()))?;
+  let page = String::from_utf8_lossy(&page);
+  let ring = ring_host(web_base_url);
+  if !page.contains(ring) {
+    return Err(SiteProblem::NoLinkBack(ring.to_string()));
+  }
+
+  Ok(())
+}
+
+/// Starts checking every verified member's site in the background, next to the token refresh.
+pub fn spawn(data: Collar, ctx: serenity::Context) {
+  let period = std::env::var("SITE_CHECK_INTERVAL_MINUTES")
+    .ok()
+    .and_then(|minutes| minutes.parse::<u64>().ok())
+    .filter(|minutes| *minutes > 0)
+    .map(|minutes| Duration::from_secs(minutes * 60))
+    .unwrap_or(DEFAULT_CHECK_INTERVAL);
+
+  tokio::spawn(async move {
+    // Leave startup alone, the first round isn't urgent.
+    let mut interval = interval_at(Instant::now() + Duration::from_secs(5 * 60), period);
+
+    loop {
+      interval.tick().await;
+
+      let instances = data.cache.read().get_instance_names();
+      for instance in instances {
+        info!("Starting site health checks for {instance}");
+        if let Err(err) = check_instance(&data, &ctx, &instance).await {
+          error!("Site health checks for {instance} failed: {err}");
+        }
+      }
+
+      send_summary_if_due(&data, &ctx).await;
+    }
+  });
+}
+
+async fn check_instance(
+  data: &Collar,
+  ctx: &serenity::Context,
+  instance: &str,
+) -> Result<(), CollarError> {
+  let users = data.petring_instance(instance).get_known_users().await?;
+  let members = users
+    .into_iter()
+    .filter(|user| user.verified && user.discord_id != 0)
+    .collect::<Vec<_>>();
+
+  let (web_base_url, guilds) = {
+    let mut cache = data.cache.write();
+    let discord_ids = members
+      .iter()
+      .map(|user| UserId::new(user.discord_id))
+      .collect::<Vec<_>>();
+    cache.retain_site_health(instance, &discord_ids);
+
+    let web_base_url = match cache.get_instance(instance) {
+      Some(found) => found.get_web_base_url(),
+      None => return Ok(()),
+    };
+    (web_base_url, cache.get_guilds_for_instance(instance))
+  };
+
+  for user in members {
+    let discord_id = UserId::new(user.discord_id);
+    let previous = {
+      let cache = data.cache.read();
+      cache.get_site_health(instance, discord_id)
+    };
+    let mut site = previous.unwrap_or_else(|| SiteHealth {
+      instance: instance.to_string(),
+      discord_id,
+      username: user.username.clone(),
+      url: user.url.clone(),
+      consecutive_failures: 0,
+      last_checked: None,
+      last_ok: None,
+      last_problem: None,
+      escalation: Escalation::None,
+    });
+
+    // A new url is a new site, whatever happened to the old one doesn't count.
+    if site.url != user.url {
+      site.url = user.url.clone();
+      site.consecutive_failures = 0;
+      site.escalation = Escalation::None;
+    }
+    site.username = user.username.clone();
+
+    let now = Utc::now();
+    site.last_checked = Some(now);
+
+    match check_site(&data.fetch_client, &site.url, &web_base_url).await {
+      Ok(()) => {
+        if site.escalation >= Escalation::ModeratorsAlerted {
+          let embed = site_embed(ctx, &site, "A member's site is back :3")
+            .description(format!(
+              "{}'s site is working again and links back to the ring",
+              discord_id.mention()
+            ))
+            .color(Color::from_rgb(0, 255, 0));
+          post_to_guilds(ctx, data, &guilds, NotifChannelType::General, embed, vec![]).await;
+        }
+
+        site.consecutive_failures = 0;
+        site.last_ok = Some(now);
+        site.last_problem = None;
+        site.escalation = Escalation::None;
+      }
+      Err(problem) => {
+        warn!(
+          "Site check for {} ({}) failed: {problem}",
+          site.username, site.url
+        );
+        site.consecutive_failures += 1;
+        site.last_problem = Some(problem.to_string());
+
+        let due = Escalation::for_failures(site.consecutive_failures);
+        if due > site.escalation {
+          escalate(data, ctx, &guilds, &site, due).await;
+          site.escalation = due;
+        }
+      }
+    }
+
+    data.cache.write().set_site_health(site);
+  }
+
+  Ok(())
+}
+
+fn site_embed(ctx: &serenity::Context, site: &SiteHealth, title: &str) -> CreateEmbed {
+  let mut embed = EmbedWrapper::new_event(ctx)
+    .title(title)
+    .field("Member", site.discord_id.mention().to_string(), true)
+    .field("Site", site.url.clone(), true)
+    .field(
+      "Failed checks in a row",
+      site.consecutive_failures.to_string(),
+      true,
+    );
+
+  if let Some(problem) = &site.last_problem {
+    embed = embed.field("Problem", problem.clone(), false);
+  }
+
+  embed
+}
+
+async fn escalate(
+  data: &Collar,
+  ctx: &serenity::Context,
+  guilds: &[GuildId],
+  site: &SiteHealth,
+  step: Escalation,
+) {
+  match step {
+    Escalation::None => (),
+    Escalation::OwnerNotified => {
+      info!("Telling {} their site is failing", site.discord_id);
+      let embed = site_embed(ctx, site, "Your PetRing site needs a look 3:")
+        .description(format!(
+          "Hi {}, collar couldn't check your site the last few times. Please make sure it's up and \
+           still links back to the ring, or it might be removed",
+          site.discord_id.mention()
+        ))
+        .color(Color::from_rgb(255, 0, 0));
+
+      if let Err(err) = Notif::from_embed(embed)
+        .dm_notif_background(&ctx.http, data, guilds, site.discord_id.get(), vec![])
+        .await
+      {
+        error!("Failed to tell {} about their site: {err}", site.discord_id);
+      }
+    }
+    Escalation::ModeratorsAlerted => {
+      let embed = site_embed(ctx, site, "A member's site keeps failing 3:")
+        .description("The owner was already told, they haven't fixed it yet")
+        .color(Color::from_rgb(255, 0, 0));
+      post_to_guilds(ctx, data, guilds, NotifChannelType::General, embed, vec![]).await;
+    }
+    Escalation::RemovalOffered => {
+      let embed = site_embed(ctx, site, "Remove this member's site? 3:")
+        .description("It's still failing after telling the owner and moderators")
+        .color(Color::from_rgb(255, 0, 0));
+      let remove_button = CreateButton::new(format!("{REMOVAL_PREFIX}{}", site.discord_id))
+        .label("Remove from the ring")
+        .style(ButtonStyle::Danger);
+      post_to_guilds(
+        ctx,
+        data,
+        guilds,
+        NotifChannelType::UserSubmit,
+        embed,
+        vec![CreateActionRow::Buttons(vec![remove_button])],
+      )
+      .await;
+    }
+  }
+}
+
+/// Alerts go to the general channel, removal offers to where moderators review users.
+async fn post_to_guilds(
+  ctx: &serenity::Context,
+  data: &Collar,
+  guilds: &[GuildId],
+  channel: NotifChannelType,
+  embed: CreateEmbed,
+  components: Vec<CreateActionRow>,
+) {
+  let notif = Notif::from_embed(embed);
+
+  for guild_id in guilds {
+    match notif
+      .send_to_channel(&ctx.http, data, *guild_id, channel, components.clone())
+      .await
+    {
+      Delivered::Sent(_) | Delivered::Queued => (),
+      Delivered::NoChannel => {
+        warn!("Guild {guild_id} has no {channel:?} channel for site health alerts")
+      }
+      Delivered::Failed(err) => error!("Failed to post site health alert in {guild_id}: {err}"),
+    }
+  }
+}
+
+async fn send_summary_if_due(data: &Collar, ctx: &serenity::Context) {
+  let now = Utc::now();
+  let (guilds, sites) = {
+    let mut cache = data.cache.write();
+    if cache
+      .get_last_health_summary()
+      .is_some_and(|last| now - last < SUMMARY_EVERY)
+    {
+      return;
+    }
+
+    cache.set_last_health_summary(now);
+
+    let guilds = cache
+      .get_instance_names()
+      .into_iter()
+      .flat_map(|instance| {
+        cache
+          .get_guilds_for_instance(&instance)
+          .into_iter()
+          .map(move |guild_id| (guild_id, instance.clone()))
+      })
+      .collect::<Vec<_>>();
+    let sites = cache.get_instance_names().into_iter().map(|instance| {
+      let sites = cache.get_site_health_for(&instance);
+      (instance, sites)
+    });
+
+    (guilds, sites.collect::<Vec<_>>())
+  };
+
+  for (guild_id, instance) in guilds {
+    let sites = sites
+      .iter()
+      .find(|(name, _)| *name == instance)
+      .map(|(_, sites)| sites.as_slice())
+      .unwrap_or_default();
+    let mut failing = sites
+      .iter()
+      .filter(|site| site.consecutive_failures > 0)
+      .collect::<Vec<_>>();
+    failing.sort_by_key(|site| std::cmp::Reverse(site.consecutive_failures));
+
+    let description = match failing.is_empty() {
+      true => String::from("Every site is up and links back to the ring :3"),
+      false => failing
+        .iter()
+        .take(SUMMARY_LIMIT)
+        .map(|site| {
+          format!(
+            "{} {} ({} in a row): {}",
+            site.discord_id.mention(),
+            site.url,
+            site.consecutive_failures,
+            site.last_problem.as_deref().unwrap_or("unknown")
+          )
+        })
+        .collect::<Vec<_>>()
+        .join("\n"),
+    };
+
+    let embed = EmbedWrapper::new_event(ctx)
+      .title("Weekly site health")
+      .field("Checked", sites.len().to_string(), true)
+      .field("Healthy", (sites.len() - failing.len()).to_string(), true)
+      .field("Failing", failing.len().to_string(), true)
+      .description(description)
+      .color(Color::from_rgb(0, 0, 255));
+    post_to_guilds(
+      ctx,
+      data,
+      &[guild_id],
+      NotifChannelType::General,
+      embed,
+      vec![],
+    )
+    .await;
+  }
+}
+
+/// The member a "Remove from the ring" button is for.
+pub fn parse_removal(custom_id: &str) -> Option<UserId> {
+  custom_id
+    .strip_prefix(REMOVAL_PREFIX)?
+    .parse::<u64>()
+    .ok()
+    .filter(|id| *id != 0)
+    .map(UserId::new)
+}
+
+/// Handles "Remove from the ring" on an escalated site health alert.
+pub async fn handle_removal(
+  shard: &serenity::Context,
+  data: &Collar,
+  mci: &ComponentInteraction,
+  discord_id: UserId,
+) -> Result<(), CollarError> {
+  if !notifs::pressed_by_moderator(mci) {
+    warn!(
+      "{} pressed remove on {discord_id} without moderator permissions",
+      mci.user.id
+    );
+    return notifs::refuse_non_moderator(shard, mci).await;
+  }
+
+  let instance = {
+    let cache = data.cache.read();
+    cache.get_guild_instance_name(mci.guild_id)
+  };
+  let instance = match instance {
+    Some(instance) => instance,
+    None => {
+      let embed = EmbedWrapper::new_event(shard)
+        .title("This server's PetRing instance isn't configured 3:")
+        .description("Pick another one with `/set_instance`")
+        .color(Color::from_rgb(255, 0, 0));
+      mci
+        .create_response(
+          &shard.http,
+          CreateInteractionResponse::Message(
+            CreateInteractionResponseMessage::new()
+              .embed(embed)
+              .ephemeral(true),
+          ),
+        )
+        .await?;
+      return Ok(());
+    }
+  };
+  let _claim = match data
+    .reviews_in_flight
+    .claim(&instance, SubmitType::User, discord_id)
+  {
+    Some(claim) => claim,
+    None => {
+      let embed = EmbedWrapper::new_event(shard)
+        .title("Someone's already handling this member :3")
+        .color(Color::from_rgb(255, 0, 0));
+      mci
+        .create_response(
+          &shard.http,
+          CreateInteractionResponse::Message(
+            CreateInteractionResponseMessage::new()
+              .embed(embed)
+              .ephemeral(true),
+          ),
+        )
+        .await?;
+      return Ok(());
+    }
+  };
+
+  let problem = {
+    let cache = data.cache.read();
+    cache
+      .get_site_health(&instance, discord_id)
+      .and_then(|site| site.last_problem)
+  };
+
+  let embed = match data.petring(mci.guild_id).delete_user(discord_id).await {
+    Ok(deleted) => {
+      info!("Removed {discord_id} after failed site health checks");
+      audit::record(
+        data,
+        AuditEntry::new(
+          mci.guild_id,
+          AuditAction::Remove,
+          SubmitType::User,
+          discord_id,
+          mci.user.id,
+        )
+        .reason(format!(
+          "Site failed health checks: {}",
+          problem.as_deref().unwrap_or("unknown")
+        )),
+      );
+
+      data.cache.write().remove_site_health(&instance, discord_id);
+      notifs::drop_review_messages(
+        &shard.http,
+        data,
+        &instance,
+        SubmitType::User,
+        discord_id,
+        None,
+      )
+      .await;
+
+      EmbedWrapper::new_event(shard)
+        .title("Removed from the ring")
+        .description(format!(
+          "{} ({}) was removed by {}",
+          discord_id.mention(),
+          deleted.url,
+          mci.user.mention()
+        ))
+        .color(Color::from_rgb(255, 0, 0))
+    }
+    Err(err) => {
+      error!("Failed to remove {discord_id} after failed site health checks: {err}");
+      let embed = EmbedWrapper::new_event(shard)
+        .title("Failed to remove 3:")
+        .description(format!("Couldn't remove {}: {err}", discord_id.mention()))
+        .color(Color::from_rgb(255, 0, 0));
+      mci
+        .create_response(
+          &shard.http,
+          CreateInteractionResponse::Message(
+            CreateInteractionResponseMessage::new()
+              .embed(embed)
+              .ephemeral(true),
+          ),
+        )
+        .await?;
+      return Ok(());
+    }
+  };
+
+  mci
+    .create_response(
+      &shard.http,
+      CreateInteractionResponse::UpdateMessage(
+        CreateInteractionResponseMessage::new()
+          .embed(embed)
+          .components(vec![]),
+      ),
+    )
+    .await?;
+
+  Ok(())
+}
@@ -1,144 +1,92 @@
-use super::{Cache, Collar, CollarError, Secrets};
+use super::{
+  CollarError, Secrets,
+  client::{PetringClient, PetringError},
+  supervisor::Availability,
+  tokens::TokenError,
+};
+use chrono::Utc;
 use dotenvy::dotenv;
-use reqwest::{Client, Method, StatusCode};
+use rand::Rng;
+use reqwest::{
+  Client, Method, StatusCode,
+  header::{HeaderMap, HeaderValue},
+};
 use serde::{Deserialize, Serialize};
 use std::{fmt::Debug, time::Duration};
-use tokio::time::sleep;
+use tokio::time::{Instant, sleep};
 #[allow(unused_imports)]
 use tracing::{debug, error, info};
 
 pub async fn make_reqwest_client() -> Result<Client, CollarError> {
-  Ok(Client::new())
+  Ok(Client::builder().build()?)
 }
 
+const TOKEN_TIMEOUT: Duration = Duration::from_secs(60);
+/// How long a request waits for a token that's being fetched, interactions can't wait long.
+const TOKEN_WAIT: Duration = Duration::from_secs(2);
+
 #[derive(Serialize, Deserialize)]
 struct GetSecretsRequest {
   bot_token: String,
 }
 
 impl Secrets {
+  /// Bootstraps a fresh pair of tokens with the bot token, one attempt.
   pub async fn get_secrets(
     &self,
     http_client: Client,
     api_base_url: String,
-  ) -> Result<Self, CollarError> {
+  ) -> Result<Self, TokenError> {
     dotenv().ok();
-    let token = std::env::var("DISCORD_BOT_TOKEN").expect("missing DISCORD_BOT_TOKEN");
+    let token = std::env::var("DISCORD_BOT_TOKEN").map_err(|_| TokenError::MissingBotToken)?;
 
     let body = GetSecretsRequest { bot_token: token };
-    let mut status: StatusCode = StatusCode::IM_A_TEAPOT;
-    let mut response: String = String::new();
-
-    while status == StatusCode::IM_A_TEAPOT {
-      let url = format!("{api_base_url}/bot/setup");
-      let resp = http_client
-        .post(url)
-        .json(&body)
-        .timeout(Duration::from_secs(60));
-      let got_response = match resp.send().await {
-        Ok(response) => {
-          status = response.status();
-          response.text().await?
-        }
-        Err(err) => {
-          sleep(Duration::from_secs(1)).await;
-          error!("Failed to get secrets, retrying: {err}",);
-          continue;
-        }
-      };
-
-      response = got_response;
-      break;
-    }
-
-    if status.is_success() {
-      let secrets = match serde_json::from_str::<Secrets>(&response) {
-        Ok(secrets) => secrets,
-        Err(err) => {
-          return Err(CollarError::from(format!(
-            "Could not deserialize secrets: {err}"
-          )));
-        }
-      };
-      return Ok(secrets);
-    }
-
-    Err(CollarError::from(format!(
-      "Could not get secrets: {response}"
-    )))
+    let url = format!("{api_base_url}/bot/setup");
+    let resp = http_client
+      .post(url)
+      .json(&body)
+      .timeout(TOKEN_TIMEOUT)
+      .send()
+      .await
+      .map_err(TokenError::Unreachable)?;
+
+    parse_secrets(resp).await
   }
 
+  /// Trades the refresh token for a new pair, one attempt.
   pub async fn refresh_secrets(
     self,
     http_client: Client,
     api_base_url: String,
-  ) -> Result<Self, CollarError> {
+  ) -> Result<Self, TokenError> {
     let body = RefreshTokenRequest {
       access_token: self.access_token,
       refresh_token: self.refresh_token,
     };
 
-    let mut response = String::new();
-    let mut status: StatusCode = StatusCode::IM_A_TEAPOT;
-
-    while response.is_empty() && status == StatusCode::IM_A_TEAPOT {
-      let url = format!("{api_base_url}/bot/refresh");
-      let req = http_client.post(url).json(&body);
-      let resp = req.send().await;
-      match resp {
-        Ok(resp) => {
-          status = resp.status();
-          response = resp.text().await?;
-          break;
-        }
-        Err(err) => {
-          error!("Failed to refresh secrets: {err}");
-          sleep(Duration::from_secs(1)).await;
-          continue;
-        }
-      }
-    }
-
-    if status.is_success() {
-      let secrets: Secrets = serde_json::from_str(&response).unwrap();
-      return Ok(secrets);
-    }
-
-    response = String::new();
-    status = StatusCode::IM_A_TEAPOT;
-
-    let cached_secrets: Secrets = Cache::new().read_from_disk()?.secrets;
-    let new_body = RefreshTokenRequest {
-      access_token: cached_secrets.access_token,
-      refresh_token: cached_secrets.refresh_token,
-    };
-
-    while response.is_empty() && status == StatusCode::IM_A_TEAPOT {
-      let url = format!("{api_base_url}/bot/refresh");
-      let resp = http_client.post(url).json(&new_body);
-      match resp.send().await {
-        Ok(resp) => {
-          status = resp.status();
-          response = resp.text().await?;
-          break;
-        }
-        Err(_) => {
-          sleep(Duration::from_secs(1)).await;
-          continue;
-        }
-      }
-    }
+    let url = format!("{api_base_url}/bot/refresh");
+    let resp = http_client
+      .post(url)
+      .json(&body)
+      .timeout(TOKEN_TIMEOUT)
+      .send()
+      .await
+      .map_err(TokenError::Unreachable)?;
 
-    if status.is_success() {
-      let secrets = serde_json::from_str::<Secrets>(&response).unwrap();
-      info!("Refreshed secrets");
+    parse_secrets(resp).await
+  }
+}
 
-      return Ok(secrets);
-    }
+async fn parse_secrets(resp: reqwest::Response) -> Result<Secrets, TokenError> {
+  let status = resp.status();
+  let body = resp.text().await.map_err(TokenError::Unreachable)?;
 
-    Err(CollarError::from(format!(
-      "Failed to refresh secrets: {response}"
-    )))
+  if status.is_success() {
+    return serde_json::from_str::<Secrets>(&body).map_err(TokenError::Decode);
+  }
+  match status.is_client_error() {
+    true => Err(TokenError::Rejected(status, body)),
+    false => Err(TokenError::Unavailable(status, body)),
   }
 }
 
@@ -154,140 +102,267 @@ pub struct ErrorResponse {
   pub message: String,
 }
 
-#[derive(Deserialize, Debug)]
-pub enum ResponseTypes<R> {
-  Success(R),
-  Error(ErrorResponse),
-}
-
-pub async fn make_request<T, R>(
-  collar: Collar,
-  body: Option<T>,
-  route: &str,
-  method: Method,
-) -> Result<ResponseTypes<R>, CollarError>
+fn parse_response<R>(status: StatusCode, resp_text: String) -> Result<R, PetringError>
 where
-  T: Serialize + Clone,
   R: for<'de> Deserialize<'de> + Debug,
 {
-  let mut cache = collar.cache.lock().await;
-  let url = format!("{}{}", cache.get_api_base_url(), route);
-  let http_client = collar.http_client;
-  let secrets = cache.get_secrets();
+  debug!("Response_text: {resp_text}");
+
+  if status.is_success() {
+    return match serde_json::from_str::<R>(&resp_text) {
+      Ok(return_type) => Ok(return_type),
+      Err(err) => {
+        error!("Failed to convert response to json: {err}, response: {resp_text}");
+        Err(PetringError::Decode {
+          status,
+          body: resp_text,
+          source: err,
+        })
+      }
+    };
+  }
 
-  info!("Making request to {url}");
+  match serde_json::from_str::<ErrorResponse>(&resp_text) {
+    Ok(error) => Err(PetringError::Api {
+      status: StatusCode::from_u16(error.status).unwrap_or(status),
+      message: error.message,
+    }),
+    Err(err) => {
+      error!("Failed to convert response to json: {err}, response: {resp_text}");
+      Err(PetringError::Decode {
+        status,
+        body: resp_text,
+        source: err,
+      })
+    }
+  }
+}
 
-  let mut headers = reqwest::header::HeaderMap::new();
+fn make_headers(method: &Method, secrets: &Secrets) -> Result<HeaderMap, PetringError> {
+  let mut headers = HeaderMap::new();
 
   if method != Method::GET {
     headers.insert(
       reqwest::header::CONTENT_TYPE,
-      reqwest::header::HeaderValue::from_str("application/json")?,
+      HeaderValue::from_static("application/json"),
     );
   }
 
   headers.insert(
     reqwest::header::ACCEPT,
-    reqwest::header::HeaderValue::from_str("application/json")?,
+    HeaderValue::from_static("application/json"),
   );
   headers.insert(
     reqwest::header::AUTHORIZATION,
-    reqwest::header::HeaderValue::from_str(&format!("Bearer {}", &secrets.access_token))?,
+    HeaderValue::from_str(&format!("Bearer {}", &secrets.access_token))
+      .map_err(|err| PetringError::Auth(err.into()))?,
   );
 
-  info!("With headers: {headers:?}");
-
-  let mut req = http_client
-    .request(method.clone(), url.clone())
-    .headers(headers.clone());
+  Ok(headers)
+}
 
-  if let Some(body) = body.clone() {
-    req = req.json(&body);
-  }
+/// How long a request to a route may take, first matching prefix wins.
+const ROUTE_TIMEOUTS: &[(&str, Duration)] = &[
+  ("/get/", Duration::from_secs(10)),
+  ("/post/", Duration::from_secs(20)),
+  ("/patch/", Duration::from_secs(15)),
+  ("/delete/", Duration::from_secs(15)),
+];
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Attempts per request, counting the first one.
+const MAX_ATTEMPTS: u32 = 3;
+const RETRY_BASE: Duration = Duration::from_millis(250);
+
+fn route_timeout(route: &str) -> Duration {
+  ROUTE_TIMEOUTS
+    .iter()
+    .find(|(prefix, _)| route.starts_with(prefix))
+    .map(|(_, timeout)| *timeout)
+    .unwrap_or(DEFAULT_TIMEOUT)
+}
 
-  let resp = req.send().await?;
-  if resp.status().is_success() {
-    let resp_text = resp.text().await?;
-    debug!("Response_text: {resp_text}");
+/// Half of `delay` plus a random part of the other half, so retries from many tasks spread out.
+pub fn jittered(delay: Duration) -> Duration {
+  let half = delay / 2;
+  half + half.mul_f64(rand::thread_rng().r#gen::<f64>())
+}
 
-    return match serde_json::from_str::<R>(&resp_text) {
-      Ok(return_type) => Ok(ResponseTypes::Success(return_type)),
-      Err(err) => {
-        error!("Failed to convert response to json: {err}, response: {resp_text}");
-        Ok(ResponseTypes::Error(ErrorResponse {
-          status: 500,
-          message: format!("Failed to convert response to json: {err}, response: {resp_text}"),
-        }))
-      }
-    };
-  } else if resp.status() != 401 {
-    let resp_text = resp.text().await?;
-    debug!("Response_text: {resp_text}");
+fn is_idempotent(method: &Method) -> bool {
+  matches!(
+    *method,
+    Method::GET | Method::HEAD | Method::PUT | Method::DELETE | Method::OPTIONS
+  )
+}
 
-    return match serde_json::from_str::<ErrorResponse>(&resp_text) {
-      Ok(error) => Ok(ResponseTypes::Error(error)),
-      Err(err) => {
-        error!("Failed to convert response to json: {err}, response: {resp_text}");
-        Ok(ResponseTypes::Error(ErrorResponse {
-          status: 500,
-          message: format!("Failed to convert response to json: {err}, response: {resp_text}"),
-        }))
-      }
-    };
+/// Whether the request is worth sending again. A refused connection never reached the API, so
+/// even a submission can go again, anything else is only retried for idempotent methods.
+fn should_retry(method: &Method, outcome: &Result<reqwest::Response, reqwest::Error>) -> bool {
+  match outcome {
+    Err(err) if err.is_connect() => true,
+    Err(_) => is_idempotent(method),
+    Ok(resp) => {
+      is_idempotent(method)
+        && matches!(
+          resp.status(),
+          StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE | StatusCode::GATEWAY_TIMEOUT
+        )
+    }
   }
+}
 
-  if resp.status() == 401 {
-    info!("Invalid token, refreshing secrets");
-    cache.refresh_secrets(&http_client).await?;
-    let secrets = cache.get_secrets();
-
-    headers = reqwest::header::HeaderMap::new();
+/// Sends the request, retrying with backoff while [`should_retry`] says so. The body is
+/// attached to every attempt, `retries` counts the attempts after the first.
+async fn send_with_retries<T>(
+  http_client: &Client,
+  method: &Method,
+  url: &str,
+  secrets: &Secrets,
+  body: Option<&T>,
+  timeout: Duration,
+  retries: &mut u32,
+) -> Result<Result<reqwest::Response, reqwest::Error>, PetringError>
+where
+  T: Serialize,
+{
+  let mut attempt = 1;
+  loop {
+    let mut req = http_client
+      .request(method.clone(), url)
+      .headers(make_headers(method, secrets)?)
+      .timeout(timeout);
+    if let Some(body) = body {
+      req = req.json(body);
+    }
 
-    if method != Method::GET {
-      headers.insert(
-        reqwest::header::CONTENT_TYPE,
-        reqwest::header::HeaderValue::from_str("application/json")?,
-      );
+    let outcome = req.send().await;
+    if attempt >= MAX_ATTEMPTS || !should_retry(method, &outcome) {
+      return Ok(outcome);
     }
 
-    headers.insert(
-      reqwest::header::ACCEPT,
-      reqwest::header::HeaderValue::from_str("application/json")?,
-    );
-    headers.insert(
-      reqwest::header::AUTHORIZATION,
-      reqwest::header::HeaderValue::from_str(&format!("Bearer {}", &secrets.access_token))?,
-    );
+    let delay = jittered(RETRY_BASE * 2u32.pow(attempt));
+    match &outcome {
+      Ok(resp) => info!(
+        "{method} {url} got {}, retrying in {delay:?}",
+        resp.status()
+      ),
+      Err(err) => info!("{method} {url} failed ({err}), retrying in {delay:?}"),
+    }
+    sleep(delay).await;
+    attempt += 1;
+    *retries += 1;
   }
+}
 
-  let new_req = http_client.request(method, url).headers(headers);
-  let resp = new_req.send().await?;
+pub async fn make_request<T, R>(
+  petring: &PetringClient,
+  body: Option<T>,
+  route: &str,
+  method: Method,
+) -> Result<R, PetringError>
+where
+  T: Serialize + Clone,
+  R: for<'de> Deserialize<'de> + Debug,
+{
+  let instance_name = petring.target.resolve(&petring.cache.read())?;
+  let started = Instant::now();
+  let mut retries = 0;
+  let result = send(
+    petring,
+    &instance_name,
+    body.as_ref(),
+    route,
+    &method,
+    &mut retries,
+  )
+  .await;
+
+  petring.metrics.record(
+    &instance_name,
+    &method,
+    route,
+    started.elapsed(),
+    retries,
+    result.as_ref().err().map(|err| err.to_string()),
+  );
+  result
+}
 
-  if !resp.status().is_success() {
-    let resp_text = resp.text().await?;
-    debug!("Response_text: {resp_text}");
+/// Fails fast while the breaker is open, waits for a token if there isn't a valid one, retries
+/// what's safe to retry, and replays the whole request once after a 401.
+async fn send<T, R>(
+  petring: &PetringClient,
+  instance_name: &str,
+  body: Option<&T>,
+  route: &str,
+  method: &Method,
+  retries: &mut u32,
+) -> Result<R, PetringError>
+where
+  T: Serialize,
+  R: for<'de> Deserialize<'de> + Debug,
+{
+  let PetringClient {
+    http_client,
+    cache,
+    tokens,
+    api_status,
+    ..
+  } = petring;
+  let (url, secrets) = {
+    let cache = cache.read();
+    let instance = cache
+      .get_instance(instance_name)
+      .ok_or_else(|| PetringError::UnknownInstance(instance_name.to_string()))?;
+    let url = format!("{}{}", instance.get_api_base_url(), route);
+    (url, instance.get_secrets())
+  };
+
+  // Fail fast instead of holding interactions up, the supervisor says when it's back.
+  if let Availability::Down { since, error } = api_status.get(instance_name) {
+    return Err(PetringError::Unavailable(format!(
+      "{instance_name} is unreachable since {}: {error}",
+      since.format("%Y-%m-%d %H:%M UTC")
+    )));
+  }
 
-    return match serde_json::from_str::<ErrorResponse>(&resp_text) {
-      Ok(error) => Ok(ResponseTypes::Error(error)),
+  let mut secrets = match secrets.access_token_expires_at > Utc::now().timestamp() {
+    true => secrets,
+    false => tokens
+      .wait_valid(cache, instance_name, TOKEN_WAIT)
+      .await
+      .map_err(|err| PetringError::Unavailable(err.to_string()))?,
+  };
+
+  info!("Making request to {url} ({instance_name})");
+
+  let timeout = route_timeout(route);
+  let mut replayed = false;
+  loop {
+    let sent = send_with_retries(http_client, method, &url, &secrets, body, timeout, retries);
+    let resp = match sent.await? {
+      Ok(resp) => resp,
       Err(err) => {
-        error!("Failed to convert response to json: {err}, response: {resp_text}");
-        Ok(ResponseTypes::Error(ErrorResponse {
-          status: 500,
-          message: format!("Failed to convert response to json: {err}, response: {resp_text}"),
-        }))
+        api_status.record_failure(instance_name, err.to_string());
+        return Err(err.into());
       }
     };
-  }
 
-  let resp_text = resp.text().await?;
-  match serde_json::from_str::<R>(&resp_text) {
-    Ok(return_type) => Ok(ResponseTypes::Success(return_type)),
-    Err(err) => {
-      error!("Failed to convert response to json: {err}, response: {resp_text}");
-      Ok(ResponseTypes::Error(ErrorResponse {
-        status: 500,
-        message: format!("Failed to convert response to json: {err}, response: {resp_text}"),
-      }))
+    let status = resp.status();
+    match status.is_server_error() {
+      true => api_status.record_failure(instance_name, format!("{method} {route}: {status}")),
+      false => api_status.record_success(instance_name),
     }
+
+    if status != StatusCode::UNAUTHORIZED || replayed {
+      return parse_response(status, resp.text().await?);
+    }
+
+    info!("Invalid token, refreshing secrets and replaying {method} {route}");
+    secrets = tokens
+      .refresh(http_client, cache, instance_name, &secrets.access_token)
+      .await
+      .map_err(|err| PetringError::Auth(err.into()))?;
+    replayed = true;
   }
 }
@@ -0,0 +1,83 @@
+use reqwest::Method;
+use std::{
+  collections::HashMap,
+  sync::{Arc, Mutex as StdMutex, PoisonError},
+  time::Duration,
+};
+
+/// What every PetRing API route has been up to since startup.
+#[derive(Clone, Debug, Default)]
+pub struct RouteStats {
+  pub requests: u64,
+  pub errors: u64,
+  pub retries: u64,
+  pub total_latency: Duration,
+  pub max_latency: Duration,
+  pub last_error: Option<String>,
+}
+
+impl RouteStats {
+  pub fn average_latency(&self) -> Duration {
+    match self.requests {
+      0 => Duration::ZERO,
+      requests => self.total_latency / requests as u32,
+    }
+  }
+}
+
+/// Latency and error counts per instance and route, kept in memory only.
+#[derive(Clone, Default)]
+pub struct RequestMetrics(Arc<StdMutex<HashMap<(String, String), RouteStats>>>);
+
+/// `/get/user/by-discord/1234` → `GET /get/user/by-discord/:id`, so every user shares a route.
+fn route_key(method: &Method, route: &str) -> String {
+  let route = route
+    .split('/')
+    .map(
+      |segment| match !segment.is_empty() && segment.bytes().all(|b| b.is_ascii_digit()) {
+        true => ":id",
+        false => segment,
+      },
+    )
+    .collect::<Vec<_>>()
+    .join("/");
+  format!("{method} {route}")
+}
+
+impl RequestMetrics {
+  pub fn record(
+    &self,
+    instance: &str,
+    method: &Method,
+    route: &str,
+    latency: Duration,
+    retries: u32,
+    error: Option<String>,
+  ) {
+    let mut routes = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+    let stats = routes
+      .entry((instance.to_string(), route_key(method, route)))
+      .or_default();
+
+    stats.requests += 1;
+    stats.retries += u64::from(retries);
+    stats.total_latency += latency;
+    stats.max_latency = stats.max_latency.max(latency);
+    if let Some(error) = error {
+      stats.errors += 1;
+      stats.last_error = Some(error);
+    }
+  }
+
+  /// Every route of `instance` that saw a request, busiest first.
+  pub fn snapshot(&self, instance: &str) -> Vec<(String, RouteStats)> {
+    let routes = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+    let mut snapshot = routes
+      .iter()
+      .filter(|((name, _), _)| name == instance)
+      .map(|((_, route), stats)| (route.clone(), stats.clone()))
+      .collect::<Vec<_>>();
+    snapshot.sort_by(|a, b| b.1.requests.cmp(&a.1.requests).then(a.0.cmp(&b.0)));
+    snapshot
+  }
+}
@@ -0,0 +1,368 @@
+use super::{
+  Collar, CollarError, NotifChannelType,
+  http::jittered,
+  notifs::{PendingSubmission, SubmitType},
+};
+use chrono::{DateTime, Utc};
+use poise::serenity_prelude::{
+  self as serenity, ChannelId, Color, CreateActionRow, CreateAttachment, CreateEmbed,
+  CreateMessage, GuildId, Http, UserId,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::path::PathBuf;
+use tokio::time::{Duration, sleep};
+use tracing::{error, info, warn};
+
+/// Attempts, counting the first one, before a notification is dead-lettered.
+pub const MAX_ATTEMPTS: u32 = 6;
+const RETRY_BASE: Duration = Duration::from_secs(30);
+const RETRY_MAX: Duration = Duration::from_secs(60 * 60);
+const CHECK_EVERY: Duration = Duration::from_secs(30);
+
+/// What a notification posts. Review posts carry what [`super::notifs::handle_event`] needs to take
+/// clicks on their buttons.
+#[derive(Clone, Default)]
+pub struct Outgoing {
+  pub embed: CreateEmbed,
+  pub components: Vec<CreateActionRow>,
+  /// Read from disk on every attempt, so a queued delivery doesn't carry the file around.
+  pub attachment: Option<PathBuf>,
+  pub review: Option<ReviewPost>,
+}
+
+impl Outgoing {
+  pub fn new(embed: CreateEmbed) -> Self {
+    Self {
+      embed,
+      ..Default::default()
+    }
+  }
+}
+
+/// Remembered as a pending submission once the review post lands.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ReviewPost {
+  pub submit_type: SubmitType,
+  pub discord_id: UserId,
+  pub reason: Option<String>,
+}
+
+/// A notification that didn't go through the first time.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Delivery {
+  pub id: u64,
+  pub guild_id: GuildId,
+  /// Resolved on every attempt, so pointing the channel type somewhere else fixes it.
+  pub channel_type: NotifChannelType,
+  /// The message as Discord takes it, embeds and components included.
+  pub message: Value,
+  #[serde(default)]
+  pub attachment: Option<PathBuf>,
+  #[serde(default)]
+  pub review: Option<ReviewPost>,
+  pub created_at: DateTime<Utc>,
+  pub attempts: u32,
+  pub next_attempt_at: DateTime<Utc>,
+  pub last_error: String,
+  /// Set once it ran out of attempts, dead deliveries only go out again through a replay.
+  pub dead_at: Option<DateTime<Utc>>,
+}
+
+impl Delivery {
+  fn new(
+    guild_id: GuildId,
+    channel_type: NotifChannelType,
+    outgoing: Outgoing,
+  ) -> Result<Self, serde_json::Error> {
+    let message = CreateMessage::new()
+      .embed(outgoing.embed)
+      .components(outgoing.components);
+    let now = Utc::now();
+
+    Ok(Self {
+      id: 0,
+      guild_id,
+      channel_type,
+      message: serde_json::to_value(message)?,
+      attachment: outgoing.attachment,
+      review: outgoing.review,
+      created_at: now,
+      attempts: 0,
+      next_attempt_at: now,
+      last_error: String::new(),
+      dead_at: None,
+    })
+  }
+
+  pub fn title(&self) -> &str {
+    self.message["embeds"][0]["title"]
+      .as_str()
+      .unwrap_or("Untitled")
+  }
+
+  fn due(&self) -> bool {
+    self.dead_at.is_none() && self.next_attempt_at <= Utc::now()
+  }
+
+  /// Counts another failed attempt and schedules the next one, dead-lettering it after
+  /// [`MAX_ATTEMPTS`]. Returns whether it just died.
+  fn failed(&mut self, error: String) -> bool {
+    self.attempts += 1;
+    self.last_error = error;
+    self.next_attempt_at = Utc::now() + retry_delay(self.attempts);
+    if self.attempts >= MAX_ATTEMPTS {
+      self.dead_at = Some(Utc::now());
+    }
+    self.dead_at.is_some()
+  }
+}
+
+/// How a notification went.
+pub enum Delivered {
+  Sent(Box<serenity::Message>),
+  /// Sending failed, the outbox keeps trying.
+  Queued,
+  /// The guild has no channel for it.
+  NoChannel,
+  /// It couldn't be sent or queued, nothing will come of it.
+  Failed(String),
+}
+
+fn retry_delay(attempts: u32) -> Duration {
+  jittered(
+    RETRY_BASE
+      .saturating_mul(1 << attempts.saturating_sub(1).min(16))
+      .min(RETRY_MAX),
+  )
+}
+
+async fn send(
+  http: &Http,
+  data: &Collar,
+  delivery: &Delivery,
+) -> Result<Option<serenity::Message>, CollarError> {
+  let channel_id = data
+    .cache
+    .read()
+    .get_notif_channel(delivery.guild_id, delivery.channel_type);
+  let channel_id = match channel_id {
+    Some(channel_id) => ChannelId::new(channel_id),
+    None => return Ok(None),
+  };
+
+  // The message was built without the file, so it has to be listed like `add_file` would.
+  let mut message = delivery.message.clone();
+  let files = match &delivery.attachment {
+    Some(path) => {
+      let file = CreateAttachment::path(path).await?;
+      message["attachments"] = json!([{ "id": 0, "filename": file.filename }]);
+      vec![file]
+    }
+    None => vec![],
+  };
+  let message = http.send_message(channel_id, files, &message).await?;
+
+  if let Some(review) = &delivery.review {
+    data.cache.write().add_pending_submission(
+      message.id,
+      PendingSubmission {
+        submit_type: review.submit_type,
+        discord_id: review.discord_id,
+        guild_id: delivery.guild_id,
+        channel_id: message.channel_id,
+        submitted_at: Utc::now(),
+        reason: review.reason.clone(),
+      },
+    );
+  }
+  Ok(Some(message))
+}
+
+/// Posts in the guild's channel for `channel_type`, handing it to the outbox when Discord
+/// doesn't take it.
+pub async fn deliver(
+  http: &Http,
+  data: &Collar,
+  guild_id: GuildId,
+  channel_type: NotifChannelType,
+  outgoing: Outgoing,
+) -> Delivered {
+  let mut delivery = match Delivery::new(guild_id, channel_type, outgoing) {
+    Ok(delivery) => delivery,
+    Err(err) => {
+      error!("Failed to build {channel_type} for {guild_id}: {err}");
+      return Delivered::Failed(err.to_string());
+    }
+  };
+
+  let err = match send(http, data, &delivery).await {
+    Ok(Some(message)) => return Delivered::Sent(Box::new(message)),
+    Ok(None) => return Delivered::NoChannel,
+    Err(err) => err,
+  };
+
+  warn!("Failed to send {channel_type} in {guild_id}, queueing it: {err}");
+  delivery.attempts = 1;
+  delivery.next_attempt_at = Utc::now() + retry_delay(1);
+  delivery.last_error = err.to_string();
+  data.cache.write().queue_delivery(delivery);
+  Delivered::Queued
+}
+
+/// Tries a queued delivery once more, dead-lettering it when that was its last attempt.
+/// Returns whether it went through.
+pub async fn attempt(http: &Http, data: &Collar, id: u64) -> bool {
+  let delivery = match data.cache.read().get_delivery(id) {
+    Some(delivery) => delivery.clone(),
+    None => return false,
+  };
+
+  let error = match send(http, data, &delivery).await {
+    Ok(Some(_)) => {
+      info!(
+        "Delivered queued notification {id} to {} after {} attempt(s)",
+        delivery.channel_type, delivery.attempts
+      );
+      data.cache.write().remove_delivery(id);
+      return true;
+    }
+    Ok(None) => format!("no {} channel is set up", delivery.channel_type),
+    Err(err) => err.to_string(),
+  };
+
+  let dead = {
+    let mut cache = data.cache.write();
+    let queued = match cache.get_delivery_mut(id) {
+      Some(queued) => queued,
+      None => return false,
+    };
+    queued.failed(error).then(|| queued.clone())
+  };
+
+  match dead {
+    Some(dead) => report_dead(http, data, &dead).await,
+    None => warn!("Queued notification {id} failed again, retrying later"),
+  }
+  false
+}
+
+/// Tells the guild's admin channel a notification gave up, never the moderator that caused it.
+async fn report_dead(http: &Http, data: &Collar, delivery: &Delivery) {
+  error!(
+    "Gave up on notification {} for {} in {}: {}",
+    delivery.id, delivery.channel_type, delivery.guild_id, delivery.last_error
+  );
+
+  let embed = CreateEmbed::default()
+    .title("Notification couldn't be delivered 3:")
+    .description(format!(
+      "Collar tried {} times, replay it with `/notification_outbox replay` once it's fixed",
+      delivery.attempts
+    ))
+    .field("Id", delivery.id.to_string(), true)
+    .field("Channel", delivery.channel_type.to_string(), true)
+    .field("Notification", delivery.title(), false)
+    .field(
+      "Last error",
+      delivery.last_error.chars().take(1024).collect::<String>(),
+      false,
+    )
+    .color(Color::from_rgb(255, 0, 0));
+
+  let report = match Delivery::new(
+    delivery.guild_id,
+    NotifChannelType::Admin,
+    Outgoing::new(embed),
+  ) {
+    Ok(report) => report,
+    Err(err) => {
+      error!(
+        "Failed to build the report of notification {}: {err}",
+        delivery.id
+      );
+      return;
+    }
+  };
+
+  match send(http, data, &report).await {
+    Ok(Some(_)) => (),
+    Ok(None) => warn!(
+      "Guild {} has no admin channel to report notification {} to",
+      delivery.guild_id, delivery.id
+    ),
+    Err(err) => error!(
+      "Failed to report notification {} to the admin channel of {}: {err}",
+      delivery.id, delivery.guild_id
+    ),
+  }
+}
+
+/// Keeps retrying queued notifications until they go through or run out of attempts.
+pub fn spawn(data: Collar, ctx: serenity::Context) {
+  tokio::spawn(async move {
+    loop {
+      let due = data
+        .cache
+        .read()
+        .get_deliveries()
+        .iter()
+        .filter(|delivery| delivery.due())
+        .map(|delivery| delivery.id)
+        .collect::<Vec<_>>();
+
+      for id in due {
+        attempt(&ctx.http, &data, id).await;
+      }
+
+      sleep(CHECK_EVERY).await;
+    }
+  });
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use poise::serenity_prelude::CreateButton;
+
+  #[test]
+  fn deliveries_are_dead_lettered_after_their_last_attempt() {
+    let outgoing = Outgoing::new(CreateEmbed::default().title("New submission"));
+    let mut delivery =
+      Delivery::new(GuildId::new(1), NotifChannelType::UserSubmit, outgoing).unwrap();
+    // `deliver` counts the first try.
+    delivery.attempts = 1;
+
+    for attempt in 2..MAX_ATTEMPTS {
+      assert!(!delivery.failed(format!("failure {attempt}")));
+      assert!(delivery.next_attempt_at > Utc::now());
+    }
+    assert!(delivery.failed(String::from("last failure")));
+    assert_eq!(delivery.attempts, MAX_ATTEMPTS);
+    assert_eq!(delivery.last_error, "last failure");
+    assert!(delivery.dead_at.is_some());
+
+    // Only a replay brings it back.
+    delivery.next_attempt_at = Utc::now();
+    assert!(!delivery.due());
+  }
+
+  #[test]
+  fn queued_deliveries_keep_their_components() {
+    let outgoing = Outgoing {
+      components: vec![CreateActionRow::Buttons(vec![
+        CreateButton::new("review:verify").label("Verify"),
+      ])],
+      ..Outgoing::new(CreateEmbed::default().title("New submission"))
+    };
+    let delivery = Delivery::new(GuildId::new(1), NotifChannelType::UserSubmit, outgoing).unwrap();
+
+    let stored = serde_json::to_string(&delivery).unwrap();
+    let delivery = serde_json::from_str::<Delivery>(&stored).unwrap();
+    assert_eq!(delivery.title(), "New submission");
+    assert_eq!(
+      delivery.message["components"][0]["components"][0]["custom_id"],
+      "review:verify"
+    );
+  }
+}
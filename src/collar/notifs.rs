@@ -1,23 +1,36 @@
-use crate::collar::{EmbedWrapper, NotifChannelType};
-
-use super::{
-  CollarAppContext, CollarError,
-  commands::{Ad, User},
-  http::{ResponseTypes, make_request},
+use crate::collar::{
+  Cache, Collar, EmbedWrapper, MAX_REJECT_REASONS, NotifChannelType, ad_archive,
+  ad_hash::{self, AdHashRecord, AdOutcome},
+  audit::{self, AuditAction, AuditEntry},
+  health,
+  notif_outbox::{self, Delivered, Outgoing, ReviewPost},
+  ownership,
 };
+
+use super::{CollarAppContext, CollarError};
+use chrono::{DateTime, Utc};
 use poise::{
-  CreateReply,
+  CreateReply, FrameworkContext,
   serenity_prelude::{
-    self as serenity, ChannelId, CreateActionRow, CreateInputText, CreateQuickModal,
+    self as serenity, ChannelId, ComponentInteraction, ComponentInteractionCollector,
+    ComponentInteractionDataKind, CreateActionRow, CreateInputText, CreateQuickModal,
+    CreateSelectMenu, CreateSelectMenuKind, CreateSelectMenuOption, EditMessage, EmbedField,
+    GuildId, Http, MessageFlags, MessageId, ModalInteraction, Permissions, UserId,
   },
 };
-use reqwest::Method;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
 use serenity::{
-  ButtonStyle, Color, ComponentInteractionCollector, CreateButton, CreateEmbed, CreateEmbedAuthor,
-  CreateInteractionResponse, CreateInteractionResponseMessage, CreateMessage, Mentionable,
+  ButtonStyle, Color, CreateButton, CreateEmbed, CreateEmbedAuthor, CreateInteractionResponse,
+  CreateInteractionResponseFollowup, CreateInteractionResponseMessage, CreateMessage, Mentionable,
 };
-use tracing::{error, info};
+use std::{
+  collections::HashSet,
+  sync::{Arc, Mutex as StdMutex, PoisonError},
+};
+use tracing::{error, info, warn};
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize, Serialize)]
 pub enum SubmitType {
   Ad,
   User,
@@ -25,423 +38,1370 @@ pub enum SubmitType {
 
 pub type VerifyType = SubmitType;
 
+impl SubmitType {
+  fn as_id_part(&self) -> &'static str {
+    match self {
+      SubmitType::Ad => "ad",
+      SubmitType::User => "user",
+    }
+  }
+
+  /// Where submissions of this type go for review.
+  pub fn channel_type(&self) -> NotifChannelType {
+    match self {
+      SubmitType::User => NotifChannelType::UserSubmit,
+      SubmitType::Ad => NotifChannelType::AdSubmit,
+    }
+  }
+
+  fn from_id_part(part: &str) -> Option<Self> {
+    match part {
+      "ad" => Some(SubmitType::Ad),
+      "user" => Some(SubmitType::User),
+      _ => None,
+    }
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReviewDecision {
+  Verify,
+  Reject,
+  /// Blocks verification until the moderator that vetoed takes it back.
+  Veto,
+}
+
+/// Where a review button lives, which decides what happens to its message afterwards.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReviewOrigin {
+  /// The message posted in the submit channel, deleted once handled.
+  ReviewMessage,
+  /// An entry in `/pending`, which stays around.
+  PendingList,
+}
+
+impl ReviewOrigin {
+  fn prefix(&self) -> &'static str {
+    match self {
+      ReviewOrigin::ReviewMessage => "review",
+      ReviewOrigin::PendingList => "pending",
+    }
+  }
+
+  fn from_prefix(prefix: &str) -> Option<Self> {
+    match prefix {
+      "review" => Some(ReviewOrigin::ReviewMessage),
+      "pending" => Some(ReviewOrigin::PendingList),
+      _ => None,
+    }
+  }
+}
+
+/// What a review button does, round-tripped through its `custom_id` as
+/// `<review|pending>:<verify|reject|veto>:<user|ad>:<discord id>` so clicks can be handled after a
+/// restart.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ReviewAction {
+  pub origin: ReviewOrigin,
+  pub decision: ReviewDecision,
+  pub submit_type: SubmitType,
+  pub discord_id: UserId,
+}
+
+impl ReviewAction {
+  pub fn custom_id(&self) -> String {
+    let decision = match self.decision {
+      ReviewDecision::Verify => "verify",
+      ReviewDecision::Reject => "reject",
+      ReviewDecision::Veto => "veto",
+    };
+
+    format!(
+      "{}:{decision}:{}:{}",
+      self.origin.prefix(),
+      self.submit_type.as_id_part(),
+      self.discord_id
+    )
+  }
+
+  pub fn parse(custom_id: &str) -> Option<Self> {
+    let mut parts = custom_id.split(':');
+    let origin = ReviewOrigin::from_prefix(parts.next()?)?;
+
+    let decision = match parts.next()? {
+      "verify" => ReviewDecision::Verify,
+      "reject" => ReviewDecision::Reject,
+      "veto" => ReviewDecision::Veto,
+      _ => return None,
+    };
+    let submit_type = SubmitType::from_id_part(parts.next()?)?;
+    let discord_id = parts.next()?.parse::<u64>().ok().filter(|id| *id != 0)?;
+
+    if parts.next().is_some() {
+      return None;
+    }
+
+    Some(Self {
+      origin,
+      decision,
+      submit_type,
+      discord_id: UserId::new(discord_id),
+    })
+  }
+
+  /// The verify, reject and veto buttons for a submission, verify greyed out while
+  /// `verify_enabled` is false.
+  pub fn buttons(
+    origin: ReviewOrigin,
+    submit_type: SubmitType,
+    discord_id: UserId,
+    verify_enabled: bool,
+  ) -> [CreateButton; 3] {
+    let verify_action = ReviewAction {
+      origin,
+      decision: ReviewDecision::Verify,
+      submit_type,
+      discord_id,
+    };
+    let reject_action = ReviewAction {
+      decision: ReviewDecision::Reject,
+      ..verify_action
+    };
+    let veto_action = ReviewAction {
+      decision: ReviewDecision::Veto,
+      ..verify_action
+    };
+
+    [
+      CreateButton::new(verify_action.custom_id())
+        .label("Verify submission")
+        .style(ButtonStyle::Success)
+        .disabled(!verify_enabled),
+      CreateButton::new(reject_action.custom_id())
+        .label("Reject submission")
+        .style(ButtonStyle::Danger),
+      CreateButton::new(veto_action.custom_id())
+        .label("Veto")
+        .style(ButtonStyle::Secondary),
+    ]
+  }
+}
+
+/// A review message that's waiting on a moderator, keyed by its message id in the cache.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PendingSubmission {
+  pub submit_type: SubmitType,
+  pub discord_id: UserId,
+  pub guild_id: GuildId,
+  pub channel_id: ChannelId,
+  pub submitted_at: DateTime<Utc>,
+  /// What the submitter wrote when submitting, kept for the audit log.
+  #[serde(default)]
+  pub reason: Option<String>,
+}
+
+impl PendingSubmission {
+  /// Whether `action` is about this submission, a button can't act on another one than the
+  /// one its message was posted for.
+  pub fn is_for(&self, action: &ReviewAction) -> bool {
+    self.submit_type == action.submit_type && self.discord_id == action.discord_id
+  }
+}
+
+/// Who approved or vetoed a submission so far, until it's verified or rejected.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ReviewVotes {
+  pub instance: String,
+  pub submit_type: SubmitType,
+  pub discord_id: UserId,
+  pub approvers: Vec<UserId>,
+  pub vetoed_by: Option<UserId>,
+}
+
+impl ReviewVotes {
+  pub fn new(instance: &str, submit_type: SubmitType, discord_id: UserId) -> Self {
+    Self {
+      instance: instance.to_string(),
+      submit_type,
+      discord_id,
+      approvers: Vec::new(),
+      vetoed_by: None,
+    }
+  }
+
+  /// The same member can have a submission on every instance, each gets its own votes.
+  pub fn is_for(&self, instance: &str, submit_type: SubmitType, discord_id: UserId) -> bool {
+    self.instance == instance && self.submit_type == submit_type && self.discord_id == discord_id
+  }
+}
+
+/// Submissions a moderator is acting on right now, so two clicks on the same submission
+/// (or a verify while a reject modal is open) can't race each other.
+#[derive(Clone, Default)]
+pub struct ReviewsInFlight(Arc<StdMutex<HashSet<(String, SubmitType, UserId)>>>);
+
+impl ReviewsInFlight {
+  /// `None` if the submission to `instance` is already being reviewed.
+  pub fn claim(
+    &self,
+    instance: &str,
+    submit_type: SubmitType,
+    discord_id: UserId,
+  ) -> Option<ReviewClaim> {
+    let key = (instance.to_string(), submit_type, discord_id);
+    let mut in_flight = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+
+    in_flight.insert(key.clone()).then(|| ReviewClaim {
+      reviews: self.clone(),
+      key,
+    })
+  }
+}
+
+/// Releases the submission when the review is done, however it ends.
+pub struct ReviewClaim {
+  reviews: ReviewsInFlight,
+  key: (String, SubmitType, UserId),
+}
+
+impl Drop for ReviewClaim {
+  fn drop(&mut self) {
+    let mut in_flight = self
+      .reviews
+      .0
+      .lock()
+      .unwrap_or_else(PoisonError::into_inner);
+    in_flight.remove(&self.key);
+  }
+}
+
+/// Checks `/verify_user` and `/verify_ad` go through before skipping the review buttons. They
+/// only stand in for a single approval, so they refuse when the server wants more, the
+/// submission was vetoed, or ownership isn't proven yet. Holds the submission like a button
+/// press does, `Err` has what to tell the moderator.
+pub fn claim_direct_verify(
+  data: &Collar,
+  guild_id: Option<GuildId>,
+  submit_type: SubmitType,
+  discord_id: UserId,
+  reviewer: UserId,
+) -> Result<(String, ReviewClaim), String> {
+  if discord_id == reviewer {
+    return Err(String::from(
+      "You can't approve your own submission, another moderator has to verify it",
+    ));
+  }
+
+  let (instance, quorum, votes, blocked) = {
+    let cache = data.cache.read();
+    let instance = match cache.get_guild_instance_name(guild_id) {
+      Some(instance) => instance,
+      None => {
+        return Err(String::from(
+          "This server's PetRing instance isn't configured, pick another one with /set_instance",
+        ));
+      }
+    };
+    let quorum = cache.get_review_quorum(guild_id).get(submit_type);
+    let votes = cache.get_review_votes(&instance, submit_type, discord_id);
+    let blocked = cache.ownership_blocks_verify(guild_id, submit_type, discord_id);
+    (instance, quorum, votes, blocked)
+  };
+
+  if quorum > 1 {
+    return Err(format!(
+      "This server needs {quorum} approvals, use the buttons on the review message instead"
+    ));
+  }
+  if let Some(vetoed_by) = votes.vetoed_by {
+    return Err(format!(
+      "{} vetoed this submission, it can't be verified until they take the veto back",
+      vetoed_by.mention()
+    ));
+  }
+  if blocked {
+    return Err(String::from(
+      "This server needs the submitter to prove they own the site before verifying",
+    ));
+  }
+
+  match data
+    .reviews_in_flight
+    .claim(&instance, submit_type, discord_id)
+  {
+    Some(claim) => Ok((instance, claim)),
+    None => Err(String::from(
+      "Someone's already reviewing this submission, wait for them to finish",
+    )),
+  }
+}
+
+/// Whether whoever pressed `mci` has the permissions the review commands need. Anyone that can
+/// see a channel can press its buttons, so the ones that change the ring check this themselves.
+pub fn pressed_by_moderator(mci: &ComponentInteraction) -> bool {
+  is_moderator(mci.member.as_ref().and_then(|member| member.permissions))
+}
+
+fn is_moderator(permissions: Option<Permissions>) -> bool {
+  permissions.is_some_and(|permissions| {
+    permissions.contains(
+      Permissions::MANAGE_CHANNELS
+        | Permissions::BAN_MEMBERS
+        | Permissions::KICK_MEMBERS
+        | Permissions::MUTE_MEMBERS,
+    )
+  })
+}
+
+/// Why a review button press is turned away before the submission is claimed.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ReviewerRefusal {
+  NotModerator,
+  /// Approving your own submission would count toward the quorum.
+  OwnSubmission,
+}
+
+/// Only moderators review, and never approve their own submission. Votes are only counted
+/// after this passes, so a refused press changes nothing.
+pub fn check_reviewer(
+  action: &ReviewAction,
+  reviewer: UserId,
+  permissions: Option<Permissions>,
+) -> Result<(), ReviewerRefusal> {
+  if !is_moderator(permissions) {
+    return Err(ReviewerRefusal::NotModerator);
+  }
+  if action.decision == ReviewDecision::Verify && action.discord_id == reviewer {
+    return Err(ReviewerRefusal::OwnSubmission);
+  }
+  Ok(())
+}
+
+/// Tells someone without [`pressed_by_moderator`] permissions the button isn't for them.
+pub async fn refuse_non_moderator(
+  shard: &serenity::Context,
+  mci: &ComponentInteraction,
+) -> Result<(), CollarError> {
+  let embed = EmbedWrapper::new_event(shard)
+    .title("Only moderators can do that 3:")
+    .description("Nothing was changed")
+    .color(Color::from_rgb(255, 0, 0));
+  respond_ephemeral(&shard.http, mci, embed).await
+}
+
+/// Custom ids used by review messages posted before the buttons carried their submission.
+const LEGACY_REVIEW_IDS: [&str; 2] = ["verify-submission", "reject-submission"];
+
 #[derive(Clone)]
 pub struct Notif {
   embed: CreateEmbed,
 }
 
-async fn process_mci(
-  user_id: u64,
-  ctx: &CollarAppContext<'_>,
+fn missing_channel_description(notify_type: NotifChannelType) -> &'static str {
+  match notify_type {
+    NotifChannelType::UserSubmit => {
+      "No channel was found for user submit notifications, please set one up using `/set_notification_channel`"
+    }
+    NotifChannelType::AdSubmit => {
+      "No channel was found for ad submit notifications, please set one up using `/set_notification_channel`"
+    }
+    NotifChannelType::UserVerify => {
+      "No channel was found for user verify notifications, please set one up using `/set_notification_channel`"
+    }
+    NotifChannelType::AdVerify => {
+      "No channel was found for ad verify notifications, please set one up using `/set_notification_channel`"
+    }
+    NotifChannelType::General => {
+      "No channel was found for general notifications, please set one up using `/set_notification_channel`"
+    }
+    NotifChannelType::DmFallback => {
+      "No channel was found for failed dm notifications, please set one up using `/set_notification_channel`"
+    }
+    NotifChannelType::Admin => {
+      "No channel was found for admin notifications, please set one up using `/set_notification_channel`"
+    }
+  }
+}
+
+/// Whether a review button press may act on the submission in its custom id.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ReviewRoute {
+  Accept,
+  /// The message was posted for another submission.
+  Mismatch,
+  /// A review message collar has no record of, its record was dropped or it predates them.
+  Untracked,
+}
+
+/// The buttons only ever act on the submission recorded for the message they're on. `/pending`
+/// pages are ephemeral replies that never get a record, their buttons are taken as they are.
+pub fn route_review(
+  action: &ReviewAction,
+  pending: Option<&PendingSubmission>,
+  ephemeral: bool,
+) -> ReviewRoute {
+  match (pending, action.origin) {
+    (Some(pending), _) if pending.is_for(action) => ReviewRoute::Accept,
+    (Some(_), _) => ReviewRoute::Mismatch,
+    (None, ReviewOrigin::PendingList) if ephemeral => ReviewRoute::Accept,
+    (None, _) => ReviewRoute::Untracked,
+  }
+}
+
+/// Global event handler, registered in `FrameworkOptions`, routing review button clicks.
+pub async fn handle_event(
   shard: &serenity::Context,
+  event: &serenity::FullEvent,
+  _framework: FrameworkContext<'_, Collar, CollarError>,
+  data: &Collar,
+) -> Result<(), CollarError> {
+  let mci = match event {
+    serenity::FullEvent::InteractionCreate {
+      interaction: serenity::Interaction::Component(mci),
+    } => mci,
+    _ => return Ok(()),
+  };
+
+  let custom_id = mci.data.custom_id.as_str();
+  if LEGACY_REVIEW_IDS.contains(&custom_id) {
+    let embed = EmbedWrapper::new_event(shard)
+      .title("This review can't be handled anymore 3:")
+      .description(
+        "It was posted by an older version of collar, use `/verify_user` or `/verify_ad` instead",
+      )
+      .color(Color::from_rgb(255, 0, 0));
+    return respond_ephemeral(&shard.http, mci, embed).await;
+  }
+
+  if let Some(discord_id) = health::parse_removal(custom_id) {
+    return health::handle_removal(shard, data, mci, discord_id).await;
+  }
+
+  if let Some(discord_id) = ad_archive::parse_rereview(custom_id) {
+    return ad_archive::handle_rereview(shard, data, mci, discord_id).await;
+  }
+
+  if let Some(discord_id) = ownership::parse_check(custom_id) {
+    return ownership::handle_check(shard, data, mci, discord_id).await;
+  }
+
+  let action = match ReviewAction::parse(custom_id) {
+    Some(action) => action,
+    None => return Ok(()),
+  };
+
+  let pending = {
+    let cache = data.cache.read();
+    cache.get_pending_submission(mci.message.id).cloned()
+  };
+  let route = route_review(&action, pending.as_ref(), is_ephemeral(mci));
+  let refusal = match route {
+    ReviewRoute::Accept => None,
+    ReviewRoute::Mismatch => Some((
+      "This review doesn't match its submission 3:",
+      "Nothing was changed, ask the submitter to submit again",
+    )),
+    ReviewRoute::Untracked => Some((
+      "This review message isn't tracked anymore 3:",
+      "Nothing was changed, repost it from `/pending` to review it",
+    )),
+  };
+  if let Some((title, description)) = refusal {
+    warn!(
+      "Refused review button {custom_id} on message {}: {route:?}",
+      mci.message.id
+    );
+    let embed = EmbedWrapper::new_event(shard)
+      .title(title)
+      .description(description)
+      .color(Color::from_rgb(255, 0, 0));
+    return respond_ephemeral(&shard.http, mci, embed).await;
+  }
+
+  let permissions = mci.member.as_ref().and_then(|member| member.permissions);
+  match check_reviewer(&action, mci.user.id, permissions) {
+    Ok(()) => (),
+    Err(ReviewerRefusal::NotModerator) => {
+      warn!(
+        "{} pressed {custom_id} without moderator permissions",
+        mci.user.id
+      );
+      return refuse_non_moderator(shard, mci).await;
+    }
+    Err(ReviewerRefusal::OwnSubmission) => {
+      let embed = EmbedWrapper::new_event(shard)
+        .title("You can't approve your own submission 3:")
+        .description("Another moderator has to verify it")
+        .color(Color::from_rgb(255, 0, 0));
+      return respond_ephemeral(&shard.http, mci, embed).await;
+    }
+  }
+
+  let instance = {
+    let cache = data.cache.read();
+    cache.get_guild_instance_name(mci.guild_id)
+  };
+  let instance = match instance {
+    Some(instance) => instance,
+    None => {
+      let embed = EmbedWrapper::new_event(shard)
+        .title("This server's PetRing instance isn't configured 3:")
+        .description("Pick another one with `/set_instance`")
+        .color(Color::from_rgb(255, 0, 0));
+      return respond_ephemeral(&shard.http, mci, embed).await;
+    }
+  };
+
+  let claim = match data
+    .reviews_in_flight
+    .claim(&instance, action.submit_type, action.discord_id)
+  {
+    Some(claim) => claim,
+    None => {
+      let embed = EmbedWrapper::new_event(shard)
+        .title("Someone's already reviewing this submission :3")
+        .description("Wait for them to finish, the message goes away once it's handled")
+        .color(Color::from_rgb(255, 0, 0));
+      return respond_ephemeral(&shard.http, mci, embed).await;
+    }
+  };
+
+  process_review(shard, data, mci, &instance, action, claim).await
+}
+
+fn is_ephemeral(mci: &ComponentInteraction) -> bool {
+  mci
+    .message
+    .flags
+    .is_some_and(|flags| flags.contains(MessageFlags::EPHEMERAL))
+}
+
+/// Takes the submission back once the moderator has given a reject reason. `None` if another
+/// review holds it or already closed it while they were typing.
+fn reclaim_review(
+  data: &Collar,
+  mci: &ComponentInteraction,
+  instance: &str,
+  action: &ReviewAction,
+) -> Option<ReviewClaim> {
+  let claim = data
+    .reviews_in_flight
+    .claim(instance, action.submit_type, action.discord_id)?;
+  let pending = {
+    let cache = data.cache.read();
+    cache.get_pending_submission(mci.message.id).cloned()
+  };
+
+  (route_review(action, pending.as_ref(), is_ephemeral(mci)) == ReviewRoute::Accept)
+    .then_some(claim)
+}
+
+async fn respond_ephemeral(
+  http: &Http,
+  mci: &ComponentInteraction,
+  embed: CreateEmbed,
+) -> Result<(), CollarError> {
+  mci
+    .create_response(
+      http,
+      CreateInteractionResponse::Message(
+        CreateInteractionResponseMessage::new()
+          .embed(embed)
+          .ephemeral(true),
+      ),
+    )
+    .await?;
+
+  Ok(())
+}
+
+/// Deletes every review message for the submission but `keep`, and forgets its pending entries
+/// and votes.
+pub async fn drop_review_messages(
+  http: &Http,
+  data: &Collar,
+  instance: &str,
   submit_type: SubmitType,
+  discord_id: UserId,
+  keep: Option<MessageId>,
+) {
+  let closed = {
+    let mut cache = data.cache.write();
+    let closed = cache.remove_pending_submissions_for(instance, submit_type, discord_id);
+    cache.remove_review_votes(instance, submit_type, discord_id);
+    closed
+  };
+
+  for (message_id, pending) in closed {
+    if Some(message_id) == keep {
+      continue;
+    }
+
+    if let Err(err) = pending.channel_id.delete_message(http, message_id).await {
+      warn!("Failed to delete review message {message_id}: {err}");
+    }
+  }
+}
+
+/// Drops every review message for the submission, and their pending entries, once a moderator
+/// is done with it.
+async fn close_review(
+  http: &Http,
+  data: &Collar,
+  mci: &ComponentInteraction,
+  instance: &str,
+  action: ReviewAction,
 ) -> Result<(), CollarError> {
-  let user = ctx.http().get_user(user_id.into()).await?;
-  let user_mention = user.mention();
-  let user_pfp = user.face();
+  drop_review_messages(
+    http,
+    data,
+    instance,
+    action.submit_type,
+    action.discord_id,
+    Some(mci.message.id),
+  )
+  .await;
 
-  let user_verification_done_embed = EmbedWrapper::new_application(ctx)
-    .title("A User has been verified :3")
-    .description(format!("Verified user: {}", user_mention))
-    .color(Color::from_rgb(0, 255, 0));
+  if action.origin == ReviewOrigin::ReviewMessage {
+    mci.channel_id.delete_message(http, mci.message.id).await?;
+  }
 
-  let ad_verification_done_embed = EmbedWrapper::new_application(ctx)
-    .title("An Ad has been verified :3")
-    .description(format!("Verified ad for: {}", user_mention))
-    .color(Color::from_rgb(0, 255, 0));
+  Ok(())
+}
 
-  let success_ad_embed = EmbedWrapper::new_application(ctx)
-    .title("Verified :3")
-    .description(format!("Verified ad for: {}", user_mention))
-    .thumbnail(&user_pfp)
-    .color(Color::from_rgb(0, 255, 0));
+/// The interaction a moderator finished picking a rejection reason with, still waiting on a
+/// response.
+enum RejectResponder {
+  /// Picked a template, the select menu gets replaced by the result.
+  Select(ComponentInteraction),
+  /// Typed the reason into the modal.
+  Modal(ModalInteraction),
+}
 
-  let success_user_embed = EmbedWrapper::new_application(ctx)
-    .title("Verified :3")
-    .description(format!("Verified user: {}", user_mention))
-    .thumbnail(&user_pfp)
-    .color(Color::from_rgb(0, 255, 0));
+impl RejectResponder {
+  async fn respond(&self, http: &Http, embed: CreateEmbed) -> Result<(), CollarError> {
+    match self {
+      RejectResponder::Select(interaction) => {
+        interaction
+          .create_response(
+            http,
+            CreateInteractionResponse::UpdateMessage(
+              CreateInteractionResponseMessage::new()
+                .embed(embed)
+                .components(vec![]),
+            ),
+          )
+          .await?
+      }
+      RejectResponder::Modal(interaction) => {
+        interaction
+          .create_response(
+            http,
+            CreateInteractionResponse::Message(
+              CreateInteractionResponseMessage::new()
+                .embed(embed)
+                .ephemeral(true),
+            ),
+          )
+          .await?
+      }
+    }
 
-  let error_ad_embed = EmbedWrapper::new_application(ctx)
-    .title("Failed to verify 3:")
-    .description(format!("Failed to verify ad for: {}", user_mention))
-    .thumbnail(&user_pfp)
-    .color(Color::from_rgb(255, 0, 0));
+    Ok(())
+  }
+}
+
+fn reject_modal(submit_type: SubmitType) -> CreateQuickModal {
+  let modal_title = match submit_type {
+    SubmitType::Ad => "Reject Ad submission",
+    SubmitType::User => "Reject User submission",
+  };
+
+  CreateQuickModal::new(modal_title)
+    .timeout(std::time::Duration::from_secs(600))
+    .field(
+      CreateInputText::new(
+        serenity::InputTextStyle::Short,
+        "Reason",
+        "rejection-reason",
+      )
+      .placeholder("Enter rejection reason here!")
+      .required(true)
+      .min_length(10),
+    )
+}
 
-  let error_user_embed = EmbedWrapper::new_application(ctx)
-    .title("Failed to verify 3:")
-    .description(format!("Failed to verify user: {}", user_mention))
-    .thumbnail(&user_pfp)
+/// Asks the moderator why they're rejecting, offering the guild's `/reject_reasons` templates
+/// before falling back to typing it out. `None` if they never answered.
+async fn ask_reject_reason(
+  shard: &serenity::Context,
+  data: &Collar,
+  mci: &ComponentInteraction,
+  submit_type: SubmitType,
+) -> Result<Option<(String, RejectResponder)>, CollarError> {
+  let templates = match mci.guild_id {
+    Some(guild_id) => data.cache.read().get_reject_reasons(guild_id),
+    None => Vec::new(),
+  };
+
+  // The modal is the response to the click, so everything after answers the modal instead.
+  if templates.is_empty() {
+    let modal = mci.quick_modal(shard, reject_modal(submit_type)).await?;
+    return Ok(modal.map(|modal| {
+      (
+        modal.inputs[0].clone(),
+        RejectResponder::Modal(modal.interaction),
+      )
+    }));
+  }
+
+  let custom_id = format!("reject-reason:{}", mci.id);
+  let mut options = templates
+    .iter()
+    .take(MAX_REJECT_REASONS)
+    .enumerate()
+    .map(|(index, template)| {
+      CreateSelectMenuOption::new(
+        template.name.chars().take(100).collect::<String>(),
+        index.to_string(),
+      )
+      .description(template.text.chars().take(100).collect::<String>())
+    })
+    .collect::<Vec<_>>();
+  options.push(CreateSelectMenuOption::new("Other", "other").description("Type out a reason"));
+
+  let menu = CreateSelectMenu::new(&custom_id, CreateSelectMenuKind::String { options })
+    .placeholder("Pick a reason");
+  let embed = EmbedWrapper::new_event(shard)
+    .title("Why is this being rejected?")
+    .description("Pick one of this server's reasons, or other to type one out")
     .color(Color::from_rgb(255, 0, 0));
 
-  let dm_user_verify_embed = EmbedWrapper::new_application(ctx)
-    .title("You've been verified!!")
-    .description(format!(
-      "Hi there, {user_mention}, you've been verified, welcome to PetRing !! :3"
-    ))
-    .author(
-      CreateEmbedAuthor::new(format!("Verified by: {}", ctx.author().name))
-        .icon_url(ctx.author().face()),
+  mci
+    .create_response(
+      &shard.http,
+      CreateInteractionResponse::Message(
+        CreateInteractionResponseMessage::new()
+          .embed(embed)
+          .components(vec![CreateActionRow::SelectMenu(menu)])
+          .ephemeral(true),
+      ),
     )
-    .color(Color::from_rgb(0, 255, 0));
+    .await?;
+
+  let pick = match ComponentInteractionCollector::new(shard)
+    .custom_ids(vec![custom_id])
+    .author_id(mci.user.id)
+    .timeout(std::time::Duration::from_secs(600))
+    .await
+  {
+    Some(pick) => pick,
+    None => return Ok(None),
+  };
+
+  let template = match &pick.data.kind {
+    ComponentInteractionDataKind::StringSelect { values } => values
+      .first()
+      .and_then(|value| value.parse::<usize>().ok())
+      .and_then(|index| templates.get(index)),
+    _ => None,
+  };
+
+  match template {
+    Some(template) => Ok(Some((template.text.clone(), RejectResponder::Select(pick)))),
+    None => {
+      let modal = pick.quick_modal(shard, reject_modal(submit_type)).await?;
+      Ok(modal.map(|modal| {
+        (
+          modal.inputs[0].clone(),
+          RejectResponder::Modal(modal.interaction),
+        )
+      }))
+    }
+  }
+}
 
-  let dm_ad_verify_embed = EmbedWrapper::new_application(ctx)
-    .title("Your ad was verified!!")
-    .description(format!(
-      "Hi, there, {user_mention}, your ad has been verified :3"
-    ))
-    .color(Color::from_rgb(0, 255, 0));
+const APPROVALS_FIELD: &str = "Approvals";
+const VETO_FIELD: &str = "Vetoed by";
+
+pub fn approvals_text(votes: &ReviewVotes, quorum: usize) -> String {
+  let approvers = match votes.approvers.is_empty() {
+    true => String::from("None yet"),
+    false => votes
+      .approvers
+      .iter()
+      .map(|approver| approver.mention().to_string())
+      .collect::<Vec<_>>()
+      .join(", "),
+  };
+
+  format!("{approvers} ({}/{quorum})", votes.approvers.len())
+}
 
-  let mut dm_reject_user_embed = EmbedWrapper::new_application(ctx)
-    .title("You were rejected 3:")
-    .color(Color::from_rgb(255, 0, 0));
+/// Rewrites `fields` on every review message for the submission, `None` drops the field.
+/// `components` replaces the buttons when given.
+pub async fn update_review_messages(
+  http: &Http,
+  data: &Collar,
+  instance: &str,
+  submit_type: SubmitType,
+  discord_id: UserId,
+  fields: &[(&str, Option<String>)],
+  components: Option<Vec<CreateActionRow>>,
+) {
+  let pending = {
+    let cache = data.cache.read();
+    cache.get_pending_submissions_for(instance, submit_type, discord_id)
+  };
+
+  for (message_id, pending) in pending {
+    let message = match pending.channel_id.message(http, message_id).await {
+      Ok(message) => message,
+      Err(err) => {
+        warn!("Failed to fetch review message {message_id}: {err}");
+        continue;
+      }
+    };
+    let mut embed = match message.embeds.into_iter().next() {
+      Some(embed) => embed,
+      None => continue,
+    };
 
-  let mut dm_reject_ad_embed = EmbedWrapper::new_application(ctx)
-    .title("Your ad was rejected 3:")
-    .color(Color::from_rgb(255, 0, 0));
+    for (name, value) in fields {
+      embed.fields.retain(|field| field.name != *name);
+      if let Some(value) = value {
+        embed
+          .fields
+          .push(EmbedField::new(*name, value.clone(), false));
+      }
+    }
 
-  let reject_ad_embed = EmbedWrapper::new_application(ctx)
-    .title("Rejected ad :3")
-    .description(format!("Rejected ad for: {user_mention}"))
-    .thumbnail(&user_pfp)
-    .color(Color::from_rgb(255, 0, 0));
+    let mut edit = EditMessage::new().embed(embed.into());
+    if let Some(components) = components.clone() {
+      edit = edit.components(components);
+    }
 
-  let reject_user_embed = EmbedWrapper::new_application(ctx)
-    .title("Rejected user :3")
-    .description(format!("Rejected user: {user_mention}"))
-    .thumbnail(&user_pfp)
-    .color(Color::from_rgb(255, 0, 0));
+    if let Err(err) = pending
+      .channel_id
+      .edit_message(http, message_id, edit)
+      .await
+    {
+      warn!("Failed to update review message {message_id}: {err}");
+    }
+  }
+}
 
-  let error_reject_user_embed = EmbedWrapper::new_application(ctx)
-    .title("Failed to reject user 3:")
-    .description(format!("Failed to reject user: {user_mention}"))
-    .thumbnail(&user_pfp)
-    .color(Color::from_rgb(255, 0, 0));
+/// Shows the approvals and veto so far on every review message for the submission.
+async fn show_votes(http: &Http, data: &Collar, votes: &ReviewVotes, quorum: usize) {
+  let fields = [
+    (APPROVALS_FIELD, Some(approvals_text(votes, quorum))),
+    (
+      VETO_FIELD,
+      votes
+        .vetoed_by
+        .map(|vetoed_by| vetoed_by.mention().to_string()),
+    ),
+  ];
+
+  update_review_messages(
+    http,
+    data,
+    &votes.instance,
+    votes.submit_type,
+    votes.discord_id,
+    &fields,
+    None,
+  )
+  .await;
+}
 
-  let error_reject_ad_embed = EmbedWrapper::new_application(ctx)
-    .title("Failed to reject ad 3:")
-    .description(format!("Failed to reject ad for: {user_mention}"))
-    .thumbnail(&user_pfp)
-    .color(Color::from_rgb(255, 0, 0));
+/// Counts the moderator's approval, `Some` with every approver once the guild's quorum is met.
+/// Records `reviewer`'s approval unless the submission is vetoed, with whether they had
+/// approved already. Presses [`check_reviewer`] refuses aren't counted.
+fn add_approval(
+  cache: &mut Cache,
+  instance: &str,
+  action: &ReviewAction,
+  reviewer: UserId,
+  permissions: Option<Permissions>,
+) -> Result<(ReviewVotes, bool), ReviewerRefusal> {
+  check_reviewer(action, reviewer, permissions)?;
+
+  let mut votes = cache.get_review_votes(instance, action.submit_type, action.discord_id);
+  let already_approved = votes.approvers.contains(&reviewer);
+  if votes.vetoed_by.is_none() && !already_approved {
+    votes.approvers.push(reviewer);
+    cache.set_review_votes(votes.clone());
+  }
+  Ok((votes, already_approved))
+}
 
-  let mut notif = Notif::new(ctx);
-
-  while let Some(mci) = ComponentInteractionCollector::new(shard).await {
-    let channel_id = mci.channel_id;
-    let id_str = mci.data.custom_id.as_str();
-
-    match id_str {
-      "verify-submission" => {
-        info!("Verifying submission");
-        match submit_type {
-          SubmitType::Ad => match verify_ad(ctx, user_id).await {
-            Ok(ad) => {
-              info!("Sending ephermeral embed for successful ad verification");
-              let success_ad_embed = success_ad_embed.thumbnail(&ad.image_url);
-
-              mci
-                .create_response(
-                  &ctx.http(),
-                  CreateInteractionResponse::Message(
-                    CreateInteractionResponseMessage::new()
-                      .embed(success_ad_embed.clone())
-                      .ephemeral(true),
-                  ),
-                )
-                .await?;
-
-              channel_id
-                .delete_message(&ctx.http(), mci.message.id)
-                .await?;
-
-              let dm_ad_verify_embed = dm_ad_verify_embed.thumbnail(&ad.image_url).author(
-                CreateEmbedAuthor::new(format!("Verified by: {}", mci.user.name))
-                  .icon_url(mci.user.face()),
-              );
-              notif
-                .set_embed(dm_ad_verify_embed)
-                .dm_notif(ctx, user_id)
-                .await?;
-
-              let ad_verification_done_embed =
-                ad_verification_done_embed.thumbnail(&ad.image_url).author(
-                  CreateEmbedAuthor::new(format!("Verified by: {}", mci.user.name))
-                    .icon_url(mci.user.face()),
-                );
-
-              Notif::new(ctx)
-                .set_embed(ad_verification_done_embed)
-                .verification(ctx, VerifyType::Ad)
-                .await?;
-              break;
-            }
-            Err(err) => {
-              error!("Failed to verify ad: {err}");
-              mci
-                .create_response(
-                  &ctx.http(),
-                  CreateInteractionResponse::Message(
-                    CreateInteractionResponseMessage::new()
-                      .embed(error_ad_embed.clone())
-                      .ephemeral(true),
-                  ),
-                )
-                .await?;
-
-              channel_id
-                .delete_message(&ctx.http(), mci.message.id)
-                .await?;
-              break;
-            }
-          },
-          SubmitType::User => match verify_user(ctx, user_id).await {
-            Ok(_) => {
-              info!("Sending ephermeral embed for successful user verification");
-              mci
-                .create_response(
-                  &ctx.http(),
-                  CreateInteractionResponse::Message(
-                    CreateInteractionResponseMessage::new()
-                      .embed(success_user_embed.clone())
-                      .ephemeral(true),
-                  ),
-                )
-                .await?;
-
-              channel_id
-                .delete_message(&ctx.http(), mci.message.id)
-                .await?;
-              notif
-                .set_embed(dm_user_verify_embed)
-                .dm_notif(ctx, user_id)
-                .await?;
-
-              let user_verification_done_embed = user_verification_done_embed.author(
-                CreateEmbedAuthor::new(format!("Verified by: {}", mci.user.name))
-                  .icon_url(mci.user.face()),
-              );
-              Notif::new(ctx)
-                .set_embed(user_verification_done_embed)
-                .verification(ctx, VerifyType::User)
-                .await?;
-              break;
-            }
-            Err(err) => {
-              error!("Failed to verify user: {err}");
-              mci
-                .create_response(
-                  &ctx.http(),
-                  CreateInteractionResponse::Message(
-                    CreateInteractionResponseMessage::new()
-                      .embed(error_user_embed.clone())
-                      .ephemeral(true),
-                  ),
-                )
-                .await?;
-
-              channel_id
-                .delete_message(&ctx.http(), mci.message.id)
-                .await?;
-              break;
-            }
-          },
-        }
+async fn count_approval(
+  shard: &serenity::Context,
+  data: &Collar,
+  mci: &ComponentInteraction,
+  instance: &str,
+  action: ReviewAction,
+) -> Result<Option<Vec<UserId>>, CollarError> {
+  let blocked = {
+    let cache = data.cache.read();
+    cache.ownership_blocks_verify(mci.guild_id, action.submit_type, action.discord_id)
+  };
+  if blocked {
+    let embed = EmbedWrapper::new_event(shard)
+      .title("Ownership isn't proven yet 3:")
+      .description("This server needs the submitter to prove they own the site before verifying")
+      .color(Color::from_rgb(255, 0, 0));
+    respond_ephemeral(&shard.http, mci, embed).await?;
+    return Ok(None);
+  }
+
+  let permissions = mci.member.as_ref().and_then(|member| member.permissions);
+  let (votes, quorum, already_approved) = {
+    let mut cache = data.cache.write();
+    let quorum = cache
+      .get_review_quorum(mci.guild_id)
+      .get(action.submit_type);
+    match add_approval(&mut cache, instance, &action, mci.user.id, permissions) {
+      Ok((votes, already_approved)) => (votes, quorum, already_approved),
+      // `handle_event` turns these away already, this keeps them from ever counting.
+      Err(_) => return Ok(None),
+    }
+  };
+
+  if let Some(vetoed_by) = votes.vetoed_by {
+    let embed = EmbedWrapper::new_event(shard)
+      .title("This submission was vetoed 3:")
+      .description(format!(
+        "{} vetoed it, it can't be verified until they take the veto back",
+        vetoed_by.mention()
+      ))
+      .color(Color::from_rgb(255, 0, 0));
+    respond_ephemeral(&shard.http, mci, embed).await?;
+    return Ok(None);
+  }
+
+  if votes.approvers.len() >= quorum {
+    return Ok(Some(votes.approvers));
+  }
+
+  info!(
+    "{} approved submission for {}, {}/{quorum}",
+    mci.user.id,
+    action.discord_id,
+    votes.approvers.len()
+  );
+  show_votes(&shard.http, data, &votes, quorum).await;
+
+  let needed = quorum - votes.approvers.len();
+  let embed = EmbedWrapper::new_event(shard)
+    .title(match already_approved {
+      true => "You already approved this :3",
+      false => "Approved :3",
+    })
+    .description(format!(
+      "It needs {needed} more approval{} before it gets verified",
+      if needed == 1 { "" } else { "s" }
+    ))
+    .field(APPROVALS_FIELD, approvals_text(&votes, quorum), false)
+    .color(Color::from_rgb(0, 255, 0));
+  respond_ephemeral(&shard.http, mci, embed).await?;
+
+  Ok(None)
+}
+
+/// Vetoes the submission, or takes the veto back if the moderator is the one that placed it.
+async fn toggle_veto(
+  shard: &serenity::Context,
+  data: &Collar,
+  mci: &ComponentInteraction,
+  instance: &str,
+  action: ReviewAction,
+) -> Result<(), CollarError> {
+  let toggled = {
+    let mut cache = data.cache.write();
+    let quorum = cache
+      .get_review_quorum(mci.guild_id)
+      .get(action.submit_type);
+    let mut votes = cache.get_review_votes(instance, action.submit_type, action.discord_id);
+
+    match votes.vetoed_by {
+      Some(vetoed_by) if vetoed_by != mci.user.id => Err(vetoed_by),
+      _ => {
+        votes.vetoed_by = match votes.vetoed_by {
+          Some(_) => None,
+          None => Some(mci.user.id),
+        };
+        cache.set_review_votes(votes.clone());
+        Ok((votes, quorum))
       }
-      "reject-submission" => {
-        info!("Rejecting submission");
-
-        match submit_type {
-          SubmitType::Ad => {
-            let reject_modal = CreateQuickModal::new("Reject Ad submission")
-              .timeout(std::time::Duration::from_secs(600))
-              .field(
-                CreateInputText::new(
-                  serenity::InputTextStyle::Short,
-                  "Reason",
-                  "rejection-reason",
-                )
-                .placeholder("Enter rejection reason here!")
-                .required(true)
-                .min_length(10),
-              );
-
-            let response = mci
-              .quick_modal(ctx.serenity_context(), reject_modal)
-              .await?;
+    }
+  };
+
+  let (votes, quorum) = match toggled {
+    Ok(toggled) => toggled,
+    Err(vetoed_by) => {
+      let embed = EmbedWrapper::new_event(shard)
+        .title("Already vetoed 3:")
+        .description(format!(
+          "{} vetoed this already, only they can take it back",
+          vetoed_by.mention()
+        ))
+        .color(Color::from_rgb(255, 0, 0));
+      return respond_ephemeral(&shard.http, mci, embed).await;
+    }
+  };
+
+  show_votes(&shard.http, data, &votes, quorum).await;
+
+  let embed = match votes.vetoed_by {
+    Some(_) => {
+      info!(
+        "{} vetoed submission for {}",
+        mci.user.id, action.discord_id
+      );
+      let submission_reason =
+        audit::submission_reason(data, instance, action.submit_type, action.discord_id);
+      audit::record(
+        data,
+        AuditEntry::new(
+          mci.guild_id,
+          AuditAction::Veto,
+          action.submit_type,
+          action.discord_id,
+          mci.user.id,
+        )
+        .submission_reason(submission_reason),
+      );
+
+      EmbedWrapper::new_event(shard)
+        .title("Vetoed :3")
+        .description(
+          "Nobody can verify it until you press veto again to take it back, rejecting still works",
+        )
+        .color(Color::from_rgb(255, 0, 0))
+    }
+    None => {
+      info!(
+        "{} took back their veto on {}",
+        mci.user.id, action.discord_id
+      );
+      EmbedWrapper::new_event(shard)
+        .title("Veto taken back :3")
+        .description("It gets verified once it has enough approvals")
+        .color(Color::from_rgb(0, 255, 0))
+    }
+  };
 
-            match response {
-              Some(modal) => {
-                let reason = &modal.inputs[0];
-                dm_reject_ad_embed = dm_reject_ad_embed
-                  .description(format!("Reason: {reason}"))
-                  .author(
-                    CreateEmbedAuthor::new(format!("Rejected by: {}", mci.user.name))
-                      .icon_url(mci.user.face()),
-                  );
-              }
-              None => {
-                let embed = EmbedWrapper::new_application(ctx)
-                  .title("You didn't specify reason")
-                  .description("No data was submitted 3:")
-                  .color(Color::from_rgb(255, 0, 0));
-                mci
-                  .create_response(
-                    &ctx.http(),
-                    CreateInteractionResponse::Message(
-                      CreateInteractionResponseMessage::new()
-                        .embed(embed)
-                        .ephemeral(true),
-                    ),
-                  )
-                  .await?;
-                continue;
-              }
-            }
+  respond_ephemeral(&shard.http, mci, embed).await
+}
 
-            match reject_ad(ctx, user_id).await {
-              Ok(ad) => {
-                info!("Sending ephermeral embed for successful ad rejection");
-                let reject_ad_embed = reject_ad_embed.thumbnail(&ad.image_url);
-
-                mci
-                  .create_response(
-                    &ctx.http(),
-                    CreateInteractionResponse::Message(
-                      CreateInteractionResponseMessage::new()
-                        .embed(reject_ad_embed)
-                        .ephemeral(true),
-                    ),
-                  )
-                  .await?;
-
-                let dm_reject_ad_embed = dm_reject_ad_embed.thumbnail(&ad.image_url);
-                channel_id
-                  .delete_message(&ctx.http(), mci.message.id)
-                  .await?;
-                notif
-                  .set_embed(dm_reject_ad_embed)
-                  .dm_notif(ctx, user_id)
-                  .await?;
-                break;
-              }
-              Err(err) => {
-                error!("Failed to reject ad: {err}");
-                mci
-                  .create_response(
-                    &ctx.http(),
-                    CreateInteractionResponse::Message(
-                      CreateInteractionResponseMessage::new()
-                        .embed(error_reject_ad_embed)
-                        .ephemeral(true),
-                    ),
-                  )
-                  .await?;
-
-                channel_id
-                  .delete_message(&ctx.http(), mci.message.id)
-                  .await?;
-                break;
-              }
-            }
+async fn process_review(
+  shard: &serenity::Context,
+  data: &Collar,
+  mci: &ComponentInteraction,
+  instance: &str,
+  action: ReviewAction,
+  claim: ReviewClaim,
+) -> Result<(), CollarError> {
+  let approvers = match action.decision {
+    ReviewDecision::Veto => return toggle_veto(shard, data, mci, instance, action).await,
+    ReviewDecision::Verify => match count_approval(shard, data, mci, instance, action).await? {
+      Some(approvers) => approvers,
+      None => return Ok(()),
+    },
+    ReviewDecision::Reject => Vec::new(),
+  };
+
+  let http = &shard.http;
+  let user = http.get_user(action.discord_id).await?;
+  let user_mention = user.mention();
+  let user_pfp = user.face();
+  let user_id = action.discord_id.get();
+
+  let petring = data.petring(mci.guild_id);
+  let moderator = CreateEmbedAuthor::new(match action.decision {
+    ReviewDecision::Verify => format!("Verified by: {}", mci.user.name),
+    ReviewDecision::Reject | ReviewDecision::Veto => format!("Rejected by: {}", mci.user.name),
+  })
+  .icon_url(mci.user.face());
+
+  let submission_reason =
+    audit::submission_reason(data, instance, action.submit_type, action.discord_id);
+  let audit_entry = |audit_action| {
+    AuditEntry::new(
+      mci.guild_id,
+      audit_action,
+      action.submit_type,
+      action.discord_id,
+      mci.user.id,
+    )
+    .submission_reason(submission_reason.clone())
+    .approvers(approvers.clone())
+  };
+  let approved_by = approvers
+    .iter()
+    .map(|approver| approver.mention().to_string())
+    .collect::<Vec<_>>()
+    .join(", ");
+
+  match (action.decision, action.submit_type) {
+    (ReviewDecision::Verify, SubmitType::Ad) => {
+      info!("Verifying ad submission for {user_id}");
+      match petring.verify_ad(action.discord_id).await {
+        Ok(ad) => {
+          info!("Sending ephermeral embed for successful ad verification");
+          let success_ad_embed = EmbedWrapper::new_event(shard)
+            .title("Verified :3")
+            .description(format!("Verified ad for: {}", user_mention))
+            .thumbnail(&ad.image_url)
+            .color(Color::from_rgb(0, 255, 0));
+          respond_ephemeral(http, mci, success_ad_embed).await?;
+          audit::record(data, audit_entry(AuditAction::Verify));
+          close_review(http, data, mci, instance, action).await?;
+
+          ad_archive::archive(data, mci.guild_id, action.discord_id, &ad.image_url).await;
+          let hash = ad_hash::fetch_hash(data, &ad.image_url).await;
+
+          let dm_ad_verify_embed = EmbedWrapper::new_event(shard)
+            .title("Your ad was verified!!")
+            .description(format!(
+              "Hi, there, {user_mention}, your ad has been verified :3"
+            ))
+            .thumbnail(&ad.image_url)
+            .author(moderator.clone())
+            .color(Color::from_rgb(0, 255, 0));
+          Notif::from_embed(dm_ad_verify_embed)
+            .dm_notif_review(http, data, mci, user_id)
+            .await?;
+
+          let mut ad_verification_done_embed = EmbedWrapper::new_event(shard)
+            .title("An Ad has been verified :3")
+            .description(format!("Verified ad for: {}", user_mention))
+            .thumbnail(&ad.image_url)
+            .field("Approved by", approved_by, false)
+            .author(moderator)
+            .color(Color::from_rgb(0, 255, 0));
+          if let Some(hash) = hash
+            && let Some(duplicates) = ad_hash::verified_duplicates(data, hash, action.discord_id)
+          {
+            ad_verification_done_embed =
+              ad_verification_done_embed.field(ad_hash::DUPLICATES_FIELD, duplicates, false);
+          }
+          let announcement = Notif::from_embed(ad_verification_done_embed)
+            .send_review(http, data, mci, NotifChannelType::AdVerify)
+            .await?;
+
+          if let Some(hash) = hash {
+            ad_hash::remember(
+              data,
+              AdHashRecord::new(
+                action.discord_id,
+                &ad.image_url,
+                hash,
+                AdOutcome::Verified,
+                mci.user.id,
+                announcement.map(|message| message.link()),
+              ),
+            )
+            .await;
+          }
+        }
+        Err(err) => {
+          error!("Failed to verify ad: {err}");
+          let error_ad_embed = EmbedWrapper::new_event(shard)
+            .title("Failed to verify 3:")
+            .description(format!("Failed to verify ad for: {}", user_mention))
+            .thumbnail(&user_pfp)
+            .color(Color::from_rgb(255, 0, 0));
+          respond_ephemeral(http, mci, error_ad_embed).await?;
+
+          if err.status() == Some(StatusCode::NOT_FOUND) {
+            close_review(http, data, mci, instance, action).await?;
+          }
+        }
+      }
+    }
+    (ReviewDecision::Verify, SubmitType::User) => {
+      info!("Verifying user submission for {user_id}");
+      match petring.verify_user(action.discord_id).await {
+        Ok(_) => {
+          info!("Sending ephermeral embed for successful user verification");
+          let success_user_embed = EmbedWrapper::new_event(shard)
+            .title("Verified :3")
+            .description(format!("Verified user: {}", user_mention))
+            .thumbnail(&user_pfp)
+            .color(Color::from_rgb(0, 255, 0));
+          respond_ephemeral(http, mci, success_user_embed).await?;
+          audit::record(data, audit_entry(AuditAction::Verify));
+          close_review(http, data, mci, instance, action).await?;
+
+          let dm_user_verify_embed = EmbedWrapper::new_event(shard)
+            .title("You've been verified!!")
+            .description(format!(
+              "Hi there, {user_mention}, you've been verified, welcome to PetRing !! :3"
+            ))
+            .author(moderator.clone())
+            .color(Color::from_rgb(0, 255, 0));
+          Notif::from_embed(dm_user_verify_embed)
+            .dm_notif_review(http, data, mci, user_id)
+            .await?;
+
+          let user_verification_done_embed = EmbedWrapper::new_event(shard)
+            .title("A User has been verified :3")
+            .description(format!("Verified user: {}", user_mention))
+            .field("Approved by", approved_by, false)
+            .author(moderator)
+            .color(Color::from_rgb(0, 255, 0));
+          Notif::from_embed(user_verification_done_embed)
+            .send_review(http, data, mci, NotifChannelType::UserVerify)
+            .await?;
+        }
+        Err(err) => {
+          error!("Failed to verify user: {err}");
+          let error_user_embed = EmbedWrapper::new_event(shard)
+            .title("Failed to verify 3:")
+            .description(format!("Failed to verify user: {}", user_mention))
+            .thumbnail(&user_pfp)
+            .color(Color::from_rgb(255, 0, 0));
+          respond_ephemeral(http, mci, error_user_embed).await?;
+
+          if err.status() == Some(StatusCode::NOT_FOUND) {
+            close_review(http, data, mci, instance, action).await?;
+          }
+        }
+      }
+    }
+    (ReviewDecision::Reject | ReviewDecision::Veto, submit_type) => {
+      info!("Rejecting submission for {user_id}");
+      // Picking or typing a reason can take minutes, other moderators can review meanwhile.
+      drop(claim);
+      let (reason, responder) = match ask_reject_reason(shard, data, mci, submit_type).await? {
+        Some(picked) => picked,
+        None => {
+          info!("Rejection of {user_id} timed out without a reason");
+          return Ok(());
+        }
+      };
+      let _claim = match reclaim_review(data, mci, instance, &action) {
+        Some(claim) => claim,
+        None => {
+          let embed = EmbedWrapper::new_event(shard)
+            .title("Someone else reviewed this submission meanwhile 3:")
+            .description("Nothing was rejected")
+            .color(Color::from_rgb(255, 0, 0));
+          return responder.respond(http, embed).await;
+        }
+      };
+      let reason = &reason;
+
+      let result = match submit_type {
+        SubmitType::Ad => petring
+          .delete_ad(action.discord_id)
+          .await
+          .map(|ad| Some(ad.image_url)),
+        SubmitType::User => petring.delete_user(action.discord_id).await.map(|_| None),
+      };
+
+      let reply_embed = match &result {
+        Ok(image_url) => {
+          info!("Sending ephermeral embed for successful rejection");
+          let thumbnail = image_url.clone().unwrap_or_else(|| user_pfp.clone());
+          let (title, description) = match submit_type {
+            SubmitType::Ad => ("Rejected ad :3", format!("Rejected ad for: {user_mention}")),
+            SubmitType::User => ("Rejected user :3", format!("Rejected user: {user_mention}")),
+          };
+
+          EmbedWrapper::new_event(shard)
+            .title(title)
+            .description(description)
+            .thumbnail(thumbnail)
+            .color(Color::from_rgb(255, 0, 0))
+        }
+        Err(err) => {
+          error!("Failed to reject submission: {err}");
+          let (title, description) = match submit_type {
+            SubmitType::Ad => (
+              "Failed to reject ad 3:",
+              format!("Failed to reject ad for: {user_mention}"),
+            ),
+            SubmitType::User => (
+              "Failed to reject user 3:",
+              format!("Failed to reject user: {user_mention}"),
+            ),
+          };
+
+          EmbedWrapper::new_event(shard)
+            .title(title)
+            .description(description)
+            .thumbnail(&user_pfp)
+            .color(Color::from_rgb(255, 0, 0))
+        }
+      };
+
+      responder.respond(http, reply_embed).await?;
+
+      match result {
+        Ok(image_url) => {
+          audit::record(
+            data,
+            audit_entry(AuditAction::Reject).reason(reason.clone()),
+          );
+          close_review(http, data, mci, instance, action).await?;
+
+          let mut dm_reject_embed = EmbedWrapper::new_event(shard)
+            .title(match submit_type {
+              SubmitType::Ad => "Your ad was rejected 3:",
+              SubmitType::User => "You were rejected 3:",
+            })
+            .description(format!("Reason: {reason}"))
+            .author(moderator.clone())
+            .color(Color::from_rgb(255, 0, 0));
+          if let Some(image_url) = &image_url {
+            dm_reject_embed = dm_reject_embed.thumbnail(image_url);
           }
-          SubmitType::User => {
-            let reject_modal = CreateQuickModal::new("Reject User submission")
-              .timeout(std::time::Duration::from_secs(600))
-              .field(
-                CreateInputText::new(
-                  serenity::InputTextStyle::Short,
-                  "Reason",
-                  "rejection-reason",
-                )
-                .placeholder("Enter rejection reason here!")
-                .required(true)
-                .min_length(10),
-              );
-
-            let response = mci
-              .quick_modal(ctx.serenity_context(), reject_modal)
-              .await?;
 
-            match response {
-              Some(modal) => {
-                let reason = &modal.inputs[0];
-                dm_reject_user_embed = dm_reject_user_embed
-                  .description(format!("Reason: {reason}"))
-                  .author(
-                    CreateEmbedAuthor::new(format!("Rejected by: {}", mci.user.name))
-                      .icon_url(mci.user.face()),
-                  );
-              }
-              None => {
-                let embed = EmbedWrapper::new_application(ctx)
-                  .title("You didn't specify reason")
-                  .description("No data was submitted 3:")
-                  .color(Color::from_rgb(255, 0, 0));
-                mci
-                  .create_response(
-                    &ctx.http(),
-                    CreateInteractionResponse::Message(
-                      CreateInteractionResponseMessage::new()
-                        .embed(embed)
-                        .ephemeral(true),
-                    ),
-                  )
-                  .await?;
-                continue;
-              }
-            }
+          Notif::from_embed(dm_reject_embed)
+            .dm_notif_review(http, data, mci, user_id)
+            .await?;
+
+          if let Some(image_url) = image_url {
+            // Rejected ads get announced so later lookalikes have a decision to link to.
+            let rejected_embed = EmbedWrapper::new_event(shard)
+              .title("Ad rejected 3:")
+              .description(format!("Rejected ad for: {user_mention}"))
+              .field("Reason", reason.clone(), false)
+              .thumbnail(&image_url)
+              .author(moderator)
+              .color(Color::from_rgb(255, 0, 0));
+            let announcement = Notif::from_embed(rejected_embed)
+              .send_review(http, data, mci, NotifChannelType::General)
+              .await?;
 
-            match reject_user(ctx, user_id).await {
-              Ok(_) => {
-                info!("Sending ephermeral embed for successful user rejection");
-                mci
-                  .create_response(
-                    &ctx.http(),
-                    CreateInteractionResponse::Message(
-                      CreateInteractionResponseMessage::new()
-                        .embed(reject_user_embed)
-                        .ephemeral(true),
-                    ),
-                  )
-                  .await?;
-
-                channel_id
-                  .delete_message(&ctx.http(), mci.message.id)
-                  .await?;
-
-                notif
-                  .set_embed(dm_reject_user_embed)
-                  .dm_notif(ctx, user_id)
-                  .await?;
-                break;
-              }
-              Err(err) => {
-                error!("Failed to reject user: {err}");
-                mci
-                  .create_response(
-                    &ctx.http(),
-                    CreateInteractionResponse::Message(
-                      CreateInteractionResponseMessage::new()
-                        .embed(error_reject_user_embed)
-                        .ephemeral(true),
-                    ),
-                  )
-                  .await?;
-
-                channel_id
-                  .delete_message(&ctx.http(), mci.message.id)
-                  .await?;
-                break;
-              }
+            if let Some(hash) = ad_hash::fetch_hash(data, &image_url).await {
+              ad_hash::remember(
+                data,
+                AdHashRecord::new(
+                  action.discord_id,
+                  &image_url,
+                  hash,
+                  AdOutcome::Rejected,
+                  mci.user.id,
+                  announcement.map(|message| message.link()),
+                ),
+              )
+              .await;
             }
           }
         }
-      }
-      _ => {
-        continue;
+        Err(err) if err.status() == Some(StatusCode::NOT_FOUND) => {
+          close_review(http, data, mci, instance, action).await?;
+        }
+        Err(_) => (),
       }
     }
   }
+
   Ok(())
 }
 
@@ -452,6 +1412,10 @@ impl Notif {
     Self { embed }
   }
 
+  pub fn from_embed(embed: CreateEmbed) -> Self {
+    Self { embed }
+  }
+
   /*pub fn get_embed(self) -> CreateEmbed {
       self.embed
   }*/
@@ -462,266 +1426,577 @@ impl Notif {
     self
   }
 
-  pub async fn general(&self, ctx: &CollarAppContext<'_>) -> Result<(), CollarError> {
-    let data = ctx.data();
-    let cache = data.cache.lock().await;
-    let channel_id = cache.get_notif_channel(NotifChannelType::General);
-    let general_channel_id = match channel_id {
-      Some(general_id) => general_id,
-      None => {
-        let save_warning_embed = EmbedWrapper::new_application(ctx).title("No channel")
-                    .description("No channel was found for general notifications, please set one up using `/set_notification_channel`")
-                    .color(Color::from_rgb(255, 0, 0));
-
-        let reply = CreateReply::default()
-          .ephemeral(true)
-          .reply(true)
-          .embed(save_warning_embed);
-        ctx.send(reply).await?;
-        return Ok(());
-      }
+  /// Posts the embed in the guild's channel for `notify_type` through the [`notif_outbox`].
+  pub async fn send_to_channel(
+    &self,
+    http: &Http,
+    data: &Collar,
+    guild_id: GuildId,
+    notify_type: NotifChannelType,
+    components: Vec<CreateActionRow>,
+  ) -> Delivered {
+    let outgoing = Outgoing {
+      components,
+      ..Outgoing::new(self.embed.clone())
     };
+    notif_outbox::deliver(http, data, guild_id, notify_type, outgoing).await
+  }
 
-    let channel_id: ChannelId = general_channel_id.into();
-    let message = CreateMessage::new().embed(self.embed.clone());
+  async fn warn_missing_channel(
+    ctx: &CollarAppContext<'_>,
+    notify_type: NotifChannelType,
+  ) -> Result<(), CollarError> {
+    let save_warning_embed = EmbedWrapper::new_application(ctx)
+      .title("No channel")
+      .description(missing_channel_description(notify_type))
+      .color(Color::from_rgb(255, 0, 0));
 
-    channel_id.send_message(&ctx.http(), message).await?;
+    let reply = CreateReply::default()
+      .ephemeral(true)
+      .reply(true)
+      .embed(save_warning_embed);
+    ctx.send(reply).await?;
 
     Ok(())
   }
 
-  pub async fn submit(
-    &mut self,
+  /// Goes through the [`notif_outbox`], so a failed send gets retried instead of failing the
+  /// command. `Ok(None)` unless it went through right away.
+  async fn send(
+    &self,
     ctx: &CollarAppContext<'_>,
-    user_id: u64,
-    submit_type: SubmitType,
-  ) -> Result<(), CollarError> {
-    let data = ctx.data();
-    let cache = data.cache.lock().await;
-
-    let what_channel_id = match submit_type {
-      SubmitType::User => cache.get_notif_channel(NotifChannelType::UserSubmit),
-      SubmitType::Ad => cache.get_notif_channel(NotifChannelType::AdSubmit),
+    notify_type: NotifChannelType,
+  ) -> Result<Option<serenity::Message>, CollarError> {
+    let delivered = match ctx.guild_id() {
+      Some(guild_id) => {
+        self
+          .send_to_channel(ctx.http(), ctx.data(), guild_id, notify_type, vec![])
+          .await
+      }
+      None => Delivered::NoChannel,
     };
 
-    let submit_channel_id = match what_channel_id {
-      Some(channel_id) => channel_id,
-      None => {
-        let mut save_warning_embed = EmbedWrapper::new_application(ctx)
-          .title("No channel")
-          .color(Color::from_rgb(255, 0, 0));
-
-        match submit_type {
-          SubmitType::User => {
-            save_warning_embed = save_warning_embed.description("No channel was found for user submit notifications, please set one up using `/set_notification_channel`");
-          }
-          SubmitType::Ad => {
-            save_warning_embed = save_warning_embed.description("No channel was found for ad submit notifications, please set one up using `/set_notification_channel`");
-          }
-        }
+    match delivered {
+      Delivered::Sent(message) => Ok(Some(*message)),
+      Delivered::Queued => Ok(None),
+      Delivered::NoChannel => {
+        Self::warn_missing_channel(ctx, notify_type).await?;
+        Ok(None)
+      }
+      Delivered::Failed(err) => Err(err.into()),
+    }
+  }
 
-        let reply = CreateReply::default()
-          .ephemeral(true)
-          .reply(true)
-          .embed(save_warning_embed);
-        ctx.send(reply).await?;
-        return Ok(());
+  /// Like [`Notif::send`], but warns the moderator working through a review instead.
+  async fn send_review(
+    &self,
+    http: &Http,
+    data: &Collar,
+    mci: &ComponentInteraction,
+    notify_type: NotifChannelType,
+  ) -> Result<Option<serenity::Message>, CollarError> {
+    let delivered = match mci.guild_id {
+      Some(guild_id) => {
+        self
+          .send_to_channel(http, data, guild_id, notify_type, vec![])
+          .await
       }
+      None => Delivered::NoChannel,
+    };
+    let sent = match delivered {
+      Delivered::Sent(message) => Some(*message),
+      Delivered::Queued => return Ok(None),
+      Delivered::NoChannel => None,
+      Delivered::Failed(err) => return Err(err.into()),
     };
 
-    let channel_id: ChannelId = submit_channel_id.into();
-    let action_row = CreateActionRow::Buttons(vec![
-      CreateButton::new("verify-submission")
-        .label("Verify submission")
-        .style(ButtonStyle::Success),
-      CreateButton::new("reject-submission")
-        .label("Reject submission")
-        .style(ButtonStyle::Danger),
-    ]);
-
-    let message = CreateMessage::new()
-      .embed(self.embed.clone())
-      .components(vec![action_row]);
-
-    channel_id.send_message(ctx.http(), message).await?;
-    process_mci(user_id, ctx, ctx.serenity_context(), submit_type).await?;
+    if sent.is_none() {
+      let save_warning_embed = CreateEmbed::default()
+        .title("No channel")
+        .description(missing_channel_description(notify_type))
+        .color(Color::from_rgb(255, 0, 0));
+
+      mci
+        .create_followup(
+          http,
+          CreateInteractionResponseFollowup::new()
+            .embed(save_warning_embed)
+            .ephemeral(true),
+        )
+        .await?;
+    }
 
-    Ok(())
+    Ok(sent)
   }
 
-  pub async fn verification(
+  /// The posted message, if the guild has a general channel.
+  pub async fn general(
     &self,
     ctx: &CollarAppContext<'_>,
-    verify_type: VerifyType,
-  ) -> Result<(), CollarError> {
-    let data = ctx.data();
-    let cache = data.cache.lock().await;
-
-    let what_channel_id = match verify_type {
-      VerifyType::User => cache.get_notif_channel(NotifChannelType::UserVerify),
-      VerifyType::Ad => cache.get_notif_channel(NotifChannelType::AdVerify),
-    };
-
-    let verification_channel_id = match what_channel_id {
-      Some(channel_id) => channel_id,
-      None => {
-        let mut save_warning_embed = EmbedWrapper::new_application(ctx)
-          .title("No channel")
-          .color(Color::from_rgb(255, 0, 0));
-
-        match verify_type {
-          VerifyType::User => {
-            save_warning_embed = save_warning_embed.description("No channel was found for user verify notifications, please set one up using `/set_notification_channel`");
-          }
-          VerifyType::Ad => {
-            save_warning_embed = save_warning_embed.description("No channel was found for ad verify notifications, please set one up using `/set_notification_channel`");
-          }
-        }
+  ) -> Result<Option<serenity::Message>, CollarError> {
+    self.send(ctx, NotifChannelType::General).await
+  }
 
-        let reply = CreateReply::default()
-          .ephemeral(true)
-          .reply(true)
-          .embed(save_warning_embed);
-        ctx.send(reply).await?;
-        return Ok(());
+  /// Posts the submission for review and remembers it, clicks are picked up by [`handle_event`].
+  pub async fn submit(
+    &mut self,
+    ctx: &CollarAppContext<'_>,
+    user_id: u64,
+    submit_type: SubmitType,
+    reason: Option<String>,
+  ) -> Result<(), CollarError> {
+    let posted = match ctx.guild_id() {
+      Some(guild_id) => {
+        self
+          .submit_background(
+            ctx.http(),
+            ctx.data(),
+            guild_id,
+            user_id,
+            submit_type,
+            reason,
+          )
+          .await
       }
+      None => false,
     };
 
-    let channel_id: ChannelId = verification_channel_id.into();
-    let message = CreateMessage::new().embed(self.embed.clone());
-
-    channel_id.send_message(&ctx.http(), message).await?;
+    if !posted {
+      Self::warn_missing_channel(ctx, submit_type.channel_type()).await?;
+    }
     Ok(())
   }
 
-  async fn dm_notif_fallback(&self, ctx: &CollarAppContext<'_>) -> Result<(), CollarError> {
-    let data = ctx.data();
-    let cache = data.cache.lock().await;
+  /// Like [`Notif::submit`] for background tasks, `false` if the guild has no channel for it.
+  /// A post Discord doesn't take is retried by the [`notif_outbox`] instead of failing the
+  /// submission.
+  pub async fn submit_background(
+    &self,
+    http: &Http,
+    data: &Collar,
+    guild_id: GuildId,
+    user_id: u64,
+    submit_type: SubmitType,
+    reason: Option<String>,
+  ) -> bool {
+    let discord_id = UserId::new(user_id);
+    let verify_enabled =
+      !data
+        .cache
+        .read()
+        .ownership_blocks_verify(Some(guild_id), submit_type, discord_id);
+    let action_row = CreateActionRow::Buttons(
+      ReviewAction::buttons(
+        ReviewOrigin::ReviewMessage,
+        submit_type,
+        discord_id,
+        verify_enabled,
+      )
+      .to_vec(),
+    );
+
+    let outgoing = Outgoing {
+      components: vec![action_row],
+      review: Some(ReviewPost {
+        submit_type,
+        discord_id,
+        reason,
+      }),
+      ..Outgoing::new(self.embed.clone())
+    };
 
-    let channel_id = cache.get_notif_channel(NotifChannelType::DmFallback);
-    let dm_fallback_channel_id = match channel_id {
-      Some(dm_fallback_channel_id) => dm_fallback_channel_id,
-      None => {
-        let save_warning_embed = EmbedWrapper::new_application(ctx)
-                    .title("No channel")
-                    .description("No channel was found for failed dm notifications, please set one up using `/set_notification_channel`")
-                    .color(Color::from_rgb(255, 0, 0));
-
-        let reply = CreateReply::default()
-          .ephemeral(true)
-          .reply(true)
-          .embed(save_warning_embed);
-        ctx.send(reply).await?;
-        return Ok(());
+    let channel_type = submit_type.channel_type();
+    match notif_outbox::deliver(http, data, guild_id, channel_type, outgoing).await {
+      Delivered::Sent(_) | Delivered::Queued => true,
+      Delivered::NoChannel => false,
+      Delivered::Failed(err) => {
+        error!("Failed to post the {channel_type} of {discord_id} in {guild_id}: {err}");
+        true
       }
+    }
+  }
+
+  /// The posted message, if it went through right away.
+  pub async fn verification(
+    &self,
+    ctx: &CollarAppContext<'_>,
+    verify_type: VerifyType,
+  ) -> Result<Option<serenity::Message>, CollarError> {
+    let notif_channel_type = match verify_type {
+      VerifyType::User => NotifChannelType::UserVerify,
+      VerifyType::Ad => NotifChannelType::AdVerify,
     };
 
-    let channel_id: ChannelId = dm_fallback_channel_id.into();
-    let message = CreateMessage::new().embed(self.embed.clone());
+    self.send(ctx, notif_channel_type).await
+  }
 
-    channel_id.send_message(&ctx.http(), message).await?;
-    Ok(())
+  async fn dm_notif_fallback(&self, ctx: &CollarAppContext<'_>) -> Result<(), CollarError> {
+    self
+      .send(ctx, NotifChannelType::DmFallback)
+      .await
+      .map(|_| ())
   }
-  pub async fn dm_notif(
+
+  /// DMs the user, `Ok(false)` if their DMs are closed.
+  async fn try_dm(
     &self,
-    ctx: &CollarAppContext<'_>,
+    http: &Http,
     user_id: u64,
-  ) -> Result<(), CollarError> {
-    let discord_user = ctx.http().get_user(user_id.into()).await?;
-    let message = CreateMessage::new().embed(self.embed.clone());
-    match discord_user.direct_message(&ctx.http(), message).await {
+    components: Vec<CreateActionRow>,
+  ) -> Result<bool, CollarError> {
+    let discord_user = http.get_user(user_id.into()).await?;
+    let message = CreateMessage::new()
+      .embed(self.embed.clone())
+      .components(components);
+    match discord_user.direct_message(http, message).await {
       Ok(_) => {
         info!(
           "Successfully dmed user: {} ({user_id}) with notif",
           discord_user.name
         );
-        Ok(())
+        Ok(true)
       }
-      Err(_) => return Self::dm_notif_fallback(self, ctx).await,
+      Err(_) => Ok(false),
     }
   }
-}
 
-async fn verify_user(ctx: &CollarAppContext<'_>, discord_id: u64) -> Result<User, CollarError> {
-  let response = make_request(
-    ctx.data().clone(),
-    None::<String>,
-    &format!("/patch/user/verify/{}", discord_id),
-    Method::PATCH,
-  )
-  .await?;
+  pub async fn dm_notif(
+    &self,
+    ctx: &CollarAppContext<'_>,
+    user_id: u64,
+  ) -> Result<(), CollarError> {
+    match self.try_dm(ctx.http(), user_id, vec![]).await? {
+      true => Ok(()),
+      false => self.dm_notif_fallback(ctx).await,
+    }
+  }
 
-  match response {
-    ResponseTypes::Success(_user) => {
-      let user: User = _user;
-      Ok(user)
+  /// Like [`Notif::dm_notif`] for background tasks, falling back to every guild in `guild_ids`.
+  pub async fn dm_notif_background(
+    &self,
+    http: &Http,
+    data: &Collar,
+    guild_ids: &[GuildId],
+    user_id: u64,
+    components: Vec<CreateActionRow>,
+  ) -> Result<(), CollarError> {
+    if self.try_dm(http, user_id, components.clone()).await? {
+      return Ok(());
     }
-    ResponseTypes::Error(error) => {
-      error!("Failed to get user: {error:?}");
-      Err(CollarError::from(format!("Failed to get user: {error:?}")))
+
+    for guild_id in guild_ids {
+      match self
+        .send_to_channel(
+          http,
+          data,
+          *guild_id,
+          NotifChannelType::DmFallback,
+          components.clone(),
+        )
+        .await
+      {
+        Delivered::Sent(_) | Delivered::Queued => (),
+        Delivered::NoChannel => {
+          warn!("Guild {guild_id} has no dm fallback channel for {user_id}")
+        }
+        Delivered::Failed(err) => {
+          error!("Failed to post the dm fallback for {user_id} in {guild_id}: {err}")
+        }
+      }
+    }
+
+    Ok(())
+  }
+
+  async fn dm_notif_review(
+    &self,
+    http: &Http,
+    data: &Collar,
+    mci: &ComponentInteraction,
+    user_id: u64,
+  ) -> Result<(), CollarError> {
+    match self.try_dm(http, user_id, vec![]).await? {
+      true => Ok(()),
+      false => self
+        .send_review(http, data, mci, NotifChannelType::DmFallback)
+        .await
+        .map(|_| ()),
     }
   }
 }
 
-async fn verify_ad(ctx: &CollarAppContext<'_>, discord_id: u64) -> Result<Ad, CollarError> {
-  let response = make_request(
-    ctx.data().clone(),
-    None::<String>,
-    &format!("/patch/ad/verify/{}", discord_id),
-    Method::PATCH,
-  )
-  .await?;
+#[cfg(test)]
+mod tests {
+  use super::*;
 
-  match response {
-    ResponseTypes::Success(_ad) => {
-      let ad: Ad = _ad;
-      Ok(ad)
+  fn action(
+    origin: ReviewOrigin,
+    decision: ReviewDecision,
+    submit_type: SubmitType,
+  ) -> ReviewAction {
+    ReviewAction {
+      origin,
+      decision,
+      submit_type,
+      discord_id: UserId::new(1234),
     }
-    ResponseTypes::Error(error) => {
-      error!("Failed to get user: {error:?}");
-      Err(CollarError::from(format!("Failed to get user: {error:?}")))
+  }
+
+  fn pending(submit_type: SubmitType, discord_id: u64) -> PendingSubmission {
+    PendingSubmission {
+      submit_type,
+      discord_id: UserId::new(discord_id),
+      guild_id: GuildId::new(1),
+      channel_id: ChannelId::new(2),
+      submitted_at: Utc::now(),
+      reason: None,
     }
   }
-}
 
-async fn reject_ad(ctx: &CollarAppContext<'_>, discord_id: u64) -> Result<Ad, CollarError> {
-  let response = make_request(
-    ctx.data().clone(),
-    None::<String>,
-    &format!("/delete/ad/by-discord/{discord_id}"),
-    Method::DELETE,
-  )
-  .await?;
+  #[test]
+  fn custom_ids_round_trip() {
+    for origin in [ReviewOrigin::ReviewMessage, ReviewOrigin::PendingList] {
+      for decision in [
+        ReviewDecision::Verify,
+        ReviewDecision::Reject,
+        ReviewDecision::Veto,
+      ] {
+        for submit_type in [SubmitType::User, SubmitType::Ad] {
+          let action = action(origin, decision, submit_type);
+          assert_eq!(ReviewAction::parse(&action.custom_id()), Some(action));
+        }
+      }
+    }
+  }
+
+  #[test]
+  fn custom_id_layout() {
+    let action = action(
+      ReviewOrigin::PendingList,
+      ReviewDecision::Veto,
+      SubmitType::Ad,
+    );
+    assert_eq!(action.custom_id(), "pending:veto:ad:1234");
+  }
 
-  match response {
-    ResponseTypes::Success(_ad) => {
-      let ad: Ad = _ad;
-      Ok(ad)
+  #[test]
+  fn buttons_carry_their_submission() {
+    let buttons = ReviewAction::buttons(
+      ReviewOrigin::ReviewMessage,
+      SubmitType::User,
+      UserId::new(1234),
+      true,
+    );
+    let ids = buttons
+      .iter()
+      .map(
+        |button| match serde_json::to_value(button).unwrap()["custom_id"].as_str() {
+          Some(id) => ReviewAction::parse(id).expect("review buttons parse"),
+          None => panic!("review buttons have a custom id"),
+        },
+      )
+      .map(|action| (action.decision, action.submit_type, action.discord_id))
+      .collect::<Vec<_>>();
+
+    assert_eq!(
+      ids,
+      [
+        (ReviewDecision::Verify, SubmitType::User, UserId::new(1234)),
+        (ReviewDecision::Reject, SubmitType::User, UserId::new(1234)),
+        (ReviewDecision::Veto, SubmitType::User, UserId::new(1234)),
+      ]
+    );
+  }
+
+  #[test]
+  fn rejects_malformed_ids() {
+    for custom_id in [
+      "",
+      "review",
+      "review:verify",
+      "review:verify:user",
+      "review:verify:user:",
+      "review:verify:user:abc",
+      "review:verify:user:-1",
+      "review:verify:user:0",
+      "review:verify:user:18446744073709551616",
+      "review:verify:user:1234:extra",
+      "review:verify:user:1234:",
+      "review:approve:user:1234",
+      "review:verify:website:1234",
+      "other:verify:user:1234",
+      "REVIEW:VERIFY:USER:1234",
+      " review:verify:user:1234",
+      "review::user:1234",
+      "verify-submission",
+      "reject-submission",
+    ] {
+      assert_eq!(ReviewAction::parse(custom_id), None, "{custom_id:?}");
     }
-    ResponseTypes::Error(error) => {
-      error!("Failed to get user: {error:?}");
-      Err(CollarError::from(format!("Failed to get user: {error:?}")))
+  }
+
+  #[test]
+  fn rejects_other_handlers_ids() {
+    for custom_id in [
+      "health:remove:1234",
+      "ownership:check:1234",
+      "archive:rereview:1234",
+    ] {
+      assert_eq!(ReviewAction::parse(custom_id), None, "{custom_id:?}");
     }
   }
-}
 
-async fn reject_user(ctx: &CollarAppContext<'_>, discord_id: u64) -> Result<User, CollarError> {
-  let response = make_request(
-    ctx.data().clone(),
-    None::<String>,
-    &format!("/delete/user/by-discord/{discord_id}"),
-    Method::DELETE,
-  )
-  .await?;
+  #[test]
+  fn pending_submission_only_accepts_its_own_buttons() {
+    let pending = pending(SubmitType::User, 1234);
+    let verify = action(
+      ReviewOrigin::ReviewMessage,
+      ReviewDecision::Verify,
+      SubmitType::User,
+    );
+    assert!(pending.is_for(&verify));
+    assert!(pending.is_for(&ReviewAction {
+      decision: ReviewDecision::Reject,
+      ..verify
+    }));
+
+    // A forged id pointing the message's buttons at someone else, or at their ad.
+    assert!(!pending.is_for(&ReviewAction {
+      discord_id: UserId::new(4321),
+      ..verify
+    }));
+    assert!(!pending.is_for(&ReviewAction {
+      submit_type: SubmitType::Ad,
+      ..verify
+    }));
+  }
 
-  match response {
-    ResponseTypes::Success(_user) => {
-      let user: User = _user;
-      Ok(user)
-    }
-    ResponseTypes::Error(error) => {
-      error!("Failed to get user: {error:?}");
-      Err(CollarError::from(format!("Failed to get user: {error:?}")))
+  #[test]
+  fn review_presses_need_their_messages_record() {
+    let verify = action(
+      ReviewOrigin::ReviewMessage,
+      ReviewDecision::Verify,
+      SubmitType::User,
+    );
+    let own = pending(SubmitType::User, 1234);
+    let other = pending(SubmitType::User, 4321);
+
+    assert_eq!(
+      route_review(&verify, Some(&own), false),
+      ReviewRoute::Accept
+    );
+    assert_eq!(
+      route_review(&verify, Some(&other), false),
+      ReviewRoute::Mismatch
+    );
+    assert_eq!(route_review(&verify, None, false), ReviewRoute::Untracked);
+    assert_eq!(route_review(&verify, None, true), ReviewRoute::Untracked);
+
+    let listed = ReviewAction {
+      origin: ReviewOrigin::PendingList,
+      ..verify
+    };
+    assert_eq!(route_review(&listed, None, true), ReviewRoute::Accept);
+    // The id says `/pending`, but it's on a message anyone can see.
+    assert_eq!(route_review(&listed, None, false), ReviewRoute::Untracked);
+    assert_eq!(
+      route_review(&listed, Some(&other), true),
+      ReviewRoute::Mismatch
+    );
+  }
+
+  #[test]
+  fn a_submission_can_only_be_claimed_once() {
+    let reviews = ReviewsInFlight::default();
+    let claim = reviews.claim("default", SubmitType::User, UserId::new(1234));
+    assert!(claim.is_some());
+    assert!(
+      reviews
+        .claim("default", SubmitType::User, UserId::new(1234))
+        .is_none()
+    );
+
+    // Other submissions aren't held up, the same member on another instance included.
+    assert!(
+      reviews
+        .claim("default", SubmitType::Ad, UserId::new(1234))
+        .is_some()
+    );
+    assert!(
+      reviews
+        .claim("default", SubmitType::User, UserId::new(4321))
+        .is_some()
+    );
+    assert!(
+      reviews
+        .claim("staging", SubmitType::User, UserId::new(1234))
+        .is_some()
+    );
+
+    drop(claim);
+    assert!(
+      reviews
+        .claim("default", SubmitType::User, UserId::new(1234))
+        .is_some()
+    );
+  }
+
+  #[test]
+  fn only_moderators_approve_and_never_their_own_submission() {
+    let mut cache = Cache::new();
+    let verify = action(
+      ReviewOrigin::ReviewMessage,
+      ReviewDecision::Verify,
+      SubmitType::User,
+    );
+    let moderator = Some(
+      Permissions::MANAGE_CHANNELS
+        | Permissions::BAN_MEMBERS
+        | Permissions::KICK_MEMBERS
+        | Permissions::MUTE_MEMBERS,
+    );
+
+    for permissions in [None, Some(Permissions::MANAGE_CHANNELS)] {
+      assert_eq!(
+        add_approval(&mut cache, "default", &verify, UserId::new(1), permissions).unwrap_err(),
+        ReviewerRefusal::NotModerator
+      );
     }
+    assert_eq!(
+      add_approval(&mut cache, "default", &verify, verify.discord_id, moderator).unwrap_err(),
+      ReviewerRefusal::OwnSubmission
+    );
+    let votes = cache.get_review_votes("default", SubmitType::User, verify.discord_id);
+    assert!(votes.approvers.is_empty(), "refused presses aren't counted");
+
+    // Rejecting or vetoing their own submission isn't an approval.
+    let reject = ReviewAction {
+      decision: ReviewDecision::Reject,
+      ..verify
+    };
+    assert_eq!(
+      check_reviewer(&reject, verify.discord_id, moderator),
+      Ok(())
+    );
+
+    let (votes, already_approved) =
+      add_approval(&mut cache, "default", &verify, UserId::new(1), moderator).unwrap();
+    assert!(!already_approved);
+    assert_eq!(votes.approvers, vec![UserId::new(1)]);
+  }
+
+  #[test]
+  fn votes_are_kept_per_instance() {
+    let mut cache = Cache::new();
+    let mut votes = cache.get_review_votes("default", SubmitType::User, UserId::new(1234));
+    votes.approvers.push(UserId::new(1));
+    cache.set_review_votes(votes);
+
+    let staging = cache.get_review_votes("staging", SubmitType::User, UserId::new(1234));
+    assert!(staging.approvers.is_empty());
+
+    cache.remove_review_votes("staging", SubmitType::User, UserId::new(1234));
+    let default = cache.get_review_votes("default", SubmitType::User, UserId::new(1234));
+    assert_eq!(default.approvers, vec![UserId::new(1)]);
   }
 }
@@ -0,0 +1,399 @@
+use super::{
+  Collar, CollarError, EmbedWrapper,
+  notifs::{self, ReviewAction, ReviewOrigin, SubmitType},
+  site_check,
+};
+use chrono::{DateTime, Utc};
+use poise::serenity_prelude::{
+  self as serenity, ButtonStyle, Color, ComponentInteraction, CreateActionRow, CreateButton,
+  CreateEmbed, CreateInteractionResponse, CreateInteractionResponseFollowup,
+  CreateInteractionResponseMessage, UserId,
+};
+use rand::{Rng, distributions::Alphanumeric};
+use reqwest::{Client, Url};
+use serde::{Deserialize, Serialize};
+use tokio::time::Duration;
+use tracing::{info, warn};
+
+pub const WELL_KNOWN_PATH: &str = "/.well-known/petring-verification.txt";
+pub const META_NAME: &str = "petring-verification";
+pub const TXT_PREFIX: &str = "petring-verification=";
+pub const OWNERSHIP_FIELD: &str = "Ownership";
+
+const CHECK_PREFIX: &str = "ownership:check:";
+const CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+/// DNS over HTTPS, so TXT records can be looked up without a resolver of our own.
+const DOH_URL: &str = "https://cloudflare-dns.com/dns-query";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OwnershipMethod {
+  WellKnown,
+  Meta,
+  DnsTxt,
+}
+
+impl std::fmt::Display for OwnershipMethod {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      OwnershipMethod::WellKnown => write!(f, "well-known file"),
+      OwnershipMethod::Meta => write!(f, "meta tag"),
+      OwnershipMethod::DnsTxt => write!(f, "DNS TXT record"),
+    }
+  }
+}
+
+/// The token a website submitter has to put on their site, one per submission to an instance.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct OwnershipChallenge {
+  pub instance: String,
+  pub discord_id: UserId,
+  pub url: String,
+  pub token: String,
+  pub issued_at: DateTime<Utc>,
+  pub proven: Option<OwnershipMethod>,
+  pub proven_at: Option<DateTime<Utc>>,
+}
+
+impl OwnershipChallenge {
+  pub fn new(instance: &str, discord_id: UserId, url: String) -> Self {
+    let token = rand::thread_rng()
+      .sample_iter(&Alphanumeric)
+      .take(32)
+      .map(char::from)
+      .collect();
+
+    Self {
+      instance: instance.to_string(),
+      discord_id,
+      url,
+      token,
+      issued_at: Utc::now(),
+      proven: None,
+      proven_at: None,
+    }
+  }
+
+  /// How to prove it, for the submitter's reply.
+  pub fn instructions(&self) -> String {
+    let host = Url::parse(&self.url)
+      .ok()
+      .and_then(|url| url.host_str().map(str::to_string))
+      .unwrap_or_else(|| String::from("your domain"));
+
+    format!(
+      "Do one of these, then press **Check ownership**:\n\
+       - Serve `{token}` at `{WELL_KNOWN_PATH}`\n\
+       - Add `<meta name=\"{META_NAME}\" content=\"{token}\">` to your page\n\
+       - Add a TXT record `{TXT_PREFIX}{token}` to `{host}`",
+      token = self.token
+    )
+  }
+}
+
+/// What the review embed says about ownership.
+pub fn status_text(challenge: Option<&OwnershipChallenge>, required: bool) -> String {
+  let status = match challenge {
+    Some(OwnershipChallenge {
+      proven: Some(method),
+      proven_at,
+      ..
+    }) => format!(
+      "Proven with a {method}{}",
+      proven_at
+        .map(|at| format!(" at {}", at.format("%Y-%m-%d %H:%M UTC")))
+        .unwrap_or_default()
+    ),
+    Some(_) => String::from("Not proven yet"),
+    None => String::from("No challenge issued"),
+  };
+
+  match required {
+    true => format!("{status} (required before verifying)"),
+    false => status,
+  }
+}
+
+pub fn check_button(discord_id: UserId) -> CreateButton {
+  CreateButton::new(format!("{CHECK_PREFIX}{discord_id}"))
+    .label("Check ownership")
+    .style(ButtonStyle::Primary)
+}
+
+pub fn parse_check(custom_id: &str) -> Option<UserId> {
+  custom_id
+    .strip_prefix(CHECK_PREFIX)?
+    .parse::<u64>()
+    .ok()
+    .filter(|id| *id != 0)
+    .map(UserId::new)
+}
+
+async fn fetch_text(client: &Client, url: Url) -> Option<String> {
+  let response = site_check::get_public(client, url, CHECK_TIMEOUT)
+    .await
+    .ok()?;
+  if !response.status().is_success() {
+    return None;
+  }
+  let body = site_check::read_capped(response, site_check::MAX_PAGE_BYTES)
+    .await
+    .ok()?;
+  Some(String::from_utf8_lossy(&body).into_owned())
+}
+
+fn has_meta_token(page: &str, token: &str) -> bool {
+  page
+    .split('<')
+    .filter(|tag| tag.to_ascii_lowercase().starts_with("meta"))
+    .any(|tag| tag.contains(META_NAME) && tag.contains(token))
+}
+
+#[derive(Deserialize)]
+struct DohResponse {
+  #[serde(rename = "Answer", default)]
+  answer: Vec<DohAnswer>,
+}
+
+#[derive(Deserialize)]
+struct DohAnswer {
+  data: String,
+}
+
+async fn has_txt_token(client: &Client, host: &str, token: &str) -> bool {
+  let response = client
+    .get(DOH_URL)
+    .query(&[("name", host), ("type", "TXT")])
+    .header(reqwest::header::ACCEPT, "application/dns-json")
+    .timeout(CHECK_TIMEOUT)
+    .send()
+    .await;
+
+  let records = match response {
+    Ok(response) => response.json::<DohResponse>().await,
+    Err(err) => {
+      warn!("Failed to look up TXT records for {host}: {err}");
+      return false;
+    }
+  };
+
+  match records {
+    Ok(records) => records.answer.iter().any(|record| {
+      // TXT data comes back quoted, long records split into several quoted strings.
+      record.data.replace("\" \"", "").trim_matches('"') == format!("{TXT_PREFIX}{token}")
+    }),
+    Err(err) => {
+      warn!("Failed to read TXT records for {host}: {err}");
+      false
+    }
+  }
+}
+
+/// Tries every method in turn, the first one that has the token wins.
+pub async fn check(client: &Client, challenge: &OwnershipChallenge) -> Option<OwnershipMethod> {
+  let url = Url::parse(&challenge.url).ok()?;
+
+  if let Ok(well_known) = url.join(WELL_KNOWN_PATH)
+    && let Some(body) = fetch_text(client, well_known).await
+    && body.trim() == challenge.token
+  {
+    return Some(OwnershipMethod::WellKnown);
+  }
+
+  if let Some(page) = fetch_text(client, url.clone()).await
+    && has_meta_token(&page, &challenge.token)
+  {
+    return Some(OwnershipMethod::Meta);
+  }
+
+  if let Some(host) = url.host_str()
+    && has_txt_token(client, host, &challenge.token).await
+  {
+    return Some(OwnershipMethod::DnsTxt);
+  }
+
+  None
+}
+
+async fn respond(
+  shard: &serenity::Context,
+  mci: &ComponentInteraction,
+  response: CreateInteractionResponse,
+) -> Result<(), CollarError> {
+  mci.create_response(&shard.http, response).await?;
+  Ok(())
+}
+
+async fn followup(
+  shard: &serenity::Context,
+  mci: &ComponentInteraction,
+  embed: CreateEmbed,
+) -> Result<(), CollarError> {
+  mci
+    .create_followup(
+      &shard.http,
+      CreateInteractionResponseFollowup::new()
+        .embed(embed)
+        .ephemeral(true),
+    )
+    .await?;
+  Ok(())
+}
+
+fn ephemeral(embed: CreateEmbed) -> CreateInteractionResponse {
+  CreateInteractionResponse::Message(
+    CreateInteractionResponseMessage::new()
+      .embed(embed)
+      .ephemeral(true),
+  )
+}
+
+/// Handles "Check ownership" on the submitter's reply.
+pub async fn handle_check(
+  shard: &serenity::Context,
+  data: &Collar,
+  mci: &ComponentInteraction,
+  discord_id: UserId,
+) -> Result<(), CollarError> {
+  if mci.user.id != discord_id {
+    let embed = EmbedWrapper::new_event(shard)
+      .title("That's not your submission 3:")
+      .color(Color::from_rgb(255, 0, 0));
+    return respond(shard, mci, ephemeral(embed)).await;
+  }
+
+  let challenge = {
+    let cache = data.cache.read();
+    cache
+      .get_guild_instance_name(mci.guild_id)
+      .and_then(|instance| cache.get_ownership_challenge(&instance, discord_id))
+  };
+  let mut challenge = match challenge {
+    Some(challenge) => challenge,
+    None => {
+      let embed = EmbedWrapper::new_event(shard)
+        .title("No ownership challenge 3:")
+        .description("Submit your website again with `/submit_user` to get a new one")
+        .color(Color::from_rgb(255, 0, 0));
+      return respond(shard, mci, ephemeral(embed)).await;
+    }
+  };
+
+  // Checking fetches up to three urls, more than the three seconds Discord waits for.
+  mci.defer_ephemeral(&shard.http).await?;
+
+  if challenge.proven.is_none() {
+    let method = match check(&data.fetch_client, &challenge).await {
+      Some(method) => method,
+      None => {
+        let embed = EmbedWrapper::new_event(shard)
+          .title("Couldn't find the token yet 3:")
+          .description(challenge.instructions())
+          .color(Color::from_rgb(255, 0, 0));
+        return followup(shard, mci, embed).await;
+      }
+    };
+
+    info!(
+      "{discord_id} proved ownership of {} with a {method}",
+      challenge.url
+    );
+    challenge.proven = Some(method);
+    challenge.proven_at = Some(Utc::now());
+
+    {
+      let mut cache = data.cache.write();
+      cache.set_ownership_challenge(challenge.clone());
+    }
+
+    refresh_review_messages(&shard.http, data, &challenge).await;
+  }
+
+  let embed = EmbedWrapper::new_event(shard)
+    .title("Ownership proven :3")
+    .description(format!(
+      "Thanks! Moderators can see you own {}",
+      challenge.url
+    ))
+    .color(Color::from_rgb(0, 255, 0));
+  followup(shard, mci, embed).await
+}
+
+/// Shows where the challenge is at on the review messages, verify only works once it's proven
+/// where that's required.
+pub async fn refresh_review_messages(
+  http: &serenity::Http,
+  data: &Collar,
+  challenge: &OwnershipChallenge,
+) {
+  let required = {
+    let cache = data.cache.read();
+    cache
+      .get_pending_submissions_for(&challenge.instance, SubmitType::User, challenge.discord_id)
+      .first()
+      .is_some_and(|(_, pending)| cache.requires_ownership(Some(pending.guild_id)))
+  };
+
+  let components = vec![CreateActionRow::Buttons(
+    ReviewAction::buttons(
+      ReviewOrigin::ReviewMessage,
+      SubmitType::User,
+      challenge.discord_id,
+      challenge.proven.is_some() || !required,
+    )
+    .to_vec(),
+  )];
+
+  notifs::update_review_messages(
+    http,
+    data,
+    &challenge.instance,
+    SubmitType::User,
+    challenge.discord_id,
+    &[(
+      OWNERSHIP_FIELD,
+      Some(status_text(Some(challenge), required)),
+    )],
+    Some(components),
+  )
+  .await;
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::collar::{Cache, DEFAULT_INSTANCE};
+  use poise::serenity_prelude::GuildId;
+
+  #[test]
+  fn proof_on_one_instance_doesnt_count_on_another() {
+    let mut cache = Cache::new();
+    let guild_id = GuildId::new(1);
+    let discord_id = UserId::new(1234);
+    cache.set_requires_ownership(guild_id, true);
+
+    let mut staging = OwnershipChallenge::new("staging", discord_id, "https://a.example".into());
+    staging.proven = Some(OwnershipMethod::Meta);
+    cache.set_ownership_challenge(staging);
+    assert!(cache.ownership_blocks_verify(Some(guild_id), SubmitType::User, discord_id));
+
+    let mut default =
+      OwnershipChallenge::new(DEFAULT_INSTANCE, discord_id, "https://a.example".into());
+    default.proven = Some(OwnershipMethod::Meta);
+    cache.set_ownership_challenge(default);
+    assert!(!cache.ownership_blocks_verify(Some(guild_id), SubmitType::User, discord_id));
+    assert!(
+      cache
+        .get_ownership_challenge("staging", discord_id)
+        .is_some()
+    );
+
+    // A new url gets a fresh, unproven challenge.
+    cache.set_ownership_challenge(OwnershipChallenge::new(
+      DEFAULT_INSTANCE,
+      discord_id,
+      "https://b.example".into(),
+    ));
+    assert!(cache.ownership_blocks_verify(Some(guild_id), SubmitType::User, discord_id));
+  }
+}
@@ -0,0 +1,577 @@
+use reqwest::{
+  Client, Response, StatusCode, Url,
+  dns::{Addrs, Name, Resolve, Resolving},
+  redirect::Policy,
+};
+use std::{
+  error::Error as _,
+  net::{IpAddr, SocketAddr},
+  sync::Arc,
+};
+use tokio::time::{Duration, Instant};
+use tracing::warn;
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(15);
+const MAX_REDIRECTS: usize = 10;
+/// Pages past this get cut off, the checks only need the top of them.
+pub const MAX_PAGE_BYTES: usize = 2 * 1024 * 1024;
+/// Past this a site counts as slow, it still passes everything else.
+const SLOW_RESPONSE: Duration = Duration::from_secs(3);
+/// Embed field values can't be longer than this.
+const FIELD_LIMIT: usize = 1024;
+
+/// How the TLS handshake with the submitted site went.
+#[derive(Clone, Debug)]
+pub enum TlsState {
+  Valid,
+  Invalid(String),
+  /// Plain http somewhere along the way.
+  NotUsed,
+}
+
+/// Everything collar saw when fetching a submitted site, handed to each [`SiteCheck`].
+#[derive(Clone, Debug)]
+pub struct SiteFetch {
+  pub url: String,
+  /// Every url that redirected, in order, not including the final one.
+  pub redirects: Vec<String>,
+  pub final_url: String,
+  pub status: Option<StatusCode>,
+  pub tls: TlsState,
+  pub elapsed: Duration,
+  pub body: Option<String>,
+  /// Why the fetch didn't get a response, if it didn't.
+  pub error: Option<String>,
+}
+
+/// What a submission claims, for checks that need more than the page.
+pub struct CheckContext<'a> {
+  pub fetch: &'a SiteFetch,
+  pub username: &'a str,
+  pub web_base_url: &'a str,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CheckStatus {
+  Pass,
+  Fail,
+  /// Nothing to judge, just something reviewers might want to see.
+  Info,
+}
+
+#[derive(Clone, Debug)]
+pub struct CheckResult {
+  pub name: &'static str,
+  pub status: CheckStatus,
+  pub detail: String,
+}
+
+impl CheckResult {
+  pub fn new(name: &'static str, status: CheckStatus, detail: impl Into<String>) -> Self {
+    Self {
+      name,
+      status,
+      detail: detail.into(),
+    }
+  }
+}
+
+/// One rule run against a fetched site, add your own with [`SiteChecker::with_check`].
+pub trait SiteCheck: Send + Sync {
+  fn run(&self, ctx: &CheckContext<'_>) -> CheckResult;
+}
+
+struct StatusCheck;
+
+impl SiteCheck for StatusCheck {
+  fn run(&self, ctx: &CheckContext<'_>) -> CheckResult {
+    match (ctx.fetch.status, &ctx.fetch.error) {
+      (Some(status), _) if status.is_success() => {
+        CheckResult::new("HTTP status", CheckStatus::Pass, status.to_string())
+      }
+      (Some(status), _) => CheckResult::new("HTTP status", CheckStatus::Fail, status.to_string()),
+      (None, Some(error)) => CheckResult::new("HTTP status", CheckStatus::Fail, error.clone()),
+      (None, None) => CheckResult::new("HTTP status", CheckStatus::Fail, "No response"),
+    }
+  }
+}
+
+struct RedirectCheck;
+
+impl SiteCheck for RedirectCheck {
+  fn run(&self, ctx: &CheckContext<'_>) -> CheckResult {
+    if ctx.fetch.redirects.is_empty() {
+      return CheckResult::new("Redirects", CheckStatus::Info, "None");
+    }
+
+    let chain = ctx
+      .fetch
+      .redirects
+      .iter()
+      .chain(std::iter::once(&ctx.fetch.final_url))
+      .map(String::as_str)
+      .collect::<Vec<_>>()
+      .join(" → ");
+    CheckResult::new("Redirects", CheckStatus::Info, chain)
+  }
+}
+
+struct TlsCheck;
+
+impl SiteCheck for TlsCheck {
+  fn run(&self, ctx: &CheckContext<'_>) -> CheckResult {
+    match &ctx.fetch.tls {
+      TlsState::Valid => CheckResult::new("TLS", CheckStatus::Pass, "Valid certificate"),
+      TlsState::Invalid(reason) => CheckResult::new("TLS", CheckStatus::Fail, reason.clone()),
+      TlsState::NotUsed => CheckResult::new("TLS", CheckStatus::Fail, "Served over plain http"),
+    }
+  }
+}
+
+struct ResponseTimeCheck;
+
+impl SiteCheck for ResponseTimeCheck {
+  fn run(&self, ctx: &CheckContext<'_>) -> CheckResult {
+    let status = match ctx.fetch.elapsed > SLOW_RESPONSE {
+      true => CheckStatus::Fail,
+      false => CheckStatus::Pass,
+    };
+    CheckResult::new(
+      "Response time",
+      status,
+      format!("{}ms", ctx.fetch.elapsed.as_millis()),
+    )
+  }
+}
+
+/// Looks for links to `<web_base_url>/user/<username>`, which is what the ring's navigation
+/// points at.
+struct RingLinksCheck;
+
+impl SiteCheck for RingLinksCheck {
+  fn run(&self, ctx: &CheckContext<'_>) -> CheckResult {
+    let body = match &ctx.fetch.body {
+      Some(body) => body,
+      None => return CheckResult::new("Ring links", CheckStatus::Fail, "No page to look at"),
+    };
+
+    let host = ctx
+      .web_base_url
+      .trim_start_matches("https://")
+      .trim_start_matches("http://")
+      .trim_end_matches('/');
+    let link = format!("{host}/user/{}", ctx.username).to_lowercase();
+    let found = body.to_lowercase().matches(&link).count();
+
+    match found {
+      0 => CheckResult::new(
+        "Ring links",
+        CheckStatus::Fail,
+        format!("No links to {link}"),
+      ),
+      found => CheckResult::new(
+        "Ring links",
+        CheckStatus::Pass,
+        format!("{found} link(s) to {link}"),
+      ),
+    }
+  }
+}
+
+struct MetadataCheck;
+
+impl SiteCheck for MetadataCheck {
+  fn run(&self, ctx: &CheckContext<'_>) -> CheckResult {
+    let body = match &ctx.fetch.body {
+      Some(body) => body,
+      None => return CheckResult::new("Page", CheckStatus::Info, "No page to look at"),
+    };
+
+    let title = page_title(body).unwrap_or_else(|| String::from("no title"));
+    let description = meta_description(body).unwrap_or_else(|| String::from("no description"));
+    CheckResult::new("Page", CheckStatus::Info, format!("{title}: {description}"))
+  }
+}
+
+fn page_title(body: &str) -> Option<String> {
+  let lower = body.to_ascii_lowercase();
+  let open = lower.find("<title")?;
+  let start = open + lower[open..].find('>')? + 1;
+  let end = start + lower[start..].find("</title>")?;
+
+  Some(clean_text(&body[start..end])).filter(|title| !title.is_empty())
+}
+
+fn meta_description(body: &str) -> Option<String> {
+  let lower = body.to_ascii_lowercase();
+  let mut offset = 0;
+
+  while let Some(found) = lower[offset..].find("<meta") {
+    let start = offset + found;
+    let end = start + lower[start..].find('>')?;
+    let tag = &body[start..end];
+    offset = end;
+
+    let tag_lower = tag.to_ascii_lowercase();
+    if !tag_lower.contains("name=\"description\"") && !tag_lower.contains("name='description'") {
+      continue;
+    }
+
+    let content = tag_lower.find("content=")? + "content=".len();
+    let value = &tag[content..];
+    let value = match value.chars().next()? {
+      quote @ ('"' | '\'') => {
+        let value = &value[quote.len_utf8()..];
+        &value[..value.find(quote)?]
+      }
+      // Unquoted, runs until the next space or the end of the tag.
+      _ => value
+        .split(|c: char| c.is_whitespace() || c == '>')
+        .next()
+        .unwrap_or_default()
+        .trim_end_matches('/'),
+    };
+    return Some(clean_text(value)).filter(|value| !value.is_empty());
+  }
+
+  None
+}
+
+fn clean_text(text: &str) -> String {
+  text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Certificate problems show up deep in the error's sources, reqwest only says "connect".
+fn tls_error(err: &reqwest::Error) -> Option<String> {
+  let mut source = err.source();
+  while let Some(inner) = source {
+    let message = inner.to_string();
+    if message.to_lowercase().contains("certificate") {
+      return Some(message);
+    }
+    source = inner.source();
+  }
+  None
+}
+
+/// Whether `ip` is out on the internet, not collar's own machine or the network it runs on.
+fn is_public(ip: IpAddr) -> bool {
+  match ip {
+    IpAddr::V4(ip) => {
+      let [first, second, ..] = ip.octets();
+      !(ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        || first == 0
+        // Carrier-grade NAT, 100.64.0.0/10.
+        || (first == 100 && (64..128).contains(&second)))
+    }
+    IpAddr::V6(ip) => {
+      if let Some(ip) = ip.to_ipv4_mapped() {
+        return is_public(IpAddr::V4(ip));
+      }
+      let first = ip.segments()[0];
+      !(ip.is_loopback()
+        || ip.is_unspecified()
+        || ip.is_multicast()
+        // Unique local fc00::/7 and link-local fe80::/10.
+        || (first & 0xfe00) == 0xfc00
+        || (first & 0xffc0) == 0xfe80)
+    }
+  }
+}
+
+/// Refuses urls members could use to point collar at something private. Hosts are checked
+/// again when they resolve, this catches the ones that are already an address.
+pub fn check_public_url(url: &Url) -> Result<(), String> {
+  if !matches!(url.scheme(), "http" | "https") {
+    return Err(format!("{} urls aren't fetched", url.scheme()));
+  }
+
+  let host = url.host_str().ok_or_else(|| String::from("No host"))?;
+  match host
+    .trim_start_matches('[')
+    .trim_end_matches(']')
+    .parse::<IpAddr>()
+  {
+    Ok(ip) if !is_public(ip) => Err(format!("{ip} isn't a public address")),
+    _ => Ok(()),
+  }
+}
+
+/// Only hands out public addresses, so a hostname can't lead to a private one either.
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+  fn resolve(&self, name: Name) -> Resolving {
+    Box::pin(async move {
+      let host = name.as_str();
+      let addrs = tokio::net::lookup_host((host, 0))
+        .await?
+        .filter(|addr| is_public(addr.ip()))
+        .collect::<Vec<SocketAddr>>();
+
+      if addrs.is_empty() {
+        return Err(format!("{host} doesn't resolve to a public address").into());
+      }
+      Ok(Box::new(addrs.into_iter()) as Addrs)
+    })
+  }
+}
+
+/// Client for urls members hand collar: sites, ad images and ownership proofs. Every hop of a
+/// redirect has to stay on public addresses. Send through [`get_public`], the first url isn't
+/// checked otherwise.
+pub fn public_client() -> reqwest::Result<Client> {
+  Client::builder()
+    .dns_resolver(Arc::new(PublicResolver))
+    .redirect(Policy::custom(|attempt| {
+      if attempt.previous().len() >= MAX_REDIRECTS {
+        return attempt.error("Too many redirects");
+      }
+      match check_public_url(attempt.url()) {
+        Ok(()) => attempt.follow(),
+        Err(reason) => attempt.error(reason),
+      }
+    }))
+    .build()
+}
+
+/// GETs a member supplied `url` with a [`public_client`].
+pub async fn get_public(client: &Client, url: Url, timeout: Duration) -> Result<Response, String> {
+  check_public_url(&url)?;
+  client
+    .get(url)
+    .timeout(timeout)
+    .send()
+    .await
+    .map_err(|err| {
+      // reqwest only says "error sending request", the reason is at the bottom.
+      let mut reason = err.to_string();
+      let mut source = err.source();
+      while let Some(inner) = source {
+        reason = inner.to_string();
+        source = inner.source();
+      }
+      reason
+    })
+}
+
+/// Reads at most `max_bytes` of the body and drops the rest, so a huge page can't be pulled
+/// into memory.
+pub async fn read_capped(mut response: Response, max_bytes: usize) -> reqwest::Result<Vec<u8>> {
+  let mut body = Vec::new();
+  while let Some(chunk) = response.chunk().await? {
+    let room = max_bytes - body.len();
+    body.extend_from_slice(&chunk[..chunk.len().min(room)]);
+    if body.len() >= max_bytes {
+      break;
+    }
+  }
+  Ok(body)
+}
+
+/// Fetches submitted sites and runs every registered [`SiteCheck`] on them.
+pub struct SiteChecker {
+  client: Client,
+  checks: Vec<Box<dyn SiteCheck>>,
+}
+
+impl SiteChecker {
+  pub fn new() -> reqwest::Result<Self> {
+    // Redirects are followed by hand so every hop ends up in the report, and gets checked.
+    let client = Client::builder()
+      .dns_resolver(Arc::new(PublicResolver))
+      .redirect(Policy::none())
+      .timeout(FETCH_TIMEOUT)
+      .build()?;
+
+    Ok(Self {
+      client,
+      checks: vec![
+        Box::new(StatusCheck),
+        Box::new(RedirectCheck),
+        Box::new(TlsCheck),
+        Box::new(ResponseTimeCheck),
+        Box::new(RingLinksCheck),
+        Box::new(MetadataCheck),
+      ],
+    })
+  }
+
+  #[allow(dead_code)]
+  pub fn with_check(mut self, check: impl SiteCheck + 'static) -> Self {
+    self.checks.push(Box::new(check));
+    self
+  }
+
+  async fn fetch(&self, url: &str) -> SiteFetch {
+    let started = Instant::now();
+    let mut fetch = SiteFetch {
+      url: url.to_string(),
+      redirects: Vec::new(),
+      final_url: url.to_string(),
+      status: None,
+      tls: TlsState::Valid,
+      elapsed: Duration::ZERO,
+      body: None,
+      error: None,
+    };
+
+    let mut current = match Url::parse(url) {
+      Ok(current) => current,
+      Err(err) => {
+        fetch.error = Some(format!("Not a valid url: {err}"));
+        fetch.tls = TlsState::NotUsed;
+        return fetch;
+      }
+    };
+
+    loop {
+      if current.scheme() != "https" {
+        fetch.tls = TlsState::NotUsed;
+      }
+      if let Err(reason) = check_public_url(&current) {
+        fetch.error = Some(reason);
+        break;
+      }
+
+      let response = match self.client.get(current.clone()).send().await {
+        Ok(response) => response,
+        Err(err) => {
+          if let Some(reason) = tls_error(&err) {
+            fetch.tls = TlsState::Invalid(reason);
+          }
+          fetch.error = Some(err.to_string());
+          break;
+        }
+      };
+
+      let status = response.status();
+      let location = response
+        .headers()
+        .get(reqwest::header::LOCATION)
+        .and_then(|location| location.to_str().ok())
+        .and_then(|location| current.join(location).ok());
+
+      if status.is_redirection()
+        && let Some(next) = location
+      {
+        if fetch.redirects.len() >= MAX_REDIRECTS {
+          fetch.status = Some(status);
+          fetch.error = Some(String::from("Too many redirects"));
+          break;
+        }
+        fetch.redirects.push(current.to_string());
+        current = next;
+        continue;
+      }
+
+      fetch.status = Some(status);
+      match read_capped(response, MAX_PAGE_BYTES).await {
+        Ok(body) => fetch.body = Some(String::from_utf8_lossy(&body).into_owned()),
+        Err(err) => fetch.error = Some(err.to_string()),
+      }
+      break;
+    }
+
+    fetch.final_url = current.to_string();
+    fetch.elapsed = started.elapsed();
+    fetch
+  }
+
+  pub async fn check(&self, url: &str, username: &str, web_base_url: &str) -> Vec<CheckResult> {
+    let fetch = self.fetch(url).await;
+    if let Some(error) = &fetch.error {
+      warn!("Site check fetch of {} failed: {error}", fetch.url);
+    }
+
+    let ctx = CheckContext {
+      fetch: &fetch,
+      username,
+      web_base_url,
+    };
+    self.checks.iter().map(|check| check.run(&ctx)).collect()
+  }
+}
+
+/// The results as an embed field value, cut to fit.
+pub fn format_results(results: &[CheckResult]) -> String {
+  let lines = results
+    .iter()
+    .map(|result| {
+      let mark = match result.status {
+        CheckStatus::Pass => "✅",
+        CheckStatus::Fail => "❌",
+        CheckStatus::Info => "ℹ️",
+      };
+      let detail = result.detail.chars().take(200).collect::<String>();
+      format!("{mark} **{}**: {detail}", result.name)
+    })
+    .collect::<Vec<_>>()
+    .join("\n");
+
+  match lines.chars().count() > FIELD_LIMIT {
+    true => lines.chars().take(FIELD_LIMIT - 1).collect::<String>() + "…",
+    false => lines,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn meta_descriptions_are_read_quoted_or_not() {
+    let description = |tag: &str| meta_description(&format!("<head>{tag}</head>"));
+
+    assert_eq!(
+      description(r#"<meta name="description" content="Hi :3">"#).as_deref(),
+      Some("Hi :3")
+    );
+    assert_eq!(
+      description(r#"<meta name='description' content='Hi :3'>"#).as_deref(),
+      Some("Hi :3")
+    );
+    assert_eq!(
+      description(r#"<meta name="description" content=ünicode here>"#).as_deref(),
+      Some("ünicode")
+    );
+    assert_eq!(
+      description(r#"<meta name="description" content=“curly”>"#).as_deref(),
+      Some("“curly”")
+    );
+  }
+
+  #[test]
+  fn private_addresses_are_refused() {
+    let check = |url: &str| check_public_url(&Url::parse(url).unwrap());
+
+    assert!(check("http://127.0.0.1/").is_err());
+    assert!(check("http://10.0.0.1/").is_err());
+    assert!(check("http://169.254.169.254/latest/meta-data").is_err());
+    assert!(check("http://100.64.0.1/").is_err());
+    assert!(check("http://[::1]/").is_err());
+    assert!(check("http://[fe80::1]/").is_err());
+    assert!(check("http://[::ffff:192.168.0.1]/").is_err());
+    assert!(check("file:///etc/passwd").is_err());
+
+    assert!(check("https://1.1.1.1/").is_ok());
+    assert!(check("https://example.com/").is_ok());
+  }
+
+  #[tokio::test]
+  async fn hostnames_cant_resolve_to_private_addresses() {
+    let client = public_client().unwrap();
+    let url = Url::parse("http://localhost:9/").unwrap();
+
+    let err = get_public(&client, url, Duration::from_secs(5))
+      .await
+      .unwrap_err();
+    assert!(err.contains("public address"), "{err}");
+  }
+}
@@ -0,0 +1,119 @@
+use super::Cache;
+use std::{
+  ops::{Deref, DerefMut},
+  sync::{Arc, Mutex as StdMutex, MutexGuard, PoisonError},
+};
+use tokio::{
+  sync::Notify,
+  time::{Duration, sleep},
+};
+use tracing::error;
+
+/// How long the flusher waits after a write for more to come, a health round writes once per
+/// member and they all land in one file write.
+const FLUSH_DELAY: Duration = Duration::from_secs(1);
+
+/// The one copy of collar's configuration and secrets, everything reads and writes through it.
+/// The lock is a plain std mutex: its guards aren't `Send`, so holding one across an `.await`
+/// doesn't compile. Everything copies out what it needs, or writes what it has, and lets go
+/// before talking to the API or Discord.
+#[derive(Clone)]
+pub struct SharedCache {
+  cache: Arc<StdMutex<Cache>>,
+  /// Wakes the flusher, `None` for caches that never touch the disk.
+  dirty: Option<Arc<Notify>>,
+}
+
+impl SharedCache {
+  /// Spawns the task that writes the cache to disk, so it needs a runtime.
+  pub fn new(cache: Cache) -> Self {
+    let shared = Self {
+      cache: Arc::new(StdMutex::new(cache)),
+      dirty: Some(Arc::new(Notify::new())),
+    };
+    shared.spawn_flusher();
+    shared
+  }
+
+  /// Never touches the disk, for tests that shouldn't share a cache file.
+  pub fn in_memory(cache: Cache) -> Self {
+    Self {
+      cache: Arc::new(StdMutex::new(cache)),
+      dirty: None,
+    }
+  }
+
+  fn guard(&self) -> MutexGuard<'_, Cache> {
+    self.cache.lock().unwrap_or_else(PoisonError::into_inner)
+  }
+
+  /// Never blocks for longer than another task's in-memory read or write.
+  pub fn read(&self) -> CacheRead<'_> {
+    CacheRead(self.guard())
+  }
+
+  /// Changes made through the guard are written to disk shortly after it's dropped, so a
+  /// restart comes back to what was set up to a second before it.
+  pub fn write(&self) -> CacheWrite<'_> {
+    CacheWrite(self.guard(), self.dirty.as_deref())
+  }
+
+  /// Writes a snapshot whenever the cache got dirty. The lock is only held for the copy, the
+  /// serializing and fsync happen on the blocking pool.
+  fn spawn_flusher(&self) {
+    let dirty = match &self.dirty {
+      Some(dirty) => dirty.clone(),
+      None => return,
+    };
+    let cache = self.cache.clone();
+
+    tokio::spawn(async move {
+      loop {
+        dirty.notified().await;
+        sleep(FLUSH_DELAY).await;
+
+        let snapshot = cache.lock().unwrap_or_else(PoisonError::into_inner).clone();
+        match tokio::task::spawn_blocking(move || snapshot.write_to_disk()).await {
+          Ok(Ok(())) => (),
+          Ok(Err(err)) => error!("Failed to write cache to disk: {err}"),
+          Err(err) => error!("Cache write panicked: {err}"),
+        }
+      }
+    });
+  }
+}
+
+pub struct CacheRead<'a>(MutexGuard<'a, Cache>);
+
+impl Deref for CacheRead<'_> {
+  type Target = Cache;
+
+  fn deref(&self) -> &Cache {
+    &self.0
+  }
+}
+
+pub struct CacheWrite<'a>(MutexGuard<'a, Cache>, Option<&'a Notify>);
+
+impl Deref for CacheWrite<'_> {
+  type Target = Cache;
+
+  fn deref(&self) -> &Cache {
+    &self.0
+  }
+}
+
+impl DerefMut for CacheWrite<'_> {
+  fn deref_mut(&mut self) -> &mut Cache {
+    &mut self.0
+  }
+}
+
+impl Drop for CacheWrite<'_> {
+  fn drop(&mut self) {
+    // Only marks it dirty, writes made before the flusher takes its snapshot all land in it.
+    if let Some(dirty) = self.1 {
+      dirty.notify_one();
+    }
+  }
+}
@@ -0,0 +1,460 @@
+use super::{
+  Collar, CollarAppContext, CollarError, EmbedWrapper,
+  ad_image::AdImage,
+  client::{PetringClient, PetringError},
+  commands::{
+    Ad, ImageSubmission, User, UserSubmission, format_timestamp, petads::ad_submitted,
+    petring::user_submitted,
+  },
+  notifs::{Notif, SubmitType},
+  ownership,
+};
+use chrono::{DateTime, Utc};
+use poise::{
+  CreateReply,
+  serenity_prelude::{
+    self as serenity, Color, CreateActionRow, CreateEmbed, FormattedTimestampStyle, GuildId, UserId,
+  },
+};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use tracing::{error, info, warn};
+
+/// What the member typed into the submission modal, already past collar's own checks.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum QueuedPayload {
+  User {
+    username: String,
+    url: String,
+    reason: Option<String>,
+  },
+  Ad {
+    image_url: String,
+  },
+}
+
+/// A submission made while the PetRing API was unavailable, sent once it's back.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct QueuedSubmission {
+  pub discord_id: UserId,
+  pub guild_id: GuildId,
+  pub payload: QueuedPayload,
+  pub queued_at: DateTime<Utc>,
+  #[serde(default)]
+  pub attempts: u32,
+  #[serde(default)]
+  pub last_error: Option<String>,
+}
+
+impl QueuedSubmission {
+  pub fn user(guild_id: GuildId, submission: UserSubmission, reason: Option<String>) -> Self {
+    Self::new(
+      UserId::new(submission.discord_id),
+      guild_id,
+      QueuedPayload::User {
+        username: submission.username,
+        url: submission.url,
+        reason,
+      },
+    )
+  }
+
+  pub fn ad(guild_id: GuildId, submission: ImageSubmission) -> Self {
+    Self::new(
+      UserId::new(submission.discord_id),
+      guild_id,
+      QueuedPayload::Ad {
+        image_url: submission.image_url,
+      },
+    )
+  }
+
+  fn new(discord_id: UserId, guild_id: GuildId, payload: QueuedPayload) -> Self {
+    Self {
+      discord_id,
+      guild_id,
+      payload,
+      queued_at: Utc::now(),
+      attempts: 0,
+      last_error: None,
+    }
+  }
+
+  pub fn submit_type(&self) -> SubmitType {
+    match self.payload {
+      QueuedPayload::User { .. } => SubmitType::User,
+      QueuedPayload::Ad { .. } => SubmitType::Ad,
+    }
+  }
+
+  /// Whether `other` is the same member submitting the same kind of thing in the same guild.
+  pub fn same_as(&self, other: &QueuedSubmission) -> bool {
+    self.discord_id == other.discord_id
+      && self.guild_id == other.guild_id
+      && self.submit_type() == other.submit_type()
+  }
+}
+
+/// Queues the submission and tells the member it'll go through once PetRing is back.
+pub async fn send_queued(
+  ctx: CollarAppContext<'_>,
+  queued: QueuedSubmission,
+  error: &PetringError,
+) -> Result<(), CollarError> {
+  info!(
+    "PetRing is unavailable, queueing the {:?} submission of {}: {error}",
+    queued.submit_type(),
+    queued.discord_id
+  );
+  let replaced = ctx.data().cache.write().queue_submission(queued).is_some();
+
+  let mut embed = EmbedWrapper::new_application(&ctx)
+    .title("Submission queued :3")
+    .description(
+      "PetRing can't be reached right now, so collar kept what you entered and sends it \
+       as soon as it's back. You'll get a DM with the result, no need to submit again :3",
+    )
+    .color(Color::from_rgb(0, 0, 255));
+  if replaced {
+    embed = embed.field(
+      "Replaced",
+      "This takes the place of the submission you had queued before",
+      false,
+    );
+  }
+
+  let reply = CreateReply::default()
+    .embed(embed)
+    .reply(true)
+    .ephemeral(true);
+  ctx.send(reply).await?;
+  Ok(())
+}
+
+/// Sends everything queued for `instance` to the API in the order it was submitted, stopping
+/// at the first submission the API still can't take.
+pub async fn replay(data: &Collar, ctx: &serenity::Context, instance: &str) {
+  let queued = {
+    let cache = data.cache.read();
+    cache
+      .get_queued_submissions()
+      .iter()
+      .filter(|queued| {
+        cache
+          .get_guild_instance_name(Some(queued.guild_id))
+          .as_deref()
+          == Some(instance)
+      })
+      .cloned()
+      .collect::<Vec<_>>()
+  };
+  if queued.is_empty() {
+    return;
+  }
+
+  info!(
+    "Replaying {} queued submission(s) to {instance}",
+    queued.len()
+  );
+  for submission in queued {
+    if let Err(err) = replay_one(data, ctx, &submission).await {
+      warn!(
+        "PetRing of {instance} still can't take the submission of {}, keeping the rest queued: {err}",
+        submission.discord_id
+      );
+      if let Some(queued) = data.cache.write().queued_submission_mut(&submission) {
+        queued.attempts += 1;
+        queued.last_error = Some(err.to_string());
+      }
+      return;
+    }
+  }
+}
+
+/// `Err` when the API is still unavailable or the submission couldn't be looked up, anything
+/// else is settled and leaves the queue. A submit that timed out may still have gone through,
+/// so the user or ad is looked up first instead of being submitted twice.
+async fn replay_one(
+  data: &Collar,
+  ctx: &serenity::Context,
+  submission: &QueuedSubmission,
+) -> Result<(), PetringError> {
+  let guild_id = submission.guild_id;
+  let petring = data.petring(Some(guild_id));
+
+  let outcome = match &submission.payload {
+    QueuedPayload::User {
+      username,
+      url,
+      reason,
+    } => {
+      let submitted = submitted_user(&petring, submission.discord_id, url).await?;
+      let sent = UserSubmission {
+        username: username.clone(),
+        url: url.clone(),
+        discord_id: submission.discord_id.get(),
+      };
+      let result = match submitted {
+        Some(user) => Ok(user),
+        None => petring.submit_user(sent).await,
+      };
+      match result {
+        Ok(user) => {
+          data.cache.write().remove_queued_submission(submission);
+          user_accepted(data, ctx, submission, &user, reason.clone()).await
+        }
+        Err(err) if err.is_unavailable() => return Err(err),
+        Err(err) => {
+          data.cache.write().remove_queued_submission(submission);
+          refused(data, ctx, submission, &err).await
+        }
+      }
+    }
+    QueuedPayload::Ad { image_url } => {
+      let submitted = submitted_ad(&petring, submission.discord_id, image_url).await?;
+
+      // The image may have changed or gone away while the submission waited. That only
+      // stops it when it wasn't submitted yet, an ad that made it gets reviewed either way.
+      let image = match data.inspect_ad_image(Some(guild_id), image_url).await {
+        Ok(image) => Ok(image),
+        Err(err) if submitted.is_some() => Err(err.to_string()),
+        Err(err) => {
+          data.cache.write().remove_queued_submission(submission);
+          let embed = result_embed(ctx, submission, "Your queued ad wasn't submitted 3:")
+            .field("Reason", err.to_string(), false)
+            .color(Color::from_rgb(255, 0, 0));
+          dm(data, ctx, submission, embed, vec![]).await;
+          return Ok(());
+        }
+      };
+
+      let sent = ImageSubmission {
+        image_url: image_url.clone(),
+        discord_id: submission.discord_id.get(),
+      };
+      let result = match submitted {
+        Some(ad) => Ok(ad),
+        None => petring.submit_ad(sent).await,
+      };
+      match result {
+        Ok(ad) => {
+          data.cache.write().remove_queued_submission(submission);
+          ad_accepted(
+            data,
+            ctx,
+            submission,
+            &ad,
+            image.as_ref().map_err(Clone::clone),
+          )
+          .await
+        }
+        Err(err) if err.is_unavailable() => return Err(err),
+        Err(err) => {
+          data.cache.write().remove_queued_submission(submission);
+          refused(data, ctx, submission, &err).await
+        }
+      }
+    }
+  };
+
+  if let Err(err) = outcome {
+    error!(
+      "Failed to finish the queued submission of {}: {err}",
+      submission.discord_id
+    );
+  }
+  Ok(())
+}
+
+/// The user an earlier attempt already got into the ring with `url`, `None` when it still has
+/// to be submitted.
+pub async fn submitted_user(
+  petring: &PetringClient,
+  discord_id: UserId,
+  url: &str,
+) -> Result<Option<User>, PetringError> {
+  match petring.get_user_by_discord(discord_id).await {
+    Ok(user) if user.url == url => Ok(Some(user)),
+    Ok(_) => Ok(None),
+    Err(err) if err.status() == Some(StatusCode::NOT_FOUND) => Ok(None),
+    Err(err) => Err(err),
+  }
+}
+
+/// Same as [`submitted_user`] for an ad with `image_url`.
+pub async fn submitted_ad(
+  petring: &PetringClient,
+  discord_id: UserId,
+  image_url: &str,
+) -> Result<Option<Ad>, PetringError> {
+  match petring.get_ad(discord_id).await {
+    Ok(ad) if ad.image_url == image_url => Ok(Some(ad)),
+    Ok(_) => Ok(None),
+    Err(err) if err.status() == Some(StatusCode::NOT_FOUND) => Ok(None),
+    Err(err) => Err(err),
+  }
+}
+
+async fn user_accepted(
+  data: &Collar,
+  ctx: &serenity::Context,
+  submission: &QueuedSubmission,
+  user: &User,
+  reason: Option<String>,
+) -> Result<(), CollarError> {
+  let author = ctx.http.get_user(submission.discord_id).await?;
+  let (embed, mut submission_embed) = user_submitted(
+    &ctx.http,
+    data,
+    EmbedWrapper::new_event(ctx),
+    submission.guild_id,
+    &author,
+    user,
+    reason.clone(),
+  )
+  .await?;
+
+  let embed = embed.description(queued_since(submission));
+  let button = CreateActionRow::Buttons(vec![ownership::check_button(author.id)]);
+  dm(data, ctx, submission, embed, vec![button]).await;
+
+  let site_check = data
+    .check_site(Some(submission.guild_id), &user.url, &user.username)
+    .await;
+  submission_embed = submission_embed
+    .field("Site check", site_check, false)
+    .field("Queued", queued_since(submission), false);
+
+  let posted = Notif::from_embed(submission_embed)
+    .submit_background(
+      &ctx.http,
+      data,
+      submission.guild_id,
+      user.discord_id,
+      SubmitType::User,
+      reason,
+    )
+    .await;
+  if !posted {
+    warn!(
+      "Guild {} has no channel to review the queued submission of {}",
+      submission.guild_id, submission.discord_id
+    );
+  }
+  Ok(())
+}
+
+async fn ad_accepted(
+  data: &Collar,
+  ctx: &serenity::Context,
+  submission: &QueuedSubmission,
+  ad: &Ad,
+  image: Result<&AdImage, String>,
+) -> Result<(), CollarError> {
+  let author = ctx.http.get_user(submission.discord_id).await?;
+  let web_base_url = data
+    .cache
+    .read()
+    .get_web_base_url(Some(submission.guild_id));
+  let (embed, submission_embed) = ad_submitted(
+    data,
+    EmbedWrapper::new_event(ctx),
+    &author,
+    &web_base_url,
+    ad,
+    image,
+  )
+  .await;
+
+  dm(
+    data,
+    ctx,
+    submission,
+    embed.description(queued_since(submission)),
+    vec![],
+  )
+  .await;
+
+  let posted = Notif::from_embed(submission_embed.field("Queued", queued_since(submission), false))
+    .submit_background(
+      &ctx.http,
+      data,
+      submission.guild_id,
+      ad.discord_id,
+      SubmitType::Ad,
+      None,
+    )
+    .await;
+  if !posted {
+    warn!(
+      "Guild {} has no channel to review the queued ad of {}",
+      submission.guild_id, submission.discord_id
+    );
+  }
+  Ok(())
+}
+
+/// The API turned the submission down now that it could answer.
+async fn refused(
+  data: &Collar,
+  ctx: &serenity::Context,
+  submission: &QueuedSubmission,
+  err: &PetringError,
+) -> Result<(), CollarError> {
+  warn!(
+    "PetRing refused the queued submission of {}: {err}",
+    submission.discord_id
+  );
+  let title = match submission.submit_type() {
+    SubmitType::User => "Your queued submission wasn't accepted 3:",
+    SubmitType::Ad => "Your queued ad wasn't accepted 3:",
+  };
+  let embed = result_embed(ctx, submission, title)
+    .field("Reason", err.to_string(), false)
+    .color(Color::from_rgb(255, 0, 0));
+  dm(data, ctx, submission, embed, vec![]).await;
+  Ok(())
+}
+
+fn queued_since(submission: &QueuedSubmission) -> String {
+  format!(
+    "Queued {} while PetRing was unavailable",
+    format_timestamp(submission.queued_at, FormattedTimestampStyle::RelativeTime)
+  )
+}
+
+fn result_embed(
+  ctx: &serenity::Context,
+  submission: &QueuedSubmission,
+  title: &str,
+) -> CreateEmbed {
+  EmbedWrapper::new_event(ctx)
+    .title(title)
+    .description(queued_since(submission))
+}
+
+/// DMs the member, falling back to the guild they submitted in.
+async fn dm(
+  data: &Collar,
+  ctx: &serenity::Context,
+  submission: &QueuedSubmission,
+  embed: CreateEmbed,
+  components: Vec<CreateActionRow>,
+) {
+  if let Err(err) = Notif::from_embed(embed)
+    .dm_notif_background(
+      &ctx.http,
+      data,
+      &[submission.guild_id],
+      submission.discord_id.get(),
+      components,
+    )
+    .await
+  {
+    error!(
+      "Failed to tell {} how their queued submission went: {err}",
+      submission.discord_id
+    );
+  }
+}
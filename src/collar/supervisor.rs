@@ -0,0 +1,203 @@
+use super::{
+  Collar, EmbedWrapper, NotifChannelType, notif_outbox::Delivered, notifs::Notif,
+  submission_outbox, tokens::TokenState,
+};
+use chrono::{DateTime, Utc};
+use poise::serenity_prelude::{self as serenity, Color};
+use std::{
+  collections::HashMap,
+  sync::{Arc, Mutex as StdMutex, PoisonError},
+};
+use tokio::time::{Duration, sleep};
+use tracing::{error, info, warn};
+
+/// How often a reachable API gets probed.
+const PROBE_EVERY: Duration = Duration::from_secs(2 * 60);
+/// How often an unreachable API gets probed, until it answers again.
+const RETRY_EVERY: Duration = Duration::from_secs(30);
+const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Whether an instance's API could be reached the last time anything tried.
+#[derive(Clone, Debug, Default)]
+pub enum Availability {
+  #[default]
+  Up,
+  Down {
+    since: DateTime<Utc>,
+    error: String,
+  },
+}
+
+/// Failed requests in a row that open the breaker.
+const FAILURE_THRESHOLD: u32 = 3;
+
+#[derive(Clone, Debug, Default)]
+struct InstanceHealth {
+  availability: Availability,
+  failures: u32,
+}
+
+/// Circuit breaker over every instance's API. [`FAILURE_THRESHOLD`] failed requests in a row
+/// open it and requests fail fast from then on, only the supervisor closes it again once a
+/// probe gets through.
+#[derive(Clone, Default)]
+pub struct ApiStatus(Arc<StdMutex<HashMap<String, InstanceHealth>>>);
+
+impl ApiStatus {
+  /// Instances nobody has tried yet count as up.
+  pub fn get(&self, instance: &str) -> Availability {
+    let status = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+    status
+      .get(instance)
+      .map(|health| health.availability.clone())
+      .unwrap_or_default()
+  }
+
+  /// Opens the breaker right away.
+  pub fn mark_down(&self, instance: &str, error: String) {
+    let mut status = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+    let health = status.entry(instance.to_string()).or_default();
+    if let Availability::Down { .. } = health.availability {
+      return;
+    }
+
+    warn!("PetRing API of {instance} is unavailable: {error}");
+    health.availability = Availability::Down {
+      since: Utc::now(),
+      error,
+    };
+  }
+
+  /// A request couldn't get through, even after retrying.
+  pub fn record_failure(&self, instance: &str, error: String) {
+    let failures = {
+      let mut status = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+      let health = status.entry(instance.to_string()).or_default();
+      health.failures += 1;
+      health.failures
+    };
+
+    if failures >= FAILURE_THRESHOLD {
+      self.mark_down(
+        instance,
+        format!("{failures} requests in a row failed, last: {error}"),
+      );
+    }
+  }
+
+  /// A request got an answer that wasn't a server error.
+  pub fn record_success(&self, instance: &str) {
+    let mut status = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some(health) = status.get_mut(instance) {
+      health.failures = 0;
+    }
+  }
+
+  /// Closes the breaker, returning since when it was open, if it was.
+  fn mark_up(&self, instance: &str) -> Option<DateTime<Utc>> {
+    let mut status = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+    let health = std::mem::take(status.entry(instance.to_string()).or_default());
+    match health.availability {
+      Availability::Down { since, .. } => Some(since),
+      Availability::Up => None,
+    }
+  }
+}
+
+/// Keeps probing every instance's API, tells the servers using it once it's back and sends
+/// whatever was submitted while it was away.
+pub fn spawn(data: Collar, ctx: serenity::Context) {
+  tokio::spawn(async move {
+    loop {
+      let instances = data.cache.read().get_instance_names();
+      let mut any_down = false;
+
+      for instance in instances {
+        if let Err(error) = probe(&data, &instance).await {
+          data.api_status.mark_down(&instance, error);
+          any_down = true;
+          continue;
+        }
+
+        if let Some(since) = data.api_status.mark_up(&instance) {
+          info!("PetRing API of {instance} is reachable again");
+          data.tokens.retry_now(&instance);
+          announce_recovery(&data, &ctx, &instance, since).await;
+        }
+        submission_outbox::replay(&data, &ctx, &instance).await;
+      }
+
+      sleep(match any_down {
+        true => RETRY_EVERY,
+        false => PROBE_EVERY,
+      })
+      .await;
+    }
+  });
+}
+
+/// The API counts as reachable when it answers without a server error and collar has tokens
+/// for it, or at least isn't failing to get them.
+async fn probe(data: &Collar, instance: &str) -> Result<(), String> {
+  let api_base_url = match data.cache.read().get_instance(instance) {
+    Some(found) => found.get_api_base_url(),
+    None => return Ok(()),
+  };
+
+  let response = data
+    .http_client
+    .get(&api_base_url)
+    .timeout(PROBE_TIMEOUT)
+    .send()
+    .await
+    .map_err(|err| format!("Couldn't reach {api_base_url}: {err}"))?;
+  if response.status().is_server_error() {
+    return Err(format!(
+      "{api_base_url} responded with {}",
+      response.status()
+    ));
+  }
+
+  match data.tokens.state(instance) {
+    TokenState::Failing { error, .. } => Err(format!("Couldn't get tokens: {error}")),
+    _ => Ok(()),
+  }
+}
+
+async fn announce_recovery(
+  data: &Collar,
+  ctx: &serenity::Context,
+  instance: &str,
+  since: DateTime<Utc>,
+) {
+  let guilds = data.cache.read().get_guilds_for_instance(instance);
+  let minutes = (Utc::now() - since).num_minutes();
+  let embed = EmbedWrapper::new_event(ctx)
+    .title("PetRing API is back :3")
+    .description(format!(
+      "Collar can reach **{instance}** again after {minutes} minute{}, commands work as usual",
+      if minutes == 1 { "" } else { "s" }
+    ))
+    .field(
+      "Unavailable since",
+      format!("<t:{}:f>", since.timestamp()),
+      true,
+    )
+    .color(Color::from_rgb(0, 255, 0));
+  let notif = Notif::from_embed(embed);
+
+  for guild_id in guilds {
+    match notif
+      .send_to_channel(&ctx.http, data, guild_id, NotifChannelType::General, vec![])
+      .await
+    {
+      Delivered::Sent(_) | Delivered::Queued => (),
+      Delivered::NoChannel => {
+        warn!("Guild {guild_id} has no general channel for the API recovery notice")
+      }
+      Delivered::Failed(err) => {
+        error!("Failed to post the API recovery notice in {guild_id}: {err}")
+      }
+    }
+  }
+}
@@ -0,0 +1,312 @@
+use super::{Secrets, http::jittered, state::SharedCache};
+use chrono::{DateTime, Utc};
+use reqwest::{Client, StatusCode};
+use std::{
+  collections::HashMap,
+  sync::{Arc, Mutex as StdMutex, PoisonError},
+};
+use tokio::{
+  sync::{Mutex, Notify, watch},
+  time::{Duration, sleep, timeout},
+};
+use tracing::{error, info, warn};
+
+/// How long before the access token expires it gets refreshed.
+const REFRESH_AHEAD: Duration = Duration::from_secs(5 * 60);
+/// Floor between scheduled refreshes, for instances handing out very short-lived tokens.
+const MIN_REFRESH_GAP: Duration = Duration::from_secs(30);
+const BACKOFF_BASE: Duration = Duration::from_secs(2);
+const BACKOFF_MAX: Duration = Duration::from_secs(5 * 60);
+
+/// Why new tokens couldn't be had.
+#[derive(Debug)]
+pub enum TokenError {
+  /// The request never got a response.
+  Unreachable(reqwest::Error),
+  /// The API turned the tokens down.
+  Rejected(StatusCode, String),
+  /// The API is having trouble of its own.
+  Unavailable(StatusCode, String),
+  /// The API answered with something that isn't a pair of tokens.
+  Decode(serde_json::Error),
+  /// No valid token showed up in time.
+  NotReady(String, TokenState),
+  UnknownInstance(String),
+  /// `DISCORD_BOT_TOKEN` isn't set, so there's nothing to bootstrap with.
+  MissingBotToken,
+}
+
+impl std::fmt::Display for TokenError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      TokenError::Unreachable(err) => write!(f, "couldn't reach the API: {err}"),
+      TokenError::Rejected(status, body) => write!(f, "the API refused ({status}): {body}"),
+      TokenError::Unavailable(status, body) => {
+        write!(f, "the API is unavailable ({status}): {body}")
+      }
+      TokenError::Decode(err) => write!(f, "couldn't read the tokens: {err}"),
+      TokenError::NotReady(instance, state) => {
+        write!(f, "no valid token for {instance} yet, {state}")
+      }
+      TokenError::UnknownInstance(instance) => write!(f, "no PetRing instance named {instance}"),
+      TokenError::MissingBotToken => write!(f, "DISCORD_BOT_TOKEN isn't set"),
+    }
+  }
+}
+
+impl std::error::Error for TokenError {}
+
+/// Where an instance's tokens are at.
+#[derive(Clone, Debug)]
+pub enum TokenState {
+  /// Nothing usable yet, the first fetch hasn't finished.
+  Pending,
+  Valid {
+    expires_at: i64,
+  },
+  /// The last attempt failed, the next one is already scheduled.
+  Failing {
+    error: String,
+    attempts: u32,
+    retry_at: DateTime<Utc>,
+  },
+}
+
+impl std::fmt::Display for TokenState {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      TokenState::Pending => write!(f, "still fetching"),
+      TokenState::Valid { expires_at } => write!(f, "valid until {expires_at}"),
+      TokenState::Failing {
+        error,
+        attempts,
+        retry_at,
+      } => write!(
+        f,
+        "failed {attempts} time(s), retrying at {}: {error}",
+        retry_at.format("%H:%M:%S")
+      ),
+    }
+  }
+}
+
+struct InstanceTokens {
+  /// Held while refreshing, so only one refresh runs per instance.
+  refreshing: Mutex<()>,
+  state: watch::Sender<TokenState>,
+  /// Cuts a backoff short.
+  retry: Notify,
+}
+
+/// Keeps every instance's tokens fresh: refreshes them ahead of expiry, backs off when the API
+/// is down, and goes back to `/bot/setup` when the refresh token is dead.
+#[derive(Clone, Default)]
+pub struct TokenManager(Arc<StdMutex<HashMap<String, Arc<InstanceTokens>>>>);
+
+impl TokenManager {
+  fn instance(&self, instance: &str) -> Arc<InstanceTokens> {
+    let mut instances = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+    instances
+      .entry(instance.to_string())
+      .or_insert_with(|| {
+        Arc::new(InstanceTokens {
+          refreshing: Mutex::new(()),
+          state: watch::channel(TokenState::Pending).0,
+          retry: Notify::new(),
+        })
+      })
+      .clone()
+  }
+
+  pub fn state(&self, instance: &str) -> TokenState {
+    self.instance(instance).state.borrow().clone()
+  }
+
+  /// Retries right away if `instance` is backing off, for when the API is known to be back.
+  pub fn retry_now(&self, instance: &str) {
+    let tokens = self.instance(instance);
+    if let TokenState::Failing { .. } = *tokens.state.borrow() {
+      tokens.retry.notify_one();
+    }
+  }
+
+  /// Waits up to `wait` for `instance` to have a token that hasn't expired.
+  pub async fn wait_valid(
+    &self,
+    cache: &SharedCache,
+    instance: &str,
+    wait: Duration,
+  ) -> Result<Secrets, TokenError> {
+    let mut state = self.instance(instance).state.subscribe();
+    let valid = || {
+      let secrets = cache
+        .read()
+        .get_instance(instance)
+        .map(|found| found.get_secrets());
+      secrets.filter(|secrets| secrets.access_token_expires_at > Utc::now().timestamp())
+    };
+
+    let waited = timeout(wait, async {
+      loop {
+        if let Some(secrets) = valid() {
+          return Some(secrets);
+        }
+        // Nothing's going to change until the next retry, no use holding the caller up.
+        if matches!(*state.borrow_and_update(), TokenState::Failing { .. }) {
+          return None;
+        }
+        if state.changed().await.is_err() {
+          return None;
+        }
+      }
+    })
+    .await;
+
+    match waited {
+      Ok(Some(secrets)) => Ok(secrets),
+      _ => Err(TokenError::NotReady(
+        instance.to_string(),
+        state.borrow().clone(),
+      )),
+    }
+  }
+
+  /// Gets `instance` new tokens, unless `stale_token` was already replaced while waiting for
+  /// another refresh to finish.
+  pub async fn refresh(
+    &self,
+    http_client: &Client,
+    cache: &SharedCache,
+    instance: &str,
+    stale_token: &str,
+  ) -> Result<Secrets, TokenError> {
+    let tokens = self.instance(instance);
+    let _refreshing = tokens.refreshing.lock().await;
+
+    let (secrets, api_base_url) = {
+      let cache = cache.read();
+      let found = cache
+        .get_instance(instance)
+        .ok_or_else(|| TokenError::UnknownInstance(instance.to_string()))?;
+      (found.get_secrets(), found.get_api_base_url())
+    };
+    if secrets.access_token != stale_token {
+      return Ok(secrets);
+    }
+
+    let secrets = renew(http_client, api_base_url, secrets, instance).await?;
+    if let Some(found) = cache.write().get_instance_mut(instance) {
+      found.set_secrets(secrets.clone());
+    }
+    tokens.state.send_replace(TokenState::Valid {
+      expires_at: secrets.access_token_expires_at,
+    });
+
+    Ok(secrets)
+  }
+
+  /// Starts keeping `instance`'s tokens fresh until it disappears from the cache.
+  pub fn spawn(&self, http_client: Client, cache: SharedCache, instance: String) {
+    let manager = self.clone();
+
+    tokio::spawn(async move {
+      let tokens = manager.instance(&instance);
+      let mut attempts = 0;
+
+      loop {
+        let secrets = cache
+          .read()
+          .get_instance(&instance)
+          .map(|found| found.get_secrets());
+        let secrets = match secrets {
+          Some(secrets) => secrets,
+          None => {
+            info!("Instance {instance} is gone, no longer refreshing its tokens");
+            return;
+          }
+        };
+
+        let until_refresh = refresh_in(&secrets);
+        if !until_refresh.is_zero() {
+          attempts = 0;
+          tokens.state.send_replace(TokenState::Valid {
+            expires_at: secrets.access_token_expires_at,
+          });
+          sleep(until_refresh).await;
+          continue;
+        }
+
+        match manager
+          .refresh(&http_client, &cache, &instance, &secrets.access_token)
+          .await
+        {
+          Ok(secrets) => {
+            info!(
+              "Tokens for {instance} are good until {}",
+              secrets.access_token_expires_at
+            );
+            attempts = 0;
+            sleep(MIN_REFRESH_GAP).await;
+          }
+          Err(err) => {
+            attempts += 1;
+            let delay = backoff(attempts);
+            error!("Failed to refresh tokens for {instance} (attempt {attempts}): {err}");
+            tokens.state.send_replace(TokenState::Failing {
+              error: err.to_string(),
+              attempts,
+              retry_at: Utc::now() + delay,
+            });
+            tokio::select! {
+              _ = sleep(delay) => (),
+              _ = tokens.retry.notified() => info!("Retrying tokens for {instance} early"),
+            }
+          }
+        }
+      }
+    });
+  }
+}
+
+/// Refreshes, or bootstraps through `/bot/setup` when the refresh token is dead or refused.
+async fn renew(
+  http_client: &Client,
+  api_base_url: String,
+  secrets: Secrets,
+  instance: &str,
+) -> Result<Secrets, TokenError> {
+  if secrets.refresh_token.is_empty() || secrets.refresh_token_expires_at <= Utc::now().timestamp()
+  {
+    info!("No valid refresh token for {instance}, fetching new tokens");
+    return secrets.get_secrets(http_client.clone(), api_base_url).await;
+  }
+
+  info!("Refreshing tokens for {instance}");
+  match secrets
+    .clone()
+    .refresh_secrets(http_client.clone(), api_base_url.clone())
+    .await
+  {
+    Err(TokenError::Rejected(status, body)) => {
+      warn!("Refresh token for {instance} was refused ({status}: {body}), fetching new tokens");
+      secrets.get_secrets(http_client.clone(), api_base_url).await
+    }
+    refreshed => refreshed,
+  }
+}
+
+/// Time left until the access token is due for a refresh, zero when it already is.
+fn refresh_in(secrets: &Secrets) -> Duration {
+  let refresh_at = secrets.access_token_expires_at - REFRESH_AHEAD.as_secs() as i64;
+  let left = refresh_at - Utc::now().timestamp();
+  Duration::from_secs(left.max(0) as u64)
+}
+
+/// Exponential backoff with jitter, so instances that went down together don't all retry in
+/// the same second.
+fn backoff(attempts: u32) -> Duration {
+  let exponential = BACKOFF_BASE
+    .saturating_mul(1 << attempts.min(16))
+    .min(BACKOFF_MAX);
+  jittered(exponential)
+}
@@ -0,0 +1,277 @@
+use super::{client::PetringClient, commands::User};
+use poise::serenity_prelude::UserId;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+/// Rules per list, the list command has to fit them all in one embed.
+pub const MAX_DOMAIN_RULES: usize = 50;
+
+/// Query parameters that only say where a link was shared, never which page it is.
+const TRACKING_PARAMS: [&str; 11] = [
+  "fbclid", "gclid", "dclid", "msclkid", "yclid", "igshid", "mc_cid", "mc_eid", "_ga", "ref_src",
+  "si",
+];
+
+#[derive(Debug)]
+pub enum UrlError {
+  Invalid(String),
+  Scheme(String),
+  NoHost,
+}
+
+impl std::fmt::Display for UrlError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      UrlError::Invalid(err) => write!(f, "that's not a valid url ({err})"),
+      UrlError::Scheme(scheme) => write!(f, "only https websites can join, not {scheme}"),
+      UrlError::NoHost => write!(f, "the url needs a domain"),
+    }
+  }
+}
+
+fn is_tracking_param(name: &str) -> bool {
+  let name = name.to_ascii_lowercase();
+  name.starts_with("utm_") || TRACKING_PARAMS.contains(&name.as_str())
+}
+
+/// Puts a submitted url in the shape the ring stores it in: https, lowercase host, no tracking
+/// parameters or fragment, and no trailing slash.
+pub fn normalize(raw: &str) -> Result<String, UrlError> {
+  let raw = raw.trim();
+  let with_scheme = match raw.contains("://") {
+    true => raw.to_string(),
+    false => format!("https://{raw}"),
+  };
+
+  let mut url = Url::parse(&with_scheme).map_err(|err| UrlError::Invalid(err.to_string()))?;
+  match url.scheme() {
+    "https" => (),
+    "http" => {
+      let _ = url.set_scheme("https");
+    }
+    scheme => return Err(UrlError::Scheme(scheme.to_string())),
+  }
+  if url.host_str().is_none_or(str::is_empty) {
+    return Err(UrlError::NoHost);
+  }
+
+  // The url crate lowercases the host and drops default ports already.
+  let query = url
+    .query_pairs()
+    .filter(|(name, _)| !is_tracking_param(name))
+    .map(|(name, value)| (name.into_owned(), value.into_owned()))
+    .collect::<Vec<_>>();
+  match query.is_empty() {
+    true => url.set_query(None),
+    false => {
+      url.query_pairs_mut().clear().extend_pairs(query);
+    }
+  }
+  url.set_fragment(None);
+
+  let path = url.path().trim_end_matches('/').to_string();
+  url.set_path(&path);
+
+  let normalized = url.to_string();
+  Ok(match url.query() {
+    Some(_) => normalized,
+    None => normalized.trim_end_matches('/').to_string(),
+  })
+}
+
+/// The host of a url without any `www.`, subdomains are kept apart.
+pub fn domain(url: &str) -> Option<String> {
+  let url = Url::parse(url).ok()?;
+  let host = url.host_str()?.to_ascii_lowercase();
+  Some(host.trim_start_matches("www.").to_string())
+}
+
+/// `*` matches any run of characters, everything else has to match exactly.
+fn wildcard_match(pattern: &str, text: &str) -> bool {
+  let mut parts = pattern.split('*');
+  let first = parts.next().unwrap_or_default();
+  let mut rest = match text.strip_prefix(first) {
+    Some(rest) => rest,
+    None => return false,
+  };
+
+  let parts = parts.collect::<Vec<_>>();
+  for (index, part) in parts.iter().enumerate() {
+    if index == parts.len() - 1 {
+      return rest.ends_with(part);
+    }
+    match rest.find(part) {
+      Some(found) => rest = &rest[found + part.len()..],
+      None => return false,
+    }
+  }
+
+  rest.is_empty()
+}
+
+/// A bare domain like `example.com` covers its subdomains too, anything with a `/` or `*`
+/// is a pattern over the url without its scheme, like `example.com/~*`.
+pub fn rule_matches(rule: &str, url: &str) -> bool {
+  let rule = rule.trim().to_ascii_lowercase();
+
+  if rule.contains('/') || rule.contains('*') {
+    let without_scheme = url
+      .trim_start_matches("https://")
+      .trim_start_matches("http://")
+      .to_ascii_lowercase();
+    return wildcard_match(&rule, &without_scheme)
+      || wildcard_match(&rule, without_scheme.trim_start_matches("www."));
+  }
+
+  match domain(url) {
+    Some(domain) => domain == rule || domain.ends_with(&format!(".{rule}")),
+    None => false,
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PolicyList {
+  Allow,
+  Deny,
+}
+
+/// Which websites a guild accepts, checked before anything reaches the API.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct DomainPolicy {
+  /// When not empty, only urls matching one of these get in.
+  #[serde(default)]
+  pub allow: Vec<String>,
+  #[serde(default)]
+  pub deny: Vec<String>,
+}
+
+#[derive(Debug)]
+pub enum PolicyViolation {
+  Denied(String),
+  NotAllowed,
+}
+
+impl std::fmt::Display for PolicyViolation {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      PolicyViolation::Denied(rule) => {
+        write!(
+          f,
+          "websites matching `{rule}` aren't accepted on this server"
+        )
+      }
+      PolicyViolation::NotAllowed => {
+        write!(f, "this server only accepts websites on its allow list")
+      }
+    }
+  }
+}
+
+impl DomainPolicy {
+  pub fn check(&self, url: &str) -> Result<(), PolicyViolation> {
+    if let Some(rule) = self.deny.iter().find(|rule| rule_matches(rule, url)) {
+      return Err(PolicyViolation::Denied(rule.clone()));
+    }
+
+    if !self.allow.is_empty() && !self.allow.iter().any(|rule| rule_matches(rule, url)) {
+      return Err(PolicyViolation::NotAllowed);
+    }
+
+    Ok(())
+  }
+
+  pub fn list_mut(&mut self, list: PolicyList) -> &mut Vec<String> {
+    match list {
+      PolicyList::Allow => &mut self.allow,
+      PolicyList::Deny => &mut self.deny,
+    }
+  }
+}
+
+/// Members already in the ring with the same site or domain as `url`, for the review embed.
+fn find_duplicates(users: &[User], url: &str, discord_id: UserId) -> Vec<String> {
+  let normalized = normalize(url).unwrap_or_else(|_| url.to_string());
+  let submitted_domain = domain(&normalized);
+
+  users
+    .iter()
+    .filter(|user| user.discord_id != discord_id.get())
+    .filter_map(|user| {
+      let existing = normalize(&user.url).unwrap_or_else(|_| user.url.clone());
+      let owner = match user.discord_id {
+        0 => user.username.clone(),
+        id => format!("{} (<@{id}>)", user.username),
+      };
+
+      if existing == normalized {
+        Some(format!("Same site as {owner}"))
+      } else if submitted_domain.is_some() && domain(&existing) == submitted_domain {
+        Some(format!("Same domain as {owner}: {}", user.url))
+      } else {
+        None
+      }
+    })
+    .collect()
+}
+
+/// Looks the url up against every member collar knows on the instance, empty if that fails.
+pub async fn duplicates(petring: &PetringClient, url: &str, discord_id: UserId) -> Vec<String> {
+  match petring.get_known_users().await {
+    Ok(users) => find_duplicates(&users, url, discord_id),
+    Err(err) => {
+      warn!("Failed to look for duplicates of {url}: {err}");
+      Vec::new()
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn urls_are_normalized_to_what_the_ring_stores() {
+    assert_eq!(
+      normalize(" HTTP://Example.COM:443/blog/?utm_source=x&page=2&fbclid=y#top ").unwrap(),
+      "https://example.com/blog?page=2"
+    );
+    assert_eq!(normalize("example.com/").unwrap(), "https://example.com");
+    assert!(matches!(
+      normalize("ftp://example.com"),
+      Err(UrlError::Scheme(_))
+    ));
+    assert!(matches!(normalize("https://"), Err(UrlError::Invalid(_))));
+  }
+
+  #[test]
+  fn deny_rules_win_over_the_allow_list() {
+    let policy = DomainPolicy {
+      allow: vec![
+        String::from("neocities.org"),
+        String::from("example.com/~*"),
+      ],
+      deny: vec![String::from("bad.neocities.org")],
+    };
+
+    assert!(policy.check("https://cat.neocities.org").is_ok());
+    assert!(policy.check("https://www.example.com/~cat").is_ok());
+    assert!(matches!(
+      policy.check("https://bad.neocities.org/page"),
+      Err(PolicyViolation::Denied(_))
+    ));
+    assert!(matches!(
+      policy.check("https://example.com/cat"),
+      Err(PolicyViolation::NotAllowed)
+    ));
+    assert!(matches!(
+      policy.check("https://notneocities.org"),
+      Err(PolicyViolation::NotAllowed)
+    ));
+    assert!(
+      DomainPolicy::default()
+        .check("https://anything.net")
+        .is_ok()
+    );
+  }
+}
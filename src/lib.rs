@@ -0,0 +1,5 @@
+//! Collar's bot logic, split out of `main.rs` so the tests in `tests/` can drive it.
+
+mod collar;
+
+pub use collar::*;
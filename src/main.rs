@@ -1,6 +1,10 @@
 use collar::{
-  Collar,
-  commands::{misc, notifications, petads, petring},
+  Collar, CollarError, ad_archive,
+  commands::{
+    ad_limits, audit, domain_policy, instances, misc, notifications, outbox, ownership, pending,
+    petads, petring, quorum, reject_reasons,
+  },
+  health, notif_outbox, notifs, supervisor,
 };
 use dotenvy::dotenv;
 use poise::{Framework, serenity_prelude as serenity};
@@ -10,20 +14,23 @@ use tracing_subscriber::{
   fmt::{Subscriber, format::debug_fn},
 };
 
-mod collar;
-
-async fn setup<U, E>(
+async fn setup<U>(
   ctx: &Context,
-  _ready: &Ready,
-  framework: &Framework<U, E>,
-) -> Result<Collar, E>
+  ready: &Ready,
+  framework: &Framework<U, CollarError>,
+) -> Result<Collar, CollarError>
 where
   U: Send + Sync,
-  E: Send + Sync + From<poise::serenity_prelude::Error>,
 {
   poise::builtins::register_globally(ctx, &framework.options().commands).await?;
 
-  Ok(Collar::new().await)
+  let collar = Collar::new(ready.user.id).await?;
+  supervisor::spawn(collar.clone(), ctx.clone());
+  health::spawn(collar.clone(), ctx.clone());
+  ad_archive::spawn(collar.clone(), ctx.clone());
+  notif_outbox::spawn(collar.clone(), ctx.clone());
+
+  Ok(collar)
 }
 
 #[tokio::main]
@@ -48,6 +55,7 @@ async fn main() {
         misc::help(),
         misc::set_feedback_webhook(),
         misc::feedback(),
+        misc::api_stats(),
         petring::me(),
         petring::get_user(),
         petring::submit_user(),
@@ -57,11 +65,28 @@ async fn main() {
         notifications::set_notif_channel(),
         notifications::get_notif_channel(),
         notifications::get_all_notif_channels(),
+        outbox::notification_outbox(),
+        instances::set_instance(),
+        instances::get_instance(),
         petads::submit_ad(),
         petads::verify_ad(),
         petads::remove_ad(),
         petads::edit_ad(),
+        petads::get_ad(),
+        petads::my_ad(),
+        pending::pending(),
+        audit::audit(),
+        reject_reasons::reject_reasons(),
+        quorum::set_quorum(),
+        quorum::get_quorum(),
+        ownership::require_ownership(),
+        domain_policy::domain_policy(),
+        ad_limits::set_ad_limits(),
+        ad_limits::get_ad_limits(),
       ],
+      event_handler: |ctx, event, framework, data| {
+        Box::pin(notifs::handle_event(ctx, event, framework, data))
+      },
       ..Default::default()
     })
     .setup(|ctx, ready, framework| Box::pin(async move { setup(ctx, ready, framework).await }))
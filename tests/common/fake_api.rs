@@ -0,0 +1,483 @@
+//! Stand-in for the PetRing API with in-memory state, so collar can be tested and run without
+//! `api.webring.pet`.
+//!
+//! The integration tests spawn one per test on a free port. `cargo run --example fake_api`
+//! serves one on `FAKE_API_ADDR` (default `127.0.0.1:8787`) to point `API_BASE_URL` at.
+
+use axum::{
+  Json, Router,
+  extract::{Path, Request, State},
+  http::{StatusCode, header::AUTHORIZATION},
+  middleware::{self, Next},
+  response::{IntoResponse, Response},
+  routing::{delete, get, patch, post},
+};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, net::SocketAddr, sync::Arc};
+use tokio::{net::TcpListener, sync::Mutex};
+use tracing::{error, info};
+
+pub type FakeError = Box<dyn std::error::Error + Send + Sync>;
+
+const DEFAULT_ACCESS_TOKEN_TTL: i64 = 15 * 60;
+const DEFAULT_REFRESH_TOKEN_TTL: i64 = 7 * 24 * 60 * 60;
+
+#[derive(Clone, Debug, Serialize)]
+struct FakeUser {
+  username: String,
+  discord_id: u64,
+  url: String,
+  verified: bool,
+  created_at: String,
+  edited_at: String,
+  verified_at: String,
+}
+
+#[derive(Clone, Debug, Serialize)]
+struct FakeAd {
+  username: String,
+  discord_id: u64,
+  image_url: String,
+  ad_url: String,
+  verified: bool,
+  created_at: String,
+  edited_at: String,
+  verified_at: String,
+}
+
+#[derive(Serialize)]
+struct FakeEditedUser {
+  old: FakeUser,
+  new: FakeUser,
+}
+
+#[derive(Serialize)]
+struct FakeSecrets {
+  access_token: String,
+  refresh_token: String,
+  access_token_expires_at: i64,
+  refresh_token_expires_at: i64,
+}
+
+#[derive(Deserialize)]
+struct SetupRequest {
+  bot_token: String,
+}
+
+#[derive(Deserialize)]
+struct RefreshRequest {
+  #[allow(dead_code)]
+  access_token: String,
+  refresh_token: String,
+}
+
+#[derive(Deserialize)]
+struct UserSubmitRequest {
+  username: String,
+  url: String,
+  discord_id: u64,
+}
+
+#[derive(Deserialize)]
+struct UserEditRequest {
+  discord_id: u64,
+  username: Option<String>,
+  url: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct AdRequest {
+  image_url: String,
+  discord_id: u64,
+}
+
+struct FakeState {
+  users: HashMap<u64, FakeUser>,
+  ads: HashMap<u64, FakeAd>,
+  /// access token -> expiry
+  access_tokens: HashMap<String, i64>,
+  /// refresh token -> expiry
+  refresh_tokens: HashMap<String, i64>,
+  access_token_ttl: i64,
+  refresh_token_ttl: i64,
+  token_counter: u64,
+  setups: u32,
+  refreshes: u32,
+}
+
+type SharedState = Arc<Mutex<FakeState>>;
+
+fn now() -> String {
+  Utc::now().to_rfc3339()
+}
+
+fn api_error(status: StatusCode, message: impl Into<String>) -> Response {
+  let body = serde_json::json!({
+    "status": status.as_u16(),
+    "message": message.into(),
+  });
+
+  (status, Json(body)).into_response()
+}
+
+impl FakeState {
+  fn new(access_token_ttl: i64, refresh_token_ttl: i64) -> Self {
+    Self {
+      users: HashMap::new(),
+      ads: HashMap::new(),
+      access_tokens: HashMap::new(),
+      refresh_tokens: HashMap::new(),
+      access_token_ttl,
+      refresh_token_ttl,
+      token_counter: 0,
+      setups: 0,
+      refreshes: 0,
+    }
+  }
+
+  fn issue_secrets(&mut self) -> FakeSecrets {
+    self.token_counter += 1;
+    let now = Utc::now().timestamp();
+
+    let secrets = FakeSecrets {
+      access_token: format!("fake-access-{}", self.token_counter),
+      refresh_token: format!("fake-refresh-{}", self.token_counter),
+      access_token_expires_at: now + self.access_token_ttl,
+      refresh_token_expires_at: now + self.refresh_token_ttl,
+    };
+
+    self.access_tokens.insert(
+      secrets.access_token.clone(),
+      secrets.access_token_expires_at,
+    );
+    self.refresh_tokens.insert(
+      secrets.refresh_token.clone(),
+      secrets.refresh_token_expires_at,
+    );
+
+    secrets
+  }
+}
+
+async fn require_token(State(state): State<SharedState>, request: Request, next: Next) -> Response {
+  let token = request
+    .headers()
+    .get(AUTHORIZATION)
+    .and_then(|value| value.to_str().ok())
+    .and_then(|value| value.strip_prefix("Bearer "))
+    .map(str::to_string);
+
+  let valid = match token {
+    Some(token) => {
+      let state = state.lock().await;
+      state
+        .access_tokens
+        .get(&token)
+        .is_some_and(|expires_at| *expires_at > Utc::now().timestamp())
+    }
+    None => false,
+  };
+
+  if !valid {
+    return api_error(StatusCode::UNAUTHORIZED, "Invalid or expired access token");
+  }
+
+  next.run(request).await
+}
+
+async fn root() -> &'static str {
+  "collar fake petring api :3"
+}
+
+async fn bot_setup(State(state): State<SharedState>, Json(body): Json<SetupRequest>) -> Response {
+  if body.bot_token.is_empty() {
+    return api_error(StatusCode::UNAUTHORIZED, "Missing bot token");
+  }
+
+  let mut state = state.lock().await;
+  info!("Fake API: issuing secrets through /bot/setup");
+  state.setups += 1;
+  Json(state.issue_secrets()).into_response()
+}
+
+async fn bot_refresh(
+  State(state): State<SharedState>,
+  Json(body): Json<RefreshRequest>,
+) -> Response {
+  let mut state = state.lock().await;
+
+  match state.refresh_tokens.remove(&body.refresh_token) {
+    Some(expires_at) if expires_at > Utc::now().timestamp() => {
+      info!("Fake API: refreshing secrets");
+      state.refreshes += 1;
+      Json(state.issue_secrets()).into_response()
+    }
+    _ => api_error(StatusCode::UNAUTHORIZED, "Invalid or expired refresh token"),
+  }
+}
+
+async fn get_user(State(state): State<SharedState>, Path(discord_id): Path<u64>) -> Response {
+  let state = state.lock().await;
+
+  match state.users.get(&discord_id) {
+    Some(user) => Json(user.clone()).into_response(),
+    None => api_error(StatusCode::NOT_FOUND, "User not found"),
+  }
+}
+
+async fn submit_user(
+  State(state): State<SharedState>,
+  Json(body): Json<UserSubmitRequest>,
+) -> Response {
+  let mut state = state.lock().await;
+
+  if state.users.contains_key(&body.discord_id) {
+    return api_error(StatusCode::CONFLICT, "User already exists");
+  }
+
+  if state
+    .users
+    .values()
+    .any(|user| user.username == body.username)
+  {
+    return api_error(StatusCode::CONFLICT, "Username already taken");
+  }
+
+  let user = FakeUser {
+    username: body.username,
+    discord_id: body.discord_id,
+    url: body.url,
+    verified: false,
+    created_at: now(),
+    edited_at: String::new(),
+    verified_at: String::new(),
+  };
+
+  state.users.insert(user.discord_id, user.clone());
+  Json(user).into_response()
+}
+
+async fn edit_user(
+  State(state): State<SharedState>,
+  Json(body): Json<UserEditRequest>,
+) -> Response {
+  let mut state = state.lock().await;
+
+  let Some(user) = state.users.get_mut(&body.discord_id) else {
+    return api_error(StatusCode::NOT_FOUND, "User not found");
+  };
+
+  let old = user.clone();
+  if let Some(username) = body.username {
+    user.username = username;
+  }
+  if let Some(url) = body.url {
+    user.url = url;
+  }
+  user.edited_at = now();
+
+  let new = user.clone();
+  Json(FakeEditedUser { old, new }).into_response()
+}
+
+async fn verify_user(State(state): State<SharedState>, Path(discord_id): Path<u64>) -> Response {
+  let mut state = state.lock().await;
+
+  let Some(user) = state.users.get_mut(&discord_id) else {
+    return api_error(StatusCode::NOT_FOUND, "User not found");
+  };
+
+  if user.verified {
+    return api_error(StatusCode::CONFLICT, "User already verified");
+  }
+
+  user.verified = true;
+  user.verified_at = now();
+  Json(user.clone()).into_response()
+}
+
+async fn delete_user(State(state): State<SharedState>, Path(discord_id): Path<u64>) -> Response {
+  let mut state = state.lock().await;
+
+  match state.users.remove(&discord_id) {
+    Some(user) => {
+      state.ads.remove(&discord_id);
+      Json(user).into_response()
+    }
+    None => api_error(StatusCode::NOT_FOUND, "User not found"),
+  }
+}
+
+async fn get_ad(State(state): State<SharedState>, Path(discord_id): Path<u64>) -> Response {
+  let state = state.lock().await;
+
+  match state.ads.get(&discord_id) {
+    Some(ad) => Json(ad.clone()).into_response(),
+    None => api_error(StatusCode::NOT_FOUND, "Ad not found"),
+  }
+}
+
+async fn submit_ad(State(state): State<SharedState>, Json(body): Json<AdRequest>) -> Response {
+  let mut state = state.lock().await;
+
+  let Some(user) = state.users.get(&body.discord_id) else {
+    return api_error(StatusCode::NOT_FOUND, "User not found");
+  };
+
+  if !user.verified {
+    return api_error(StatusCode::FORBIDDEN, "User not verified");
+  }
+
+  if state.ads.contains_key(&body.discord_id) {
+    return api_error(StatusCode::CONFLICT, "Ad already exists");
+  }
+
+  let ad = FakeAd {
+    username: user.username.clone(),
+    discord_id: body.discord_id,
+    image_url: body.image_url,
+    ad_url: user.url.clone(),
+    verified: false,
+    created_at: now(),
+    edited_at: String::new(),
+    verified_at: String::new(),
+  };
+
+  state.ads.insert(ad.discord_id, ad.clone());
+  Json(ad).into_response()
+}
+
+async fn edit_ad(State(state): State<SharedState>, Json(body): Json<AdRequest>) -> Response {
+  let mut state = state.lock().await;
+
+  let Some(ad) = state.ads.get_mut(&body.discord_id) else {
+    return api_error(StatusCode::NOT_FOUND, "Ad not found");
+  };
+
+  ad.image_url = body.image_url;
+  ad.edited_at = now();
+  Json(ad.clone()).into_response()
+}
+
+async fn verify_ad(State(state): State<SharedState>, Path(discord_id): Path<u64>) -> Response {
+  let mut state = state.lock().await;
+
+  let Some(ad) = state.ads.get_mut(&discord_id) else {
+    return api_error(StatusCode::NOT_FOUND, "Ad not found");
+  };
+
+  if ad.verified {
+    return api_error(StatusCode::CONFLICT, "Ad already verified");
+  }
+
+  ad.verified = true;
+  ad.verified_at = now();
+  Json(ad.clone()).into_response()
+}
+
+async fn delete_ad(State(state): State<SharedState>, Path(discord_id): Path<u64>) -> Response {
+  let mut state = state.lock().await;
+
+  match state.ads.remove(&discord_id) {
+    Some(ad) => Json(ad).into_response(),
+    None => api_error(StatusCode::NOT_FOUND, "Ad not found"),
+  }
+}
+
+fn router(state: SharedState) -> Router {
+  let authed = Router::new()
+    .route("/get/user/by-discord/{discord_id}", get(get_user))
+    .route("/post/user/submit", post(submit_user))
+    .route("/patch/user/edit/", patch(edit_user))
+    .route("/patch/user/verify/{discord_id}", patch(verify_user))
+    .route("/delete/user/by-discord/{discord_id}", delete(delete_user))
+    .route("/get/ad/{discord_id}", get(get_ad))
+    .route("/post/ad/submit", post(submit_ad))
+    .route("/patch/ad/edit/", patch(edit_ad))
+    .route("/patch/ad/verify/{discord_id}", patch(verify_ad))
+    .route("/delete/ad/by-discord/{discord_id}", delete(delete_ad))
+    .route_layer(middleware::from_fn_with_state(state.clone(), require_token));
+
+  Router::new()
+    .route("/", get(root))
+    .route("/bot/setup", post(bot_setup))
+    .route("/bot/refresh", post(bot_refresh))
+    .merge(authed)
+    .with_state(state)
+}
+
+fn ttl_from_env(key: &str, default: i64) -> i64 {
+  match std::env::var(key) {
+    Ok(ttl) => ttl.parse::<i64>().unwrap_or(default),
+    Err(_) => default,
+  }
+}
+
+/// A running fake API, the state stays reachable so tests can look at it and mess with it.
+pub struct FakeApi {
+  pub addr: SocketAddr,
+  state: SharedState,
+}
+
+impl FakeApi {
+  pub fn base_url(&self) -> String {
+    format!("http://{}", self.addr)
+  }
+
+  /// How often `/bot/setup` handed out tokens.
+  pub async fn setups(&self) -> u32 {
+    self.state.lock().await.setups
+  }
+
+  /// How often `/bot/refresh` handed out tokens.
+  pub async fn refreshes(&self) -> u32 {
+    self.state.lock().await.refreshes
+  }
+
+  /// Invalidates every access token while collar still thinks it's good, the next request
+  /// gets a 401.
+  pub async fn revoke_access_tokens(&self) {
+    self.state.lock().await.access_tokens.clear();
+  }
+}
+
+/// Binds the fake API to `addr` and serves it in the background.
+///
+/// Token lifetimes can be shortened with `FAKE_API_ACCESS_TOKEN_TTL` and
+/// `FAKE_API_REFRESH_TOKEN_TTL` (seconds) to exercise the refresh and 401 paths.
+pub async fn spawn(addr: &str) -> Result<FakeApi, FakeError> {
+  spawn_with_ttls(
+    addr,
+    ttl_from_env("FAKE_API_ACCESS_TOKEN_TTL", DEFAULT_ACCESS_TOKEN_TTL),
+    ttl_from_env("FAKE_API_REFRESH_TOKEN_TTL", DEFAULT_REFRESH_TOKEN_TTL),
+  )
+  .await
+}
+
+pub async fn spawn_with_ttls(
+  addr: &str,
+  access_token_ttl: i64,
+  refresh_token_ttl: i64,
+) -> Result<FakeApi, FakeError> {
+  let state = Arc::new(Mutex::new(FakeState::new(
+    access_token_ttl,
+    refresh_token_ttl,
+  )));
+
+  let listener = TcpListener::bind(addr).await?;
+  let local_addr = listener.local_addr()?;
+  let app = router(state.clone());
+
+  tokio::spawn(async move {
+    if let Err(err) = axum::serve(listener, app).await {
+      error!("Fake API stopped: {err}");
+    }
+  });
+
+  info!("Fake PetRing API listening on http://{local_addr}");
+  Ok(FakeApi {
+    addr: local_addr,
+    state,
+  })
+}
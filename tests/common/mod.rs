@@ -0,0 +1,106 @@
+// Shared with `examples/fake_api.rs`, each side only uses part of it.
+#[allow(dead_code)]
+pub mod fake_api;
+
+use collar::{
+  Cache, DEFAULT_INSTANCE, Urls,
+  client::{InstanceTarget, PetringClient},
+  metrics::RequestMetrics,
+  state::SharedCache,
+  supervisor::ApiStatus,
+  tokens::TokenManager,
+};
+use fake_api::FakeApi;
+use reqwest::Client;
+use std::{sync::Once, time::Duration};
+
+/// Long enough that nothing refreshes on its own while a test runs.
+const ACCESS_TOKEN_TTL: i64 = 60 * 60;
+const REFRESH_TOKEN_TTL: i64 = 24 * 60 * 60;
+
+/// A collar client talking to its own fake API.
+pub struct Harness {
+  pub api: FakeApi,
+  pub cache: SharedCache,
+  pub tokens: TokenManager,
+  pub http_client: Client,
+  pub petring: PetringClient,
+}
+
+/// `/bot/setup` is bootstrapped with the bot token from the environment.
+fn set_bot_token() {
+  static BOT_TOKEN: Once = Once::new();
+  BOT_TOKEN.call_once(|| {
+    // SAFETY: every test calls this before it starts anything that reads the environment.
+    unsafe { std::env::set_var("DISCORD_BOT_TOKEN", "fake-bot-token") };
+  });
+}
+
+/// Spawns a fake API and a client for it, without any tokens yet.
+pub async fn start_without_tokens() -> Harness {
+  set_bot_token();
+  let api = fake_api::spawn_with_ttls("127.0.0.1:0", ACCESS_TOKEN_TTL, REFRESH_TOKEN_TTL)
+    .await
+    .expect("fake API should bind");
+
+  let mut cache = Cache::new();
+  cache.set_instance_urls(
+    DEFAULT_INSTANCE,
+    Urls::new(api.base_url(), String::from("https://webring.example")),
+  );
+  let cache = SharedCache::in_memory(cache);
+  let tokens = TokenManager::default();
+  let http_client = Client::new();
+
+  let petring = PetringClient::new(
+    http_client.clone(),
+    cache.clone(),
+    tokens.clone(),
+    ApiStatus::default(),
+    RequestMetrics::default(),
+    InstanceTarget::Named(DEFAULT_INSTANCE.to_string()),
+  );
+
+  Harness {
+    api,
+    cache,
+    tokens,
+    http_client,
+    petring,
+  }
+}
+
+/// Spawns a fake API and a client for it, with tokens bootstrapped the way `Collar::new` does.
+pub async fn start() -> Harness {
+  let harness = start_without_tokens().await;
+  harness
+    .bootstrap(Duration::from_secs(10))
+    .await
+    .expect("tokens should bootstrap against the fake API");
+  harness
+}
+
+impl Harness {
+  /// Starts the token refresher and waits for a valid access token.
+  pub async fn bootstrap(&self, wait: Duration) -> Result<(), collar::tokens::TokenError> {
+    self.tokens.spawn(
+      self.http_client.clone(),
+      self.cache.clone(),
+      DEFAULT_INSTANCE.to_string(),
+    );
+    self
+      .tokens
+      .wait_valid(&self.cache, DEFAULT_INSTANCE, wait)
+      .await
+      .map(|_| ())
+  }
+
+  pub fn secrets(&self) -> collar::Secrets {
+    self
+      .cache
+      .read()
+      .get_instance(DEFAULT_INSTANCE)
+      .expect("the default instance is configured")
+      .get_secrets()
+  }
+}
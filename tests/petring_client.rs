@@ -0,0 +1,314 @@
+//! Drives the PetRing client against the fake API: token bootstrap, the 401 refresh-and-replay,
+//! the verify and reject flows, and the lookups that keep queued submissions from going in twice.
+
+mod common;
+
+use collar::{
+  DEFAULT_INSTANCE, Urls,
+  client::{InstanceTarget, PetringClient, PetringError, RosterEntry},
+  commands::{ImageSubmission, UserSubmission},
+  metrics::RequestMetrics,
+  notifs::SubmitType,
+  submission_outbox::{submitted_ad, submitted_user},
+  supervisor::ApiStatus,
+};
+use poise::serenity_prelude::{GuildId, UserId};
+use reqwest::StatusCode;
+use std::time::Duration;
+
+const DISCORD_ID: u64 = 1234;
+
+fn user_submission() -> UserSubmission {
+  UserSubmission {
+    username: String::from("kitty"),
+    url: String::from("https://kitty.example"),
+    discord_id: DISCORD_ID,
+  }
+}
+
+#[tokio::test]
+async fn bootstraps_tokens_through_bot_setup() {
+  let harness = common::start_without_tokens().await;
+  assert!(harness.secrets().access_token.is_empty());
+
+  harness
+    .bootstrap(Duration::from_secs(10))
+    .await
+    .expect("tokens should bootstrap");
+
+  assert_eq!(harness.api.setups().await, 1);
+  assert_eq!(harness.api.refreshes().await, 0);
+  let secrets = harness.secrets();
+  assert!(!secrets.access_token.is_empty());
+  assert!(!secrets.refresh_token.is_empty());
+
+  // The bootstrapped token gets the client in.
+  let err = harness
+    .petring
+    .get_user_by_discord(UserId::new(DISCORD_ID))
+    .await
+    .expect_err("nobody was submitted yet");
+  assert_eq!(err.status(), Some(StatusCode::NOT_FOUND));
+}
+
+#[tokio::test]
+async fn refreshes_and_replays_after_a_401() {
+  let harness = common::start().await;
+  let stale = harness.secrets().access_token;
+
+  harness.api.revoke_access_tokens().await;
+  let user = harness
+    .petring
+    .submit_user(user_submission())
+    .await
+    .expect("the submission should be replayed with a fresh token");
+
+  assert_eq!(user.discord_id, DISCORD_ID);
+  assert_eq!(harness.api.refreshes().await, 1);
+  assert_ne!(harness.secrets().access_token, stale);
+
+  // The replayed POST carried its body, and went through only once.
+  let stored = harness
+    .petring
+    .get_user_by_discord(UserId::new(DISCORD_ID))
+    .await
+    .expect("the user was submitted");
+  assert_eq!(stored.url, "https://kitty.example");
+}
+
+#[tokio::test]
+async fn verifies_a_submitted_user() {
+  let harness = common::start().await;
+  let discord_id = UserId::new(DISCORD_ID);
+
+  let submitted = harness
+    .petring
+    .submit_user(user_submission())
+    .await
+    .unwrap();
+  assert!(!submitted.verified);
+  assert!(submitted.verified_at.is_none());
+
+  let verified = harness.petring.verify_user(discord_id).await.unwrap();
+  assert!(verified.verified);
+  assert!(verified.verified_at.is_some());
+
+  let err = harness
+    .petring
+    .verify_user(discord_id)
+    .await
+    .expect_err("a user can only be verified once");
+  assert_eq!(err.status(), Some(StatusCode::CONFLICT));
+
+  let known = harness.petring.get_known_users().await.unwrap();
+  assert_eq!(known.len(), 1);
+  assert!(known[0].verified);
+}
+
+#[tokio::test]
+async fn rejecting_a_user_deletes_them_and_drops_them_from_the_roster() {
+  let harness = common::start().await;
+  let discord_id = UserId::new(DISCORD_ID);
+  let entry = RosterEntry {
+    instance: DEFAULT_INSTANCE.to_string(),
+    submit_type: SubmitType::User,
+    discord_id,
+  };
+
+  harness
+    .petring
+    .submit_user(user_submission())
+    .await
+    .unwrap();
+  assert!(harness.cache.read().in_roster(&entry));
+
+  harness.petring.delete_user(discord_id).await.unwrap();
+  assert!(!harness.cache.read().in_roster(&entry));
+
+  let err = harness
+    .petring
+    .get_user_by_discord(discord_id)
+    .await
+    .expect_err("the rejected user is gone");
+  assert_eq!(err.status(), Some(StatusCode::NOT_FOUND));
+  assert!(harness.petring.get_known_users().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn verifies_and_rejects_ads() {
+  let harness = common::start().await;
+  let discord_id = UserId::new(DISCORD_ID);
+  harness
+    .petring
+    .submit_user(user_submission())
+    .await
+    .unwrap();
+  harness.petring.verify_user(discord_id).await.unwrap();
+
+  let ad = harness
+    .petring
+    .submit_ad(ImageSubmission {
+      image_url: String::from("https://kitty.example/ad.png"),
+      discord_id: DISCORD_ID,
+    })
+    .await
+    .unwrap();
+  assert_eq!(ad.ad_url, "https://kitty.example");
+  assert!(!ad.verified);
+
+  assert!(
+    harness
+      .petring
+      .verify_ad(discord_id)
+      .await
+      .unwrap()
+      .verified
+  );
+  let known = harness.petring.get_known_ads().await.unwrap();
+  assert_eq!(known.len(), 1);
+  assert!(known[0].verified);
+
+  harness.petring.delete_ad(discord_id).await.unwrap();
+  let err = harness.petring.get_ad(discord_id).await.unwrap_err();
+  assert_eq!(err.status(), Some(StatusCode::NOT_FOUND));
+}
+
+#[tokio::test]
+async fn replays_look_for_an_earlier_submit_before_sending_again() {
+  let harness = common::start().await;
+  let petring = &harness.petring;
+  let discord_id = UserId::new(DISCORD_ID);
+  let submission = user_submission();
+
+  assert!(
+    submitted_user(petring, discord_id, &submission.url)
+      .await
+      .unwrap()
+      .is_none()
+  );
+
+  // A submit that timed out on collar's side but went through.
+  petring.submit_user(user_submission()).await.unwrap();
+  let found = submitted_user(petring, discord_id, &submission.url)
+    .await
+    .unwrap()
+    .expect("the earlier submit is found instead of sending it again");
+  assert_eq!(found.username, submission.username);
+  let err = petring
+    .submit_user(user_submission())
+    .await
+    .expect_err("sending it again would have been refused");
+  assert_eq!(err.status(), Some(StatusCode::CONFLICT));
+
+  // The member's entry with another site isn't this submission.
+  assert!(
+    submitted_user(petring, discord_id, "https://other.example")
+      .await
+      .unwrap()
+      .is_none()
+  );
+
+  petring.verify_user(discord_id).await.unwrap();
+  let image_url = "https://kitty.example/ad.png";
+  assert!(
+    submitted_ad(petring, discord_id, image_url)
+      .await
+      .unwrap()
+      .is_none()
+  );
+  petring
+    .submit_ad(ImageSubmission {
+      image_url: image_url.to_string(),
+      discord_id: DISCORD_ID,
+    })
+    .await
+    .unwrap();
+  assert!(
+    submitted_ad(petring, discord_id, image_url)
+      .await
+      .unwrap()
+      .is_some()
+  );
+  assert!(
+    submitted_ad(petring, discord_id, "https://kitty.example/new.png")
+      .await
+      .unwrap()
+      .is_none()
+  );
+
+  // Without an answer it stays queued instead of guessing.
+  harness.cache.write().set_instance_urls(
+    DEFAULT_INSTANCE,
+    Urls::new(
+      String::from("http://127.0.0.1:1"),
+      String::from("https://webring.example"),
+    ),
+  );
+  assert!(
+    submitted_user(petring, discord_id, &submission.url)
+      .await
+      .is_err()
+  );
+}
+
+#[tokio::test]
+async fn an_unreachable_instance_is_reported_unavailable() {
+  let harness = common::start_without_tokens().await;
+
+  // Nothing listens on a port that was just freed.
+  let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+  let dead_url = format!("http://{}", listener.local_addr().unwrap());
+  drop(listener);
+  harness.cache.write().set_instance_urls(
+    DEFAULT_INSTANCE,
+    Urls::new(dead_url, String::from("https://webring.example")),
+  );
+
+  assert!(harness.bootstrap(Duration::from_secs(5)).await.is_err());
+  let err = harness
+    .petring
+    .get_user_by_discord(UserId::new(DISCORD_ID))
+    .await
+    .expect_err("there's no API to answer");
+  assert!(err.is_unavailable(), "{err}");
+  assert!(err.never_reached(), "safe to queue and send again: {err}");
+}
+
+#[tokio::test]
+async fn a_guild_bound_to_a_missing_instance_doesnt_fall_back_to_the_default() {
+  let harness = common::start().await;
+  let guild_id = GuildId::new(42);
+  {
+    let mut cache = harness.cache.write();
+    cache.set_guild_instance(guild_id, String::from("staging"));
+    cache.retain_instances(&[DEFAULT_INSTANCE.to_string()]);
+    assert_eq!(cache.get_guild_instance_name(Some(guild_id)), None);
+    assert_eq!(
+      cache.get_guild_instance_name(Some(GuildId::new(43))),
+      Some(DEFAULT_INSTANCE.to_string())
+    );
+  }
+
+  let petring = PetringClient::new(
+    harness.http_client.clone(),
+    harness.cache.clone(),
+    harness.tokens.clone(),
+    ApiStatus::default(),
+    RequestMetrics::default(),
+    InstanceTarget::Guild(Some(guild_id)),
+  );
+  let err = petring
+    .submit_user(user_submission())
+    .await
+    .expect_err("staging isn't configured");
+  assert!(matches!(err, PetringError::UnknownInstance(ref name) if name == "staging"));
+  assert_eq!(harness.api.setups().await, 1);
+  assert!(
+    harness
+      .petring
+      .get_user_by_discord(UserId::new(DISCORD_ID))
+      .await
+      .is_err(),
+    "nothing reached the default instance"
+  );
+}